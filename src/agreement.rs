@@ -0,0 +1,70 @@
+/// How many of the sources of evidence about the MIME type of a file
+/// agree with the result of a guess.
+///
+/// The agreement is only computed if the [`check_agreement`] method of
+/// [`GuessBuilder`] is enabled, and it is returned by the [`agreement`]
+/// method of the result.
+///
+/// Each source either provided no evidence, or agreed or disagreed with
+/// the result; a source agrees if the MIME type it identified is the
+/// guessed one, or if either of them is a subclass of the other.
+///
+/// ```rust
+/// # let mime_db = xdg_mime::SharedMimeInfo::new();
+/// // let mime_db = ...
+/// let guess = mime_db
+///     .guess_mime_type()
+///     .file_name("picture.png")
+///     .data(b"\x89PNG\r\n\x1a\n")
+///     .check_agreement(true)
+///     .guess();
+/// let agreement = guess.agreement().unwrap();
+/// assert_eq!(agreement.file_name(), Some(true));
+/// assert_eq!(agreement.data(), Some(true));
+/// assert_eq!((agreement.agreeing(), agreement.sources()), (2, 2));
+/// ```
+///
+/// [`check_agreement`]: struct.GuessBuilder.html#method.check_agreement
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+/// [`agreement`]: struct.Guess.html#method.agreement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Agreement {
+    pub(crate) file_name: Option<bool>,
+    pub(crate) data: Option<bool>,
+    pub(crate) metadata: Option<bool>,
+}
+
+impl Agreement {
+    /// Whether the glob patterns matching the file name agree with the
+    /// result, or `None` if no pattern matches.
+    pub fn file_name(&self) -> Option<bool> {
+        self.file_name
+    }
+
+    /// Whether the magic entries matching the data agree with the result,
+    /// or `None` if no data is available, or no magic entry matches it.
+    pub fn data(&self) -> Option<bool> {
+        self.data
+    }
+
+    /// Whether the special type for the metadata of the file, like
+    /// `inode/directory`, agrees with the result, or `None` if the
+    /// metadata does not identify a special type.
+    pub fn metadata(&self) -> Option<bool> {
+        self.metadata
+    }
+
+    /// The number of sources that provided some evidence.
+    pub fn sources(&self) -> usize {
+        self.votes().flatten().count()
+    }
+
+    /// The number of sources that agree with the result.
+    pub fn agreeing(&self) -> usize {
+        self.votes().filter(|vote| *vote == Some(true)).count()
+    }
+
+    fn votes(&self) -> impl Iterator<Item = Option<bool>> {
+        [self.file_name, self.data, self.metadata].into_iter()
+    }
+}
@@ -1,16 +1,22 @@
 use std::fmt;
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 use mime::Mime;
 
+use crate::diagnostics::ParseContext;
+use crate::special;
+use crate::tier::Tier;
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct Alias {
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     pub alias: Mime,
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     pub mime_type: Mime,
+    pub tier: Tier,
 }
 
 impl fmt::Debug for Alias {
@@ -24,20 +30,25 @@ impl Alias {
         Alias {
             alias: alias.clone(),
             mime_type: mime_type.clone(),
+            tier: Tier::System,
         }
     }
 
     pub fn from_string(s: &str) -> Option<Alias> {
         let mut chunks = s.split_whitespace().fuse();
-        let alias = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
-        let mime_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
+        let alias = chunks.next().and_then(special::parse_mime_type)?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
 
         // Consume the leftovers, if any
         if chunks.next().is_some() {
             return None;
         }
 
-        Some(Alias { alias, mime_type })
+        Some(Alias {
+            alias,
+            mime_type,
+            tier: Tier::System,
+        })
     }
 
     pub fn is_equivalent(&self, other: &Alias) -> bool {
@@ -45,6 +56,7 @@ impl Alias {
     }
 }
 
+#[derive(Clone)]
 pub struct AliasesList {
     aliases: Vec<Alias>,
 }
@@ -56,8 +68,16 @@ impl AliasesList {
         }
     }
 
+    // Adds the aliases of a directory, with a lower precedence than the
+    // directories added before; only the first definition of each alias is
+    // kept, so the lists of aliases of a MIME type never include the ones
+    // that resolve to another type
     pub fn add_aliases(&mut self, aliases: Vec<Alias>) {
-        self.aliases.extend(aliases);
+        for a in aliases {
+            if !self.aliases.iter().any(|old| old.alias == a.alias) {
+                self.aliases.push(a);
+            }
+        }
         self.sort();
     }
 
@@ -65,6 +85,10 @@ impl AliasesList {
         self.aliases.sort_by(|a, b| a.alias.cmp(&b.alias))
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Alias> {
+        self.aliases.iter()
+    }
+
     pub fn unalias_mime_type(&self, mime_type: &Mime) -> Option<Mime> {
         self.aliases
             .iter()
@@ -72,26 +96,34 @@ impl AliasesList {
             .map(|a| a.mime_type.clone())
     }
 
+    // The tier of the definition of an alias that takes precedence
+    pub fn alias_tier(&self, mime_type: &Mime) -> Option<Tier> {
+        self.aliases
+            .iter()
+            .find(|a| a.alias == *mime_type)
+            .map(|a| a.tier)
+    }
+
     pub fn clear(&mut self) {
         self.aliases.clear();
     }
 }
 
-pub fn read_aliases_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Alias> {
+pub fn read_aliases_from_file<P: AsRef<Path>>(file_name: P, ctx: &mut ParseContext) -> Vec<Alias> {
     let mut res = Vec::new();
 
-    let f = match File::open(file_name) {
+    let f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return res,
     };
 
     let file = BufReader::new(&f);
-    for line in file.lines() {
+    for (n, line) in file.lines().enumerate() {
         if line.is_err() {
             return res; // FIXME: return error instead
         }
 
-        let line = line.unwrap();
+        let line = ctx.normalize_line(&line.unwrap(), ' ');
 
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -99,24 +131,47 @@ pub fn read_aliases_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Alias> {
 
         match Alias::from_string(&line) {
             Some(v) => res.push(v),
-            None => continue,
+            None => ctx.skip_entry(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split_whitespace().take(2),
+            ),
         }
     }
 
     res
 }
 
-pub fn read_aliases_from_dir<P: AsRef<Path>>(dir: P) -> Vec<Alias> {
+pub fn read_aliases_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Vec<Alias> {
     let mut alias_file = PathBuf::new();
     alias_file.push(dir);
     alias_file.push("aliases");
 
-    read_aliases_from_file(alias_file)
+    read_aliases_from_file(alias_file, ctx)
+}
+
+// Aliases are sorted by alias; if an alias is defined more than once,
+// only the definition that takes precedence is written
+pub fn write_aliases<W: Write>(aliases: &AliasesList, w: &mut W) -> io::Result<()> {
+    let mut previous: Option<&Mime> = None;
+
+    for a in aliases.iter() {
+        if previous == Some(&a.alias) {
+            continue;
+        }
+
+        writeln!(w, "{} {}", a.alias, a.mime_type)?;
+        previous = Some(&a.alias);
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     #[test]
     fn new_alias() {
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use mime::Mime;
+
+use crate::SharedMimeInfo;
+
+/// A potential problem found in the shared MIME database by the
+/// [`audit`] method of [`SharedMimeInfo`].
+///
+/// [`audit`]: struct.SharedMimeInfo.html#method.audit
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Finding {
+    /// A MIME type has globs or magic rules, but no XML definition in
+    /// any of the MIME directories, which means it has no description.
+    MissingDefinition {
+        mime_type: Mime,
+        has_globs: bool,
+        has_magic: bool,
+    },
+    /// A MIME type is declared as a subclass of a type that is not
+    /// defined in the database.
+    UnknownParent { mime_type: Mime, parent: Mime },
+    /// An alias points to a MIME type that is not defined in the
+    /// database.
+    UnknownAliasTarget { alias: Mime, mime_type: Mime },
+    /// An icon is associated to a MIME type that is not defined in
+    /// the database.
+    IconForUnknownType {
+        mime_type: Mime,
+        icon_name: String,
+        generic: bool,
+    },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::MissingDefinition {
+                mime_type,
+                has_globs,
+                has_magic,
+            } => {
+                let source = match (has_globs, has_magic) {
+                    (true, true) => "globs and magic",
+                    (true, false) => "globs",
+                    _ => "magic",
+                };
+                write!(f, "{} has {} but no XML definition", mime_type, source)
+            }
+            Finding::UnknownParent { mime_type, parent } => {
+                write!(f, "{} is a subclass of unknown type {}", mime_type, parent)
+            }
+            Finding::UnknownAliasTarget { alias, mime_type } => {
+                write!(f, "{} is an alias of unknown type {}", alias, mime_type)
+            }
+            Finding::IconForUnknownType {
+                mime_type,
+                icon_name,
+                generic,
+            } => {
+                let kind = if *generic { "generic icon" } else { "icon" };
+                write!(
+                    f,
+                    "{} '{}' is set for unknown type {}",
+                    kind, icon_name, mime_type
+                )
+            }
+        }
+    }
+}
+
+// The types the specification defines on its own, and that do not
+// need to be part of any package
+fn is_implicit_type(mime_type: &Mime) -> bool {
+    *mime_type == mime::TEXT_PLAIN
+        || *mime_type == mime::APPLICATION_OCTET_STREAM
+        || mime_type.type_() == "inode"
+}
+
+fn has_definition(db: &SharedMimeInfo, mime_type: &Mime) -> bool {
+    let file_name = format!("{}.xml", mime_type.essence_str());
+
+    db.mime_dirs
+        .iter()
+        .any(|dir| dir.path.join(&file_name).is_file())
+}
+
+// The MIME types with globs, magic entries or parents
+fn known_types(db: &SharedMimeInfo) -> (HashSet<&Mime>, HashSet<&Mime>, HashSet<&Mime>) {
+    let glob_types: HashSet<&Mime> = db.globs.mime_types().collect();
+    let magic_types: HashSet<&Mime> = db.magic.entries().iter().map(|m| m.mime_type()).collect();
+
+    let mut known: HashSet<&Mime> = glob_types.union(&magic_types).cloned().collect();
+    known.extend(db.parents.iter().map(|(mime_type, _)| mime_type));
+
+    (known, glob_types, magic_types)
+}
+
+fn is_known(db: &SharedMimeInfo, known: &HashSet<&Mime>, mime_type: &Mime) -> bool {
+    known.contains(mime_type) || is_implicit_type(mime_type) || has_definition(db, mime_type)
+}
+
+// The subclasses of unknown types, and the aliases of unknown types
+fn add_dangling_references(db: &SharedMimeInfo, known: &HashSet<&Mime>, res: &mut Vec<Finding>) {
+    let is_known = |mime_type: &Mime| is_known(db, known, mime_type);
+
+    for (mime_type, parents) in db.parents.iter() {
+        for parent in parents.iter().filter(|p| !is_known(p)) {
+            res.push(Finding::UnknownParent {
+                mime_type: mime_type.clone(),
+                parent: parent.clone(),
+            });
+        }
+    }
+
+    for alias in db.aliases.iter().filter(|a| !is_known(&a.mime_type)) {
+        res.push(Finding::UnknownAliasTarget {
+            alias: alias.alias.clone(),
+            mime_type: alias.mime_type.clone(),
+        });
+    }
+}
+
+pub fn dangling_references(db: &SharedMimeInfo) -> Vec<Finding> {
+    let (known, _, _) = known_types(db);
+
+    let mut res = Vec::new();
+    add_dangling_references(db, &known, &mut res);
+    res.sort();
+
+    res
+}
+
+pub fn audit(db: &SharedMimeInfo) -> Vec<Finding> {
+    let (known, glob_types, magic_types) = known_types(db);
+
+    let mut res = Vec::new();
+
+    for mime_type in &known {
+        if is_implicit_type(mime_type) || has_definition(db, mime_type) {
+            continue;
+        }
+
+        let has_globs = glob_types.contains(mime_type);
+        let has_magic = magic_types.contains(mime_type);
+        if has_globs || has_magic {
+            res.push(Finding::MissingDefinition {
+                mime_type: (*mime_type).clone(),
+                has_globs,
+                has_magic,
+            });
+        }
+    }
+
+    add_dangling_references(db, &known, &mut res);
+
+    let icons = db.icons.iter().map(|icon| (icon, false));
+    let generic_icons = db.generic_icons.iter().map(|icon| (icon, true));
+    for (icon, generic) in icons.chain(generic_icons) {
+        if !is_known(db, &known, icon.mime_type()) {
+            res.push(Finding::IconForUnknownType {
+                mime_type: icon.mime_type().clone(),
+                icon_name: icon.icon_name().to_string(),
+                generic,
+            });
+        }
+    }
+
+    res.sort();
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    #[test]
+    fn audit_test_data() {
+        let mut db = SharedMimeInfo::new_for_directory("test_files");
+        let findings = audit(&db);
+
+        // The test data does not ship the XML definitions
+        assert!(findings.contains(&Finding::MissingDefinition {
+            mime_type: Mime::from_str("image/png").unwrap(),
+            has_globs: true,
+            has_magic: true,
+        }));
+        assert!(findings.contains(&Finding::UnknownParent {
+            mime_type: Mime::from_str("image/x-canon-cr2").unwrap(),
+            parent: Mime::from_str("image/x-dcraw").unwrap(),
+        }));
+
+        let unknown = Mime::from_str("application/x-unknown").unwrap();
+        Arc::make_mut(&mut db.generic_icons)
+            .add_icons(vec![crate::icon::Icon::new("foo-x-generic", &unknown)]);
+        Arc::make_mut(&mut db.aliases).add_aliases(vec![crate::alias::Alias::new(
+            &Mime::from_str("application/x-old-unknown").unwrap(),
+            &unknown,
+        )]);
+
+        let findings = audit(&db);
+        assert!(findings.contains(&Finding::IconForUnknownType {
+            mime_type: unknown.clone(),
+            icon_name: "foo-x-generic".to_string(),
+            generic: true,
+        }));
+        assert!(findings.contains(&Finding::UnknownAliasTarget {
+            alias: Mime::from_str("application/x-old-unknown").unwrap(),
+            mime_type: unknown,
+        }));
+    }
+
+    #[test]
+    fn dangling() {
+        let mut dir = crate::testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo")
+            .subclass("text/x-foo", "text/plain")
+            .subclass("text/x-foo", "text/x-missing")
+            .subclass("text/x-bar", "text/x-foo")
+            .alias("text/x-old-foo", "text/x-foo")
+            .alias("text/x-old-missing", "text/x-missing")
+            .icon("text/x-missing", "missing");
+        let db = dir.load().unwrap();
+
+        let missing = Mime::from_str("text/x-missing").unwrap();
+        assert_eq!(
+            dangling_references(&db),
+            vec![
+                Finding::UnknownParent {
+                    mime_type: Mime::from_str("text/x-foo").unwrap(),
+                    parent: missing.clone(),
+                },
+                Finding::UnknownAliasTarget {
+                    alias: Mime::from_str("text/x-old-missing").unwrap(),
+                    mime_type: missing,
+                },
+            ]
+        );
+
+        // The audit also reports the icon of the unknown type, and the
+        // missing definition of text/x-foo
+        assert_eq!(audit(&db).len(), 4);
+    }
+}
@@ -0,0 +1,89 @@
+/// How the [`guess`] method of [`GuessBuilder`] treats the names of backup
+/// files, like `notes.txt~`, or the numbered backups of GNU tools, like
+/// `notes.txt.~2~`.
+///
+/// The shared MIME database matches the names of backup files with a
+/// pattern of `application/x-trash`, which hides the type of their
+/// contents. Synchronization and versioning tools usually want to know
+/// both: that the file is a backup, and of which type of file.
+///
+/// ```rust
+/// use xdg_mime::BackupHandling;
+///
+/// # let mime_db = xdg_mime::SharedMimeInfo::new();
+/// // let mime_db = ...
+/// let guess = mime_db
+///     .guess_mime_type()
+///     .file_name("notes.txt~")
+///     .backup_handling(BackupHandling::StripAndFlag)
+///     .guess();
+/// assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+/// assert_eq!(guess.is_backup_of(), Some(&mime::TEXT_PLAIN));
+/// ```
+///
+/// [`guess`]: struct.GuessBuilder.html#method.guess
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BackupHandling {
+    /// The file name is matched as it is, so backup files get the type of
+    /// the patterns matching their suffix, usually `application/x-trash`.
+    #[default]
+    AsIs,
+    /// The backup suffix is removed before matching the file name, and
+    /// the result is flagged as a backup, using [`Guess::is_backup_of`].
+    ///
+    /// [`Guess::is_backup_of`]: struct.Guess.html#method.is_backup_of
+    StripAndFlag,
+    /// The backup suffix is removed before matching the file name, and
+    /// the file is treated like any other file of the same type.
+    Ignore,
+}
+
+impl BackupHandling {
+    // The name to match against the glob patterns
+    pub(crate) fn file_name<'a>(&self, file_name: &'a str) -> &'a str {
+        match self {
+            BackupHandling::AsIs => file_name,
+            _ => strip_backup_suffix(file_name).unwrap_or(file_name),
+        }
+    }
+
+    pub(crate) fn flags(&self, file_name: &str) -> bool {
+        *self == BackupHandling::StripAndFlag && strip_backup_suffix(file_name).is_some()
+    }
+}
+
+// Removes the suffix of a numbered backup, like `.~2~`, or the trailing
+// tilde of a simple backup; returns None if the name is not the one of a
+// backup, or if nothing would be left of it
+pub fn strip_backup_suffix(file_name: &str) -> Option<&str> {
+    let rest = file_name.strip_suffix('~')?;
+
+    let base = match rest.rsplit_once(".~") {
+        Some((base, n)) if !n.is_empty() && n.bytes().all(|ch| ch.is_ascii_digit()) => base,
+        _ => rest,
+    };
+
+    (!base.is_empty()).then_some(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_suffix() {
+        assert_eq!(strip_backup_suffix("notes.txt~"), Some("notes.txt"));
+        assert_eq!(strip_backup_suffix("notes.txt.~12~"), Some("notes.txt"));
+        assert_eq!(strip_backup_suffix("notes.txt.~a~"), Some("notes.txt.~a"));
+        assert_eq!(strip_backup_suffix("notes.txt"), None);
+        assert_eq!(strip_backup_suffix("~"), None);
+        assert_eq!(strip_backup_suffix(".~1~"), None);
+
+        assert_eq!(BackupHandling::AsIs.file_name("a.c~"), "a.c~");
+        assert_eq!(BackupHandling::Ignore.file_name("a.c~"), "a.c");
+        assert!(!BackupHandling::Ignore.flags("a.c~"));
+        assert!(BackupHandling::StripAndFlag.flags("a.c~"));
+        assert!(!BackupHandling::StripAndFlag.flags("a.c"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+// The environment variable listing additional data directories
+pub const EXTRA_DIRS_VAR: &str = "XDG_MIME_EXTRA_DIRS";
+
+// The environment variable listing additional data directories that take
+// precedence over the standard ones
+pub const OVERRIDE_DIRS_VAR: &str = "XDG_MIME_OVERRIDE_DIRS";
+
+// Where the host data directories are exposed inside a Flatpak sandbox
+const FLATPAK_HOST_DIRS: &[&str] = &["run/host/usr/share", "run/host/usr/local/share"];
+
+// Where the host data directories are exposed inside a Snap sandbox
+const SNAP_HOST_DIRS: &[&str] = &[
+    "var/lib/snapd/hostfs/usr/share",
+    "var/lib/snapd/hostfs/usr/local/share",
+];
+
+// Only absolute paths are valid, as per the base directory specification:
+//
+// "All paths set in these environment variables must be absolute. If an
+// implementation encounters a relative path in any of these variables it
+// should consider the path invalid and ignore it."
+fn from_env_value(value: Option<OsString>) -> Option<PathBuf> {
+    value.map(PathBuf::from).filter(|path| path.is_absolute())
+}
+
+fn data_home_from(xdg_data_home: Option<OsString>, home: Option<PathBuf>) -> Option<PathBuf> {
+    from_env_value(xdg_data_home).or_else(|| home.map(|home| home.join(".local/share")))
+}
+
+fn split_env_value(value: Option<OsString>) -> Vec<PathBuf> {
+    match value {
+        Some(v) => env::split_paths(&v)
+            .filter(|path| path.is_absolute())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn data_dirs_from(xdg_data_dirs: Option<OsString>) -> Vec<PathBuf> {
+    let dirs = split_env_value(xdg_data_dirs);
+
+    if dirs.is_empty() {
+        vec![
+            PathBuf::from("/usr/local/share"),
+            PathBuf::from("/usr/share"),
+        ]
+    } else {
+        dirs
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(dirs_next::home_dir)
+}
+
+// $XDG_DATA_HOME, or $HOME/.local/share if unset
+pub fn data_home() -> Option<PathBuf> {
+    data_home_from(env::var_os("XDG_DATA_HOME"), home_dir())
+}
+
+// The mime.types file of the user, in the Apache format
+pub fn user_mime_types() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".mime.types"))
+}
+
+// $XDG_DATA_DIRS, or /usr/local/share:/usr/share if unset
+pub fn data_dirs() -> Vec<PathBuf> {
+    data_dirs_from(env::var_os("XDG_DATA_DIRS"))
+}
+
+// The directories listed in $XDG_MIME_EXTRA_DIRS
+pub fn extra_dirs() -> Vec<PathBuf> {
+    split_env_value(env::var_os(EXTRA_DIRS_VAR))
+}
+
+// The directories listed in $XDG_MIME_OVERRIDE_DIRS
+pub fn override_dirs() -> Vec<PathBuf> {
+    split_env_value(env::var_os(OVERRIDE_DIRS_VAR))
+}
+
+fn sandbox_data_dirs_from(root: &Path, snap: bool) -> Vec<PathBuf> {
+    let candidates = if root.join(".flatpak-info").is_file() {
+        FLATPAK_HOST_DIRS
+    } else if snap {
+        SNAP_HOST_DIRS
+    } else {
+        return Vec::new();
+    };
+
+    candidates
+        .iter()
+        .map(|dir| root.join(dir))
+        .filter(|dir| dir.join("mime").is_dir())
+        .collect()
+}
+
+// The data directories of the host system, if we are running inside
+// a Flatpak or Snap sandbox
+pub fn sandbox_data_dirs() -> Vec<PathBuf> {
+    sandbox_data_dirs_from(Path::new("/"), env::var_os("SNAP").is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempMimeDir;
+
+    #[test]
+    fn data_home_fallback() {
+        let home = Some(PathBuf::from("/home/user"));
+
+        assert_eq!(
+            data_home_from(Some(OsString::from("/data")), home.clone()),
+            Some(PathBuf::from("/data"))
+        );
+        assert_eq!(
+            data_home_from(None, home.clone()),
+            Some(PathBuf::from("/home/user/.local/share"))
+        );
+        assert_eq!(
+            data_home_from(Some(OsString::from("")), home.clone()),
+            Some(PathBuf::from("/home/user/.local/share"))
+        );
+        assert_eq!(
+            data_home_from(Some(OsString::from("relative/path")), home),
+            Some(PathBuf::from("/home/user/.local/share"))
+        );
+        assert_eq!(data_home_from(None, None), None);
+    }
+
+    #[test]
+    fn data_dirs_fallback() {
+        let defaults = vec![
+            PathBuf::from("/usr/local/share"),
+            PathBuf::from("/usr/share"),
+        ];
+
+        assert_eq!(data_dirs_from(None), defaults);
+        assert_eq!(data_dirs_from(Some(OsString::from(""))), defaults);
+        assert_eq!(
+            data_dirs_from(Some(OsString::from("/opt/share:relative:/usr/share"))),
+            vec![PathBuf::from("/opt/share"), PathBuf::from("/usr/share")]
+        );
+    }
+
+    #[test]
+    fn sandbox_dirs() {
+        let dir = TempMimeDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("run/host/usr/share/mime")).unwrap();
+        std::fs::create_dir_all(root.join("var/lib/snapd/hostfs/usr/share/mime")).unwrap();
+
+        assert!(sandbox_data_dirs_from(root, false).is_empty());
+        assert_eq!(
+            sandbox_data_dirs_from(root, true),
+            vec![root.join("var/lib/snapd/hostfs/usr/share")]
+        );
+
+        std::fs::write(root.join(".flatpak-info"), "[Application]\n").unwrap();
+        assert_eq!(
+            sandbox_data_dirs_from(root, false),
+            vec![root.join("run/host/usr/share")]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+// The binary snapshots written by SharedMimeInfo::serialize_snapshot(),
+// encoded with bincode after a fixed header; the MIME types are encoded as
+// strings, and interned again when they are decoded
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bincode::Options;
+use mime::Mime;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::comment::Comments;
+use crate::magic::{MagicEntry, MagicSegment, MagicSource};
+use crate::special;
+use crate::{MimeData, MimeDirectory, Segment, SharedMimeInfo, Subsystems, Tier};
+
+// The first bytes of the snapshots, followed by the version of the crate
+// that wrote them; the snapshots are only restored by the same version,
+// since the encoding of the MIME data can change between versions
+const SNAPSHOT_MAGIC: &[u8] = b"XDG-MIME-SNAPSHOT\0";
+
+// A MIME type, encoded as a string
+pub struct MimeRef<'a>(pub &'a Mime);
+
+impl Serialize for MimeRef<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.0.as_ref())
+    }
+}
+
+// A MIME type decoded from a string, interned like the ones parsed from
+// the files
+pub struct OwnedMime(pub Mime);
+
+impl<'de> Deserialize<'de> for OwnedMime {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<OwnedMime, D::Error> {
+        let s = String::deserialize(d)?;
+        let mime_type = Mime::from_str(&s).map_err(D::Error::custom)?;
+
+        Ok(OwnedMime(special::intern(mime_type)))
+    }
+}
+
+// For the fields holding a MIME type, using #[serde(with = "...")]
+pub mod mime_type {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &Mime, s: S) -> Result<S::Ok, S::Error> {
+        MimeRef(v).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Mime, D::Error> {
+        OwnedMime::deserialize(d).map(|v| v.0)
+    }
+}
+
+pub mod option_mime {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &Option<Mime>, s: S) -> Result<S::Ok, S::Error> {
+        v.as_ref().map(MimeRef).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Mime>, D::Error> {
+        Option::<OwnedMime>::deserialize(d).map(|v| v.map(|v| v.0))
+    }
+}
+
+pub mod mime_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &[Mime], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(MimeRef))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Mime>, D::Error> {
+        let v = Vec::<OwnedMime>::deserialize(d)?;
+
+        Ok(v.into_iter().map(|v| v.0).collect())
+    }
+}
+
+pub mod comment_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &[(Mime, Comments)], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(|(mime_type, c)| (MimeRef(mime_type), c)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(Mime, Comments)>, D::Error> {
+        let v = Vec::<(OwnedMime, Comments)>::deserialize(d)?;
+
+        Ok(v.into_iter()
+            .map(|(mime_type, c)| (mime_type.0, c))
+            .collect())
+    }
+}
+
+// The contents of a directory or mime.types file; the magic entries are
+// parsed, if they were not yet, and the problems found while reading the
+// files are not kept
+#[derive(Serialize, Deserialize)]
+struct DirectorySnapshot {
+    // The paths are encoded as strings; a path that is not valid UTF-8
+    // does not match the one of the builder, so the snapshot is rejected
+    // as stale
+    path: String,
+    mtime: Option<Duration>,
+    tier: Tier,
+    version: Option<String>,
+    #[serde(with = "comment_list")]
+    comments: Vec<(Mime, Comments)>,
+    data: MimeData,
+    source_path: Option<String>,
+    globs_path: String,
+    magic: Option<Vec<MagicEntry>>,
+}
+
+impl DirectorySnapshot {
+    fn new(dir: &MimeDirectory, lenient: bool, strict: bool) -> DirectorySnapshot {
+        let segment = &dir.segment;
+
+        // Sorted, so that the same database is always encoded the same way
+        let mut comments: Vec<_> = dir
+            .comments
+            .iter()
+            .map(|(mime_type, c)| (mime_type.clone(), c.clone()))
+            .collect();
+        comments.sort_by(|a, b| a.0.cmp(&b.0));
+
+        DirectorySnapshot {
+            path: dir.path.to_string_lossy().into_owned(),
+            mtime: dir
+                .mtime
+                .map(|v| v.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()),
+            tier: dir.tier,
+            version: dir.version.clone(),
+            comments,
+            data: segment.data.clone(),
+            source_path: segment
+                .source_path
+                .as_ref()
+                .map(|v| v.to_string_lossy().into_owned()),
+            globs_path: segment.globs_path.to_string_lossy().into_owned(),
+            magic: segment
+                .magic
+                .as_ref()
+                .map(|magic| magic.entries(lenient, strict).to_vec()),
+        }
+    }
+
+    fn into_directory(self) -> Option<MimeDirectory> {
+        let mtime = match self.mtime {
+            Some(mtime) => Some(SystemTime::UNIX_EPOCH.checked_add(mtime)?),
+            None => None,
+        };
+        let segment = Segment {
+            data: self.data,
+            source_path: self.source_path.map(PathBuf::from),
+            globs_path: PathBuf::from(self.globs_path),
+            magic: self
+                .magic
+                .map(|entries| Arc::new(MagicSegment::new(MagicSource::Entries(entries)))),
+            diagnostics: Vec::new(),
+        };
+
+        Some(MimeDirectory {
+            path: PathBuf::from(self.path),
+            mtime,
+            tier: self.tier,
+            version: self.version,
+            comments: Arc::new(self.comments.into_iter().collect()),
+            segment: Arc::new(segment),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: String,
+    load: Subsystems,
+    mime_dirs: Vec<DirectorySnapshot>,
+    mime_types_files: Vec<DirectorySnapshot>,
+}
+
+// The load flags, the directories, and the mime.types files of a snapshot
+pub type SnapshotContents = (Subsystems, Vec<MimeDirectory>, Vec<MimeDirectory>);
+
+pub fn serialize(db: &SharedMimeInfo) -> Vec<u8> {
+    let dirs = |dirs: &[MimeDirectory]| {
+        dirs.iter()
+            .map(|dir| DirectorySnapshot::new(dir, db.lenient, db.strict))
+            .collect()
+    };
+    let snapshot = Snapshot {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        load: db.load,
+        mime_dirs: dirs(&db.mime_dirs),
+        mime_types_files: dirs(&db.mime_types_files),
+    };
+
+    let mut res = SNAPSHOT_MAGIC.to_vec();
+    bincode::DefaultOptions::new()
+        .serialize_into(&mut res, &snapshot)
+        .expect("the snapshot can be encoded");
+
+    res
+}
+
+// Returns None if the data was not written by the same version of this
+// crate, or if it is truncated
+pub fn deserialize(snapshot: &[u8]) -> Option<SnapshotContents> {
+    let data = snapshot.strip_prefix(SNAPSHOT_MAGIC)?;
+    let options = bincode::DefaultOptions::new().with_limit(data.len() as u64);
+
+    // The version comes first, so that the rest is not decoded if it was
+    // written by another version
+    let version: String = options.deserialize_from(data).ok()?;
+    if version != env!("CARGO_PKG_VERSION") {
+        return None;
+    }
+
+    let snapshot: Snapshot = options.deserialize(data).ok()?;
+    let dirs = |dirs: Vec<DirectorySnapshot>| {
+        dirs.into_iter()
+            .map(DirectorySnapshot::into_directory)
+            .collect::<Option<Vec<_>>>()
+    };
+
+    Some((
+        snapshot.load,
+        dirs(snapshot.mime_dirs)?,
+        dirs(snapshot.mime_types_files)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interned_mime_types() {
+        #[derive(Serialize, Deserialize)]
+        struct Record {
+            #[serde(with = "mime_type")]
+            mime_type: Mime,
+            #[serde(with = "option_mime")]
+            parent: Option<Mime>,
+            #[serde(with = "mime_list")]
+            aliases: Vec<Mime>,
+        }
+
+        let record = Record {
+            mime_type: Mime::from_str("inode/directory").unwrap(),
+            parent: Some(mime::TEXT_PLAIN),
+            aliases: vec![Mime::from_str("text/x-foo").unwrap()],
+        };
+        let bytes = bincode::serialize(&record).unwrap();
+        let decoded: Record = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.mime_type, *special::INODE_DIRECTORY);
+        assert_eq!(decoded.parent, Some(mime::TEXT_PLAIN));
+        assert_eq!(decoded.aliases, record.aliases);
+
+        // Not a MIME type, or truncated
+        let bytes = bincode::serialize(&("text", None::<String>, Vec::<String>::new())).unwrap();
+        assert!(bincode::deserialize::<Record>(&bytes).is_err());
+        let bytes = bincode::serialize(&record).unwrap();
+        assert!(bincode::deserialize::<Record>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
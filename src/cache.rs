@@ -0,0 +1,995 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+#[cfg(not(feature = "mmap"))]
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError, Weak};
+use std::time::SystemTime;
+
+use mime::Mime;
+
+use crate::alias::Alias;
+use crate::diagnostics::ParseContext;
+use crate::glob::{Glob, MAX_PATTERN_LEN};
+use crate::icon::Icon;
+use crate::magic::{self, MagicEntry, MagicRule, NO_MAGIC};
+use crate::namespace::XmlNamespace;
+use crate::parent::Subclass;
+use crate::special;
+use crate::{MimeData, Subsystems};
+
+// The versions of the mime.cache format written by update-mime-database
+// that we can read; every 1.x version after 1.1 has the same layout
+const MAJOR_VERSION: u16 = 1;
+const MINOR_VERSIONS: [u16; 2] = [1, 2];
+
+// The size of the header, with the version and the offsets of each list
+const HEADER_LEN: usize = 40;
+
+// The depth of the magic matchlets, after which the cache is considered
+// corrupted; it only bounds the depth, while the number of matchlets read
+// is bounded by the size of the file, see Reader::budget()
+const MAX_MATCHLET_DEPTH: u32 = 64;
+
+// The size of the nodes of the suffix tree, and of the magic matchlets
+const SUFFIX_NODE_LEN: u32 = 12;
+const MATCHLET_LEN: u32 = 32;
+
+// The flag set on the weight of case sensitive globs
+const CASE_SENSITIVE: u32 = 0x100;
+
+// The contents of a mime.cache file: mapped in memory, if possible, so
+// that the pages of the file are shared between processes, and only the
+// ones used by the lookups are read
+#[cfg(feature = "mmap")]
+type Contents = memmap2::Mmap;
+#[cfg(not(feature = "mmap"))]
+type Contents = Vec<u8>;
+
+// update-mime-database writes a new mime.cache file, and renames it over
+// the old one, so the mapped file is never modified; a file truncated in
+// place while it is mapped makes the lookups crash, as documented at the
+// top of the crate
+#[cfg(feature = "mmap")]
+fn read_contents(file: File) -> io::Result<Contents> {
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_contents(mut file: File) -> io::Result<Contents> {
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+// What tells whether a file was replaced since it was opened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileId {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+}
+
+impl FileId {
+    fn new(metadata: &fs::Metadata) -> FileId {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+
+        FileId {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            dev: metadata.dev(),
+            #[cfg(unix)]
+            ino: metadata.ino(),
+        }
+    }
+}
+
+// The mime.cache files used by any database, keyed by their path
+static OPEN_FILES: LazyLock<Mutex<HashMap<PathBuf, Weak<CacheFile>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// A mime.cache file with a version we can read; each file is opened once
+// per process, and shared by all the databases using it, until it is
+// replaced by update-mime-database
+pub struct CacheFile {
+    path: PathBuf,
+    id: FileId,
+    contents: Contents,
+}
+
+impl fmt::Debug for CacheFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheFile")
+            .field("path", &self.path)
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CacheFile {
+    // Returns None if the file does not exist, or uses a version of the
+    // format we cannot read
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<Arc<CacheFile>> {
+        let path = path.as_ref();
+        let file = File::open(path).ok()?;
+        let id = FileId::new(&file.metadata().ok()?);
+
+        let mut open_files = OPEN_FILES.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cache) = open_files.get(path).and_then(Weak::upgrade) {
+            if cache.id == id {
+                return Some(cache);
+            }
+        }
+
+        let contents = read_contents(file).ok()?;
+        Reader::new(&contents, path)?;
+
+        let cache = Arc::new(CacheFile {
+            path: path.to_path_buf(),
+            id,
+            contents,
+        });
+        open_files.retain(|_, cache| cache.strong_count() > 0);
+        open_files.insert(path.to_path_buf(), Arc::downgrade(&cache));
+
+        Some(cache)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn reader(&self) -> Reader<'_> {
+        Reader {
+            buf: &self.contents,
+            path: &self.path,
+        }
+    }
+}
+
+// A view over the contents of a mime.cache file; all the integers are
+// stored in big endian order, and all the offsets are relative to the
+// start of the file
+struct Reader<'a> {
+    buf: &'a [u8],
+    path: &'a Path,
+}
+
+impl<'a> Reader<'a> {
+    // Checks the version of the file
+    fn new(buf: &'a [u8], path: &'a Path) -> Option<Reader<'a>> {
+        let r = Reader { buf, path };
+
+        if buf.len() < HEADER_LEN
+            || r.u16(0)? != MAJOR_VERSION
+            || !MINOR_VERSIONS.contains(&r.u16(2)?)
+        {
+            return None;
+        }
+
+        Some(r)
+    }
+
+    fn bytes(&self, offset: u32, len: u32) -> Option<&'a [u8]> {
+        let start = offset as usize;
+        let end = start.checked_add(len as usize)?;
+
+        self.buf.get(start..end)
+    }
+
+    fn u16(&self, offset: u32) -> Option<u16> {
+        let bytes = self.bytes(offset, 2)?;
+
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, offset: u32) -> Option<u32> {
+        let bytes = self.bytes(offset, 4)?;
+
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // The number of records of `size` bytes that fit in the file; the
+    // trees of the cache can refer to the same children more than once,
+    // if it is corrupted, so they are read with at most this many nodes,
+    // instead of growing exponentially with their depth
+    fn budget(&self, size: u32) -> u32 {
+        (self.buf.len() / size as usize) as u32
+    }
+
+    // The offset of the `idx`-th record of `size` bytes, after `start`
+    fn record(&self, start: u32, idx: u32, size: u32) -> Option<u32> {
+        idx.checked_mul(size)?.checked_add(start)
+    }
+
+    // The items of a list: the number of items, followed by the items
+    fn list(&self, offset: u32, size: u32) -> Option<impl Iterator<Item = u32>> {
+        let n_items = self.u32(offset)?;
+        let start = offset.checked_add(4)?;
+
+        // Make sure the whole list is available, before iterating it
+        self.bytes(start, n_items.checked_mul(size)?)?;
+
+        Some((0..n_items).map(move |idx| start + idx * size))
+    }
+
+    // A nul-terminated string
+    fn string(&self, offset: u32) -> Option<&'a str> {
+        let bytes = self.buf.get(offset as usize..)?;
+        let len = bytes.iter().position(|b| *b == 0)?;
+
+        str::from_utf8(&bytes[..len]).ok()
+    }
+
+    fn matchlet(&self, offset: u32) -> Option<Matchlet<'a>> {
+        let range_start = self.u32(offset)?;
+        let range_len = self.u32(offset + 4)?;
+        let value_len = self.u32(offset + 12)?;
+        let value = self.bytes(self.u32(offset + 16)?, value_len)?;
+        let mask = match self.u32(offset + 20)? {
+            0 => None,
+            mask_offset => Some(self.bytes(mask_offset, value_len)?),
+        };
+        range_start.checked_add(range_len)?;
+
+        Some(Matchlet {
+            range_start,
+            range_len,
+            word_size: self.u32(offset + 8)?,
+            value,
+            mask,
+        })
+    }
+
+    // A MIME type, or None if it cannot be parsed; invalid MIME types are
+    // reported, and only the entries using them are skipped
+    fn mime_type(&self, offset: u32, ctx: &mut ParseContext) -> Option<Option<Mime>> {
+        let s = self.string(offset)?;
+        let mime_type = special::parse_mime_type(s);
+        if mime_type.is_none() {
+            ctx.check_mime_types(self.path, None, s, [s]);
+        }
+
+        Some(mime_type)
+    }
+}
+
+fn read_aliases(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<Alias>> {
+    let mut res = Vec::new();
+
+    for entry in r.list(offset, 8)? {
+        let alias = r.mime_type(r.u32(entry)?, ctx)?;
+        let mime_type = r.mime_type(r.u32(entry + 4)?, ctx)?;
+
+        if let (Some(alias), Some(mime_type)) = (alias, mime_type) {
+            res.push(Alias::new(&alias, &mime_type));
+        }
+    }
+
+    Some(res)
+}
+
+fn read_subclasses(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<Subclass>> {
+    let mut res = Vec::new();
+
+    for entry in r.list(offset, 8)? {
+        let mime_type = r.mime_type(r.u32(entry)?, ctx)?;
+
+        for parent in r.list(r.u32(entry + 4)?, 4)? {
+            let parent_type = r.mime_type(r.u32(parent)?, ctx)?;
+
+            if let (Some(mime_type), Some(parent_type)) = (&mime_type, parent_type) {
+                res.push(Subclass::new(mime_type, &parent_type));
+            }
+        }
+    }
+
+    Some(res)
+}
+
+fn read_icons(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<Icon>> {
+    let mut res = Vec::new();
+
+    for entry in r.list(offset, 8)? {
+        let mime_type = r.mime_type(r.u32(entry)?, ctx)?;
+        let icon_name = r.string(r.u32(entry + 4)?)?;
+
+        if let Some(mime_type) = mime_type {
+            res.push(Icon::new(icon_name, &mime_type));
+        }
+    }
+
+    Some(res)
+}
+
+fn read_namespaces(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<XmlNamespace>> {
+    let mut res = Vec::new();
+
+    for entry in r.list(offset, 12)? {
+        let namespace = r.string(r.u32(entry)?)?;
+        let local_name = r.string(r.u32(entry + 4)?)?;
+        let mime_type = r.mime_type(r.u32(entry + 8)?, ctx)?;
+
+        if let Some(mime_type) = mime_type {
+            res.push(XmlNamespace::new(namespace, local_name, &mime_type));
+        }
+    }
+
+    Some(res)
+}
+
+// Reads the glob pattern, the MIME type, and the weight of a glob; the
+// patterns rejected by the globs files are skipped here as well
+fn read_glob(
+    r: &Reader,
+    pattern: &str,
+    offset: u32,
+    ctx: &mut ParseContext,
+) -> Option<Option<Glob>> {
+    let mime_type = r.mime_type(r.u32(offset)?, ctx)?;
+    let flags = r.u32(offset + 4)?;
+    let weight = (flags & 0xff) as i32;
+    let case_sensitive = flags & CASE_SENSITIVE != 0;
+
+    Some(mime_type.and_then(|m| Glob::checked(&m, pattern, weight, case_sensitive)))
+}
+
+// The literal and glob lists have the same layout: the pattern, the MIME
+// type, and the weight of each glob
+fn read_glob_list(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<Glob>> {
+    let mut res = Vec::new();
+
+    for entry in r.list(offset, 12)? {
+        let pattern = r.string(r.u32(entry)?)?;
+        res.extend(read_glob(r, pattern, entry + 4, ctx)?);
+    }
+
+    Some(res)
+}
+
+// The suffix tree contains the `*<suffix>` globs, with the characters of
+// each suffix in reverse order; the children of the last character of a
+// suffix include a leaf for each MIME type using it, with a nul character
+fn read_suffix_nodes(
+    r: &Reader,
+    n_nodes: u32,
+    offset: u32,
+    suffix: &mut Vec<char>,
+    budget: &mut u32,
+    globs: &mut Vec<Glob>,
+    ctx: &mut ParseContext,
+) -> Option<()> {
+    if suffix.len() > MAX_PATTERN_LEN {
+        return None;
+    }
+
+    for idx in 0..n_nodes {
+        *budget = budget.checked_sub(1)?;
+        let node = r.record(offset, idx, SUFFIX_NODE_LEN)?;
+        let ch = r.u32(node)?;
+
+        if ch == 0 {
+            let pattern: String = std::iter::once('*')
+                .chain(suffix.iter().rev().copied())
+                .collect();
+            globs.extend(read_glob(r, &pattern, node + 4, ctx)?);
+            continue;
+        }
+
+        suffix.push(char::from_u32(ch)?);
+        let (n_children, first_child) = (r.u32(node + 4)?, r.u32(node + 8)?);
+        read_suffix_nodes(r, n_children, first_child, suffix, budget, globs, ctx)?;
+        suffix.pop();
+    }
+
+    Some(())
+}
+
+fn read_suffix_tree(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<Glob>> {
+    let mut res = Vec::new();
+
+    let n_roots = r.u32(offset)?;
+    let first_root = r.u32(offset + 4)?;
+    let mut budget = r.budget(SUFFIX_NODE_LEN);
+    read_suffix_nodes(
+        r,
+        n_roots,
+        first_root,
+        &mut Vec::new(),
+        &mut budget,
+        &mut res,
+        ctx,
+    )?;
+
+    Some(res)
+}
+
+// A magic matchlet, borrowing its value and mask from the file
+struct Matchlet<'a> {
+    range_start: u32,
+    range_len: u32,
+    word_size: u32,
+    value: &'a [u8],
+    mask: Option<&'a [u8]>,
+}
+
+impl Matchlet<'_> {
+    fn to_rule(&self, indent: u32) -> Option<MagicRule> {
+        MagicRule::new(
+            indent,
+            self.range_start..self.range_start + self.range_len,
+            self.word_size,
+            self.value,
+            self.mask,
+        )
+    }
+
+    // The same checks as MagicRule::new()
+    fn is_valid(&self) -> bool {
+        !self.value.is_empty() && self.range_len > 0
+    }
+
+    fn matches_data(&self, data: &[u8]) -> bool {
+        magic::matches_range(
+            data,
+            self.range_start,
+            self.range_len,
+            self.value,
+            self.mask,
+        )
+    }
+
+    fn extent(&self) -> usize {
+        self.value.len() + self.range_start as usize + self.range_len as usize
+    }
+
+    fn min_extent(&self) -> usize {
+        self.range_start as usize + self.value.len()
+    }
+}
+
+// Walks a tree of matchlets depth first, returning the offset of each
+// matchlet with its depth, which is the indentation level of the rules
+// of the magic file; a None item means that the tree is corrupted
+struct Matchlets<'r, 'a> {
+    r: &'r Reader<'a>,
+    // The first matchlet at each depth, their number, and the next one
+    stack: [(u32, u32, u32); MAX_MATCHLET_DEPTH as usize],
+    depth: usize,
+    budget: &'r mut u32,
+}
+
+impl<'r, 'a> Matchlets<'r, 'a> {
+    fn new(r: &'r Reader<'a>, n_matchlets: u32, offset: u32, budget: &'r mut u32) -> Self {
+        let mut stack = [(0, 0, 0); MAX_MATCHLET_DEPTH as usize];
+        stack[0] = (offset, n_matchlets, 0);
+
+        Matchlets {
+            r,
+            stack,
+            depth: 1,
+            budget,
+        }
+    }
+
+    fn read(&mut self, indent: usize, offset: u32, idx: u32) -> Option<(u32, u32)> {
+        *self.budget = self.budget.checked_sub(1)?;
+        let matchlet = self.r.record(offset, idx, MATCHLET_LEN)?;
+        let n_children = self.r.u32(matchlet + 24)?;
+        let first_child = self.r.u32(matchlet + 28)?;
+
+        if n_children > 0 {
+            if indent + 1 >= MAX_MATCHLET_DEPTH as usize {
+                return None;
+            }
+            self.stack[indent + 1] = (first_child, n_children, 0);
+            self.depth = indent + 2;
+        }
+
+        Some((indent as u32, matchlet))
+    }
+}
+
+impl Iterator for Matchlets<'_, '_> {
+    type Item = Option<(u32, u32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let indent = self.depth.checked_sub(1)?;
+            let (offset, n_matchlets, idx) = self.stack[indent];
+            if idx < n_matchlets {
+                self.stack[indent].2 += 1;
+                return Some(self.read(indent, offset, idx));
+            }
+
+            self.depth = indent;
+        }
+    }
+}
+
+// Flattens a tree of matchlets into the rules of a magic entry, using the
+// depth of each matchlet as its indentation level, like the magic file
+fn read_matchlets(
+    r: &Reader,
+    n_matchlets: u32,
+    offset: u32,
+    budget: &mut u32,
+    rules: &mut Vec<Option<MagicRule>>,
+) -> Option<()> {
+    for item in Matchlets::new(r, n_matchlets, offset, budget) {
+        let (indent, matchlet) = item?;
+        rules.push(r.matchlet(matchlet)?.to_rule(indent));
+    }
+
+    Some(())
+}
+
+fn read_magic(r: &Reader, offset: u32, ctx: &mut ParseContext) -> Option<Vec<MagicEntry>> {
+    let mut res = Vec::new();
+
+    let n_matches = r.u32(offset)?;
+    let first_match = r.u32(offset + 8)?;
+    let mut budget = r.budget(MATCHLET_LEN);
+
+    for idx in 0..n_matches {
+        let entry = r.record(first_match, idx, 16)?;
+        let priority = r.u32(entry)?;
+        let mime_type = r.mime_type(r.u32(entry + 4)?, ctx)?;
+
+        let mut rules = Vec::new();
+        let (n_matchlets, first_matchlet) = (r.u32(entry + 8)?, r.u32(entry + 12)?);
+        read_matchlets(r, n_matchlets, first_matchlet, &mut budget, &mut rules)?;
+
+        // Entries with invalid rules are skipped as a whole, since their
+        // children would end up attached to the wrong rule
+        let rules: Option<Vec<MagicRule>> = rules.into_iter().collect();
+        if let (Some(mime_type), Some(rules)) = (mime_type, rules) {
+            if !rules.is_empty() {
+                res.push(MagicEntry::new(&mime_type, priority, rules));
+            }
+        }
+    }
+
+    Some(res)
+}
+
+// The sections that are not loaded are not read, so they cannot make the
+// whole file invalid
+fn from_bytes(
+    buf: &[u8],
+    path: &Path,
+    load: Subsystems,
+    ctx: &mut ParseContext,
+) -> Option<MimeData> {
+    let r = Reader::new(buf, path)?;
+
+    let mut globs = Vec::new();
+    if load.globs {
+        globs.extend(read_glob_list(&r, r.u32(12)?, ctx)?);
+        globs.extend(read_suffix_tree(&r, r.u32(16)?, ctx)?);
+        globs.extend(read_glob_list(&r, r.u32(20)?, ctx)?);
+    }
+
+    let magic = if load.magic {
+        read_magic(&r, r.u32(24)?, ctx)?
+    } else {
+        Vec::new()
+    };
+
+    let (icons, generic_icons) = if load.icons {
+        (
+            read_icons(&r, r.u32(32)?, ctx)?,
+            read_icons(&r, r.u32(36)?, ctx)?,
+        )
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    Some(MimeData {
+        aliases: read_aliases(&r, r.u32(4)?, ctx)?,
+        subclasses: read_subclasses(&r, r.u32(8)?, ctx)?,
+        globs,
+        magic,
+        namespaces: read_namespaces(&r, r.u32(28)?, ctx)?,
+        icons,
+        generic_icons,
+        treemagic: Vec::new(),
+        types: Vec::new(),
+        comments: Vec::new(),
+    })
+}
+
+// Reads a mime.cache file; returns None if the file does not exist, uses
+// a version of the format we cannot read, or is corrupted, so that the
+// plain text files can be used instead
+pub fn read_cache_from_file<P: AsRef<Path>>(
+    path: P,
+    load: Subsystems,
+    ctx: &mut ParseContext,
+) -> Option<(Arc<CacheFile>, MimeData)> {
+    let file = CacheFile::open(path)?;
+
+    // Do not report invalid MIME types from a file we end up discarding
+    let mut cache_ctx = ParseContext::new(ctx.lenient);
+    let cache = from_bytes(&file.contents, &file.path, load, &mut cache_ctx)?;
+    ctx.diagnostics.append(&mut cache_ctx.diagnostics);
+
+    Some((file, cache))
+}
+
+// Copies the magic entries of a mime.cache file, for the methods listing
+// them; returns None like read_cache_from_file()
+pub fn read_magic_from_cache(file: &CacheFile, ctx: &mut ParseContext) -> Option<Vec<MagicEntry>> {
+    let r = file.reader();
+
+    let mut cache_ctx = ParseContext::new(ctx.lenient);
+    let magic = read_magic(&r, r.u32(24)?, &mut cache_ctx)?;
+    ctx.diagnostics.append(&mut cache_ctx.diagnostics);
+
+    Some(magic)
+}
+
+// A magic entry of a mime.cache file, with the extents of its rules
+#[derive(Debug)]
+struct MappedEntry {
+    priority: u32,
+    mime_type: u32,
+    n_matchlets: u32,
+    first_matchlet: u32,
+    min_data_size: usize,
+    max_extents: usize,
+    // Parsed the first time it is needed; unset if it is invalid
+    parsed_mime_type: OnceLock<Option<Mime>>,
+}
+
+// The magic entries of a mime.cache file, which are matched in place,
+// instead of being copied like the ones of the plain text files; only the
+// offsets of each entry, and the extents of its rules, are kept
+#[derive(Debug)]
+pub struct MappedMagic {
+    file: Arc<CacheFile>,
+    entries: Vec<MappedEntry>,
+    // The MIME types of the `__NOMAGIC__` entries
+    markers: Vec<Mime>,
+}
+
+impl MappedMagic {
+    // Checks the magic entries of the file; returns None if they cannot
+    // be read, like read_magic(), and the entries with invalid rules are
+    // skipped
+    pub fn new(file: Arc<CacheFile>) -> Option<MappedMagic> {
+        let r = file.reader();
+        let mut entries = Vec::new();
+        let mut markers = Vec::new();
+
+        let offset = r.u32(24)?;
+        let n_matches = r.u32(offset)?;
+        let first_match = r.u32(offset + 8)?;
+        let mut budget = r.budget(MATCHLET_LEN);
+
+        for idx in 0..n_matches {
+            let entry = r.record(first_match, idx, 16)?;
+            let priority = r.u32(entry)?;
+            let mime_type = r.u32(entry + 4)?;
+            r.string(mime_type)?;
+            let n_matchlets = r.u32(entry + 8)?;
+            let first_matchlet = r.u32(entry + 12)?;
+
+            let mut valid = true;
+            let mut is_marker = None;
+            let mut min_data_size = usize::MAX;
+            let mut max_extents = 0;
+            for item in Matchlets::new(&r, n_matchlets, first_matchlet, &mut budget) {
+                let (indent, matchlet) = item?;
+                let matchlet = r.matchlet(matchlet)?;
+
+                valid &= matchlet.is_valid();
+                is_marker.get_or_insert(matchlet.value == NO_MAGIC);
+                if indent == 0 {
+                    min_data_size = min_data_size.min(matchlet.min_extent());
+                }
+                max_extents = max_extents.max(matchlet.extent());
+            }
+
+            match (valid, is_marker) {
+                (true, Some(true)) => {
+                    markers.extend(special::parse_mime_type(r.string(mime_type)?))
+                }
+                (true, Some(false)) => entries.push(MappedEntry {
+                    priority,
+                    mime_type,
+                    n_matchlets,
+                    first_matchlet,
+                    min_data_size,
+                    max_extents,
+                    parsed_mime_type: OnceLock::new(),
+                }),
+                _ => (),
+            }
+        }
+
+        Some(MappedMagic {
+            file,
+            entries,
+            markers,
+        })
+    }
+
+    fn mime_type<'a>(&self, entry: &'a MappedEntry) -> Option<&'a Mime> {
+        entry
+            .parsed_mime_type
+            .get_or_init(|| {
+                let s = self.file.reader().string(entry.mime_type)?;
+                special::parse_mime_type(s)
+            })
+            .as_ref()
+    }
+
+    fn matches(&self, entry: &MappedEntry, data: &[u8]) -> bool {
+        let r = self.file.reader();
+        let mut budget = u32::MAX;
+
+        // The entries were checked when the file was opened
+        let rules = Matchlets::new(&r, entry.n_matchlets, entry.first_matchlet, &mut budget)
+            .map_while(|item| item)
+            .filter_map(|(indent, matchlet)| Some((indent, r.matchlet(matchlet)?)));
+
+        magic::matches_tree(rules, |matchlet| matchlet.matches_data(data))
+    }
+
+    pub fn markers(&self) -> &[Mime] {
+        &self.markers
+    }
+
+    // The MIME type and the priority of the entries matching the data, in
+    // the order of the file, which lists them by descending priority
+    pub fn lookup_data<'a: 'b, 'b>(
+        &'a self,
+        data: &'b [u8],
+    ) -> impl Iterator<Item = (&'a Mime, u32)> + 'b {
+        self.entries
+            .iter()
+            .filter(move |e| e.min_data_size <= data.len() && self.matches(e, data))
+            .filter_map(|e| Some((self.mime_type(e)?, e.priority)))
+    }
+
+    // The length of the shortest data matching each entry, and of the
+    // longest data its rules can look at, without the entries whose MIME
+    // type is removed; the entries with an invalid MIME type, which never
+    // match, are not left out, to avoid parsing all of them
+    pub fn extents<'a>(
+        &'a self,
+        deleted: &'a [&Mime],
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.entries
+            .iter()
+            .filter(move |e| {
+                deleted.is_empty() || !matches!(self.mime_type(e), Some(m) if deleted.contains(&m))
+            })
+            .map(|e| (e.min_data_size, e.max_extents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    use crate::alias;
+    use crate::glob;
+    use crate::magic;
+    use crate::namespace;
+
+    #[test]
+    fn read_test_cache() {
+        let mut ctx = ParseContext::default();
+        let (file, cache) = read_cache_from_file(
+            "test_files/mime/mime.cache",
+            Subsystems::default(),
+            &mut ctx,
+        )
+        .unwrap();
+        assert!(ctx.diagnostics.is_empty());
+
+        // The cache contains the same data as the plain text files; the
+        // globs2 file repeats the case sensitive globs without their flag,
+        // for compatibility, and only the first entry of each is loaded
+        let text_globs: HashSet<Glob> = glob::read_globs_from_dir("test_files/mime", &mut ctx)
+            .into_iter()
+            .collect();
+        let text_globs: HashSet<String> = text_globs.iter().map(Glob::to_v2_string).collect();
+        let cache_globs: HashSet<String> = cache.globs.iter().map(Glob::to_v2_string).collect();
+        assert_eq!(cache_globs, text_globs);
+
+        let text_aliases = alias::read_aliases_from_dir("test_files/mime", &mut ctx);
+        assert_eq!(cache.aliases, text_aliases);
+
+        let text_magic = magic::read_magic_from_dir("test_files/mime", &mut ctx);
+        assert_eq!(cache.magic.len(), text_magic.len());
+        let cache_magic = read_magic_from_cache(&file, &mut ctx).unwrap();
+        assert_eq!(cache_magic, cache.magic);
+        assert_eq!(
+            magic::lookup_data(&cache.magic, b"\x89PNG\r\n\x1a\n"),
+            Some((&mime::IMAGE_PNG, 50))
+        );
+
+        let text_namespaces = namespace::read_namespaces_from_dir("test_files/mime", &mut ctx);
+        assert_eq!(cache.namespaces, text_namespaces);
+
+        assert!(!cache.subclasses.is_empty());
+        assert!(!cache.generic_icons.is_empty());
+    }
+
+    #[test]
+    fn match_mapped_magic() {
+        let mut ctx = ParseContext::default();
+        let file = CacheFile::open("test_files/mime/mime.cache").unwrap();
+        let entries = read_magic_from_cache(&file, &mut ctx).unwrap();
+        let mapped = MappedMagic::new(Arc::clone(&file)).unwrap();
+
+        // The entries matched in place give the same results as the copies
+        // of the entries
+        assert_eq!(mapped.entries.len(), entries.len());
+        assert!(mapped.markers().is_empty());
+        let samples: [&[u8]; 5] = [
+            b"\x89PNG\r\n\x1a\n",
+            b"%PDF-1.7",
+            b"\n\n<html>",
+            b"GIF89a",
+            b"nothing to see here",
+        ];
+        for data in samples {
+            let matches: Vec<(&Mime, u32)> = mapped.lookup_data(data).collect();
+            let copied: Vec<(&Mime, u32)> = entries
+                .iter()
+                .filter_map(|e| magic::lookup_data(std::slice::from_ref(e), data))
+                .collect();
+            assert_eq!(matches, copied);
+        }
+
+        let extents = mapped.extents(&[]).map(|(_, max)| max).max();
+        assert_eq!(extents, Some(magic::max_extents(&entries)));
+    }
+
+    #[test]
+    fn share_open_files() {
+        let first = CacheFile::open("test_files/mime/mime.cache").unwrap();
+        let second = CacheFile::open("test_files/mime/mime.cache").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // A file replaced by update-mime-database is opened again
+        let dir = crate::testing::TempMimeDir::new().unwrap();
+        let path = dir.mime_path().join("mime.cache");
+        fs::copy("test_files/mime/mime.cache", &path).unwrap();
+        let first = CacheFile::open(&path).unwrap();
+        let copy = path.with_extension("new");
+        fs::copy("test_files/mime/mime.cache", &copy).unwrap();
+        fs::rename(&copy, &path).unwrap();
+        let second = CacheFile::open(&path).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(&first.contents[..], &second.contents[..]);
+
+        assert!(CacheFile::open(dir.mime_path().join("missing.cache")).is_none());
+    }
+
+    #[test]
+    fn reject_invalid_caches() {
+        let mut ctx = ParseContext::default();
+        let path = Path::new("mime.cache");
+        let load = Subsystems::default();
+
+        assert!(from_bytes(b"", path, load, &mut ctx).is_none());
+
+        // Unknown version
+        let mut header = vec![0u8; HEADER_LEN];
+        header[1] = 2;
+        assert!(from_bytes(&header, path, load, &mut ctx).is_none());
+
+        // Offsets outside of the file
+        header[1] = 1;
+        header[3] = 2;
+        header[4..8].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(from_bytes(&header, path, load, &mut ctx).is_none());
+
+        // Empty lists at the end of the header
+        let mut buf = header.clone();
+        buf.extend_from_slice(&[0u8; 12]);
+        for offset in (4..HEADER_LEN).step_by(4) {
+            buf[offset..offset + 4].copy_from_slice(&(HEADER_LEN as u32).to_be_bytes());
+        }
+        let cache = from_bytes(&buf, path, load, &mut ctx).unwrap();
+        assert!(cache.globs.is_empty() && cache.magic.is_empty());
+
+        // Sections that are not loaded are not checked
+        buf[24..28].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_none());
+        let load = Subsystems {
+            magic: false,
+            ..load
+        };
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_some());
+    }
+
+    // Builds a cache whose lists are all empty, except for the given
+    // section, appended after the header
+    fn cache_with_section(header_offset: usize, section: &[u32]) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_LEN];
+        buf[1] = 1;
+        buf[3] = 2;
+        let empty = HEADER_LEN as u32;
+        let section_offset = empty + 12;
+        for offset in (4..HEADER_LEN).step_by(4) {
+            let list = if offset == header_offset {
+                section_offset
+            } else {
+                empty
+            };
+            buf[offset..offset + 4].copy_from_slice(&list.to_be_bytes());
+        }
+        buf.extend_from_slice(&[0u8; 12]);
+        for value in section {
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+
+        buf
+    }
+
+    #[test]
+    fn reject_shared_subtrees() {
+        let mut ctx = ParseContext::default();
+        let path = Path::new("mime.cache");
+        let load = Subsystems::default();
+        let start = HEADER_LEN as u32 + 12;
+        let depth = 48;
+
+        // A node referring to its own list is only read until the depth
+        // limit; lists of two nodes, both having the next list as their
+        // children, would be read 2^depth times
+        let nodes = start + 8;
+        let buf = cache_with_section(16, &[1, nodes, 'a' as u32, 1, nodes]);
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_none());
+
+        let mut section = vec![2, nodes];
+        for level in 0..depth {
+            let children = nodes + (level + 1) * 2 * SUFFIX_NODE_LEN;
+            let n_children = if level + 1 < depth { 2 } else { 0 };
+            for ch in ['a', 'b'] {
+                section.extend([ch as u32, n_children, children]);
+            }
+        }
+        let buf = cache_with_section(16, &section);
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_none());
+
+        // The same for the matchlets of a magic entry
+        let entry = start + 12;
+        let mime_type = entry + 16;
+        let value = mime_type + 12;
+        let matchlets = value + 4;
+        let mut buf = cache_with_section(24, &[1, 0, entry, 50, mime_type, 2, matchlets]);
+        buf.extend_from_slice(b"text/plain\0\0a\0\0\0");
+        for level in 0..depth {
+            let children = matchlets + (level + 1) * 2 * MATCHLET_LEN;
+            let n_children = if level + 1 < depth { 2 } else { 0 };
+            for _ in 0..2 {
+                for value in [0, 1, 1, 1, value, 0, n_children, children] {
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_none());
+        let load = Subsystems {
+            magic: false,
+            ..load
+        };
+        assert!(from_bytes(&buf, path, load, &mut ctx).is_some());
+    }
+}
@@ -0,0 +1,249 @@
+// The descriptions of the MIME types, from the `<comment>` elements of
+// the XML definition each MIME type has in the `mime` directories, like
+// `image/png.xml`
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use mime::Mime;
+
+use crate::xml::{Event, Reader};
+
+// The comments of a MIME type, untranslated or in the language of their
+// `xml:lang` attribute
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comments(Vec<(Option<String>, String)>);
+
+// The variants of a locale, from the most to the least specific, like
+// `sr_RS@latin`, `sr_RS`, `sr@latin` and `sr`; the encoding is ignored,
+// and the `C` locale has no variants, since it uses the untranslated
+// comment
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split('.').next().unwrap_or(rest);
+    let (lang, country) = match rest.split_once(['_', '-']) {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut res = Vec::new();
+    if let Some(country) = country {
+        if let Some(modifier) = modifier {
+            res.push(format!("{}_{}@{}", lang, country, modifier));
+        }
+        res.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        res.push(format!("{}@{}", lang, modifier));
+    }
+    res.push(lang.to_string());
+
+    res
+}
+
+// The locales of the messages of the process, selected like gettext does:
+// the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set is the
+// locale, and unless it is the `C` locale, the colon-separated list of
+// `LANGUAGE` is tried before it
+pub fn process_locales() -> Vec<String> {
+    locales_from_env(|name| env::var(name).ok())
+}
+
+fn locales_from_env(var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|name| var(name).filter(|value| !value.is_empty()));
+    let locale = match locale {
+        Some(locale) => locale,
+        None => return Vec::new(),
+    };
+    if locale_variants(&locale).is_empty() {
+        return vec![locale];
+    }
+
+    let mut res: Vec<String> = var("LANGUAGE")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|lang| !lang.is_empty())
+        .map(str::to_string)
+        .collect();
+    res.push(locale);
+
+    res
+}
+
+impl Comments {
+    // Replaces the comment for the same language, if any
+    pub fn insert(&mut self, lang: Option<String>, text: String) {
+        self.0.retain(|(l, _)| *l != lang);
+        self.0.push((lang, text));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn find(&self, lang: Option<&str>) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(l, _)| match (l, lang) {
+                (Some(l), Some(lang)) => l.eq_ignore_ascii_case(lang),
+                (None, None) => true,
+                _ => false,
+            })
+            .map(|(_, text)| text.as_str())
+    }
+
+    // Tries each locale in order, and falls back to the untranslated
+    // comment; the `C` locale stops the search
+    pub fn lookup(&self, locales: &[&str]) -> Option<&str> {
+        for locale in locales {
+            let variants = locale_variants(locale);
+            if variants.is_empty() {
+                break;
+            }
+
+            if let Some(text) = variants.iter().find_map(|v| self.find(Some(v))) {
+                return Some(text);
+            }
+        }
+
+        self.find(None)
+    }
+}
+
+pub fn read_comments(contents: &str) -> Comments {
+    let mut res = Comments::default();
+    let mut reader = Reader::new(contents);
+
+    while let Some(event) = reader.next() {
+        let is_comment = matches!(
+            event,
+            Event::Start {
+                name: "comment",
+                empty: false,
+                ..
+            }
+        );
+        if !is_comment {
+            continue;
+        }
+
+        let lang = event.attribute("xml:lang").map(str::to_string);
+        if let Some(Event::Text(text)) = reader.next() {
+            if !text.trim().is_empty() {
+                res.insert(lang, text.trim().to_string());
+            }
+        }
+    }
+
+    res
+}
+
+pub fn read_comments_from_dir(dir: &Path, mime_type: &Mime) -> Comments {
+    let file_name = format!("{}.xml", mime_type.essence_str());
+
+    match fs::read_to_string(dir.join(file_name)) {
+        Ok(contents) => read_comments(&contents),
+        Err(_) => Comments::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_test_comments() {
+        let dir = Path::new("test_files/mime");
+        let comments = read_comments_from_dir(dir, &mime::IMAGE_SVG);
+        assert_eq!(comments.lookup(&[]), Some("SVG image"));
+        assert!(read_comments_from_dir(dir, &mime::IMAGE_PNG).is_empty());
+
+        let doc = r#"<mime-type type="text/x-foo">
+  <comment xml:lang="fr">Fichier Foo</comment>
+  <comment/>
+  <comment>Foo &amp; bar file</comment>
+  <comment xml:lang="sr@latin">Foo datoteka</comment>
+  <comment xml:lang="pt_BR">Arquivo Foo</comment>
+  <comment xml:lang="pt"></comment>
+</mime-type>"#;
+        let comments = read_comments(doc);
+        assert_eq!(comments.lookup(&[]), Some("Foo & bar file"));
+        assert_eq!(comments.lookup(&["fr_CA.UTF-8"]), Some("Fichier Foo"));
+        assert_eq!(comments.lookup(&["sr_RS@latin"]), Some("Foo datoteka"));
+        assert_eq!(comments.lookup(&["sr_RS"]), Some("Foo & bar file"));
+        assert_eq!(comments.lookup(&["pt-BR"]), Some("Arquivo Foo"));
+        assert_eq!(comments.lookup(&["pt_PT"]), Some("Foo & bar file"));
+
+        // The locales are tried in order, until the C locale
+        assert_eq!(comments.lookup(&["de", "fr"]), Some("Fichier Foo"));
+        assert_eq!(comments.lookup(&["C", "fr"]), Some("Foo & bar file"));
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            locale_variants("sr_RS.UTF-8@latin"),
+            vec!["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert_eq!(locale_variants("de"), vec!["de"]);
+        assert!(locale_variants("C.UTF-8").is_empty());
+        assert!(locale_variants("POSIX").is_empty());
+    }
+
+    #[test]
+    fn env_locales() {
+        let locales = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            locales_from_env(move |name| {
+                vars.iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, value)| value.clone())
+            })
+        };
+
+        assert!(locales(&[]).is_empty());
+        assert_eq!(locales(&[("LANG", "de_DE.UTF-8")]), vec!["de_DE.UTF-8"]);
+        assert_eq!(
+            locales(&[
+                ("LANG", "de_DE.UTF-8"),
+                ("LC_MESSAGES", "fr_FR"),
+                ("LC_ALL", "")
+            ]),
+            vec!["fr_FR"]
+        );
+        assert_eq!(
+            locales(&[("LANGUAGE", "pt_BR::sr@latin"), ("LC_ALL", "de_DE")]),
+            vec!["pt_BR", "sr@latin", "de_DE"]
+        );
+
+        // LANGUAGE is ignored for the C locale, or without a locale
+        assert_eq!(
+            locales(&[("LANGUAGE", "fr"), ("LANG", "C.UTF-8")]),
+            vec!["C.UTF-8"]
+        );
+        assert!(locales(&[("LANGUAGE", "fr")]).is_empty());
+
+        let doc = r#"<mime-type type="text/x-foo">
+  <comment>Foo file</comment>
+  <comment xml:lang="sr@latin">Foo datoteka</comment>
+</mime-type>"#;
+        let comments = read_comments(doc);
+        let locales = locales(&[("LANGUAGE", "pt_BR:sr_RS@latin"), ("LANG", "de_DE")]);
+        let locales: Vec<&str> = locales.iter().map(String::as_str).collect();
+        assert_eq!(comments.lookup(&locales), Some("Foo datoteka"));
+    }
+}
@@ -0,0 +1,376 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::Instant;
+
+use mime::Mime;
+
+use crate::debounce::{Debounce, Debouncer};
+use crate::priority::MagicPriority;
+use crate::record::MimeTypeRecord;
+use crate::SharedMimeInfo;
+
+/// A [`SharedMimeInfo`] database that can be shared between threads, and
+/// reloaded while it is being queried.
+///
+/// The queries are performed on a snapshot of the database; reloading the
+/// database loads a new snapshot in the calling thread, and then replaces
+/// the current one, so queries running on other threads never have to
+/// wait for the MIME data to be loaded.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let mime_db = Arc::new(xdg_mime::SyncSharedMimeInfo::new());
+///
+/// let reader = {
+///     let mime_db = Arc::clone(&mime_db);
+///     thread::spawn(move || mime_db.get_mime_types_from_file_name("file.txt"))
+/// };
+///
+/// mime_db.reload();
+/// assert_eq!(reader.join().unwrap(), vec![mime::TEXT_PLAIN]);
+/// ```
+///
+/// Methods that borrow from the database, like [`guess_mime_type`], are
+/// available on the [`snapshot`].
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [`guess_mime_type`]: struct.SharedMimeInfo.html#method.guess_mime_type
+/// [`snapshot`]: #method.snapshot
+pub struct SyncSharedMimeInfo {
+    current: RwLock<Arc<SharedMimeInfo>>,
+    // Serializes the reloads, so that the directories are loaded once
+    reloading: Mutex<()>,
+    // The changes seen by poll(), waiting to be coalesced
+    debouncer: Mutex<Debouncer>,
+}
+
+impl Default for SyncSharedMimeInfo {
+    fn default() -> Self {
+        SyncSharedMimeInfo::new()
+    }
+}
+
+impl From<SharedMimeInfo> for SyncSharedMimeInfo {
+    fn from(db: SharedMimeInfo) -> Self {
+        SyncSharedMimeInfo {
+            current: RwLock::new(Arc::new(db)),
+            reloading: Mutex::new(()),
+            debouncer: Mutex::new(Debouncer::default()),
+        }
+    }
+}
+
+impl SyncSharedMimeInfo {
+    /// Loads all the MIME information under the standard directories, like
+    /// [`SharedMimeInfo::new`].
+    ///
+    /// To load the MIME information from a different set of directories,
+    /// convert a [`SharedMimeInfo`] instance using `From`.
+    ///
+    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+    /// [`SharedMimeInfo::new`]: struct.SharedMimeInfo.html#method.new
+    pub fn new() -> SyncSharedMimeInfo {
+        SyncSharedMimeInfo::from(SharedMimeInfo::new())
+    }
+
+    /// Retrieves the current snapshot of the database.
+    ///
+    /// The snapshot is not affected by later reloads.
+    pub fn snapshot(&self) -> Arc<SharedMimeInfo> {
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+
+        Arc::clone(&current)
+    }
+
+    /// Reloads the contents of the database from the directories used to
+    /// populate it, if they changed, like [`SharedMimeInfo::reload`].
+    ///
+    /// Queries performed while the database is reloaded use the previous
+    /// snapshot. Returns `true` if the contents of the database were
+    /// updated.
+    ///
+    /// The database is reloaded right away, even if the changes seen by
+    /// the [`poll`] method are not due yet, so this method can be used to
+    /// flush them, for instance once an application knows that an update
+    /// of the system finished.
+    ///
+    /// [`SharedMimeInfo::reload`]: struct.SharedMimeInfo.html#method.reload
+    /// [`poll`]: #method.poll
+    pub fn reload(&self) -> bool {
+        let _reloading = self
+            .reloading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.debouncer().clear();
+
+        let db = match self.snapshot().reloaded() {
+            Some(db) => Arc::new(db),
+            None => return false,
+        };
+
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = db;
+
+        true
+    }
+
+    fn debouncer(&self) -> MutexGuard<'_, Debouncer> {
+        self.debouncer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sets how the changes seen by the [`poll`] method are coalesced.
+    ///
+    /// The changes that are already pending are checked against the new
+    /// controls at the next poll.
+    ///
+    /// [`poll`]: #method.poll
+    pub fn set_debounce(&self, debounce: Debounce) {
+        self.debouncer().debounce = debounce;
+    }
+
+    /// Retrieves how the changes seen by the [`poll`] method are
+    /// coalesced.
+    ///
+    /// [`poll`]: #method.poll
+    pub fn debounce(&self) -> Debounce {
+        self.debouncer().debounce
+    }
+
+    /// Checks whether the directories used to populate the database
+    /// changed, and reloads it once the changes are due, according to the
+    /// [debounce controls].
+    ///
+    /// Applications can call this method periodically, or every time they
+    /// are notified of a change, without reloading the database for each
+    /// of the files written by `update-mime-database`. Returns `true` if
+    /// the contents of the database were updated.
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SyncSharedMimeInfo::new();
+    ///
+    /// // On a timer, or when a directory changes
+    /// if mime_db.poll() {
+    ///     println!("The MIME database was reloaded");
+    /// }
+    /// ```
+    ///
+    /// [debounce controls]: #method.set_debounce
+    pub fn poll(&self) -> bool {
+        self.poll_at(Instant::now())
+    }
+
+    fn poll_at(&self, now: Instant) -> bool {
+        let mtimes = self.snapshot().changed_mtimes();
+        if !self.debouncer().poll(mtimes, now) {
+            return false;
+        }
+
+        self.reload()
+    }
+
+    /// Checks whether the [`poll`] method saw changes that are not due
+    /// yet.
+    ///
+    /// [`poll`]: #method.poll
+    pub fn has_pending_changes(&self) -> bool {
+        self.debouncer().has_pending()
+    }
+
+    /// See [`SharedMimeInfo::unalias_mime_type`].
+    ///
+    /// [`SharedMimeInfo::unalias_mime_type`]: struct.SharedMimeInfo.html#method.unalias_mime_type
+    pub fn unalias_mime_type(&self, mime_type: &Mime) -> Option<Mime> {
+        self.snapshot().unalias_mime_type(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::lookup_icon_names`].
+    ///
+    /// [`SharedMimeInfo::lookup_icon_names`]: struct.SharedMimeInfo.html#method.lookup_icon_names
+    pub fn lookup_icon_names(&self, mime_type: &Mime) -> Vec<String> {
+        self.snapshot().lookup_icon_names(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::lookup_generic_icon_name`].
+    ///
+    /// [`SharedMimeInfo::lookup_generic_icon_name`]: struct.SharedMimeInfo.html#method.lookup_generic_icon_name
+    pub fn lookup_generic_icon_name(&self, mime_type: &Mime) -> Option<String> {
+        self.snapshot().lookup_generic_icon_name(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::mime_type_record`].
+    ///
+    /// [`SharedMimeInfo::mime_type_record`]: struct.SharedMimeInfo.html#method.mime_type_record
+    pub fn mime_type_record(&self, mime_type: &Mime) -> MimeTypeRecord {
+        self.snapshot().mime_type_record(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::get_parents`].
+    ///
+    /// [`SharedMimeInfo::get_parents`]: struct.SharedMimeInfo.html#method.get_parents
+    pub fn get_parents(&self, mime_type: &Mime) -> Option<Vec<Mime>> {
+        self.snapshot().get_parents(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::get_mime_types_from_file_name`].
+    ///
+    /// [`SharedMimeInfo::get_mime_types_from_file_name`]: struct.SharedMimeInfo.html#method.get_mime_types_from_file_name
+    pub fn get_mime_types_from_file_name(&self, file_name: &str) -> Vec<Mime> {
+        self.snapshot().get_mime_types_from_file_name(file_name)
+    }
+
+    /// See [`SharedMimeInfo::first_mime_type_from_file_name`].
+    ///
+    /// [`SharedMimeInfo::first_mime_type_from_file_name`]: struct.SharedMimeInfo.html#method.first_mime_type_from_file_name
+    pub fn first_mime_type_from_file_name(&self, file_name: &str) -> Option<Mime> {
+        self.snapshot().first_mime_type_from_file_name(file_name)
+    }
+
+    /// See [`SharedMimeInfo::is_special_filename`].
+    ///
+    /// [`SharedMimeInfo::is_special_filename`]: struct.SharedMimeInfo.html#method.is_special_filename
+    pub fn is_special_filename(&self, file_name: &str) -> bool {
+        self.snapshot().is_special_filename(file_name)
+    }
+
+    /// See [`SharedMimeInfo::unknown_type`].
+    ///
+    /// [`SharedMimeInfo::unknown_type`]: struct.SharedMimeInfo.html#method.unknown_type
+    pub fn unknown_type(&self) -> Mime {
+        self.snapshot().unknown_type().clone()
+    }
+
+    /// See [`SharedMimeInfo::is_unknown_type`].
+    ///
+    /// [`SharedMimeInfo::is_unknown_type`]: struct.SharedMimeInfo.html#method.is_unknown_type
+    pub fn is_unknown_type(&self, mime_type: &Mime) -> bool {
+        self.snapshot().is_unknown_type(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::get_mime_type_for_data`].
+    ///
+    /// [`SharedMimeInfo::get_mime_type_for_data`]: struct.SharedMimeInfo.html#method.get_mime_type_for_data
+    pub fn get_mime_type_for_data(&self, data: &[u8]) -> Option<(Mime, MagicPriority)> {
+        self.snapshot().get_mime_type_for_data(data)
+    }
+
+    /// See [`SharedMimeInfo::mime_type_equal`].
+    ///
+    /// [`SharedMimeInfo::mime_type_equal`]: struct.SharedMimeInfo.html#method.mime_type_equal
+    pub fn mime_type_equal(&self, mime_a: &Mime, mime_b: &Mime) -> bool {
+        self.snapshot().mime_type_equal(mime_a, mime_b)
+    }
+
+    /// See [`SharedMimeInfo::generic_chain`].
+    ///
+    /// [`SharedMimeInfo::generic_chain`]: struct.SharedMimeInfo.html#method.generic_chain
+    pub fn generic_chain(&self, mime_type: &Mime) -> Vec<Mime> {
+        self.snapshot().generic_chain(mime_type)
+    }
+
+    /// See [`SharedMimeInfo::mime_type_subclass`].
+    ///
+    /// [`SharedMimeInfo::mime_type_subclass`]: struct.SharedMimeInfo.html#method.mime_type_subclass
+    pub fn mime_type_subclass(&self, mime_type: &Mime, base: &Mime) -> bool {
+        self.snapshot().mime_type_subclass(mime_type, base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::debounce::Coalescing;
+    use crate::testing::TempMimeDir;
+
+    // Moves the modification time of a directory forward, so that a change
+    // is seen even if it happens within the resolution of the timestamps
+    fn touch(path: &Path) {
+        let mtime = fs::metadata(path).unwrap().modified().unwrap();
+        File::open(path)
+            .unwrap()
+            .set_modified(mtime + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn reload_while_querying() {
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo");
+        let db = Arc::new(SyncSharedMimeInfo::from(dir.load().unwrap()));
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+
+        let snapshot = db.snapshot();
+        assert!(!db.reload());
+
+        fs::write(dir.mime_path().join("globs2"), "50:text/x-bar:*.foo\n").unwrap();
+        touch(&dir.mime_path());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                let (foo, bar) = (foo.clone(), bar.clone());
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let res = db.get_mime_types_from_file_name("a.foo");
+                        assert!(res == [foo.clone()] || res == [bar.clone()]);
+                    }
+                })
+            })
+            .collect();
+
+        assert!(db.reload());
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(db.get_mime_types_from_file_name("a.foo"), vec![bar]);
+        assert_eq!(snapshot.get_mime_types_from_file_name("a.foo"), vec![foo]);
+    }
+
+    #[test]
+    fn debounce_reloads() {
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo");
+        let db = SyncSharedMimeInfo::from(dir.load().unwrap());
+        let interval = Duration::from_millis(50);
+        db.set_debounce(Debounce::new(interval, Coalescing::Quiet));
+        assert_eq!(db.debounce().interval(), interval);
+        assert!(!db.poll());
+        assert!(!db.has_pending_changes());
+
+        // The first change is only recorded
+        let start = Instant::now();
+        fs::write(dir.mime_path().join("globs"), "text/x-bar:*.bar\n").unwrap();
+        touch(&dir.mime_path());
+        assert!(!db.poll_at(start));
+        assert!(db.has_pending_changes());
+
+        assert!(db.poll_at(start + interval));
+        assert!(!db.has_pending_changes());
+        assert_eq!(
+            db.get_mime_types_from_file_name("a.foo"),
+            vec![Mime::from_str("text/x-foo").unwrap()]
+        );
+
+        // Reloading flushes the pending changes
+        fs::remove_file(dir.mime_path().join("globs2")).unwrap();
+        touch(&dir.mime_path());
+        db.set_debounce(Debounce::new(Duration::from_secs(60), Coalescing::Fixed));
+        assert!(!db.poll());
+        assert!(db.has_pending_changes());
+        assert!(db.reload());
+        assert!(!db.has_pending_changes());
+        assert_eq!(
+            db.get_mime_types_from_file_name("a.foo"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+/// The behavior of the MIME type guessing, in the cases where the
+/// shared-mime-info specification and GIO disagree, or where the
+/// specification leaves room for interpretation.
+///
+/// The differences between the modes are:
+///
+/// | Case | `Spec` | `GioCompatible` | `Lenient` |
+/// |------|--------|-----------------|-----------|
+/// | Data matching no magic rule, and no file name match | `text/plain` for textual data | `text/plain` for textual data | the [unknown type] |
+/// | Conflicting file name matches | the file name match that is a subclass of the sniffed type, otherwise the sniffed type | the sniffed type if its priority is at least 80, otherwise the file name match that is a subclass of the sniffed type | the sniffed type if its priority is at least 80, or if it is a subclass of a file name match |
+/// | Empty files | the file name match, otherwise `application/x-zerosize` | `text/plain` | `application/x-zerosize` |
+/// | Executable files, with only their metadata available | the [unknown type] | the [unknown type] | `application/x-executable` |
+///
+/// In every mode, the file name is used on its own when it matches a
+/// single MIME type, and desktop files without a `.desktop` extension are
+/// never recognized from their contents, for security reasons.
+///
+/// This is unrelated to the [`lenient`] parsing of the database.
+///
+/// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+/// [`lenient`]: struct.SharedMimeInfoBuilder.html#method.lenient
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Conformance {
+    /// Follows the "Recommended checking order" of the shared-mime-info
+    /// specification.
+    Spec,
+    /// Returns the same results as `g_content_type_guess()` and the local
+    /// file implementation in GIO.
+    GioCompatible,
+    /// The default behavior of this crate.
+    #[default]
+    Lenient,
+}
@@ -0,0 +1,286 @@
+//! Utilities to check the MIME type detection against a corpus of sample
+//! files.
+//!
+//! A corpus is a directory containing sample files, each one associated
+//! to the MIME type it is expected to be detected as. The expected type
+//! can be specified in two ways:
+//!
+//!  - with a sidecar file, named after the sample with an additional
+//!    `.mime` extension, containing the expected MIME type; empty lines
+//!    and lines starting with `#` are ignored
+//!  - with the directory layout, by placing the sample inside a
+//!    `<media type>/<subtype>` directory, like `image/png/logo.png`
+//!
+//! Sidecar files take precedence over the directory layout; files without
+//! an expected MIME type are ignored.
+//!
+//! ```rust
+//! # let mime_db = xdg_mime::SharedMimeInfo::new_for_directory("test_files");
+//! // let mime_db = ...
+//! let report = xdg_mime::corpus::run(&mime_db, "test_files/corpus").unwrap();
+//! println!("{}", report);
+//! assert!(report.is_success());
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use mime::Mime;
+
+use crate::SharedMimeInfo;
+
+const SIDECAR_EXTENSION: &str = "mime";
+
+/// A sample file, and the MIME type it is expected to be detected as.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    path: PathBuf,
+    expected: Mime,
+}
+
+impl Sample {
+    /// The path of the sample file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The expected MIME type of the sample file.
+    pub fn expected(&self) -> &Mime {
+        &self.expected
+    }
+}
+
+/// The result of the detection for a single [`Sample`].
+///
+/// [`Sample`]: struct.Sample.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleResult {
+    sample: Sample,
+    guessed: Mime,
+    uncertain: bool,
+    passed: bool,
+}
+
+impl SampleResult {
+    /// The sample file.
+    pub fn sample(&self) -> &Sample {
+        &self.sample
+    }
+
+    /// The guessed MIME type.
+    pub fn guessed(&self) -> &Mime {
+        &self.guessed
+    }
+
+    /// Whether the guess was uncertain.
+    pub fn uncertain(&self) -> bool {
+        self.uncertain
+    }
+
+    /// Whether the guessed MIME type matches the expected one, taking
+    /// aliases into account.
+    pub fn passed(&self) -> bool {
+        self.passed
+    }
+}
+
+/// The report produced by [`run`].
+///
+/// [`run`]: fn.run.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    results: Vec<SampleResult>,
+}
+
+impl Report {
+    /// The results for each sample, sorted by path.
+    pub fn results(&self) -> &[SampleResult] {
+        &self.results
+    }
+
+    /// The results of the samples that were not detected as expected.
+    pub fn failures(&self) -> impl Iterator<Item = &SampleResult> {
+        self.results.iter().filter(|r| !r.passed)
+    }
+
+    /// The number of samples detected as expected.
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    /// The number of samples not detected as expected.
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    /// Whether all samples were detected as expected.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for res in &self.results {
+            if res.passed {
+                writeln!(f, "PASS {}: {}", res.sample.path.display(), res.guessed)?;
+            } else {
+                writeln!(
+                    f,
+                    "FAIL {}: expected {}, got {}{}",
+                    res.sample.path.display(),
+                    res.sample.expected,
+                    res.guessed,
+                    if res.uncertain { " (uncertain)" } else { "" }
+                )?;
+            }
+        }
+
+        write!(f, "{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+fn read_sidecar(path: &Path) -> io::Result<Mime> {
+    let contents = fs::read_to_string(path)?;
+
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+
+    Mime::from_str(line).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid MIME type in {}", path.display()),
+        )
+    })
+}
+
+fn expected_from_layout(root: &Path, path: &Path) -> Option<Mime> {
+    let dir = path.parent()?.strip_prefix(root).ok()?;
+
+    let mut components = dir.iter().rev();
+    let subtype = components.next()?.to_str()?;
+    let media_type = components.next()?.to_str()?;
+
+    Mime::from_str(&format!("{}/{}", media_type, subtype)).ok()
+}
+
+fn collect_dir(root: &Path, dir: &Path, res: &mut Vec<Sample>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_dir(root, &path, res)?;
+            continue;
+        }
+
+        if path.extension().is_some_and(|ext| ext == SIDECAR_EXTENSION) {
+            continue;
+        }
+
+        let mut sidecar = path.clone().into_os_string();
+        sidecar.push(".");
+        sidecar.push(SIDECAR_EXTENSION);
+        let sidecar = PathBuf::from(sidecar);
+
+        let expected = if sidecar.is_file() {
+            Some(read_sidecar(&sidecar)?)
+        } else {
+            expected_from_layout(root, &path)
+        };
+
+        if let Some(expected) = expected {
+            res.push(Sample { path, expected });
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects all the samples inside the `dir` directory, recursively.
+pub fn collect_samples<P: AsRef<Path>>(dir: P) -> io::Result<Vec<Sample>> {
+    let mut res = Vec::new();
+
+    collect_dir(dir.as_ref(), dir.as_ref(), &mut res)?;
+
+    Ok(res)
+}
+
+/// Guesses the MIME type of each sample in `samples` using the full
+/// detection pipeline of `db`, and reports the results.
+pub fn check_samples(db: &SharedMimeInfo, samples: Vec<Sample>) -> Report {
+    let results = samples
+        .into_iter()
+        .map(|sample| {
+            let guess = db.guess_mime_type().path(&sample.path).guess();
+            let guessed = guess.mime_type().clone();
+            let passed = db.mime_type_equal(&guessed, &sample.expected);
+
+            SampleResult {
+                sample,
+                guessed,
+                uncertain: guess.uncertain(),
+                passed,
+            }
+        })
+        .collect();
+
+    Report { results }
+}
+
+/// Collects all the samples inside the `dir` directory, and checks them
+/// against the `db` database.
+pub fn run<P: AsRef<Path>>(db: &SharedMimeInfo, dir: P) -> io::Result<Report> {
+    let samples = collect_samples(dir)?;
+
+    Ok(check_samples(db, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_corpus() {
+        let samples = collect_samples("test_files/corpus").unwrap();
+        let expected: Vec<(&str, &str)> = samples
+            .iter()
+            .map(|s| (s.path().to_str().unwrap(), s.expected().essence_str()))
+            .collect();
+
+        assert_eq!(
+            expected,
+            vec![
+                ("test_files/corpus/image/svg+xml/logo.svg", "image/svg+xml"),
+                ("test_files/corpus/misc/launcher", "text/plain"),
+                ("test_files/corpus/misc/logo", "image/png"),
+                ("test_files/corpus/text/plain/notes.txt", "text/plain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_failures() {
+        let db = SharedMimeInfo::new_for_directory("test_files");
+        let samples = vec![Sample {
+            path: PathBuf::from("test_files/files/text"),
+            expected: Mime::from_str("image/png").unwrap(),
+        }];
+
+        let report = check_samples(&db, samples);
+        assert!(!report.is_success());
+        assert_eq!(report.failed(), 1);
+        assert_eq!(
+            report.failures().next().unwrap().guessed(),
+            &mime::TEXT_PLAIN
+        );
+    }
+}
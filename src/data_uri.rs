@@ -0,0 +1,146 @@
+// Parsing of `data:` URIs, as defined by RFC 2397:
+//
+//   dataurl    := "data:" [ mediatype ] [ ";base64" ] "," data
+//   mediatype  := [ type "/" subtype ] *( ";" parameter )
+
+pub struct DataUri<'a> {
+    // The declared media type, without its parameters; unset if the URI
+    // only has parameters, or nothing at all
+    pub media_type: Option<&'a str>,
+    pub payload: Vec<u8>,
+}
+
+impl<'a> DataUri<'a> {
+    pub fn parse(uri: &'a str) -> Option<DataUri<'a>> {
+        let scheme = uri.get(..5)?;
+        if !scheme.eq_ignore_ascii_case("data:") {
+            return None;
+        }
+
+        let (header, data) = uri[5..].split_once(',')?;
+        let (header, base64) = match header.len().checked_sub(7) {
+            Some(idx)
+                if header.is_char_boundary(idx)
+                    && header[idx..].eq_ignore_ascii_case(";base64") =>
+            {
+                (&header[..idx], true)
+            }
+            _ => (header, false),
+        };
+
+        let media_type = header
+            .split(';')
+            .next()
+            .map(str::trim)
+            .filter(|media_type| !media_type.is_empty());
+
+        let payload = if base64 {
+            decode_base64(&percent_decode(data))?
+        } else {
+            percent_decode(data)
+        };
+
+        Some(DataUri {
+            media_type,
+            payload,
+        })
+    }
+}
+
+// Invalid escape sequences are kept as they are
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = bytes
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            if let Some(byte) = hex {
+                res.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+
+        res.push(bytes[idx]);
+        idx += 1;
+    }
+
+    res
+}
+
+// Whitespace is skipped, and the padding is optional
+pub fn decode_base64(data: &[u8]) -> Option<Vec<u8>> {
+    fn value(ch: u8) -> Option<u32> {
+        match ch {
+            b'A'..=b'Z' => Some((ch - b'A') as u32),
+            b'a'..=b'z' => Some((ch - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((ch - b'0') as u32 + 52),
+            b'+' | b'-' => Some(62),
+            b'/' | b'_' => Some(63),
+            _ => None,
+        }
+    }
+
+    let mut res = Vec::with_capacity(data.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut padding = false;
+
+    for &ch in data.iter().filter(|ch| !ch.is_ascii_whitespace()) {
+        if ch == b'=' {
+            padding = true;
+            continue;
+        }
+
+        // Nothing can follow the padding
+        if padding {
+            return None;
+        }
+
+        acc = (acc << 6) | value(ch)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_data_uris() {
+        let uri = DataUri::parse("data:image/png;base64,iVBORw0KGgo=").unwrap();
+        assert_eq!(uri.media_type, Some("image/png"));
+        assert_eq!(uri.payload, b"\x89PNG\r\n\x1a\n");
+
+        let uri = DataUri::parse("DATA:text/html;charset=utf-8,%3Chtml%3E%zz").unwrap();
+        assert_eq!(uri.media_type, Some("text/html"));
+        assert_eq!(uri.payload, b"<html>%zz");
+
+        let uri = DataUri::parse("data:;charset=utf-8;BASE64,aGk").unwrap();
+        assert_eq!(uri.media_type, None);
+        assert_eq!(uri.payload, b"hi");
+
+        let uri = DataUri::parse("data:,").unwrap();
+        assert_eq!(uri.media_type, None);
+        assert!(uri.payload.is_empty());
+
+        assert!(DataUri::parse("data:image/png;base64,iVBO*w0KGgo=").is_none());
+        assert!(DataUri::parse("data:image/png;base64,aGk=aGk").is_none());
+        assert!(DataUri::parse("data:image/png").is_none());
+        assert!(DataUri::parse("http://example.com").is_none());
+        assert!(DataUri::parse("dat").is_none());
+    }
+}
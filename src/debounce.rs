@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// How the changes to the MIME directories are coalesced into a single
+/// reload by the [`poll`] method of [`SyncSharedMimeInfo`].
+///
+/// `update-mime-database` rewrites several files in sequence, so an
+/// application checking the directories while it runs would otherwise see
+/// each of them change, and reload the database every time.
+///
+/// [`poll`]: struct.SyncSharedMimeInfo.html#method.poll
+/// [`SyncSharedMimeInfo`]: struct.SyncSharedMimeInfo.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Coalescing {
+    /// The database is reloaded once the directories did not change for
+    /// the whole debounce interval; a long sequence of changes postpones
+    /// the reload until it ends.
+    #[default]
+    Quiet,
+    /// The database is reloaded once the debounce interval elapsed since
+    /// the first change, even if the directories are still changing, so
+    /// that the reloads are never postponed for longer than the interval.
+    Fixed,
+}
+
+/// The debounce controls of [`SyncSharedMimeInfo`], set using its
+/// [`set_debounce`] method.
+///
+/// By default, the database is reloaded once the directories did not
+/// change for one second, which is enough for `update-mime-database` to
+/// write all its files.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use xdg_mime::{Coalescing, Debounce, SyncSharedMimeInfo};
+///
+/// let mime_db = SyncSharedMimeInfo::new();
+/// mime_db.set_debounce(Debounce::new(Duration::from_secs(5), Coalescing::Fixed));
+/// ```
+///
+/// [`SyncSharedMimeInfo`]: struct.SyncSharedMimeInfo.html
+/// [`set_debounce`]: struct.SyncSharedMimeInfo.html#method.set_debounce
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Debounce {
+    interval: Duration,
+    coalescing: Coalescing,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Debounce::new(Debounce::DEFAULT_INTERVAL, Coalescing::default())
+    }
+}
+
+impl Debounce {
+    /// The default debounce interval.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Creates new debounce controls; a zero interval reloads the database
+    /// as soon as a change is seen.
+    pub fn new(interval: Duration, coalescing: Coalescing) -> Debounce {
+        Debounce {
+            interval,
+            coalescing,
+        }
+    }
+
+    /// The debounce interval.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// How the changes are coalesced.
+    pub fn coalescing(&self) -> Coalescing {
+        self.coalescing
+    }
+}
+
+// The changes seen since the database was loaded, and the modification
+// times of the directories when they were last seen changing
+#[derive(Clone, Debug)]
+struct Pending {
+    mtimes: Vec<Option<SystemTime>>,
+    first_change: Instant,
+    last_change: Instant,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Debouncer {
+    pub debounce: Debounce,
+    pending: Option<Pending>,
+}
+
+impl Debouncer {
+    // Records the current modification times of the directories, or None
+    // if they did not change since the database was loaded, and returns
+    // whether the database should be reloaded now; the pending changes are
+    // cleared when they are due
+    pub fn poll(&mut self, mtimes: Option<Vec<Option<SystemTime>>>, now: Instant) -> bool {
+        let mtimes = match mtimes {
+            Some(mtimes) => mtimes,
+            None => {
+                self.pending = None;
+                return false;
+            }
+        };
+
+        match &mut self.pending {
+            Some(pending) if pending.mtimes == mtimes => {}
+            Some(pending) => {
+                pending.mtimes = mtimes;
+                pending.last_change = now;
+            }
+            None => {
+                self.pending = Some(Pending {
+                    mtimes,
+                    first_change: now,
+                    last_change: now,
+                })
+            }
+        }
+
+        let pending = self.pending.as_ref().unwrap();
+        let since = match self.debounce.coalescing {
+            Coalescing::Quiet => pending.last_change,
+            Coalescing::Fixed => pending.first_change,
+        };
+        let due = now.duration_since(since) >= self.debounce.interval;
+        if due {
+            self.pending = None;
+        }
+
+        due
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesce_changes() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mtimes = |secs: u64| {
+            Some(vec![Some(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            )])
+        };
+        let interval = Duration::from_millis(100);
+
+        let mut quiet = Debouncer {
+            debounce: Debounce::new(interval, Coalescing::Quiet),
+            ..Debouncer::default()
+        };
+        assert!(!quiet.poll(None, at(0)));
+        assert!(!quiet.poll(mtimes(1), at(0)));
+        assert!(!quiet.poll(mtimes(2), at(80)));
+        assert!(!quiet.poll(mtimes(2), at(150)));
+        assert!(quiet.has_pending());
+        assert!(quiet.poll(mtimes(2), at(180)));
+        assert!(!quiet.has_pending());
+
+        // The changes that are undone before they are due are dropped
+        assert!(!quiet.poll(mtimes(3), at(200)));
+        assert!(!quiet.poll(None, at(250)));
+        assert!(!quiet.has_pending());
+
+        let mut fixed = Debouncer {
+            debounce: Debounce::new(interval, Coalescing::Fixed),
+            ..Debouncer::default()
+        };
+        assert!(!fixed.poll(mtimes(1), at(0)));
+        assert!(!fixed.poll(mtimes(2), at(80)));
+        assert!(fixed.poll(mtimes(3), at(100)));
+
+        let mut immediate = Debouncer {
+            debounce: Debounce::new(Duration::ZERO, Coalescing::Quiet),
+            ..Debouncer::default()
+        };
+        assert!(immediate.poll(mtimes(1), at(0)));
+    }
+}
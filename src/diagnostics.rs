@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use mime::Mime;
+
+/// A problem found while loading the shared MIME database.
+///
+/// Diagnostics are collected while loading each MIME directory, and can be
+/// retrieved using the [`diagnostics`] method of [`SharedMimeInfo`].
+///
+/// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// An entry refers to a MIME type that cannot be parsed, and it was
+    /// skipped.
+    InvalidMimeType {
+        /// The file containing the entry.
+        path: PathBuf,
+        /// The line of the entry, for text files.
+        line: Option<usize>,
+        /// The MIME type that could not be parsed.
+        mime_type: String,
+        /// The contents of the entry.
+        entry: String,
+    },
+    /// An entry was ignored, because an entry for the same MIME type or
+    /// pattern was loaded first, from a directory with higher precedence.
+    ShadowedEntry {
+        /// The file containing the ignored entry.
+        path: PathBuf,
+        /// The contents of the ignored entry.
+        entry: String,
+        /// The file containing the entry that takes precedence.
+        shadowed_by: PathBuf,
+        /// The contents of the entry that takes precedence.
+        winner: String,
+    },
+    /// A glob pattern matching almost every file name was dropped, or
+    /// loaded with the lowest weight, according to the [`GenericGlobs`]
+    /// policy of the database.
+    ///
+    /// [`GenericGlobs`]: enum.GenericGlobs.html
+    GenericGlob {
+        /// The file containing the pattern.
+        path: PathBuf,
+        /// The contents of the entry.
+        entry: String,
+        /// Whether the pattern was dropped, instead of down-weighted.
+        dropped: bool,
+    },
+    /// No glob pattern and no magic entry were found in any of the MIME
+    /// directories, so the MIME type of every file is unknown; this
+    /// usually means that shared-mime-info is not installed, or that the
+    /// data directories are misconfigured.
+    NoMimeData {
+        /// The MIME directories that were searched.
+        searched: Vec<PathBuf>,
+    },
+    /// An entry could not be parsed, and it was skipped; only reported in
+    /// [`strict`] mode, unless the entry refers to an invalid MIME type.
+    ///
+    /// [`strict`]: struct.SharedMimeInfoBuilder.html#method.strict
+    MalformedEntry {
+        /// The file containing the entry.
+        path: PathBuf,
+        /// The line of the entry, for text files.
+        line: Option<usize>,
+        /// The contents of the entry.
+        entry: String,
+    },
+    /// The parents of a MIME type are nested deeper than the limit set
+    /// using [`SharedMimeInfoBuilder::subclass_depth_limit`], or form a
+    /// cycle reaching it, so the subclass checks for this type may not be
+    /// able to give an answer.
+    ///
+    /// [`SharedMimeInfoBuilder::subclass_depth_limit`]: struct.SharedMimeInfoBuilder.html#method.subclass_depth_limit
+    SubclassDepthExceeded {
+        /// The MIME type whose parents are too deep.
+        mime_type: Mime,
+        /// The number of levels of parents that are followed.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::InvalidMimeType {
+                path,
+                line,
+                mime_type,
+                ..
+            } => {
+                write!(f, "{}", path.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(f, ": invalid MIME type '{}'", mime_type)
+            }
+            Diagnostic::ShadowedEntry {
+                path,
+                entry,
+                shadowed_by,
+                winner,
+            } => write!(
+                f,
+                "{}: '{}' is shadowed by '{}' in {}",
+                path.display(),
+                entry,
+                winner,
+                shadowed_by.display()
+            ),
+            Diagnostic::GenericGlob {
+                path,
+                entry,
+                dropped,
+            } => {
+                let action = if *dropped {
+                    "dropped"
+                } else {
+                    "given the lowest weight"
+                };
+                write!(
+                    f,
+                    "{}: '{}' matches almost every file name, and was {}",
+                    path.display(),
+                    entry,
+                    action
+                )
+            }
+            Diagnostic::NoMimeData { searched } => {
+                write!(f, "no MIME data found")?;
+                for (idx, path) in searched.iter().enumerate() {
+                    let sep = if idx == 0 { " in " } else { ", " };
+                    write!(f, "{}{}", sep, path.display())?;
+                }
+                Ok(())
+            }
+            Diagnostic::MalformedEntry { path, line, entry } => {
+                write!(f, "{}", path.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(f, ": malformed entry '{}'", entry)
+            }
+            Diagnostic::SubclassDepthExceeded { mime_type, limit } => write!(
+                f,
+                "the parents of '{}' are nested deeper than {} levels",
+                mime_type, limit
+            ),
+        }
+    }
+}
+
+// The state shared by the parsers while loading the MIME directories
+#[derive(Clone, Debug, Default)]
+pub struct ParseContext {
+    pub lenient: bool,
+    pub strict: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    // The file and contents of the first entry loaded for each key of
+    // each table, used to report the entries that were shadowed
+    origins: HashMap<(&'static str, String), (PathBuf, String)>,
+}
+
+impl ParseContext {
+    pub fn new(lenient: bool) -> ParseContext {
+        ParseContext {
+            lenient,
+            strict: false,
+            diagnostics: Vec::new(),
+            origins: HashMap::new(),
+        }
+    }
+
+    // Records an entry of `table`; if an entry for the same key was already
+    // loaded, the first one takes precedence, and the new one is reported
+    // as shadowed, unless they are identical
+    pub fn check_shadowed(&mut self, table: &'static str, key: String, path: &Path, entry: String) {
+        match self.origins.get(&(table, key.clone())) {
+            Some((shadowed_by, winner)) => {
+                if *winner != entry {
+                    self.diagnostics.push(Diagnostic::ShadowedEntry {
+                        path: path.to_path_buf(),
+                        entry,
+                        shadowed_by: shadowed_by.clone(),
+                        winner: winner.clone(),
+                    });
+                }
+            }
+            None => {
+                self.origins
+                    .insert((table, key), (path.to_path_buf(), entry));
+            }
+        }
+    }
+
+    // In lenient mode, recover entries written by hand and saved with
+    // stray byte order marks, whitespace, or quotes around each field
+    pub fn normalize_line(&self, line: &str, separator: char) -> String {
+        if !self.lenient {
+            return line.to_string();
+        }
+
+        let line = line.trim_start_matches('\u{feff}');
+        let fields: Vec<&str> = line
+            .split(separator)
+            .map(|field| field.trim().trim_matches('"'))
+            .collect();
+
+        fields.join(&separator.to_string())
+    }
+
+    // Records an entry that could not be parsed: the first field of
+    // `fields` that is not a valid MIME type is reported, or in strict
+    // mode, the entry itself
+    pub fn skip_entry<'a, I>(&mut self, path: &Path, line: Option<usize>, entry: &str, fields: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let reported = self.diagnostics.len();
+        self.check_mime_types(path, line, entry, fields);
+
+        if self.strict && self.diagnostics.len() == reported {
+            self.diagnostics.push(Diagnostic::MalformedEntry {
+                path: path.to_path_buf(),
+                line,
+                entry: entry.to_string(),
+            });
+        }
+    }
+
+    // Records the first field of `fields` that is not a valid MIME type
+    pub fn check_mime_types<'a, I>(
+        &mut self,
+        path: &Path,
+        line: Option<usize>,
+        entry: &str,
+        fields: I,
+    ) where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let invalid = fields
+            .into_iter()
+            .find(|field| !field.is_empty() && Mime::from_str(field).is_err());
+
+        if let Some(mime_type) = invalid {
+            self.diagnostics.push(Diagnostic::InvalidMimeType {
+                path: path.to_path_buf(),
+                line,
+                mime_type: mime_type.to_string(),
+                entry: entry.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_line() {
+        let strict = ParseContext::new(false);
+        assert_eq!(
+            strict.normalize_line(" 50 : text/plain :*.txt", ':'),
+            " 50 : text/plain :*.txt"
+        );
+
+        let lenient = ParseContext::new(true);
+        assert_eq!(
+            lenient.normalize_line("\u{feff}50: \"text/plain\" :*.txt", ':'),
+            "50:text/plain:*.txt"
+        );
+    }
+
+    #[test]
+    fn check_mime_types() {
+        let mut ctx = ParseContext::new(false);
+        let path = Path::new("aliases");
+
+        ctx.check_mime_types(
+            path,
+            Some(1),
+            "text/x-foo text/plain",
+            ["text/x-foo", "text/plain"],
+        );
+        assert!(ctx.diagnostics.is_empty());
+
+        ctx.check_mime_types(
+            path,
+            Some(2),
+            "text/x-{} text/plain",
+            ["text/x-{}", "text/plain"],
+        );
+        assert_eq!(
+            ctx.diagnostics,
+            vec![Diagnostic::InvalidMimeType {
+                path: PathBuf::from("aliases"),
+                line: Some(2),
+                mime_type: "text/x-{}".to_string(),
+                entry: "text/x-{} text/plain".to_string(),
+            }]
+        );
+        assert_eq!(
+            ctx.diagnostics[0].to_string(),
+            "aliases:2: invalid MIME type 'text/x-{}'"
+        );
+    }
+
+    #[test]
+    fn skip_entry() {
+        let path = Path::new("globs2");
+
+        let mut ctx = ParseContext::new(false);
+        ctx.skip_entry(path, Some(1), "50:text/plain", ["text/plain"]);
+        assert!(ctx.diagnostics.is_empty());
+
+        ctx.strict = true;
+        ctx.skip_entry(path, Some(1), "50:text/plain", ["text/plain"]);
+        ctx.skip_entry(path, Some(2), "50:text/x-{}:*.foo", ["text/x-{}"]);
+        assert_eq!(ctx.diagnostics.len(), 2);
+        assert_eq!(
+            ctx.diagnostics[0].to_string(),
+            "globs2:1: malformed entry '50:text/plain'"
+        );
+        assert!(matches!(
+            ctx.diagnostics[1],
+            Diagnostic::InvalidMimeType { .. }
+        ));
+    }
+
+    #[test]
+    fn check_shadowed() {
+        let mut ctx = ParseContext::new(false);
+        let home = Path::new("home/aliases");
+        let system = Path::new("system/aliases");
+
+        ctx.check_shadowed("aliases", "a/b".into(), home, "a/b c/d".into());
+        ctx.check_shadowed("aliases", "a/b".into(), system, "a/b c/d".into());
+        ctx.check_shadowed("icons", "a/b".into(), system, "a/b:icon".into());
+        assert!(ctx.diagnostics.is_empty());
+
+        ctx.check_shadowed("aliases", "a/b".into(), system, "a/b e/f".into());
+        assert_eq!(
+            ctx.diagnostics[0].to_string(),
+            "system/aliases: 'a/b e/f' is shadowed by 'a/b c/d' in home/aliases"
+        );
+    }
+}
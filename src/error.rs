@@ -0,0 +1,66 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::Diagnostic;
+
+/// The errors returned when the shared MIME database cannot be loaded as
+/// a whole, by [`SharedMimeInfo::try_new`] and
+/// [`SharedMimeInfoBuilder::try_build`], or restored from a snapshot, by
+/// [`SharedMimeInfo::from_snapshot`].
+///
+/// [`SharedMimeInfo::try_new`]: struct.SharedMimeInfo.html#method.try_new
+/// [`SharedMimeInfoBuilder::try_build`]: struct.SharedMimeInfoBuilder.html#method.try_build
+/// [`SharedMimeInfo::from_snapshot`]: struct.SharedMimeInfo.html#method.from_snapshot
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The home directory of the user could not be found, so the
+    /// directory of the MIME data of the user has no default location.
+    MissingHome,
+    /// A `mime` directory exists, but its contents cannot be listed.
+    UnreadableDirectory {
+        /// The `mime` directory.
+        path: PathBuf,
+        /// The error returned when listing the directory.
+        source: io::Error,
+    },
+    /// An entry of the database could not be parsed; the diagnostic
+    /// describes the first one.
+    Parse(Diagnostic),
+    /// The snapshot was not written by the same version of this crate,
+    /// or it is truncated.
+    InvalidSnapshot,
+    /// The snapshot was written for other directories, or one of them
+    /// changed since it was written.
+    StaleSnapshot,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingHome => write!(f, "the home directory of the user cannot be found"),
+            Error::UnreadableDirectory { path, source } => {
+                write!(
+                    f,
+                    "{}: cannot read the directory: {}",
+                    path.display(),
+                    source
+                )
+            }
+            Error::Parse(diagnostic) => write!(f, "{}", diagnostic),
+            Error::InvalidSnapshot => write!(f, "the snapshot of the database is invalid"),
+            Error::StaleSnapshot => write!(f, "the snapshot of the database is out of date"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::UnreadableDirectory { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Logs the directories loaded, the files skipped, the entries that cannot
+// be parsed and the slow magic matches, using the `log` crate, with the
+// `xdg_mime::events` target
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use mime::Mime;
+
+use crate::Diagnostic;
+
+// The threshold of slow magic matches, in microseconds
+static SLOW_MAGIC_THRESHOLD: AtomicU64 = AtomicU64::new(10_000);
+
+/// Sets how long matching some data against the magic entries can take
+/// before the match is logged.
+///
+/// Defaults to 10 milliseconds.
+///
+/// This function requires the `events` feature.
+pub fn set_slow_magic_threshold(threshold: Duration) {
+    let micros = u64::try_from(threshold.as_micros()).unwrap_or(u64::MAX);
+    SLOW_MAGIC_THRESHOLD.store(micros, Ordering::Relaxed);
+}
+
+pub(crate) fn directory_loaded(path: &Path, source: Option<&Path>, elapsed: Duration) {
+    log::debug!(
+        path:% = path.display(),
+        source:? = source,
+        elapsed:? = elapsed;
+        "loaded {}",
+        path.display()
+    );
+}
+
+pub(crate) fn file_skipped(path: &Path, reason: &str) {
+    log::info!(path:% = path.display(), reason; "skipped {}: {}", path.display(), reason);
+}
+
+pub(crate) fn parse_failure(diagnostic: &Diagnostic) {
+    log::warn!("{}", diagnostic);
+}
+
+pub(crate) fn check_magic_match(len: usize, mime_type: Option<&Mime>, elapsed: Duration) {
+    let threshold = Duration::from_micros(SLOW_MAGIC_THRESHOLD.load(Ordering::Relaxed));
+    if elapsed >= threshold {
+        log::debug!(
+            len,
+            mime_type = mime_type.map(Mime::essence_str),
+            elapsed:? = elapsed;
+            "matching {} bytes against the magic entries took {:?}",
+            len,
+            elapsed
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use log::{Level, Log, Metadata, Record};
+
+    use crate::testing::TempMimeDir;
+    use crate::SharedMimeInfo;
+
+    // Keeps the level, the message and the MIME type of the records
+    struct TestLogger(Mutex<Vec<(Level, String, Option<String>)>>);
+
+    impl Log for TestLogger {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "xdg_mime::events"
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if self.enabled(record.metadata()) {
+                let mime_type = record.key_values().get("mime_type".into());
+                self.0.lock().unwrap().push((
+                    record.level(),
+                    record.args().to_string(),
+                    mime_type.map(|m| m.to_string()),
+                ));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+
+    #[test]
+    fn log_events() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-events", "*.events")
+            .magic(50, "text/x-events", 0, b"EVENTS");
+        dir.write().unwrap();
+        let mime_path = dir.mime_path();
+        fs::write(mime_path.join("mime.cache"), b"").unwrap();
+        fs::write(mime_path.join("aliases"), b"text/x-{}\n").unwrap();
+
+        set_slow_magic_threshold(Duration::ZERO);
+        let mime_db = SharedMimeInfo::builder()
+            .override_dirs(Vec::<PathBuf>::new())
+            .data_dirs([dir.path()])
+            .data_home("/does/not/exist")
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        assert!(mime_db.get_mime_type_for_data(b"EVENTS").is_some());
+        set_slow_magic_threshold(Duration::from_millis(10));
+
+        // The other tests can log concurrently, so only the records about
+        // this directory are kept
+        let mime_path = mime_path.display().to_string();
+        let records: Vec<(Level, String)> = LOGGER
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, message, mime_type)| {
+                message.contains(&mime_path) || mime_type.as_deref() == Some("text/x-events")
+            })
+            .map(|(level, message, _)| (*level, message.replace(&mime_path, "$MIME")))
+            .collect();
+        assert_eq!(
+            records[..3],
+            [
+                (
+                    Level::Info,
+                    "skipped $MIME/mime.cache: the compiled cache cannot be parsed".to_string()
+                ),
+                (
+                    Level::Warn,
+                    "$MIME/aliases:1: invalid MIME type 'text/x-{}'".to_string()
+                ),
+                (Level::Debug, "loaded $MIME".to_string()),
+            ]
+        );
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3].0, Level::Debug);
+        assert!(records[3]
+            .1
+            .starts_with("matching 6 bytes against the magic entries took"));
+    }
+}
@@ -0,0 +1,123 @@
+use mime::Mime;
+
+use crate::{GlobWeight, Guess, MagicPriority, SharedMimeInfo};
+
+/// A piece of evidence about the MIME type of a file, to be reconciled
+/// with other evidence using [`SharedMimeInfo::resolve`].
+///
+/// The evidence can come from this crate, or from other detectors, like
+/// bindings to libmagic, or a classifier; each variant carries the
+/// strength of the evidence, on the same scale as the weights of glob
+/// patterns and the priorities of magic entries.
+///
+/// [`SharedMimeInfo::resolve`]: struct.SharedMimeInfo.html#method.resolve
+#[derive(Clone, Debug, PartialEq)]
+pub enum Evidence {
+    /// A glob pattern matching the file name, with its weight.
+    FromName(Mime, GlobWeight),
+    /// A magic entry matching the contents, with its priority.
+    FromMagic(Mime, MagicPriority),
+    /// A MIME type declared by the source of the file, like the
+    /// `Content-Type` header of an HTTP response, or the type of an e-mail
+    /// attachment; it counts as much as a glob pattern with the default
+    /// weight.
+    Declared(Mime),
+    /// The result of another detector, with a confidence between 0 and 1;
+    /// values outside of this range are clamped.
+    External(Mime, f32),
+}
+
+impl Evidence {
+    /// The MIME type the evidence points to.
+    pub fn mime_type(&self) -> &Mime {
+        match self {
+            Evidence::FromName(mime_type, _)
+            | Evidence::FromMagic(mime_type, _)
+            | Evidence::Declared(mime_type)
+            | Evidence::External(mime_type, _) => mime_type,
+        }
+    }
+
+    // The strength of the evidence, between 0 and 100
+    fn score(&self) -> u32 {
+        match self {
+            Evidence::FromName(_, weight) => weight.get(),
+            Evidence::FromMagic(_, priority) => priority.get(),
+            Evidence::Declared(_) => GlobWeight::DEFAULT.get(),
+            // A NaN confidence is cast to 0
+            Evidence::External(_, confidence) => {
+                (confidence.clamp(0.0, 1.0) * 100.0).round() as u32
+            }
+        }
+    }
+}
+
+// Each candidate is supported by the evidence for its own MIME type, and
+// for any of its parents, so that a specific type, like `image/svg+xml`,
+// collects the evidence for a generic one, like `application/xml`; the
+// evidence that is neither a parent nor a subclass of the result is a
+// conflict, and makes the result uncertain; the strongest conflict, or
+// the first one among equals, is reported
+pub fn resolve(db: &SharedMimeInfo, evidence: Vec<Evidence>) -> Guess {
+    let evidence: Vec<(Mime, u32)> = evidence
+        .into_iter()
+        .filter(|e| !db.is_unknown_type(e.mime_type()))
+        .map(|e| {
+            let score = e.score();
+            let mime_type = db
+                .unalias_mime_type(e.mime_type())
+                .unwrap_or_else(|| e.mime_type().clone());
+            (mime_type, score)
+        })
+        .collect();
+
+    let support = |candidate: &Mime| -> u32 {
+        evidence
+            .iter()
+            .filter(|(m, _)| db.mime_type_subclass(candidate, m))
+            .map(|(_, score)| score)
+            .sum()
+    };
+
+    // Ties go to the most specific MIME type, and then to the evidence
+    // that comes first
+    let mut best: Option<(&Mime, u32)> = None;
+    for (candidate, _) in &evidence {
+        let score = support(candidate);
+        let better = match best {
+            None => true,
+            Some((m, best_score)) => {
+                score > best_score
+                    || (score == best_score
+                        && m != candidate
+                        && db.mime_type_subclass(candidate, m))
+            }
+        };
+        if better {
+            best = Some((candidate, score));
+        }
+    }
+
+    let mime_type = match best {
+        Some((mime_type, _)) => mime_type,
+        None => {
+            let mut res = Guess::new(db.unknown_type.clone(), true);
+            res.unknown = true;
+            return res;
+        }
+    };
+
+    let conflict = evidence
+        .iter()
+        .rev()
+        .filter(|(m, _)| {
+            !db.mime_type_subclass(mime_type, m) && !db.mime_type_subclass(m, mime_type)
+        })
+        .max_by_key(|(_, score)| *score)
+        .map(|(m, _)| m.clone());
+
+    let mut res = Guess::new(mime_type.clone(), conflict.is_some());
+    res.conflict = conflict.map(std::borrow::Cow::Owned);
+
+    res
+}
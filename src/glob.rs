@@ -1,21 +1,30 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(feature = "glob")]
+use std::sync::{LazyLock, Mutex, PoisonError};
 
+#[cfg(feature = "glob")]
 use glob::Pattern;
 use mime::Mime;
+#[cfg(feature = "unicase")]
 use unicase::UniCase;
 
+use crate::diagnostics::ParseContext;
+use crate::priority::GlobWeight;
+use crate::special;
+use crate::tier::Tier;
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum GlobType {
     Literal(String),
     Simple(String),
-    Full(Pattern),
+    #[cfg(feature = "glob")]
+    Full(Arc<Pattern>),
 }
 
 impl fmt::Debug for GlobType {
@@ -23,36 +32,202 @@ impl fmt::Debug for GlobType {
         match self {
             GlobType::Literal(name) => write!(f, "Literal '{}'", name),
             GlobType::Simple(pattern) => write!(f, "Simple glob '*{}'", pattern),
+            #[cfg(feature = "glob")]
             GlobType::Full(pattern) => write!(f, "Full glob '{}'", pattern),
         }
     }
 }
 
+impl GlobType {
+    // The pattern as it appears in the globs files
+    fn pattern(&self) -> String {
+        match self {
+            GlobType::Literal(s) => s.clone(),
+            GlobType::Simple(s) => format!("*{}", s),
+            #[cfg(feature = "glob")]
+            GlobType::Full(p) => p.as_str().to_string(),
+        }
+    }
+}
+
 impl fmt::Display for GlobType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             GlobType::Literal(str) => write!(f, "{}", str),
             GlobType::Simple(str) => write!(f, "{}", str),
+            #[cfg(feature = "glob")]
             GlobType::Full(pattern) => write!(f, "{}", pattern),
         }
     }
 }
 
-fn determine_type(glob: &str) -> GlobType {
-    let mut maybe_simple = false;
+/// The longest file name, in bytes, that is matched in full against the
+/// globs in the database.
+///
+/// Longer names are only matched through their trailing bytes, and only by
+/// patterns that are anchored at the end of the name, like `*.txt`; this
+/// keeps the cost of a lookup bounded regardless of the size of the input.
+pub const MAX_FILE_NAME_LEN: usize = 4096;
+
+/// The longest glob pattern, in bytes, accepted when loading the database.
+pub const MAX_PATTERN_LEN: usize = 255;
+
+/// The maximum number of `*` wildcards accepted in a glob pattern when
+/// loading the database.
+pub const MAX_PATTERN_STARS: usize = 4;
+
+// The pattern used by a directory to remove all the globs of a MIME type
+// defined in the directories with a lower precedence
+const NO_GLOBS: &str = "__NOGLOBS__";
+
+// Whether the pattern needs a full glob matcher, instead of being a
+// literal name or a simple `*.ext` suffix
+fn is_full_pattern(glob: &str) -> bool {
+    glob.bytes()
+        .enumerate()
+        .any(|(idx, ch)| ch == b'\\' || ch == b'[' || ch == b'?' || (idx > 0 && ch == b'*'))
+}
+
+// The number of compiled patterns kept in the cache before the ones that
+// are not used by any database are dropped
+#[cfg(feature = "glob")]
+const PATTERN_CACHE_SIZE: usize = 1024;
+
+// The compiled full patterns, keyed by their string; the same patterns
+// appear in many directories, and again on every reload, so they are
+// compiled once and shared between all the databases
+#[cfg(feature = "glob")]
+static PATTERN_CACHE: LazyLock<Mutex<HashMap<String, Arc<Pattern>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(feature = "glob")]
+fn compile_pattern(glob: &str) -> Option<Arc<Pattern>> {
+    let mut cache = PATTERN_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
 
-    for (idx, ch) in glob.bytes().enumerate() {
-        if idx == 0 && ch == b'*' {
-            maybe_simple = true;
-        } else if ch == b'\\' || ch == b'[' || ch == b'*' || ch == b'?' {
-            return GlobType::Full(Pattern::new(glob).unwrap());
+    if let Some(pattern) = cache.get(glob) {
+        return Some(Arc::clone(pattern));
+    }
+
+    let pattern = Arc::new(Pattern::new(glob).ok()?);
+
+    if cache.len() >= PATTERN_CACHE_SIZE {
+        cache.retain(|_, p| Arc::strong_count(p) > 1);
+    }
+    if cache.len() < PATTERN_CACHE_SIZE {
+        cache.insert(glob.to_string(), Arc::clone(&pattern));
+    }
+
+    Some(pattern)
+}
+
+fn determine_type(glob: &str) -> GlobType {
+    if is_full_pattern(glob) {
+        #[cfg(feature = "glob")]
+        if let Some(pattern) = compile_pattern(glob) {
+            return GlobType::Full(pattern);
         }
+
+        return GlobType::Literal(glob.to_string());
     }
 
-    if maybe_simple {
-        GlobType::Simple(glob[1..].to_string())
-    } else {
-        GlobType::Literal(glob.to_string())
+    match glob.strip_prefix('*') {
+        Some(suffix) => GlobType::Simple(suffix.to_string()),
+        None => GlobType::Literal(glob.to_string()),
+    }
+}
+
+// Rejects patterns that are too expensive to match, or that cannot be
+// compiled at all
+fn is_acceptable_pattern(glob: &str) -> bool {
+    if glob.is_empty() || glob.len() > MAX_PATTERN_LEN {
+        return false;
+    }
+
+    if glob.bytes().filter(|ch| *ch == b'*').count() > MAX_PATTERN_STARS {
+        return false;
+    }
+
+    // Simple patterns always compile, and full ones are kept in the
+    // cache for when the glob is created
+    #[cfg(feature = "glob")]
+    return !is_full_pattern(glob) || compile_pattern(glob).is_some();
+
+    // Full patterns are only supported with the `glob` feature
+    #[cfg(not(feature = "glob"))]
+    return !is_full_pattern(glob);
+}
+
+/// How glob patterns that match almost every file name, like `*` or `*.*`,
+/// are handled while loading the MIME database.
+///
+/// Some third-party packages ship such patterns by mistake; since they
+/// match every file, they would be returned alongside the right MIME type,
+/// or instead of the [unknown type]. A pattern is considered generic if it
+/// contains a `*` wildcard, and it has no character other than `*`, `?`
+/// and `.`.
+///
+/// Generic patterns that are dropped or down-weighted are reported by the
+/// [`diagnostics`] method of [`SharedMimeInfo`].
+///
+/// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+/// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GenericGlobs {
+    /// Generic patterns are loaded like any other pattern.
+    #[default]
+    Keep,
+    /// Generic patterns are loaded with the lowest weight, so they are
+    /// only used if no other pattern matches.
+    DownWeight,
+    /// Generic patterns are not loaded.
+    Drop,
+}
+
+// Whether the pattern matches almost every file name
+fn is_generic_pattern(glob: &str) -> bool {
+    glob.contains('*') && glob.chars().all(|ch| matches!(ch, '*' | '?' | '.'))
+}
+
+// A full pattern that starts with a wildcard and contains no other `*`
+// always matches a fixed number of trailing characters, so it can be
+// safely checked against the tail of a file name
+#[cfg(feature = "glob")]
+fn is_suffix_pattern(pattern: &Pattern) -> bool {
+    let s = pattern.as_str();
+
+    s.starts_with('*') && !s[1..].contains('*')
+}
+
+// The file name prepared for matching against the globs: pathological
+// inputs are reduced to their tail, and the case folding is performed
+// only once per lookup instead of once per glob
+struct FileName<'a> {
+    name: &'a str,
+    lowercase: String,
+    truncated: bool,
+}
+
+impl<'a> FileName<'a> {
+    fn new(file_name: &'a str) -> FileName<'a> {
+        let mut name = file_name;
+        let mut truncated = false;
+
+        if name.len() > MAX_FILE_NAME_LEN {
+            let mut start = name.len() - MAX_FILE_NAME_LEN;
+            while !name.is_char_boundary(start) {
+                start += 1;
+            }
+
+            name = &name[start..];
+            truncated = true;
+        }
+
+        FileName {
+            name,
+            lowercase: name.to_lowercase(),
+            truncated,
+        }
     }
 }
 
@@ -62,6 +237,7 @@ pub struct Glob {
     weight: i32,
     case_sensitive: bool,
     mime_type: Mime,
+    tier: Tier,
 }
 
 impl PartialEq for Glob {
@@ -92,35 +268,48 @@ impl fmt::Debug for Glob {
 impl Glob {
     pub fn simple(mime_type: &Mime, glob: &str) -> Glob {
         Glob {
-            mime_type: mime_type.clone(),
+            mime_type: special::intern(mime_type.clone()),
             glob: determine_type(glob),
             weight: 50,
             case_sensitive: false,
+            tier: Tier::System,
         }
     }
 
     pub fn with_weight(mime_type: &Mime, glob: &str, weight: i32) -> Glob {
         Glob {
-            mime_type: mime_type.clone(),
+            mime_type: special::intern(mime_type.clone()),
             glob: determine_type(glob),
             weight,
             case_sensitive: false,
+            tier: Tier::System,
         }
     }
 
     pub fn new(mime_type: &Mime, glob: &str, weight: i32, cs: bool) -> Glob {
         Glob {
-            mime_type: mime_type.clone(),
+            mime_type: special::intern(mime_type.clone()),
             glob: determine_type(glob),
             weight,
             case_sensitive: cs,
+            tier: Tier::System,
         }
     }
 
+    // Like new(), but rejects the patterns that would be rejected when
+    // loading the globs files
+    pub fn checked(mime_type: &Mime, glob: &str, weight: i32, cs: bool) -> Option<Glob> {
+        if !is_acceptable_pattern(glob) {
+            return None;
+        }
+
+        Some(Glob::new(mime_type, glob, weight, cs))
+    }
+
     pub fn from_v1_string(s: &str) -> Option<Glob> {
         let mut chunks = s.split(':').fuse();
-        let mime_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
-        let glob = chunks.next().filter(|s| !s.is_empty())?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
+        let glob = chunks.next().filter(|s| is_acceptable_pattern(s))?;
 
         // The globs file is not extensible, so consume any
         // leftover tokens
@@ -139,15 +328,15 @@ impl Glob {
             .and_then(|v| v.parse::<i32>().ok())
             .filter(|n| *n >= 0)?;
 
-        let mime_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
-        let glob = chunks.next()?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
+        let glob = chunks.next().filter(|s| is_acceptable_pattern(s))?;
 
         let mut case_sensitive = false;
         if let Some(flags) = chunks.next() {
             let flags_chunks = flags.split(',').collect::<Vec<&str>>();
 
             // Allow for extra flags
-            if flags_chunks.iter().any(|&f| f == "cs") {
+            if flags_chunks.contains(&"cs") {
                 case_sensitive = true;
             }
         }
@@ -159,28 +348,93 @@ impl Glob {
         Some(Glob::new(&mime_type, glob, weight, case_sensitive))
     }
 
+    pub fn pattern(&self) -> String {
+        self.glob.pattern()
+    }
+
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime_type
+    }
+
+    // Creates the marker removing the globs of `mime_type` from the
+    // directories with a lower precedence
+    pub fn no_globs(mime_type: &Mime) -> Glob {
+        Glob::new(mime_type, NO_GLOBS, 0, true)
+    }
+
+    pub fn is_no_globs(&self) -> bool {
+        matches!(&self.glob, GlobType::Literal(s) if s == NO_GLOBS)
+    }
+
+    pub fn set_tier(&mut self, tier: Tier) {
+        self.tier = tier;
+    }
+
+    pub fn set_weight(&mut self, weight: GlobWeight) {
+        self.weight = weight.get() as i32;
+    }
+
+    pub fn is_generic(&self) -> bool {
+        is_generic_pattern(&self.pattern())
+    }
+
+    // The length of the pattern, in characters, used to rank globs with
+    // the same weight
+    fn pattern_len(&self) -> usize {
+        match &self.glob {
+            GlobType::Literal(s) => s.chars().count(),
+            GlobType::Simple(s) => s.chars().count() + 1,
+            #[cfg(feature = "glob")]
+            GlobType::Full(p) => p.as_str().chars().count(),
+        }
+    }
+
+    pub fn to_v2_string(&self) -> String {
+        let mut res = format!("{}:{}:{}", self.weight, self.mime_type, self.glob.pattern());
+
+        if self.case_sensitive {
+            res.push_str(":cs");
+        }
+
+        res
+    }
+
     fn compare(&self, file_name: &str) -> bool {
+        self.matches(&FileName::new(file_name))
+    }
+
+    fn matches(&self, file_name: &FileName) -> bool {
         match &self.glob {
             GlobType::Literal(s) => {
-                let a = UniCase::new(s);
-                let b = UniCase::new(file_name);
+                if file_name.truncated {
+                    return false;
+                }
+
+                #[cfg(feature = "unicase")]
+                return UniCase::new(s) == UniCase::new(file_name.name);
 
-                return a == b;
+                #[cfg(not(feature = "unicase"))]
+                return s
+                    .chars()
+                    .flat_map(char::to_lowercase)
+                    .eq(file_name.lowercase.chars());
             }
             GlobType::Simple(s) => {
-                if file_name.ends_with(s) {
+                if file_name.name.ends_with(s) {
                     return true;
                 }
 
-                if !self.case_sensitive {
-                    let lc_file_name = file_name.to_lowercase();
-                    if lc_file_name.ends_with(s) {
-                        return true;
-                    }
+                if !self.case_sensitive && file_name.lowercase.ends_with(s) {
+                    return true;
                 }
             }
+            #[cfg(feature = "glob")]
             GlobType::Full(p) => {
-                return p.matches(file_name);
+                if file_name.truncated && !is_suffix_pattern(p) {
+                    return false;
+                }
+
+                return p.matches(file_name.name);
             }
         }
 
@@ -188,20 +442,106 @@ impl Glob {
     }
 }
 
-pub fn read_globs_v1_from_file<P: AsRef<Path>>(file_name: P) -> Option<Vec<Glob>> {
-    let f = match File::open(file_name) {
+// The globs of a GlobMap, sorted by precedence, for matching many file
+// names in a row: the case insensitive globs for a plain extension, like
+// `*.txt`, are indexed by extension, and the other globs are only matched
+// until one that could take precedence over the indexed one is found
+pub(crate) struct BatchLookup<'a> {
+    ranked: Vec<&'a Glob>,
+    extensions: HashMap<&'a str, usize>,
+    others: Vec<usize>,
+}
+
+impl<'a> BatchLookup<'a> {
+    fn new(map: &'a GlobMap) -> BatchLookup<'a> {
+        let mut ranked: Vec<&Glob> = map.iter().collect();
+        ranked.sort_by(|a, b| {
+            b.weight
+                .cmp(&a.weight)
+                .then_with(|| b.pattern_len().cmp(&a.pattern_len()))
+                .then_with(|| a.mime_type.cmp(&b.mime_type))
+        });
+
+        let mut extensions = HashMap::new();
+        let mut others = Vec::new();
+        for (idx, glob) in ranked.iter().enumerate() {
+            let extension = match &glob.glob {
+                GlobType::Simple(suffix) if !glob.case_sensitive => suffix
+                    .strip_prefix('.')
+                    .filter(|ext| !ext.contains('.'))
+                    .filter(|ext| {
+                        !ext.bytes()
+                            .any(|ch| ch.is_ascii_uppercase() || !ch.is_ascii())
+                    }),
+                _ => None,
+            };
+
+            match extension {
+                // The globs are ranked, so the first one for each extension
+                // takes precedence
+                Some(ext) => {
+                    extensions.entry(ext).or_insert(idx);
+                }
+                None => others.push(idx),
+            }
+        }
+
+        BatchLookup {
+            ranked,
+            extensions,
+            others,
+        }
+    }
+
+    // The index of the glob that takes precedence among the ones matching
+    // the file name, which is the same glob found by
+    // GlobMap::first_mime_type_for_file_name()
+    pub fn lookup(&self, file_name: &str) -> Option<usize> {
+        let file_name = FileName::new(file_name);
+
+        let indexed = file_name
+            .lowercase
+            .rsplit_once('.')
+            .and_then(|(_, ext)| self.extensions.get(ext))
+            .copied();
+
+        let other = self
+            .others
+            .iter()
+            .copied()
+            .take_while(|idx| indexed.map_or(true, |best| *idx < best))
+            .find(|idx| self.ranked[*idx].matches(&file_name));
+
+        other.or(indexed)
+    }
+
+    // The MIME type of the glob at the index returned by lookup()
+    pub fn mime_type(&self, idx: usize) -> &'a Mime {
+        &self.ranked[idx].mime_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranked.len()
+    }
+}
+
+pub fn read_globs_v1_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Option<Vec<Glob>> {
+    let f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return None,
     };
 
     let mut res = Vec::new();
     let file = BufReader::new(&f);
-    for line in file.lines() {
+    for (n, line) in file.lines().enumerate() {
         if line.is_err() {
             return None;
         }
 
-        let line = line.unwrap();
+        let line = ctx.normalize_line(&line.unwrap(), ':');
 
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -209,27 +549,82 @@ pub fn read_globs_v1_from_file<P: AsRef<Path>>(file_name: P) -> Option<Vec<Glob>
 
         match Glob::from_v1_string(&line) {
             Some(v) => res.push(v),
-            None => continue,
+            None => ctx.skip_entry(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split(':').take(1),
+            ),
         }
     }
 
     Some(res)
 }
 
-pub fn read_globs_v2_from_file<P: AsRef<Path>>(file_name: P) -> Option<Vec<Glob>> {
-    let f = match File::open(file_name) {
+// Reads a mime.types file in the Apache format, where each line contains
+// a MIME type followed by its extensions; each extension becomes a case
+// insensitive `*.ext` glob, with the default weight
+pub fn read_globs_from_mime_types_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Option<Vec<Glob>> {
+    let f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return None,
     };
 
     let mut res = Vec::new();
     let file = BufReader::new(&f);
-    for line in file.lines() {
+    for (n, line) in file.lines().enumerate() {
         if line.is_err() {
             return None;
         }
 
         let line = line.unwrap();
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut chunks = line.split_whitespace();
+        let mime_type = chunks.next().unwrap_or_default();
+        let mime_type = match special::parse_mime_type(mime_type) {
+            Some(v) => v,
+            None => {
+                ctx.check_mime_types(file_name.as_ref(), Some(n + 1), line, [mime_type]);
+                continue;
+            }
+        };
+
+        for ext in chunks.filter(|ext| !ext.contains(':')) {
+            let pattern = format!("*.{}", ext.to_lowercase());
+            if let Some(glob) = Glob::checked(&mime_type, &pattern, 50, false) {
+                res.push(glob);
+            }
+        }
+    }
+
+    Some(res)
+}
+
+pub fn read_globs_v2_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Option<Vec<Glob>> {
+    let f = match File::open(&file_name) {
+        Ok(v) => v,
+        Err(_) => return None,
+    };
+
+    let mut res = Vec::new();
+    let file = BufReader::new(&f);
+    for (n, line) in file.lines().enumerate() {
+        if line.is_err() {
+            return None;
+        }
+
+        let line = ctx.normalize_line(&line.unwrap(), ':');
 
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -237,95 +632,558 @@ pub fn read_globs_v2_from_file<P: AsRef<Path>>(file_name: P) -> Option<Vec<Glob>
 
         match Glob::from_v2_string(&line) {
             Some(v) => res.push(v),
-            None => continue,
+            None => ctx.skip_entry(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split(':').skip(1).take(1),
+            ),
         }
     }
 
     Some(res)
 }
 
-pub fn read_globs_from_dir<P: AsRef<Path>>(dir: P) -> Vec<Glob> {
+pub fn read_globs_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Vec<Glob> {
     let mut globs_file = PathBuf::new();
     globs_file.push(dir);
     globs_file.push("globs2");
 
-    match read_globs_v2_from_file(&globs_file) {
+    match read_globs_v2_from_file(&globs_file, ctx) {
         Some(v) => v,
         None => {
             globs_file.pop();
             globs_file.push("globs");
 
-            read_globs_v1_from_file(globs_file).unwrap_or_default()
+            read_globs_v1_from_file(globs_file, ctx).unwrap_or_default()
         }
     }
 }
 
+pub fn write_globs_v2<W: Write>(globs: &GlobMap, w: &mut W) -> io::Result<()> {
+    let mut globs: Vec<&Glob> = globs.iter().collect();
+    globs.sort_by(|a, b| {
+        b.weight
+            .cmp(&a.weight)
+            .then_with(|| a.glob.pattern().cmp(&b.glob.pattern()))
+            .then_with(|| a.mime_type.cmp(&b.mime_type))
+    });
+
+    for glob in globs {
+        writeln!(w, "{}", glob.to_v2_string())?;
+    }
+
+    Ok(())
+}
+
+/// A set of glob patterns, used to map file names to MIME types.
+///
+/// The [`SharedMimeInfo`] database uses the patterns of the `globs2` files
+/// in the XDG data directories; a `GlobMap` can also be created with any
+/// set of patterns using a [`GlobMapBuilder`], and used on its own:
+///
+/// ```rust
+/// use xdg_mime::{GlobMap, GlobWeight};
+///
+/// let globs = GlobMap::builder()
+///     .add_glob(&mime::TEXT_PLAIN, "*.txt", GlobWeight::DEFAULT)
+///     .add_glob(&mime::TEXT_CSV, "*.csv", GlobWeight::DEFAULT)
+///     .add_case_sensitive_glob(&mime::TEXT_CSS, "*.CSS", GlobWeight::MAX)
+///     .build();
+///
+/// assert_eq!(globs.lookup("notes.TXT"), vec![mime::TEXT_PLAIN]);
+/// assert_eq!(globs.lookup("style.CSS"), vec![mime::TEXT_CSS]);
+/// assert!(globs.lookup("style.css").is_empty());
+/// ```
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [`GlobMapBuilder`]: struct.GlobMapBuilder.html
+#[derive(Clone, Default)]
 pub struct GlobMap {
     globs: HashSet<Glob>,
+    // The MIME types whose globs were removed by a `__NOGLOBS__` marker
+    deleted: HashSet<Mime>,
+    // The globs looked up after the ones of the map, if the map is an
+    // overlay; the globs of the base with the same pattern and MIME type
+    // as one of the map are hidden, and the base is never an overlay
+    base: Option<Arc<GlobMap>>,
 }
 
 impl GlobMap {
+    /// Creates an empty set of glob patterns.
     pub fn new() -> GlobMap {
         GlobMap {
             globs: HashSet::new(),
+            deleted: HashSet::new(),
+            base: None,
         }
     }
 
-    pub fn add_glob(&mut self, glob: Glob) {
-        self.globs.insert(glob);
-    }
+    // Creates an overlay of `base`, whose globs replace the ones of the
+    // base with the same pattern and MIME type; the globs of the base are
+    // shared, not copied
+    pub(crate) fn layered(base: &Arc<GlobMap>, globs: Vec<Glob>) -> GlobMap {
+        let (mut overlay, base) = match &base.base {
+            Some(inner) => (base.globs.clone(), inner.clone()),
+            None => (HashSet::new(), base.clone()),
+        };
+        for glob in globs {
+            overlay.replace(glob);
+        }
 
-    pub fn add_globs(&mut self, globs: &[Glob]) {
-        self.globs.extend(globs.iter().cloned());
+        GlobMap {
+            globs: overlay,
+            deleted: HashSet::new(),
+            base: Some(base),
+        }
     }
 
-    pub fn lookup_mime_type_for_file_name(&self, file_name: &str) -> Option<Vec<Mime>> {
-        let mut matching_globs = Vec::new();
+    // Iterates over the globs of the map, followed by the ones of the base
+    // that are not hidden
+    fn iter(&self) -> impl Iterator<Item = &Glob> {
+        let base = self
+            .base
+            .iter()
+            .flat_map(|base| base.globs.iter())
+            .filter(move |glob| !self.globs.contains(*glob));
 
-        for glob in &self.globs {
-            if glob.compare(file_name) {
-                matching_globs.push(glob.clone());
+        self.globs.iter().chain(base)
+    }
+
+    // Copies the globs of the base into the map, before modifying it
+    fn flatten(&mut self) {
+        if let Some(base) = self.base.take() {
+            for glob in base.globs.iter() {
+                if !self.globs.contains(glob) {
+                    self.globs.insert(glob.clone());
+                }
             }
+            self.deleted.clone_from(&base.deleted);
         }
+    }
+
+    /// Creates a [`GlobMapBuilder`], to populate a new set of patterns.
+    ///
+    /// [`GlobMapBuilder`]: struct.GlobMapBuilder.html
+    pub fn builder() -> GlobMapBuilder {
+        GlobMapBuilder::new()
+    }
 
-        // Sort in descending order by weight
-        matching_globs.sort_by(|a, b| b.weight.cmp(&a.weight));
+    /// The number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
 
-        let biggest_weight = matching_globs.first()?.weight;
+    /// Checks whether the set contains no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
 
-        // "Keep only globs with the biggest weight."
-        // -- shared-mime-info, "Recommended checking order"
-        let matching_globs = matching_globs
+    /// Retrieves the MIME types matching `file_name`.
+    ///
+    /// Following the [recommended checking order], only the patterns
+    /// with the highest weight are taken into account and, among those,
+    /// only the longest ones. The resulting MIME types are sorted
+    /// alphabetically.
+    ///
+    /// Returns an empty vector if no pattern matches.
+    ///
+    /// [recommended checking order]: https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html
+    pub fn lookup(&self, file_name: &str) -> Vec<Mime> {
+        self.lookup_mime_type_for_file_name(file_name)
+            .unwrap_or_default()
+    }
+
+    /// Retrieves every MIME type with a pattern matching `file_name`, with
+    /// the highest weight among its matching patterns.
+    ///
+    /// The list is sorted by weight, in descending order, then by the
+    /// length of the longest pattern with that weight, and then by MIME
+    /// type; the first entry is always one of the types returned by
+    /// [`lookup`].
+    ///
+    /// [`lookup`]: #method.lookup
+    pub fn lookup_all(&self, file_name: &str) -> Vec<(Mime, GlobWeight)> {
+        self.lookup_all_mime_types_for_file_name(file_name)
+            .into_iter()
+            .map(|(mime_type, weight)| (mime_type, GlobWeight::saturating(weight)))
+            .collect()
+    }
+
+    // Returns every glob matching the file name, in descending order by
+    // weight, then by pattern length, and then by MIME type and pattern
+    pub(crate) fn matching_globs(&self, file_name: &str) -> Vec<GlobMatch<'_>> {
+        let file_name = FileName::new(file_name);
+        let mut res: Vec<&Glob> = self
             .iter()
-            .filter(|glob| glob.weight == biggest_weight);
+            .filter(|glob| glob.matches(&file_name))
+            .collect();
+
+        res.sort_by(|a, b| {
+            b.weight
+                .cmp(&a.weight)
+                .then_with(|| b.pattern_len().cmp(&a.pattern_len()))
+                .then_with(|| a.mime_type.cmp(&b.mime_type))
+                .then_with(|| a.glob.pattern().cmp(&b.glob.pattern()))
+        });
+
+        res.into_iter().map(|glob| GlobMatch { glob }).collect()
+    }
+
+    pub(crate) fn add_glob(&mut self, glob: Glob) {
+        self.flatten();
+        self.globs.insert(glob);
+    }
+
+    // Adds a glob, replacing an existing glob with the same pattern and
+    // MIME type; the replaced glob is returned
+    pub(crate) fn replace_glob(&mut self, glob: Glob) -> Option<Glob> {
+        self.flatten();
+        self.globs.replace(glob)
+    }
+
+    // Adds the globs of a directory, with a lower precedence than the
+    // directories added before; the `__NOGLOBS__` markers remove the globs
+    // of their MIME type from the directories added afterwards, but not
+    // the ones listed alongside them; a glob repeating the pattern and MIME
+    // type of one already added keeps its first definition
+    pub(crate) fn add_globs(&mut self, globs: &[Glob]) {
+        self.flatten();
+
+        let deleted: Vec<Mime> = globs
+            .iter()
+            .filter(|glob| glob.is_no_globs())
+            .map(|glob| glob.mime_type.clone())
+            .collect();
+
+        self.globs.extend(
+            globs
+                .iter()
+                .filter(|glob| !glob.is_no_globs() && !self.deleted.contains(&glob.mime_type))
+                .cloned(),
+        );
+        self.deleted.extend(deleted);
+    }
 
-        // Needs to be after filtering for biggest weight
-        // in case it changes which glob is the longest.
-        let biggest_glob_length = matching_globs
-            .clone()
-            .map(|glob| glob.glob.to_string().len())
-            .max()?;
+    // Returns the MIME types of the globs with the biggest weight and,
+    // among those, with the longest pattern, sorted by MIME type
+    pub(crate) fn lookup_mime_type_for_file_name(&self, file_name: &str) -> Option<Vec<Mime>> {
+        let file_name = FileName::new(file_name);
 
+        // "Keep only globs with the biggest weight."
         // "If the patterns are different, keep only the globs
         // with the longest pattern, as previously discussed."
         // -- shared-mime-info, "Recommended checking order"
-        let res = matching_globs
-            .filter(|glob| glob.glob.to_string().len() == biggest_glob_length)
-            .map(|glob| glob.mime_type.clone())
+        //
+        // Only the MIME types of the best globs seen so far are kept, so
+        // that the other matches are neither collected nor sorted; there
+        // is seldom more than one
+        let mut best: Option<(i32, usize)> = None;
+        let mut candidates: Vec<&Mime> = Vec::new();
+        for glob in self.iter().filter(|glob| glob.matches(&file_name)) {
+            let key = (glob.weight, glob.pattern_len());
+            match best {
+                Some(b) if key < b => continue,
+                Some(b) if key == b => {}
+                _ => {
+                    best = Some(key);
+                    candidates.clear();
+                }
+            }
+            candidates.push(&glob.mime_type);
+        }
+        best?;
+
+        // The globs are stored in a set, so the MIME types are sorted to
+        // get a stable order
+        candidates.sort();
+        candidates.dedup();
+
+        Some(candidates.into_iter().cloned().collect())
+    }
+
+    // Returns the first MIME type that lookup_mime_type_for_file_name()
+    // would return, without collecting the matching globs
+    pub(crate) fn first_mime_type_for_file_name(&self, file_name: &str) -> Option<&Mime> {
+        let file_name = FileName::new(file_name);
+        let mut best: Option<&Glob> = None;
+
+        for glob in self.iter().filter(|glob| glob.matches(&file_name)) {
+            let better = match best {
+                Some(b) => b
+                    .weight
+                    .cmp(&glob.weight)
+                    .then_with(|| b.pattern_len().cmp(&glob.pattern_len()))
+                    .then_with(|| glob.mime_type.cmp(&b.mime_type))
+                    .is_lt(),
+                None => true,
+            };
+
+            if better {
+                best = Some(glob);
+            }
+        }
+
+        best.map(|glob| &glob.mime_type)
+    }
+
+    // Prepares the globs for looking up the first MIME type of many file
+    // names
+    pub(crate) fn batch_lookup(&self) -> BatchLookup<'_> {
+        BatchLookup::new(self)
+    }
+
+    // Returns every MIME type with a glob matching the file name, with the
+    // highest weight among its matching globs, in descending order by
+    // weight, then by the length of the longest pattern with that weight,
+    // and then by MIME type
+    pub(crate) fn lookup_all_mime_types_for_file_name(&self, file_name: &str) -> Vec<(Mime, i32)> {
+        let file_name = FileName::new(file_name);
+        let mut res: Vec<(Mime, i32, usize)> = Vec::new();
+
+        for glob in self.iter().filter(|glob| glob.matches(&file_name)) {
+            let key = (glob.weight, glob.pattern_len());
+            match res.iter_mut().find(|(mime, _, _)| *mime == glob.mime_type) {
+                Some(entry) => {
+                    if key > (entry.1, entry.2) {
+                        entry.1 = key.0;
+                        entry.2 = key.1;
+                    }
+                }
+                None => res.push((glob.mime_type.clone(), key.0, key.1)),
+            }
+        }
+
+        res.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| b.2.cmp(&a.2))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+
+        res.into_iter()
+            .map(|(mime, weight, _)| (mime, weight))
+            .collect()
+    }
+
+    // Returns the literal file names, and their MIME type, sorted by name
+    pub(crate) fn literal_names(&self) -> Vec<(&str, &Mime)> {
+        let mut res: Vec<(&str, &Mime)> = self
+            .iter()
+            .filter_map(|glob| match &glob.glob {
+                GlobType::Literal(name) => Some((name.as_str(), &glob.mime_type)),
+                _ => None,
+            })
             .collect();
 
-        Some(res)
+        res.sort();
+
+        res
+    }
+
+    pub(crate) fn is_literal_name(&self, file_name: &str) -> bool {
+        let file_name = FileName::new(file_name);
+
+        self.iter()
+            .filter(|glob| matches!(glob.glob, GlobType::Literal(_)))
+            .any(|glob| glob.matches(&file_name))
+    }
+
+    /// Retrieves the patterns for `mime_type`, sorted by weight, in
+    /// descending order, and then alphabetically.
+    pub fn patterns_for_mime_type(&self, mime_type: &Mime) -> Vec<String> {
+        self.patterns_for_mime_types(&[mime_type])
+    }
+
+    /// Retrieves the globs for `mime_type`, in the same order as
+    /// [`patterns_for_mime_type`].
+    ///
+    /// [`patterns_for_mime_type`]: #method.patterns_for_mime_type
+    pub fn globs_for_mime_type(&self, mime_type: &Mime) -> Vec<GlobRef<'_>> {
+        let mut globs: Vec<GlobRef> = self
+            .iter()
+            .filter(|glob| glob.mime_type == *mime_type)
+            .map(|glob| GlobRef { glob })
+            .collect();
+        globs.sort_by(|a, b| {
+            b.glob
+                .weight
+                .cmp(&a.glob.weight)
+                .then_with(|| a.glob.glob.pattern().cmp(&b.glob.glob.pattern()))
+        });
+
+        globs
+    }
+
+    // Like patterns_for_mime_type(), for the globs of any of the MIME
+    // types; each pattern is returned once, with its highest weight
+    pub(crate) fn patterns_for_mime_types(&self, mime_types: &[&Mime]) -> Vec<String> {
+        let mut globs: Vec<&Glob> = self
+            .iter()
+            .filter(|glob| mime_types.contains(&&glob.mime_type))
+            .collect();
+        globs.sort_by(|a, b| {
+            b.weight
+                .cmp(&a.weight)
+                .then_with(|| a.glob.pattern().cmp(&b.glob.pattern()))
+        });
+
+        // The same pattern can be listed for more than one of the types
+        let mut seen = HashSet::new();
+        globs
+            .iter()
+            .map(|glob| glob.glob.pattern())
+            .filter(|pattern| seen.insert(pattern.clone()))
+            .collect()
+    }
+
+    /// Iterates over the MIME type of each pattern in the set, in no
+    /// particular order.
+    ///
+    /// A MIME type is returned once for each of its patterns.
+    pub fn mime_types(&self) -> impl Iterator<Item = &Mime> {
+        self.iter().map(|glob| &glob.mime_type)
     }
 
-    pub fn clear(&mut self) {
+    pub(crate) fn clear(&mut self) {
         self.globs.clear();
+        self.deleted.clear();
+        self.base = None;
+    }
+}
+
+/// A glob pattern of a [`GlobMap`], returned by
+/// [`GlobMap::globs_for_mime_type`].
+///
+/// [`GlobMap`]: struct.GlobMap.html
+/// [`GlobMap::globs_for_mime_type`]: struct.GlobMap.html#method.globs_for_mime_type
+#[derive(Clone, Copy, Debug)]
+pub struct GlobRef<'a> {
+    glob: &'a Glob,
+}
+
+impl GlobRef<'_> {
+    /// The pattern, as it appears in the `globs2` files.
+    pub fn pattern(&self) -> String {
+        self.glob.glob.pattern()
+    }
+
+    /// The MIME type of the pattern.
+    pub fn mime_type(&self) -> &Mime {
+        &self.glob.mime_type
+    }
+
+    /// The weight of the pattern.
+    pub fn weight(&self) -> GlobWeight {
+        GlobWeight::saturating(self.glob.weight)
+    }
+
+    /// Whether the pattern is matched taking case into account.
+    pub fn case_sensitive(&self) -> bool {
+        self.glob.case_sensitive
+    }
+
+    /// Where the pattern comes from.
+    pub fn source_tier(&self) -> Tier {
+        self.glob.tier
+    }
+}
+
+/// A glob pattern matching a file name, returned by
+/// [`SharedMimeInfo::matching_globs`].
+///
+/// [`SharedMimeInfo::matching_globs`]: struct.SharedMimeInfo.html#method.matching_globs
+#[derive(Clone, Copy, Debug)]
+pub struct GlobMatch<'a> {
+    glob: &'a Glob,
+}
+
+impl GlobMatch<'_> {
+    /// The pattern, as it appears in the `globs2` files.
+    pub fn pattern(&self) -> String {
+        self.glob.glob.pattern()
+    }
+
+    /// The MIME type of the pattern, as listed in the `globs2` files; it
+    /// can be an alias.
+    pub fn mime_type(&self) -> &Mime {
+        &self.glob.mime_type
+    }
+
+    /// The weight of the pattern.
+    pub fn weight(&self) -> GlobWeight {
+        GlobWeight::saturating(self.glob.weight)
+    }
+
+    /// Whether the pattern is matched taking case into account.
+    pub fn case_sensitive(&self) -> bool {
+        self.glob.case_sensitive
+    }
+}
+
+/// A builder type to create a [`GlobMap`] from a set of patterns.
+///
+/// [`GlobMap`]: struct.GlobMap.html
+#[derive(Clone, Debug, Default)]
+pub struct GlobMapBuilder {
+    globs: Vec<Glob>,
+}
+
+impl GlobMapBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> GlobMapBuilder {
+        GlobMapBuilder { globs: Vec::new() }
+    }
+
+    /// Adds a pattern for `mime_type`, with the given weight.
+    ///
+    /// Patterns are matched ignoring case. If the same pattern is added
+    /// more than once for `mime_type`, the last weight is used. Patterns
+    /// that would be rejected when loading the database, like empty or
+    /// overly complex ones, are ignored.
+    pub fn add_glob(&mut self, mime_type: &Mime, pattern: &str, weight: GlobWeight) -> &mut Self {
+        self.push(mime_type, pattern, weight, false)
+    }
+
+    /// Adds a case sensitive pattern for `mime_type`, with the given
+    /// weight.
+    ///
+    /// See [`add_glob`] for details.
+    ///
+    /// [`add_glob`]: #method.add_glob
+    pub fn add_case_sensitive_glob(
+        &mut self,
+        mime_type: &Mime,
+        pattern: &str,
+        weight: GlobWeight,
+    ) -> &mut Self {
+        self.push(mime_type, pattern, weight, true)
+    }
+
+    fn push(&mut self, mime_type: &Mime, pattern: &str, weight: GlobWeight, cs: bool) -> &mut Self {
+        if let Some(glob) = Glob::checked(mime_type, pattern, weight.get() as i32, cs) {
+            self.globs.push(glob);
+        }
+
+        self
+    }
+
+    /// Creates a new [`GlobMap`] with the patterns added to the builder.
+    ///
+    /// [`GlobMap`]: struct.GlobMap.html
+    pub fn build(&self) -> GlobMap {
+        let mut res = GlobMap::new();
+        for glob in &self.globs {
+            res.replace_glob(glob.clone());
+        }
+
+        res
     }
 }
 
 impl fmt::Debug for GlobMap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut lines = String::new();
-        for glob in &self.globs {
+        for glob in self.iter() {
             lines.push_str(&format!("{:?}", glob));
             lines.push('\n');
         }
@@ -334,10 +1192,135 @@ impl fmt::Debug for GlobMap {
     }
 }
 
+// The glob patterns are encoded as strings in the snapshots, and compiled
+// again when they are restored
+#[cfg(feature = "snapshot")]
+impl serde::Serialize for Glob {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mime_type = crate::binary::MimeRef(&self.mime_type);
+
+        serde::Serialize::serialize(
+            &(
+                self.pattern(),
+                self.weight,
+                self.case_sensitive,
+                mime_type,
+                self.tier,
+            ),
+            s,
+        )
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<'de> serde::Deserialize<'de> for Glob {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Glob, D::Error> {
+        let (pattern, weight, case_sensitive, mime_type, tier): (
+            String,
+            i32,
+            bool,
+            crate::binary::OwnedMime,
+            Tier,
+        ) = serde::Deserialize::deserialize(d)?;
+        let mut glob = Glob::new(&mime_type.0, &pattern, weight, case_sensitive);
+        glob.set_tier(tier);
+
+        Ok(glob)
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::unnecessary_to_owned)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn to_v2_string() {
+        for line in [
+            "50:text/plain:*.txt",
+            "80:text/x-c++src:*.C:cs",
+            "50:text/x-copying:copying",
+        ] {
+            assert_eq!(Glob::from_v2_string(line).unwrap().to_v2_string(), line);
+        }
+
+        #[cfg(feature = "glob")]
+        {
+            let line = "50:text/x-readme:README*";
+            assert_eq!(Glob::from_v2_string(line).unwrap().to_v2_string(), line);
+        }
+    }
+
+    #[test]
+    fn layered_map() {
+        let png = Mime::from_str("image/png").unwrap();
+        let base = Arc::new(
+            GlobMap::builder()
+                .add_glob(&png, "*.png", GlobWeight::DEFAULT)
+                .add_glob(&mime::TEXT_PLAIN, "*.txt", GlobWeight::DEFAULT)
+                .build(),
+        );
+
+        let overlay = Arc::new(GlobMap::layered(
+            &base,
+            vec![
+                Glob::with_weight(&png, "*.png", 10),
+                Glob::with_weight(&png, "*.pic", 50),
+            ],
+        ));
+        assert_eq!(overlay.len(), 3);
+        assert_eq!(
+            overlay.lookup_all("a.png"),
+            vec![(png.clone(), GlobWeight::new(10).unwrap())]
+        );
+        assert_eq!(overlay.lookup("a.pic"), vec![png.clone()]);
+        assert_eq!(overlay.lookup("a.txt"), vec![mime::TEXT_PLAIN]);
+        assert_eq!(
+            base.lookup_all("a.png"),
+            vec![(png.clone(), GlobWeight::DEFAULT)]
+        );
+        assert!(base.lookup("a.pic").is_empty());
+
+        // An overlay of an overlay shares the same base
+        let nested = GlobMap::layered(&overlay, vec![Glob::with_weight(&png, "*.txt", 90)]);
+        assert!(Arc::ptr_eq(nested.base.as_ref().unwrap(), &base));
+        assert_eq!(nested.lookup("a.txt"), vec![png.clone()]);
+        assert_eq!(nested.lookup("a.pic"), vec![png.clone()]);
+
+        // Modifying an overlay copies the globs of the base
+        let mut copy = nested.clone();
+        copy.add_glob(Glob::simple(&mime::TEXT_CSV, "*.csv"));
+        assert!(copy.base.is_none());
+        assert_eq!(copy.len(), 5);
+        assert_eq!(copy.lookup_all("a.png"), nested.lookup_all("a.png"));
+    }
 
+    #[cfg(not(feature = "glob"))]
+    #[test]
+    fn full_patterns_need_glob_feature() {
+        assert_eq!(
+            determine_type("*.gif"),
+            GlobType::Simple(".gif".to_string())
+        );
+        assert_eq!(
+            determine_type("Makefile"),
+            GlobType::Literal("Makefile".to_string())
+        );
+        assert!(Glob::from_v2_string("50:text/x-readme:README*").is_none());
+        assert!(Glob::from_v2_string("50:text/x-csrc:tree.[ch]").is_none());
+    }
+
+    #[cfg(not(feature = "unicase"))]
+    #[test]
+    fn literal_case_folding() {
+        let glob = Glob::simple(&Mime::from_str("text/x-makefile").unwrap(), "Makefile");
+        assert!(glob.compare("makefile"));
+        assert!(glob.compare("MAKEFILE"));
+        assert!(!glob.compare("Makefile.am"));
+    }
+
+    #[cfg(feature = "glob")]
     #[test]
     fn glob_type() {
         assert_eq!(
@@ -346,11 +1329,11 @@ mod tests {
         );
         assert_eq!(
             determine_type("Foo*.gif"),
-            GlobType::Full(Pattern::new("Foo*.gif").unwrap())
+            GlobType::Full(Arc::new(Pattern::new("Foo*.gif").unwrap()))
         );
         assert_eq!(
             determine_type("*[4].gif"),
-            GlobType::Full(Pattern::new("*[4].gif").unwrap())
+            GlobType::Full(Arc::new(Pattern::new("*[4].gif").unwrap()))
         );
         assert_eq!(
             determine_type("Makefile"),
@@ -358,14 +1341,30 @@ mod tests {
         );
         assert_eq!(
             determine_type("sldkfjvlsdf\\\\slkdjf"),
-            GlobType::Full(Pattern::new("sldkfjvlsdf\\\\slkdjf").unwrap())
+            GlobType::Full(Arc::new(Pattern::new("sldkfjvlsdf\\\\slkdjf").unwrap()))
         );
         assert_eq!(
             determine_type("tree.[ch]"),
-            GlobType::Full(Pattern::new("tree.[ch]").unwrap())
+            GlobType::Full(Arc::new(Pattern::new("tree.[ch]").unwrap()))
         );
     }
 
+    #[cfg(feature = "glob")]
+    #[test]
+    fn shared_patterns() {
+        let a = Glob::from_v2_string("50:text/x-chdr:cache-test.[ch]").unwrap();
+        let b = Glob::from_v2_string("50:text/x-csrc:cache-test.[ch]").unwrap();
+
+        match (&a.glob, &b.glob) {
+            (GlobType::Full(p), GlobType::Full(q)) => assert!(Arc::ptr_eq(p, q)),
+            _ => panic!("unexpected glob types"),
+        }
+
+        assert!(a.compare("cache-test.h"));
+        assert!(!a.compare("cache-test.o"));
+        assert!(Glob::from_v2_string("50:text/x-csrc:cache-test.[ch").is_none());
+    }
+
     #[test]
     fn glob_v1_string() {
         assert_eq!(
@@ -438,6 +1437,7 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "glob")]
     #[test]
     fn compare() {
         // Literal
@@ -472,4 +1472,232 @@ mod tests {
         assert_eq!(video_x_anim.compare(&"foo.animk".to_string()), false);
         assert_eq!(video_x_anim.compare(&"foo.animj".to_string()), true);
     }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn reject_pathological_patterns() {
+        let long_pattern = format!("*.{}", "x".repeat(MAX_PATTERN_LEN));
+        assert_eq!(
+            Glob::from_v2_string(&format!("50:text/plain:{}", long_pattern)),
+            None
+        );
+        assert_eq!(Glob::from_v2_string("50:text/plain:*a*a*a*a*a*b"), None);
+        assert_eq!(Glob::from_v2_string("50:text/plain:[abc"), None);
+        assert_eq!(Glob::from_v1_string("text/plain:[abc"), None);
+        assert!(Glob::from_v2_string("50:text/plain:*.so.[0-9]*").is_some());
+    }
+
+    #[test]
+    fn huge_file_names() {
+        let mut globs = GlobMap::new();
+        globs.add_glob(Glob::new(&mime::TEXT_PLAIN, "*.txt", 50, false));
+        globs.add_glob(Glob::new(&mime::TEXT_HTML, "*a*a*a*b", 60, false));
+        globs.add_glob(Glob::new(&mime::TEXT_CSS, "README*", 70, false));
+        globs.add_glob(Glob::new(&mime::TEXT_XML, "aaaa", 80, false));
+
+        // A multi-megabyte name, crafted to make backtracking matchers
+        // explode; only the tail is taken into account
+        let mut name = "a".repeat(8 * 1024 * 1024);
+        name.push_str(".TXT");
+        assert_eq!(
+            globs.lookup_mime_type_for_file_name(&name),
+            Some(vec![mime::TEXT_PLAIN])
+        );
+
+        // Patterns anchored at the start of the name never match
+        // truncated names, and neither do literals
+        let name = format!("README{}", "a".repeat(MAX_FILE_NAME_LEN));
+        assert_eq!(globs.lookup_mime_type_for_file_name(&name), None);
+
+        // Multi-byte characters straddling the truncation point
+        let name = format!("{}.txt", "\u{00e9}".repeat(MAX_FILE_NAME_LEN));
+        assert_eq!(
+            globs.lookup_mime_type_for_file_name(&name),
+            Some(vec![mime::TEXT_PLAIN])
+        );
+    }
+
+    #[test]
+    fn glob_map_builder() {
+        let weight = |w| GlobWeight::new(w).unwrap();
+        let globs = GlobMap::builder()
+            .add_glob(&mime::TEXT_PLAIN, "*.txt", weight(50))
+            .add_glob(&mime::TEXT_PLAIN, "*.txt", weight(20))
+            .add_glob(&mime::TEXT_CSV, "*.txt", weight(40))
+            .add_case_sensitive_glob(&mime::TEXT_CSS, "*.CSS", weight(50))
+            .add_glob(&mime::TEXT_HTML, "", weight(50))
+            .build();
+
+        assert_eq!(globs.len(), 3);
+        assert_eq!(globs.lookup("a.txt"), vec![mime::TEXT_CSV]);
+        assert_eq!(
+            globs.lookup_all("a.txt"),
+            vec![(mime::TEXT_CSV, weight(40)), (mime::TEXT_PLAIN, weight(20))]
+        );
+        assert_eq!(globs.lookup("a.CSS"), vec![mime::TEXT_CSS]);
+        assert!(globs.lookup("a.css").is_empty());
+        assert!(globs.lookup_all("a.html").is_empty());
+        assert_eq!(globs.patterns_for_mime_type(&mime::TEXT_CSS), vec!["*.CSS"]);
+        assert!(GlobMap::new().is_empty());
+    }
+
+    // A xorshift generator, so that the randomized tests below are
+    // reproducible without additional dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[(self.next() % items.len() as u64) as usize]
+        }
+    }
+
+    fn random_globs(rng: &mut Rng) -> Vec<Glob> {
+        let mimes = [
+            "text/plain",
+            "text/x-a",
+            "text/x-b",
+            "image/x-c",
+            "application/x-d",
+        ];
+        let mut patterns = vec![
+            "*.gz",
+            "*.tar.gz",
+            "*.z",
+            "*.tgz",
+            "*.c",
+            "*.C",
+            "foo.tar.gz",
+            "foo.c",
+            "makefile",
+        ];
+        if cfg!(feature = "glob") {
+            patterns.extend(["foo*", "*.tar.?z", "f*.c", "[fm]*"]);
+        }
+        let weights = [0, 10, 50, 50, 80, 100];
+
+        let mut globs: Vec<Glob> = (0..1 + rng.next() % 12)
+            .map(|_| {
+                let mime = *rng.pick(&mimes);
+                let mime = Mime::from_str(mime).unwrap();
+                let pattern = rng.pick(&patterns);
+                let cs = rng.next() % 4 == 0;
+                Glob::new(&mime, pattern, *rng.pick(&weights), cs)
+            })
+            .collect();
+
+        // Only the first glob for each pattern and MIME type is kept when
+        // loading, so generate unique pairs to make the order irrelevant
+        let mut seen = HashSet::new();
+        globs.retain(|glob| seen.insert(glob.clone()));
+        globs
+    }
+
+    #[test]
+    fn no_globs_marker() {
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+
+        let marker = Glob::from_v2_string("50:text/x-foo:__NOGLOBS__").unwrap();
+        assert!(marker.is_no_globs());
+        assert!(!Glob::from_v2_string("50:text/x-foo:__noglobs__")
+            .unwrap()
+            .is_no_globs());
+
+        // The marker only applies to the globs added afterwards
+        let mut map = GlobMap::new();
+        map.add_globs(&[marker, Glob::simple(&foo, "*.foo2")]);
+        map.add_globs(&[Glob::simple(&foo, "*.foo"), Glob::simple(&bar, "*.bar")]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.lookup("a.foo2"), vec![foo.clone()]);
+        assert!(map.lookup("a.foo").is_empty());
+        assert!(map.lookup("__NOGLOBS__").is_empty());
+        assert_eq!(map.lookup("a.bar"), vec![bar]);
+    }
+
+    #[test]
+    fn ranked_lookup_ordering() {
+        let names = [
+            "foo.tar.gz",
+            "FOO.TAR.GZ",
+            "foo.c",
+            "foo.C",
+            "makefile",
+            "bar.z",
+            "BAR.Z",
+            "x",
+        ];
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..500 {
+            let globs = random_globs(&mut rng);
+            let mut map = GlobMap::new();
+            map.add_globs(&globs);
+
+            // The same globs, inserted in reverse order
+            let mut reversed = GlobMap::new();
+            for glob in globs.iter().rev() {
+                reversed.add_glob(glob.clone());
+            }
+            let batch = map.batch_lookup();
+
+            for name in names {
+                let file_name = FileName::new(name);
+                let mut matching: Vec<&Glob> =
+                    map.globs.iter().filter(|g| g.matches(&file_name)).collect();
+
+                let best = map.lookup_mime_type_for_file_name(name);
+                assert_eq!(best, reversed.lookup_mime_type_for_file_name(name));
+                assert_eq!(best, map.lookup_mime_type_for_file_name(name));
+                assert_eq!(
+                    best.as_ref().map(|v| &v[0]),
+                    map.first_mime_type_for_file_name(name)
+                );
+                assert_eq!(
+                    best.as_ref().map(|v| &v[0]),
+                    batch.lookup(name).map(|idx| batch.mime_type(idx))
+                );
+
+                let all = map.lookup_all_mime_types_for_file_name(name);
+                assert_eq!(all, reversed.lookup_all_mime_types_for_file_name(name));
+                assert_eq!(all.len(), {
+                    let mut mimes: Vec<&Mime> = matching.iter().map(|g| &g.mime_type).collect();
+                    mimes.sort();
+                    mimes.dedup();
+                    mimes.len()
+                });
+                assert!(all.windows(2).all(|w| w[0].1 >= w[1].1));
+
+                let best = match best {
+                    Some(v) => v,
+                    None => {
+                        assert!(matching.is_empty());
+                        continue;
+                    }
+                };
+
+                // Only the globs with the biggest weight, and the longest
+                // pattern among those, are kept, sorted by MIME type
+                let key = |g: &Glob| (g.weight, g.pattern_len());
+                let top = matching.iter().map(|g| key(g)).max().unwrap();
+                matching.retain(|g| key(g) == top);
+                let mut expected: Vec<Mime> =
+                    matching.iter().map(|g| g.mime_type.clone()).collect();
+                expected.sort();
+                expected.dedup();
+                assert_eq!(best, expected);
+
+                // The first entry of the full ranking agrees with the best
+                // match, and the weights never go up
+                assert_eq!(all[0].1, top.0);
+                assert!(best.contains(&all[0].0));
+            }
+        }
+    }
 }
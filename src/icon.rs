@@ -1,16 +1,22 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 use mime::Mime;
 
+use crate::diagnostics::ParseContext;
+use crate::special;
+use crate::tier::Tier;
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct Icon {
     icon_name: String,
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     mime_type: Mime,
+    tier: Tier,
 }
 
 impl fmt::Debug for Icon {
@@ -24,12 +30,29 @@ impl Icon {
         Icon {
             icon_name: icon_name.to_string(),
             mime_type: mime_type.clone(),
+            tier: Tier::System,
         }
     }
 
+    pub fn icon_name(&self) -> &str {
+        &self.icon_name
+    }
+
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime_type
+    }
+
+    pub fn tier(&self) -> Tier {
+        self.tier
+    }
+
+    pub fn set_tier(&mut self, tier: Tier) {
+        self.tier = tier;
+    }
+
     pub fn from_string(s: &str) -> Option<Icon> {
         let mut chunks = s.split(':').fuse();
-        let mime_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
         let icon_name = chunks.next().filter(|s| !s.is_empty())?;
 
         // Consume the leftovers, if any
@@ -40,24 +63,25 @@ impl Icon {
         Some(Icon {
             icon_name: icon_name.to_string(),
             mime_type,
+            tier: Tier::System,
         })
     }
 }
 
-pub fn read_icons_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Icon> {
-    let f = match File::open(file_name) {
+pub fn read_icons_from_file<P: AsRef<Path>>(file_name: P, ctx: &mut ParseContext) -> Vec<Icon> {
+    let f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return Vec::new(),
     };
 
     let mut res = Vec::new();
     let file = BufReader::new(&f);
-    for line in file.lines() {
+    for (n, line) in file.lines().enumerate() {
         if line.is_err() {
             return res; // FIXME: return error instead
         }
 
-        let line = line.unwrap();
+        let line = ctx.normalize_line(&line.unwrap(), ':');
 
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -65,7 +89,12 @@ pub fn read_icons_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Icon> {
 
         match Icon::from_string(&line) {
             Some(v) => res.push(v),
-            None => continue,
+            None => ctx.skip_entry(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split(':').take(1),
+            ),
         }
     }
 
@@ -74,7 +103,11 @@ pub fn read_icons_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Icon> {
     res
 }
 
-pub fn read_icons_from_dir<P: AsRef<Path>>(dir: P, generic: bool) -> Vec<Icon> {
+pub fn read_icons_from_dir<P: AsRef<Path>>(
+    dir: P,
+    generic: bool,
+    ctx: &mut ParseContext,
+) -> Vec<Icon> {
     let mut icons_file = PathBuf::new();
     icons_file.push(dir);
 
@@ -84,22 +117,60 @@ pub fn read_icons_from_dir<P: AsRef<Path>>(dir: P, generic: bool) -> Vec<Icon> {
         icons_file.push("icons");
     }
 
-    read_icons_from_file(icons_file)
+    read_icons_from_file(icons_file, ctx)
+}
+
+// The icons of the MIME types, with a single icon for each of them, sorted
+// by MIME type
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IconMap {
+    icons: BTreeMap<Mime, Icon>,
 }
 
-pub fn find_icon(icons: &[Icon], mime_type: &Mime) -> Option<String> {
-    for icon in icons {
-        if icon.mime_type == *mime_type {
-            return Some(icon.icon_name.clone());
+impl IconMap {
+    pub fn new() -> IconMap {
+        IconMap::default()
+    }
+
+    // Adds the icons of a directory after the ones of the directories
+    // with a higher precedence, in which the directory of the user comes
+    // before the ones of the system; the icons of the MIME types that
+    // already have one are ignored, like the later definitions of a MIME
+    // type in the same directory
+    pub fn add_icons(&mut self, icons: Vec<Icon>) {
+        for icon in icons {
+            self.icons.entry(icon.mime_type.clone()).or_insert(icon);
         }
     }
 
-    None
+    pub fn find_icon(&self, mime_type: &Mime) -> Option<&Icon> {
+        self.icons.get(mime_type)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Icon> {
+        self.icons.values()
+    }
+}
+
+pub fn find_icon(icons: &IconMap, mime_type: &Mime) -> Option<String> {
+    icons
+        .find_icon(mime_type)
+        .map(|icon| icon.icon_name.clone())
+}
+
+// Icons are sorted by MIME type
+pub fn write_icons<W: Write>(icons: &IconMap, w: &mut W) -> io::Result<()> {
+    for icon in icons.iter() {
+        writeln!(w, "{}:{}", icon.mime_type, icon.icon_name)?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     #[test]
     fn from_str() {
@@ -109,6 +180,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn icon_precedence() {
+        let png = Mime::from_str("image/png").unwrap();
+        let svg = Mime::from_str("image/svg+xml").unwrap();
+
+        let mut user = Icon::new("user-png", &png);
+        user.set_tier(Tier::User);
+        let mut icons = IconMap::new();
+        icons.add_icons(vec![user, Icon::new("user-png-again", &png)]);
+        icons.add_icons(vec![Icon::new("system-png", &png), Icon::new("svg", &svg)]);
+
+        assert_eq!(find_icon(&icons, &png).as_deref(), Some("user-png"));
+        assert_eq!(icons.find_icon(&png).unwrap().tier(), Tier::User);
+        assert_eq!(icons.find_icon(&svg).unwrap().tier(), Tier::System);
+
+        let mut out = Vec::new();
+        write_icons(&icons, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "image/png:user-png\nimage/svg+xml:svg\n"
+        );
+    }
+
     #[test]
     fn from_str_catches_syntax_error() {
         assert!(Icon::from_string("one:two:three").is_none());
@@ -18,16 +18,28 @@
 //! load all the instances of shared MIME databases available in the following
 //! directories, in this specified order:
 //!
+//!  - `$XDG_MIME_OVERRIDE_DIRS/mime`, if set
 //!  - `$XDG_DATA_HOME/mime`
 //!    - if `XDG_DATA_HOME` is unset, this corresponds to `$HOME/.local/share/mime`
 //!  - `$XDG_DATA_DIRS/mime`
 //!    - if `XDG_DATA_DIRS` is unset, this corresponds to `/usr/local/share/mime`
 //!      and `/usr/share/mime`
 //!
+//!  - `$XDG_MIME_EXTRA_DIRS/mime`, if set
+//!  - the data directories of the host system, when running inside a
+//!    [Flatpak][flatpak] or [Snap][snap] sandbox; for instance,
+//!    `/run/host/usr/share/mime`
+//!
 //! For more information on the `XDG_DATA_HOME` and `XDG_DATA_DIRS` environment
-//! variables, see the [XDG base directory specification][xdg-basedir].
+//! variables, see the [XDG base directory specification][xdg-basedir]. The
+//! `XDG_MIME_OVERRIDE_DIRS` and `XDG_MIME_EXTRA_DIRS` environment variables
+//! use the same format as `XDG_DATA_DIRS`, and can be used to inject custom
+//! MIME data, with the highest and the lowest precedence respectively,
+//! without changing the data directories of the whole process.
 //!
 //! [xdg-basedir]: https://specifications.freedesktop.org/basedir-spec/latest/
+//! [flatpak]: https://flatpak.org
+//! [snap]: https://snapcraft.io
 //!
 //! The MIME data in each directory will be coalesced into a single database.
 //!
@@ -62,1134 +74,7344 @@
 //! [`GuessBuilder`]: struct.GuessBuilder.html
 //! [`guess_mime_type`]: struct.SharedMimeInfo.html#method.guess_mime_type
 //! [`guess`]: struct.GuessBuilder.html#method.guess
+//!
+//! ## Memory mapped caches
+//!
+//! With the optional `mmap` feature, the compiled `mime.cache` files are
+//! mapped in memory, instead of being read, so that their pages are shared
+//! between processes, and only the ones used by the lookups are read.
+//!
+//! `update-mime-database` writes a new `mime.cache` file, and renames it
+//! over the old one, so a mapped file is never modified. However, like
+//! with the xdgmime C library, a `mime.cache` file truncated in place by
+//! another tool while it is mapped makes the lookups crash the process
+//! with `SIGBUS`, instead of failing; without the feature, the files are
+//! read in memory, and a truncated file is rejected when it is loaded.
+//!
+//! ## Events
+//!
+//! With the optional `events` feature, the database logs why a file may
+//! resolve to an unexpected type on a given system, using the [`log`]
+//! crate, with the `xdg_mime::events` target:
+//!
+//!  - every `mime` directory loaded, at the debug level, with its `path`,
+//!    the compiled file it was read from as `source`, if any, and the
+//!    `elapsed` time
+//!  - every file of a `mime` directory that exists, but is not used, like
+//!    a `mime.cache` file that cannot be parsed, at the info level, with
+//!    its `path` and the `reason`
+//!  - every entry that cannot be parsed, at the warn level; the same
+//!    problems are reported by the [`diagnostics`] of the database
+//!  - every match of some data against the magic entries that took longer
+//!    than the threshold set using [`set_slow_magic_threshold`], at the
+//!    debug level, with the `len` of the data, the matching `mime_type`, if
+//!    any, and the `elapsed` time
+//!
+//! [`log`]: https://docs.rs/log
+//! [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+//! [`set_slow_magic_threshold`]: fn.set_slow_magic_threshold.html
 
 use mime::Mime;
-use std::env;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 extern crate dirs_next;
 extern crate nom;
 
+mod agreement;
 mod alias;
+mod audit;
+mod backup;
+mod basedir;
+#[cfg(feature = "snapshot")]
+mod binary;
+mod cache;
+mod comment;
+mod concurrent;
+mod conformance;
+pub mod corpus;
+mod data_uri;
+mod debounce;
+mod diagnostics;
+mod error;
+#[cfg(feature = "events")]
+mod events;
+mod evidence;
 mod glob;
 mod icon;
+#[cfg(feature = "libmagic")]
+mod libmagic;
+mod loading;
 mod magic;
+#[cfg(feature = "message")]
+mod message;
+mod namespace;
+mod overlay;
+mod package;
 mod parent;
+mod preview;
+mod priority;
+mod record;
+#[cfg(feature = "hashes")]
+mod snapshot;
+mod sniffer;
+mod special;
+#[cfg(feature = "special-names")]
+mod special_names;
+mod stack;
+pub mod testing;
+mod text;
+#[cfg(feature = "thumbnailer")]
+pub mod thumbnailer;
+mod tier;
+mod timings;
+mod treemagic;
+mod types;
+#[cfg(feature = "watch")]
+mod watch;
+mod xml;
+
+pub use agreement::Agreement;
+pub use audit::Finding;
+pub use backup::BackupHandling;
+pub use concurrent::SyncSharedMimeInfo;
+pub use conformance::Conformance;
+pub use debounce::{Coalescing, Debounce};
+pub use diagnostics::Diagnostic;
+pub use error::Error;
+#[cfg(feature = "events")]
+pub use events::set_slow_magic_threshold;
+pub use evidence::Evidence;
+pub use glob::{
+    GenericGlobs, GlobMap, GlobMapBuilder, GlobMatch, GlobRef, MAX_FILE_NAME_LEN, MAX_PATTERN_LEN,
+    MAX_PATTERN_STARS,
+};
+#[cfg(feature = "libmagic")]
+pub use libmagic::{CrossCheck, LibMagic, Parity};
+pub use loading::Loading;
+pub use magic::MagicDatabase;
+#[cfg(feature = "message")]
+pub use message::MessagePart;
+pub use overlay::Overlay;
+pub use preview::{PreviewDecision, PreviewPolicy};
+pub use priority::{GlobWeight, MagicPriority};
+pub use record::MimeTypeRecord;
+pub use sniffer::Sniffer;
+pub use special::{
+    APPLICATION_X_DESKTOP, APPLICATION_X_EXECUTABLE, APPLICATION_X_ZEROSIZE, INODE,
+    INODE_BLOCKDEVICE, INODE_CHARDEVICE, INODE_DIRECTORY, INODE_FIFO, INODE_SOCKET, INODE_SYMLINK,
+    X_CONTENT,
+};
+pub use stack::DatabaseStack;
+pub use text::StreamKind;
+pub use tier::Tier;
+pub use timings::GuessTimings;
+#[cfg(feature = "watch")]
+pub use watch::{WatchMode, Watcher};
+
+use text::looks_like_text;
+use timings::timed;
+
+// The MIME data of a directory, read from the mime.cache file, from the
+// plain text files, or from the source XML files
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+struct MimeData {
+    aliases: Vec<alias::Alias>,
+    subclasses: Vec<parent::Subclass>,
+    icons: Vec<icon::Icon>,
+    generic_icons: Vec<icon::Icon>,
+    globs: Vec<glob::Glob>,
+    magic: Vec<magic::MagicEntry>,
+    treemagic: Vec<treemagic::TreeMagicEntry>,
+    namespaces: Vec<namespace::XmlNamespace>,
+    #[cfg_attr(feature = "snapshot", serde(with = "binary::mime_list"))]
+    types: Vec<Mime>,
+    // Only read from the source XML files; the compiled directories have
+    // a separate definition for each MIME type
+    #[cfg_attr(feature = "snapshot", serde(with = "binary::comment_list"))]
+    comments: Vec<(Mime, comment::Comments)>,
+}
+
+// The parts of the MIME data loaded from each directory, as chosen on the
+// builder; the aliases, subclasses, namespaces and types are always loaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+struct Subsystems {
+    globs: bool,
+    magic: bool,
+    treemagic: bool,
+    icons: bool,
+}
+
+impl Default for Subsystems {
+    fn default() -> Self {
+        Subsystems {
+            globs: true,
+            magic: true,
+            treemagic: true,
+            icons: true,
+        }
+    }
+}
+
+// The files written by SharedMimeInfo::export_snapshot(), sorted by name,
+// with the method writing the contents of each one
+#[cfg(feature = "hashes")]
+type SnapshotWriter = fn(&SharedMimeInfo, &mut Vec<u8>) -> io::Result<()>;
+#[cfg(feature = "hashes")]
+const SNAPSHOT_FILES: [(&str, SnapshotWriter); 9] = [
+    ("XMLnamespaces", SharedMimeInfo::write_namespaces),
+    ("aliases", SharedMimeInfo::write_aliases),
+    ("generic-icons", SharedMimeInfo::write_generic_icons),
+    ("globs2", SharedMimeInfo::write_globs),
+    ("icons", SharedMimeInfo::write_icons),
+    ("magic", SharedMimeInfo::write_magic),
+    ("subclasses", SharedMimeInfo::write_subclasses),
+    ("treemagic", SharedMimeInfo::write_treemagic),
+    ("types", SharedMimeInfo::write_types),
+];
+
+// The MIME data read from a directory, or from a mime.types file, before
+// it is merged with the other directories; it is kept, so that reloading
+// the database only reads the directories that changed again
+#[derive(Default)]
+struct Segment {
+    data: MimeData,
+    // The compiled cache or the packages directory the data was read from,
+    // if it was not read from the plain text files
+    source_path: Option<PathBuf>,
+    globs_path: PathBuf,
+    // Unset if the magic entries are not loaded
+    magic: Option<Arc<magic::MagicSegment>>,
+    // The problems found while reading the data
+    diagnostics: Vec<Diagnostic>,
+}
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 struct MimeDirectory {
     path: PathBuf,
-    mtime: SystemTime,
+    // Unset if the directory did not exist when it was loaded
+    mtime: Option<SystemTime>,
+    tier: Tier,
+    // The version of shared-mime-info that compiled the directory, if any
+    version: Option<String>,
+    // The comments from the source XML files, if the directory was never
+    // compiled
+    comments: Arc<HashMap<Mime, comment::Comments>>,
+    segment: Arc<Segment>,
+}
+
+impl MimeDirectory {
+    fn new(path: PathBuf, tier: Tier) -> MimeDirectory {
+        let mtime = MimeDirectory::current_mtime(&path);
+
+        MimeDirectory {
+            path,
+            mtime,
+            tier,
+            version: None,
+            comments: Arc::new(HashMap::new()),
+            segment: Arc::default(),
+        }
+    }
+
+    // The version file written by update-mime-database, with a single line
+    fn read_version(path: &Path) -> Option<String> {
+        let contents = fs::read_to_string(path.join("version")).ok()?;
+        let version = contents.lines().next()?.trim();
+
+        (!version.is_empty()).then(|| version.to_string())
+    }
+
+    fn current_mtime(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path)
+            .ok()
+            .map(|v| v.modified().unwrap_or(SystemTime::UNIX_EPOCH))
+    }
+
+    // Whether the directory was changed, created, or removed since the
+    // last time we looked into it
+    fn is_changed(&self) -> bool {
+        MimeDirectory::current_mtime(&self.path) != self.mtime
+    }
 }
 
 /// The shared MIME info database.
+///
+/// Cloning the database is cheap, since the loaded MIME data is shared
+/// between the clones, and only copied when one of them is modified, for
+/// instance by [adding a directory]; each worker thread can own a clone,
+/// while a coordinator [reloads] the database, and hands out new clones:
+///
+/// ```rust
+/// use std::thread;
+///
+/// let mut mime_db = xdg_mime::SharedMimeInfo::new();
+///
+/// let worker = {
+///     let mime_db = mime_db.clone();
+///     thread::spawn(move || mime_db.get_mime_types_from_file_name("file.txt"))
+/// };
+///
+/// mime_db.reload();
+/// assert_eq!(worker.join().unwrap(), vec![mime::TEXT_PLAIN]);
+/// ```
+///
+/// [adding a directory]: #method.add_directory
+/// [reloads]: #method.reload
+#[derive(Clone)]
 pub struct SharedMimeInfo {
-    aliases: alias::AliasesList,
-    parents: parent::ParentsMap,
-    icons: Vec<icon::Icon>,
-    generic_icons: Vec<icon::Icon>,
-    globs: glob::GlobMap,
-    magic: Vec<magic::MagicEntry>,
+    aliases: Arc<alias::AliasesList>,
+    parents: Arc<parent::ParentsMap>,
+    icons: Arc<icon::IconMap>,
+    generic_icons: Arc<icon::IconMap>,
+    globs: Arc<glob::GlobMap>,
+    magic: Arc<magic::DeferredMagic>,
+    treemagic: Arc<Vec<treemagic::TreeMagicEntry>>,
+    namespaces: Arc<Vec<namespace::XmlNamespace>>,
+    // Sorted by name
+    types: Arc<Vec<Mime>>,
     mime_dirs: Vec<MimeDirectory>,
+    mime_types_files: Vec<MimeDirectory>,
+    unknown_type: Mime,
+    lenient: bool,
+    strict: bool,
+    conformance: Conformance,
+    generic_globs: GenericGlobs,
+    #[cfg(feature = "special-names")]
+    special_names: bool,
+    subclass_depth_limit: usize,
+    ancestors: Option<Arc<parent::Ancestors>>,
+    load: Subsystems,
+    diagnostics: Arc<Vec<Diagnostic>>,
+    // The diagnostics of the loading, followed by the ones of the magic
+    // entries, which are only parsed when needed
+    all_diagnostics: Arc<OnceLock<Vec<Diagnostic>>>,
 }
 
-/// A builder type to specify the parameters for guessing a MIME type.
-///
-/// Each instance of `GuessBuilder` is tied to the lifetime of the
-/// [`SharedMimeInfo`] instance that created it.
-///
-/// The `GuessBuilder` returned by the [`guess_mime_type`] method is
-/// empty, and will always return a `mime::APPLICATION_OCTET_STREAM`
-/// guess.
+/// A builder type to specify the directories used to load a
+/// [`SharedMimeInfo`] instance.
 ///
-/// You can use the builder methods to specify the file name, the data,
-/// or both, to be used to guess the MIME type:
+/// By default, the builder will use the same directories as the
+/// [`new`] method; applications running inside a container can override
+/// them, for instance to point at the MIME data of the host system:
 ///
 /// ```rust
-/// # use std::error::Error;
-/// # use std::str::FromStr;
-/// # use mime::Mime;
-/// #
-/// # fn main() -> Result<(), Box<dyn Error>> {
-/// # let mime_db = xdg_mime::SharedMimeInfo::new();
-/// // let mime_db = ...
-/// let mut guess_builder = mime_db.guess_mime_type();
-/// let guess = guess_builder.file_name("foo.png").guess();
-/// assert_eq!(guess.mime_type(), &Mime::from_str("image/png")?);
-/// #
-/// # Ok(())
-/// # }
+/// let mime_db = xdg_mime::SharedMimeInfo::builder()
+///     .data_home("/var/lib/app/share")
+///     .data_dirs(["/run/host/usr/share", "/usr/share"])
+///     .build();
 /// ```
 ///
-/// The guessed MIME type can have a degree of uncertainty; for instance,
-/// if you only set the [`file_name`] there can be multiple matching MIME
-/// types to choose from. Alternatively, if you only set the [`data`], the
-/// content might not match any existing rule. Even in the case of setting
-/// both the file name and the data the match can be uncertain. This
-/// information is preserved by the [`Guess`] type, and can be retrieved
-/// using the [`uncertain`] method.
-///
 /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
-/// [`guess_mime_type`]: struct.SharedMimeInfo.html#method.guess_mime_type
-/// [`file_name`]: #method.file_name
-/// [`data`]: #method.data
-/// [`Guess`]: struct.Guess.html
-/// [`uncertain`]: struct.Guess.html#method.uncertain
-pub struct GuessBuilder<'a> {
-    db: &'a SharedMimeInfo,
-    file_name: Option<String>,
-    data: Vec<u8>,
-    metadata: Option<fs::Metadata>,
-    path: Option<PathBuf>,
-    zero_size: bool,
+/// [`new`]: struct.SharedMimeInfo.html#method.new
+#[derive(Clone, Debug)]
+pub struct SharedMimeInfoBuilder {
+    data_home: Option<PathBuf>,
+    data_dirs: Option<Vec<PathBuf>>,
+    override_dirs: Option<Vec<PathBuf>>,
+    extra_dirs: Option<Vec<PathBuf>>,
+    detect_sandbox: bool,
+    mime_types_files: Vec<PathBuf>,
+    user_mime_types: bool,
+    unknown_type: Option<Mime>,
+    lenient: bool,
+    strict: bool,
+    conformance: Conformance,
+    generic_globs: GenericGlobs,
+    #[cfg(feature = "special-names")]
+    special_names: bool,
+    subclass_depth_limit: usize,
+    precompute_ancestors: bool,
+    load: Subsystems,
+    default_dirs: bool,
 }
 
-/// The result of the [`guess`] method of [`GuessBuilder`].
-///
-/// [`guess`]: struct.GuessBuilder.html#method.guess
-/// [`GuessBuilder`]: struct.GuessBuilder.html
-pub struct Guess {
-    mime: mime::Mime,
-    uncertain: bool,
+impl Default for SharedMimeInfoBuilder {
+    fn default() -> Self {
+        SharedMimeInfoBuilder {
+            data_home: None,
+            data_dirs: None,
+            override_dirs: None,
+            extra_dirs: None,
+            detect_sandbox: true,
+            mime_types_files: Vec::new(),
+            user_mime_types: false,
+            unknown_type: None,
+            lenient: false,
+            strict: false,
+            conformance: Conformance::default(),
+            generic_globs: GenericGlobs::default(),
+            #[cfg(feature = "special-names")]
+            special_names: false,
+            subclass_depth_limit: parent::DEFAULT_DEPTH_LIMIT,
+            precompute_ancestors: false,
+            load: Subsystems::default(),
+            default_dirs: true,
+        }
+    }
 }
 
-impl<'a> GuessBuilder<'a> {
-    /// Sets the file name to be used to guess its MIME type.
-    ///
-    /// If you have a full path, you should extract the last component,
-    /// for instance using the [`Path::file_name()`][path_file_name]
-    /// method.
-    ///
-    /// [path_file_name]: https://doc.rust-lang.org/std/path/struct.Path.html#method.file_name
-    pub fn file_name(&mut self, name: &str) -> &mut Self {
-        self.file_name = Some(name.to_string());
+impl SharedMimeInfoBuilder {
+    /// Overrides the `$XDG_DATA_HOME` directory.
+    pub fn data_home<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.data_home = Some(dir.as_ref().to_path_buf());
 
         self
     }
 
-    /// Sets the data for which you want to guess the MIME type.
-    pub fn data(&mut self, data: &[u8]) -> &mut Self {
-        // If we have enough data, just copy the largest chunk
-        // necessary to match any rule in the magic entries
-        let max_data_size = magic::max_extents(&self.db.magic);
-        if data.len() > max_data_size {
-            self.data.extend_from_slice(&data[..max_data_size]);
-        } else {
-            self.data.extend(data.iter().cloned());
-        }
+    /// Overrides the `$XDG_DATA_DIRS` directories, in order of precedence.
+    pub fn data_dirs<I, P>(&mut self, dirs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.data_dirs = Some(dirs.into_iter().map(|d| d.as_ref().to_path_buf()).collect());
 
         self
     }
 
-    /// Sets the metadata of the file for which you want to get the MIME type.
+    /// Overrides the `$XDG_MIME_OVERRIDE_DIRS` directories, loaded before
+    /// `$XDG_DATA_HOME`, in order of precedence.
     ///
-    /// The metadata can be used to match an existing file or path, for instance:
+    /// The MIME data in these directories takes precedence over the data
+    /// in every other directory.
+    pub fn override_dirs<I, P>(&mut self, dirs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.override_dirs = Some(dirs.into_iter().map(|d| d.as_ref().to_path_buf()).collect());
+
+        self
+    }
+
+    /// Overrides the `$XDG_MIME_EXTRA_DIRS` directories, loaded after
+    /// `$XDG_DATA_DIRS`, in order of precedence.
+    pub fn extra_dirs<I, P>(&mut self, dirs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.extra_dirs = Some(dirs.into_iter().map(|d| d.as_ref().to_path_buf()).collect());
+
+        self
+    }
+
+    /// Sets whether the data directories of the host system should be
+    /// added when running inside a Flatpak or Snap sandbox.
     ///
-    /// ```rust
-    /// # use std::error::Error;
-    /// use std::fs;
-    /// use std::str::FromStr;
-    /// use mime::Mime;
-    /// #
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
-    /// // let mime_db = ...
-    /// # let metadata = fs::metadata("src/lib.rs")?;
-    /// // let metadata = fs::metadata("/path/to/lib.rs")?;
-    /// let mut guess_builder = mime_db.guess_mime_type();
-    /// let guess = guess_builder
-    ///     .file_name("lib.rs")
-    ///     .metadata(metadata)
-    ///     .guess();
-    /// assert_eq!(guess.mime_type(), &Mime::from_str("text/rust")?);
-    /// #
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn metadata(&mut self, metadata: fs::Metadata) -> &mut Self {
-        self.metadata = Some(metadata);
+    /// Defaults to true.
+    pub fn detect_sandbox(&mut self, detect: bool) -> &mut Self {
+        self.detect_sandbox = detect;
 
         self
     }
 
-    /// Sets the path of the file for which you want to get the MIME type.
+    /// Adds files in the Apache `mime.types` format, like
+    /// `/etc/mime.types`, to the sources of glob patterns, in order of
+    /// precedence.
     ///
-    /// The `path` will be used by the [`guess`] method to extract the
-    /// file name, metadata, and contents, unless you called the [`file_name`],
-    /// [`metadata`], and [`data`] methods, respectively.
+    /// Each line of these files contains a MIME type followed by a list of
+    /// extensions; every extension is added as a case insensitive `*.ext`
+    /// pattern, with the default weight of 50. The files are loaded after
+    /// all the MIME directories.
     ///
     /// ```rust
-    /// # use std::error::Error;
-    /// use std::fs;
-    /// use std::str::FromStr;
-    /// use mime::Mime;
-    /// #
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
-    /// // let mime_db = ...
-    /// let mut guess_builder = mime_db.guess_mime_type();
-    /// let guess = guess_builder
-    ///     .path("src")
-    ///     .guess();
-    /// assert_eq!(guess.mime_type(), &Mime::from_str("inode/directory")?);
-    /// #
-    /// # Ok(())
-    /// # }
+    /// let mime_db = xdg_mime::SharedMimeInfo::builder()
+    ///     .mime_types_files(["/etc/mime.types"])
+    ///     .build();
     /// ```
-    ///
-    /// [`guess`]: #method.guess
-    /// [`file_name`]: #method.file_name
-    /// [`metadata`]: #method.metadata
-    /// [`data`]: #method.data
-    pub fn path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
-        let mut buf = PathBuf::new();
-        buf.push(path);
-
-        self.path = Some(buf);
+    pub fn mime_types_files<I, P>(&mut self, files: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.mime_types_files = files
+            .into_iter()
+            .map(|f| f.as_ref().to_path_buf())
+            .collect();
 
         self
     }
 
-    /// Sets whether or not the [`guess`] method will return `application/x-zerosize` for empty files.
-    ///
-    /// If `allow` is set to `true`, using [`guess`] to guess the MIME types of empty files will result in `application/x-zerosize`.
-    /// Otherwise, the check for empty files will be disabled and other methods will be used to determine the MIME type.
-    /// Does not affect the results of non-empty files.
+    /// Sets whether the `$HOME/.mime.types` file of the user should be
+    /// loaded, like the files set using [`mime_types_files`], and with
+    /// precedence over them.
     ///
-    /// Defaults to true.
+    /// Defaults to false.
     ///
-    /// [`guess`]: #method.guess
-    pub fn zero_size(&mut self, allow: bool) -> &mut Self {
-        self.zero_size = allow;
+    /// [`mime_types_files`]: #method.mime_types_files
+    pub fn user_mime_types(&mut self, load: bool) -> &mut Self {
+        self.user_mime_types = load;
+
         self
     }
 
-    /// Guesses the MIME type using the data set on the builder. The result is
-    /// a [`Guess`] instance that contains both the guessed MIME type, and whether
-    /// the result of the guess is certain.
+    /// Sets the MIME type returned when the MIME type of a file name, or
+    /// of some data, cannot be determined.
     ///
-    /// [`Guess`]: struct.Guess.html
-    pub fn guess(&mut self) -> Guess {
-        if let Some(path) = &self.path {
-            // Fill out the metadata
-            if self.metadata.is_none() {
-                self.metadata = match fs::metadata(path) {
-                    Ok(m) => Some(m),
-                    Err(_) => None,
-                };
-            }
+    /// Defaults to `application/octet-stream`.
+    pub fn unknown_type(&mut self, mime_type: &Mime) -> &mut Self {
+        self.unknown_type = Some(mime_type.clone());
 
-            fn load_data_chunk<P: AsRef<Path>>(path: P, chunk_size: usize) -> Option<Vec<u8>> {
-                if chunk_size == 0 {
-                    return None;
-                }
+        self
+    }
 
-                let mut f = match File::open(&path) {
-                    Ok(file) => file,
-                    Err(_) => return None,
-                };
+    /// Sets whether entries that were written by hand should be recovered
+    /// while loading the database.
+    ///
+    /// In lenient mode, a byte order mark at the start of a line, and any
+    /// whitespace or quotes around each field of an entry, are ignored.
+    /// Entries containing a MIME type that still cannot be parsed are
+    /// skipped, and reported by the [`diagnostics`] method.
+    ///
+    /// Defaults to false.
+    ///
+    /// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+    pub fn lenient(&mut self, lenient: bool) -> &mut Self {
+        self.lenient = lenient;
 
-                let mut buf = vec![0u8; chunk_size];
+        self
+    }
 
-                if f.read_exact(&mut buf).is_err() {
-                    return None;
-                }
+    /// Sets whether the entries that cannot be parsed should be reported,
+    /// instead of being silently skipped.
+    ///
+    /// In strict mode, the malformed lines of the `globs2`, `globs`,
+    /// `aliases`, `subclasses`, `icons` and `generic-icons` files, and the
+    /// malformed entries of the `magic` file, are reported by the
+    /// [`diagnostics`] method as [`Diagnostic::MalformedEntry`]. Since the
+    /// `mime.cache` file is compiled from the same data, and has no lines
+    /// to report, the plain text files are read instead. Strict mode can
+    /// be combined with the [`lenient`] mode, in which case only the
+    /// entries that cannot be recovered are reported.
+    ///
+    /// Entries referring to an invalid MIME type are always reported.
+    ///
+    /// Defaults to false.
+    ///
+    /// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+    /// [`Diagnostic::MalformedEntry`]: enum.Diagnostic.html#variant.MalformedEntry
+    /// [`lenient`]: #method.lenient
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
 
-                Some(buf)
-            }
+        self
+    }
 
-            // Load the minimum amount of data necessary for a match
-            if self.data.is_empty() {
-                let mut max_data_size = magic::max_extents(&self.db.magic);
+    /// Sets how the MIME types are guessed in the cases where the
+    /// specification and GIO disagree.
+    ///
+    /// Defaults to [`Conformance::Lenient`].
+    ///
+    /// [`Conformance::Lenient`]: enum.Conformance.html#variant.Lenient
+    pub fn conformance(&mut self, conformance: Conformance) -> &mut Self {
+        self.conformance = conformance;
 
-                if let Some(metadata) = &self.metadata {
-                    let file_size: usize = metadata.len() as usize;
-                    if file_size < max_data_size {
-                        max_data_size = file_size;
-                    }
-                }
+        self
+    }
 
-                match load_data_chunk(path, max_data_size) {
-                    Some(v) => self.data.extend(v),
-                    None => self.data.clear(),
-                }
-            }
+    /// Sets how glob patterns that match almost every file name, like `*`
+    /// or `*.*`, are handled while loading the database.
+    ///
+    /// Defaults to [`GenericGlobs::Keep`].
+    ///
+    /// [`GenericGlobs::Keep`]: enum.GenericGlobs.html#variant.Keep
+    pub fn generic_globs(&mut self, policy: GenericGlobs) -> &mut Self {
+        self.generic_globs = policy;
 
-            // Set the file name
-            if self.file_name.is_none() {
-                if let Some(file_name) = path.file_name() {
-                    self.file_name = match file_name.to_os_string().into_string() {
-                        Ok(v) => Some(v),
-                        Err(_) => None,
-                    };
-                }
-            }
-        }
+        self
+    }
 
-        if let Some(metadata) = &self.metadata {
-            let file_type = metadata.file_type();
+    /// Sets whether the files that file systems, file managers and sync
+    /// clients leave behind in shared directories, like `desktop.ini`,
+    /// `Thumbs.db`, `.DS_Store` or `.directory`, should be recognized
+    /// even if the database does not know them.
+    ///
+    /// These names are added to the database from a curated table, as
+    /// case insensitive literal patterns with a weight of 10, after all
+    /// the other sources; the names already matched by a pattern of the
+    /// database are left alone, so the classification only changes on
+    /// older databases. The MIME types that the database does not define,
+    /// like `application/x-windows-thumbnail-cache` for `Thumbs.db`, are
+    /// added along with their parent.
+    ///
+    /// Defaults to false.
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::builder()
+    ///     .special_names(true)
+    ///     .build();
+    /// ```
+    #[cfg(feature = "special-names")]
+    pub fn special_names(&mut self, load: bool) -> &mut Self {
+        self.special_names = load;
 
-            // Special type for directories
-            if file_type.is_dir() {
-                return Guess {
-                    mime: "inode/directory".parse::<mime::Mime>().unwrap(),
-                    uncertain: true,
-                };
-            }
+        self
+    }
 
-            // Special type for symbolic links
-            if file_type.is_symlink() {
-                return Guess {
-                    mime: "inode/symlink".parse::<mime::Mime>().unwrap(),
-                    uncertain: true,
-                };
-            }
+    /// Sets the number of levels of parents followed when checking whether
+    /// a MIME type is a subclass of another, for instance by the
+    /// [`mime_type_subclass`] method.
+    ///
+    /// The parents of the MIME types defined by shared-mime-info are only
+    /// a few levels deep, but the data of third-party packages can define
+    /// much deeper chains, or even cycles; the MIME types whose parents
+    /// reach the limit are reported by the [`diagnostics`] method, and the
+    /// [`checked_mime_type_subclass`] method can tell when the limit was
+    /// reached before finding an answer.
+    ///
+    /// Defaults to 32.
+    ///
+    /// [`mime_type_subclass`]: struct.SharedMimeInfo.html#method.mime_type_subclass
+    /// [`checked_mime_type_subclass`]: struct.SharedMimeInfo.html#method.checked_mime_type_subclass
+    /// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+    pub fn subclass_depth_limit(&mut self, limit: usize) -> &mut Self {
+        self.subclass_depth_limit = limit;
 
-            // Special type for empty files
-            if self.zero_size && metadata.len() == 0 {
-                return Guess {
-                    mime: "application/x-zerosize".parse::<mime::Mime>().unwrap(),
-                    uncertain: true,
-                };
-            }
-        }
+        self
+    }
 
-        let name_mime_types: Vec<mime::Mime> = match &self.file_name {
-            Some(file_name) => self.db.get_mime_types_from_file_name(file_name),
-            None => Vec::new(),
-        };
+    /// Sets whether the ancestors of every MIME type should be computed
+    /// once, while loading the database, instead of following the parents
+    /// for each subclass check.
+    ///
+    /// This makes the subclass checks faster, at the cost of some memory,
+    /// which is useful for applications checking many files, like file
+    /// managers or indexers; the ancestors are computed again when the
+    /// database is reloaded.
+    ///
+    /// Defaults to false.
+    pub fn precompute_ancestors(&mut self, precompute: bool) -> &mut Self {
+        self.precompute_ancestors = precompute;
 
-        // File name match, and no conflicts
-        if name_mime_types.len() == 1 && name_mime_types[0] != mime::APPLICATION_OCTET_STREAM {
-            return Guess {
-                mime: name_mime_types[0].clone(),
-                uncertain: false,
-            };
-        }
+        self
+    }
 
-        let sniffed_mime = self
-            .db
-            .get_mime_type_for_data(&self.data)
-            .unwrap_or((mime::APPLICATION_OCTET_STREAM, 80));
+    /// Sets whether the glob patterns should be loaded.
+    ///
+    /// Without them, the MIME type of a file name cannot be guessed, and
+    /// only the contents of a file are checked; the [`mime_types_files`]
+    /// and the special names are not loaded either.
+    ///
+    /// Defaults to true.
+    ///
+    /// [`mime_types_files`]: #method.mime_types_files
+    pub fn load_globs(&mut self, load: bool) -> &mut Self {
+        self.load.globs = load;
 
-        if name_mime_types.is_empty() {
-            // No names and no data => unknown MIME type
-            if self.data.is_empty() {
-                return Guess {
-                    mime: mime::APPLICATION_OCTET_STREAM,
-                    uncertain: true,
-                };
-            }
-
-            return Guess {
-                mime: sniffed_mime.0.clone(),
-                uncertain: sniffed_mime.0 == mime::APPLICATION_OCTET_STREAM,
-            };
-        } else {
-            let (mut mime, priority) = sniffed_mime;
-
-            // "If no magic rule matches the data (or if the content is not
-            // available), use the default type of application/octet-stream
-            // for binary data, or text/plain for textual data."
-            // -- shared-mime-info, "Recommended checking order"
-            if mime == mime::APPLICATION_OCTET_STREAM
-                && !self.data.is_empty()
-                && looks_like_text(&self.data)
-            {
-                mime = mime::TEXT_PLAIN;
-            }
-
-            // From the content type guessing implementation in GIO:
-            //
-            // For security reasons we don't ever want to sniff desktop files
-            // where we know the filename and it doesn't have a .desktop extension.
-            // This is because desktop files allow executing any application and
-            // we don't want to make it possible to hide them looking like something
-            // else.
-            if self.file_name.is_some() {
-                let x_desktop = "application/x-desktop".parse::<mime::Mime>().unwrap();
-
-                if mime == x_desktop {
-                    mime = mime::TEXT_PLAIN;
-                }
-            }
-
-            if mime != mime::APPLICATION_OCTET_STREAM {
-                // We found a match with a high confidence value
-                if priority >= 80 {
-                    return Guess {
-                        mime,
-                        uncertain: false,
-                    };
-                }
-
-                // We have possible conflicts, but the data matches the
-                // file name, so let's see if the sniffed MIME type is
-                // a subclass of the MIME type associated to the file name,
-                // and use that as a tie breaker.
-                if name_mime_types
-                    .iter()
-                    .any(|m| self.db.mime_type_subclass(&mime, m))
-                {
-                    return Guess {
-                        mime,
-                        uncertain: false,
-                    };
-                }
-            }
+        self
+    }
 
-            // If there are conflicts, and the data does not help us,
-            // we just pick the first result
-            if let Some(mime_type) = name_mime_types.first() {
-                return Guess {
-                    mime: mime_type.clone(),
-                    uncertain: true,
-                };
-            }
-        }
+    /// Sets whether the magic entries should be loaded.
+    ///
+    /// Without them, the MIME type of some data cannot be guessed, and
+    /// only the file names are checked; applications that only look at
+    /// file names, like a file chooser, can skip the largest part of the
+    /// database this way:
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::builder()
+    ///     .load_magic(false)
+    ///     .load_treemagic(false)
+    ///     .load_icons(false)
+    ///     .build();
+    /// ```
+    ///
+    /// When a directory has a `mime.cache` file, the sections of the
+    /// data that are not loaded are not read, nor validated.
+    ///
+    /// Defaults to true.
+    pub fn load_magic(&mut self, load: bool) -> &mut Self {
+        self.load.magic = load;
 
-        // Okay, we give up
-        Guess {
-            mime: mime::APPLICATION_OCTET_STREAM,
-            uncertain: true,
-        }
+        self
     }
-}
 
-fn looks_like_text(data: &[u8]) -> bool {
-    // "Checking the first 128 bytes of the file for ASCII
-    // control characters is a good way to guess whether a
-    // file is binary or text."
-    // -- shared-mime-info, "Recommended checking order"
-    !data
-        .iter()
-        .take(128)
-        .any(|ch| ch.is_ascii_control() && !ch.is_ascii_whitespace())
-}
+    /// Sets whether the tree magic entries, used to guess the MIME type
+    /// of mounted volumes, should be loaded.
+    ///
+    /// Defaults to true.
+    pub fn load_treemagic(&mut self, load: bool) -> &mut Self {
+        self.load.treemagic = load;
 
-impl Guess {
-    /// The guessed MIME type.
-    pub fn mime_type(&self) -> &mime::Mime {
-        &self.mime
+        self
     }
 
-    /// Whether the guessed MIME type is uncertain.
+    /// Sets whether the icon names and the generic icon names of the
+    /// MIME types should be loaded.
     ///
-    /// If the MIME type was guessed only from its file name there can be
-    /// multiple matches, but the [`mime_type`] method will return just the
-    /// first match.
+    /// Without them, the [`lookup_icon_names`] method only returns the
+    /// names derived from each MIME type.
     ///
-    /// If you only have a file name, and you want to gather all potential
-    /// matches, you should use the [`get_mime_types_from_file_name`] method
-    /// instead of performing a guess.
+    /// Defaults to true.
     ///
-    /// [`mime_type`]: #method.mime_type
-    /// [`get_mime_types_from_file_name`]: struct.SharedMimeInfo.html#method.get_mime_types_from_file_name
-    pub fn uncertain(&self) -> bool {
-        self.uncertain
-    }
-}
-
-impl Default for SharedMimeInfo {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+    /// [`lookup_icon_names`]: struct.SharedMimeInfo.html#method.lookup_icon_names
+    pub fn load_icons(&mut self, load: bool) -> &mut Self {
+        self.load.icons = load;
 
-impl SharedMimeInfo {
-    fn create() -> SharedMimeInfo {
-        SharedMimeInfo {
-            aliases: alias::AliasesList::new(),
-            parents: parent::ParentsMap::new(),
-            icons: Vec::new(),
-            generic_icons: Vec::new(),
-            globs: glob::GlobMap::new(),
-            magic: Vec::new(),
-            mime_dirs: Vec::new(),
-        }
+        self
     }
 
-    fn load_directory<P: AsRef<Path>>(&mut self, directory: P) {
-        let mut mime_path = PathBuf::new();
-        mime_path.push(directory);
-        mime_path.push("mime");
-
-        let aliases = alias::read_aliases_from_dir(&mime_path);
-        self.aliases.add_aliases(aliases);
-
-        let icons = icon::read_icons_from_dir(&mime_path, false);
-        self.icons.extend(icons);
-
-        let generic_icons = icon::read_icons_from_dir(&mime_path, true);
-        self.generic_icons.extend(generic_icons);
+    /// Sets whether the directories that were not set on the builder
+    /// should fall back to the standard XDG base directories, like in
+    /// the [`new`] method.
+    ///
+    /// Without the defaults, only the directories set using
+    /// [`data_home`], [`data_dirs`], [`override_dirs`] and
+    /// [`extra_dirs`] are loaded, and the directories of the host system
+    /// are not added inside a sandbox, so the database does not depend
+    /// on the environment:
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::builder()
+    ///     .data_dirs(["/usr/share"])
+    ///     .default_dirs(false)
+    ///     .build();
+    /// ```
+    ///
+    /// Defaults to true.
+    ///
+    /// [`new`]: struct.SharedMimeInfo.html#method.new
+    /// [`data_home`]: #method.data_home
+    /// [`data_dirs`]: #method.data_dirs
+    /// [`override_dirs`]: #method.override_dirs
+    /// [`extra_dirs`]: #method.extra_dirs
+    pub fn default_dirs(&mut self, fall_back: bool) -> &mut Self {
+        self.default_dirs = fall_back;
 
-        let subclasses = parent::read_subclasses_from_dir(&mime_path);
-        self.parents.add_subclasses(subclasses);
+        self
+    }
 
-        let globs = glob::read_globs_from_dir(&mime_path);
-        self.globs.add_globs(&globs);
+    /// Loads all the MIME information under the directories set on the
+    /// builder, and creates a new [`SharedMimeInfo`] instance for it.
+    ///
+    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+    pub fn build(&self) -> SharedMimeInfo {
+        let mut db = self.create();
+        let mut ctx = db.parse_context();
 
-        let magic_entries = magic::read_magic_from_dir(&mime_path);
-        self.magic.extend(magic_entries);
+        for (dir, tier) in self.directories() {
+            db.load_directory(dir, tier, &mut ctx);
+        }
 
-        let mime_dir = match fs::metadata(&mime_path) {
-            Ok(v) => {
-                let mtime = v.modified().unwrap_or_else(|_| SystemTime::now());
+        for (file, tier) in self.mime_types_files_to_load() {
+            db.load_mime_types_file(file, tier, &mut ctx);
+        }
 
-                MimeDirectory {
-                    path: mime_path,
-                    mtime,
-                }
-            }
-            Err(_) => MimeDirectory {
-                path: mime_path,
-                mtime: SystemTime::now(),
-            },
-        };
+        self.finish(&mut db, ctx);
 
-        self.mime_dirs.push(mime_dir);
+        db
     }
 
-    /// Creates a new `SharedMimeInfo` instance containing all MIME information
-    /// under the [standard XDG base directories][xdg-basedir].
-    ///
-    /// [xdg-basedir]: http://standards.freedesktop.org/basedir-spec/basedir-spec-latest.html
-    pub fn new() -> SharedMimeInfo {
+    // An empty database, with the settings of the builder
+    fn create(&self) -> SharedMimeInfo {
         let mut db = SharedMimeInfo::create();
 
-        let data_home = dirs_next::data_dir().expect("Data directory is unset");
-        db.load_directory(data_home);
-
-        let data_dirs = match env::var_os("XDG_DATA_DIRS") {
-            Some(v) => env::split_paths(&v).collect(),
-            None => vec![
-                PathBuf::from("/usr/local/share"),
-                PathBuf::from("/usr/share"),
-            ],
-        };
+        if let Some(unknown_type) = &self.unknown_type {
+            db.unknown_type = special::intern(unknown_type.clone());
+        }
 
-        for dir in data_dirs {
-            db.load_directory(dir)
+        db.lenient = self.lenient;
+        db.strict = self.strict;
+        db.magic = Arc::new(magic::DeferredMagic::new(self.lenient, self.strict));
+        db.conformance = self.conformance;
+        db.generic_globs = self.generic_globs;
+        db.subclass_depth_limit = self.subclass_depth_limit;
+        if self.precompute_ancestors {
+            db.ancestors = Some(Arc::default());
         }
+        db.load = self.load;
 
         db
     }
 
-    /// Loads all the MIME information under `directory`, and creates a new
-    /// [`SharedMimeInfo`] instance for it.
-    ///
-    /// This method is only really useful for testing purposes; you should
-    /// always use the [`new`] method, instead.
-    ///
-    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
-    /// [`new`]: #method.new
-    pub fn new_for_directory<P: AsRef<Path>>(directory: P) -> SharedMimeInfo {
-        let mut db = SharedMimeInfo::create();
-
-        db.load_directory(directory);
+    // The checks of the whole database, once all the directories were
+    // merged
+    fn finish(&self, db: &mut SharedMimeInfo, mut ctx: diagnostics::ParseContext) {
+        #[cfg(feature = "special-names")]
+        if self.special_names && self.load.globs {
+            db.load_special_names();
+        }
 
-        db
+        db.check_subclasses(&mut ctx);
+        db.check_empty(&mut ctx);
+        db.diagnostics = Arc::new(ctx.diagnostics);
     }
 
-    /// Reloads the contents of the [`SharedMimeInfo`] type from the directories
-    /// used to populate it at construction time. You should use this method
-    /// if you're planning to keep the database around for long running operations
-    /// or applications.
+    /// Loads all the MIME information under the directories set on the
+    /// builder, like [`build`], but fails if the home directory of the
+    /// user cannot be found, if one of the `mime` directories exists but
+    /// cannot be read, or if an entry cannot be parsed.
     ///
-    /// This method does not do anything if the directories haven't changed
-    /// since the time they were loaded last.
+    /// The problems that do not prevent the database from being used,
+    /// like the entries shadowed by another directory, are only reported
+    /// by the [`diagnostics`] of the database. The magic entries are not
+    /// checked, since they are only parsed when they are needed.
     ///
-    /// This method will return `true` if the contents of the shared MIME
-    /// database were updated.
+    /// ```rust
+    /// let res = xdg_mime::SharedMimeInfo::builder()
+    ///     .data_home("/does/not/exist")
+    ///     .try_build();
+    /// assert!(res.is_ok());
+    /// ```
     ///
-    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
-    pub fn reload(&mut self) -> bool {
-        let mut dropped_db = false;
-
-        // Do not reload the data if nothing has changed
-        for dir in &self.mime_dirs {
-            let mtime = match fs::metadata(&dir.path) {
-                Ok(v) => v.modified().unwrap_or(dir.mtime),
-                Err(_) => dir.mtime,
-            };
-
-            // Drop everything if a directory was changed since
-            // the last time we looked into it
-            if dir.mtime < mtime {
-                dropped_db = true;
-
-                self.aliases.clear();
-                self.parents.clear();
-                self.globs.clear();
-                self.icons.clear();
-                self.generic_icons.clear();
-                self.magic.clear();
+    /// [`build`]: #method.build
+    /// [`diagnostics`]: struct.SharedMimeInfo.html#method.diagnostics
+    pub fn try_build(&self) -> Result<SharedMimeInfo, Error> {
+        if self.data_home.is_none() && self.default_dirs && basedir::data_home().is_none() {
+            return Err(Error::MissingHome);
+        }
 
-                break;
+        // The directories that do not exist are skipped, like in build()
+        for (dir, _) in self.directories() {
+            let path = dir.join("mime");
+            match fs::read_dir(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(Error::UnreadableDirectory { path, source: e })
+                }
+                _ => {}
             }
         }
 
-        if dropped_db {
-            let mime_dirs: Vec<MimeDirectory> = self.mime_dirs.to_vec();
-
-            self.mime_dirs.clear();
-
-            for dir in &mime_dirs {
-                // Pop the `mime` chunk, since load_directory() will
-                // automatically add it back
-                let mut base_dir = PathBuf::new();
-                base_dir.push(&dir.path);
-                base_dir.pop();
-
-                self.load_directory(base_dir);
-            }
+        let db = self.build();
+        let invalid = db.diagnostics.iter().find(|d| {
+            matches!(
+                d,
+                Diagnostic::InvalidMimeType { .. } | Diagnostic::MalformedEntry { .. }
+            )
+        });
+        if let Some(diagnostic) = invalid {
+            return Err(Error::Parse(diagnostic.clone()));
         }
 
-        dropped_db
+        Ok(db)
     }
 
-    /// Retrieves the MIME type aliased by a MIME type, if any.
-    pub fn unalias_mime_type(&self, mime_type: &Mime) -> Option<Mime> {
-        self.aliases.unalias_mime_type(mime_type)
+    /// Loads all the MIME information under the directories set on the
+    /// builder on a background thread, like [`build`], and returns a
+    /// handle to check whether the database is ready.
+    ///
+    /// [`build`]: #method.build
+    pub fn spawn_build(&self) -> Loading {
+        Loading::spawn(self.clone())
     }
 
-    /// Looks up the icons associated to a MIME type.
+    /// Restores a database from a snapshot written by
+    /// [`SharedMimeInfo::serialize_snapshot`], instead of reading the
+    /// directories set on the builder again.
     ///
-    /// The icons can be looked up within the current [icon theme][xdg-icon-theme].
+    /// The snapshot is only used if it was written for the same
+    /// directories and `mime.types` files, and with the same parts of the
+    /// MIME data loaded, as set on the builder; the other settings of the
+    /// builder apply to the restored database, like in [`build`].
     ///
-    /// [xdg-icon-theme]: https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
-    pub fn lookup_icon_names(&self, mime_type: &Mime) -> Vec<String> {
-        let mut res = Vec::new();
-
-        if let Some(v) = icon::find_icon(&self.icons, mime_type) {
-            res.push(v);
+    /// Returns [`Error::StaleSnapshot`] if the snapshot does not match the
+    /// builder, or if one of the directories or files changed since it was
+    /// written, and [`Error::InvalidSnapshot`] if it was not written by
+    /// the same version of this crate, or if it is truncated; in both
+    /// cases, the database should be loaded using [`build`], and a new
+    /// snapshot written.
+    ///
+    /// This method requires the `snapshot` feature.
+    ///
+    /// [`SharedMimeInfo::serialize_snapshot`]: struct.SharedMimeInfo.html#method.serialize_snapshot
+    /// [`build`]: #method.build
+    /// [`Error::StaleSnapshot`]: enum.Error.html#variant.StaleSnapshot
+    /// [`Error::InvalidSnapshot`]: enum.Error.html#variant.InvalidSnapshot
+    #[cfg(feature = "snapshot")]
+    pub fn build_from_snapshot(&self, snapshot: &[u8]) -> Result<SharedMimeInfo, Error> {
+        let (load, mime_dirs, mime_types_files) =
+            binary::deserialize(snapshot).ok_or(Error::InvalidSnapshot)?;
+
+        let dirs: Vec<(PathBuf, Tier)> = self
+            .directories()
+            .into_iter()
+            .map(|(dir, tier)| (dir.join("mime"), tier))
+            .collect();
+        let same = |loaded: &[MimeDirectory], expected: &[(PathBuf, Tier)]| {
+            loaded.len() == expected.len()
+                && loaded
+                    .iter()
+                    .zip(expected)
+                    .all(|(dir, (path, tier))| dir.path == *path && dir.tier == *tier)
         };
+        if load != self.load
+            || !same(&mime_dirs, &dirs)
+            || !same(&mime_types_files, &self.mime_types_files_to_load())
+            || mime_dirs
+                .iter()
+                .chain(&mime_types_files)
+                .any(MimeDirectory::is_changed)
+        {
+            return Err(Error::StaleSnapshot);
+        }
 
-        res.push(mime_type.essence_str().replace('/', "-"));
+        let mut db = self.create();
+        let mut ctx = db.parse_context();
 
-        match icon::find_icon(&self.generic_icons, mime_type) {
-            Some(v) => res.push(v),
-            None => {
-                let generic = format!("{}-x-generic", mime_type.type_());
-                res.push(generic);
-            }
-        };
+        for dir in mime_dirs {
+            db.merge_directory(dir, &mut ctx);
+        }
 
-        res
-    }
+        for file in mime_types_files {
+            db.merge_mime_types_file(file, &mut ctx);
+        }
 
-    /// Looks up the generic icon associated to a MIME type.
-    ///
-    /// The icon can be looked up within the current [icon theme][xdg-icon-theme].
-    ///
-    /// [xdg-icon-theme]: https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
-    pub fn lookup_generic_icon_name(&self, mime_type: &Mime) -> Option<String> {
-        let res = match icon::find_icon(&self.generic_icons, mime_type) {
-            Some(v) => v,
-            None => format!("{}-x-generic", mime_type.type_()),
-        };
+        self.finish(&mut db, ctx);
 
-        Some(res)
+        Ok(db)
     }
 
-    /// Retrieves all the parent MIME types associated to `mime_type`.
-    pub fn get_parents(&self, mime_type: &Mime) -> Option<Vec<Mime>> {
-        let unaliased = match self.aliases.unalias_mime_type(mime_type) {
-            Some(v) => v,
-            None => return None,
+    // The directories to load the MIME data from, in order of precedence;
+    // without the defaults, the directories that were not set are left
+    // empty
+    fn directories(&self) -> Vec<(PathBuf, Tier)> {
+        let dirs = |dirs: &Option<Vec<PathBuf>>, default: fn() -> Vec<PathBuf>| match dirs {
+            Some(dirs) => dirs.clone(),
+            None if self.default_dirs => default(),
+            None => Vec::new(),
         };
+        let system = |dirs: Vec<PathBuf>| dirs.into_iter().map(|dir| (dir, Tier::System));
 
-        let mut res = vec![unaliased.clone()];
+        let mut res: Vec<(PathBuf, Tier)> =
+            system(dirs(&self.override_dirs, basedir::override_dirs)).collect();
 
-        if let Some(parents) = self.parents.lookup(&unaliased) {
-            for parent in parents {
-                res.push(parent.clone());
-            }
+        let data_home = match &self.data_home {
+            Some(dir) => Some(dir.clone()),
+            None if self.default_dirs => basedir::data_home(),
+            None => None,
         };
+        res.extend(data_home.map(|dir| (dir, Tier::User)));
 
-        Some(res)
-    }
+        res.extend(system(dirs(&self.data_dirs, basedir::data_dirs)));
+        res.extend(system(dirs(&self.extra_dirs, basedir::extra_dirs)));
 
-    /// Retrieves the list of matching MIME types for the given file name,
-    /// without looking at the data inside the file.
-    ///
-    /// If no specific MIME-type can be determined, returns a single
-    /// element vector containing the `application/octet-stream` MIME type.
-    ///
-    /// ```rust
-    /// # use std::error::Error;
-    /// # use std::str::FromStr;
-    /// # use mime::Mime;
-    /// #
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
-    /// // let mime_db = ...
-    /// let mime_types: Vec<Mime> = mime_db.get_mime_types_from_file_name("file.txt");
-    /// assert_eq!(mime_types, vec![Mime::from_str("text/plain")?]);
-    /// #
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn get_mime_types_from_file_name(&self, file_name: &str) -> Vec<Mime> {
-        match self.globs.lookup_mime_type_for_file_name(file_name) {
-            Some(v) => v,
-            None => {
-                vec![mime::APPLICATION_OCTET_STREAM.clone()]
-            }
+        if self.detect_sandbox && self.default_dirs {
+            res.extend(system(basedir::sandbox_data_dirs()));
         }
+
+        res
     }
 
-    /// Retrieves the MIME type for the given data, and the priority of the
-    /// match. A priority above 80 means a certain match.
-    pub fn get_mime_type_for_data(&self, data: &[u8]) -> Option<(Mime, u32)> {
-        if data.is_empty() {
-            let empty_mime: mime::Mime = "application/x-zerosize".parse().unwrap();
-            return Some((empty_mime, 100));
+    // The mime.types files to load the glob patterns from, after the
+    // directories
+    fn mime_types_files_to_load(&self) -> Vec<(PathBuf, Tier)> {
+        let mut res = Vec::new();
+        if !self.load.globs {
+            return res;
+        }
+
+        if self.user_mime_types {
+            res.extend(basedir::user_mime_types().map(|file| (file, Tier::User)));
         }
+        res.extend(
+            self.mime_types_files
+                .iter()
+                .map(|file| (file.clone(), Tier::System)),
+        );
 
-        magic::lookup_data(&self.magic, data)
+        res
     }
+}
 
-    /// Checks whether two MIME types are equal, taking into account
-    /// eventual aliases.
-    ///
-    /// ```rust
-    /// # use std::error::Error;
-    /// # use std::str::FromStr;
-    /// # use mime::Mime;
-    /// #
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
-    /// // let mime_db = ...
-    /// let x_markdown: Mime = "text/x-markdown".parse()?;
-    /// let markdown: Mime = "text/markdown".parse()?;
-    /// assert!(mime_db.mime_type_equal(&x_markdown, &markdown));
-    /// #
-    /// # Ok(())
-    /// # }
+/// A builder type to specify the parameters for guessing a MIME type.
+///
+/// Each instance of `GuessBuilder` is tied to the lifetime of the
+/// [`SharedMimeInfo`] instance that created it.
+///
+/// The `GuessBuilder` returned by the [`guess_mime_type`] method is
+/// empty, and will always return an [unknown] guess.
+///
+/// You can use the builder methods to specify the file name, the data,
+/// or both, to be used to guess the MIME type:
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use std::str::FromStr;
+/// # use mime::Mime;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let mime_db = xdg_mime::SharedMimeInfo::new();
+/// // let mime_db = ...
+/// let mut guess_builder = mime_db.guess_mime_type();
+/// let guess = guess_builder.file_name("foo.png").guess();
+/// assert_eq!(guess.mime_type(), &Mime::from_str("image/png")?);
+/// #
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The guessed MIME type can have a degree of uncertainty; for instance,
+/// if you only set the [`file_name`] there can be multiple matching MIME
+/// types to choose from. Alternatively, if you only set the [`data`], the
+/// content might not match any existing rule. Even in the case of setting
+/// both the file name and the data the match can be uncertain. This
+/// information is preserved by the [`Guess`] type, and can be retrieved
+/// using the [`uncertain`] method.
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [`guess_mime_type`]: struct.SharedMimeInfo.html#method.guess_mime_type
+/// [`file_name`]: #method.file_name
+/// [`data`]: #method.data
+/// [`Guess`]: struct.Guess.html
+/// [`uncertain`]: struct.Guess.html#method.uncertain
+/// [unknown]: struct.Guess.html#method.is_unknown
+pub struct GuessBuilder<'a> {
+    db: &'a SharedMimeInfo,
+    file_name: Option<String>,
+    data: Vec<u8>,
+    metadata: Option<fs::Metadata>,
+    path: Option<PathBuf>,
+    zero_size: bool,
+    prefer_file_name: bool,
+    backup_handling: BackupHandling,
+    deadline: Option<Duration>,
+    deadline_exceeded: bool,
+    read_error: Option<io::Error>,
+    timings: Option<GuessTimings>,
+    agreement: bool,
+    // The number of bytes read from the path, or inspected by the magic
+    // entries, during the last guess
+    bytes_examined: usize,
+}
+
+/// The result of the [`guess`] method of [`GuessBuilder`].
+///
+/// [`guess`]: struct.GuessBuilder.html#method.guess
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+pub struct Guess {
+    // The special types are borrowed from their statics
+    mime: Cow<'static, mime::Mime>,
+    uncertain: bool,
+    unknown: bool,
+    conflict: Option<Cow<'static, mime::Mime>>,
+    deadline_exceeded: bool,
+    read_error: Option<io::Error>,
+    timings: Option<GuessTimings>,
+    agreement: Option<Agreement>,
+    bytes_examined: usize,
+    backup_of: Option<mime::Mime>,
+}
+
+/// A candidate MIME type returned by the [`guess_all`] method of
+/// [`GuessBuilder`], alongside its score.
+///
+/// The score is the sum of the weight of the best glob matching the
+/// file name, and of the priority of the best magic entry matching the
+/// data, so candidates matching both the file name and the contents
+/// rank above candidates matching only one of them.
+///
+/// [`guess_all`]: struct.GuessBuilder.html#method.guess_all
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScoredGuess {
+    mime: mime::Mime,
+    score: u32,
+    glob_weight: Option<GlobWeight>,
+    magic_priority: Option<MagicPriority>,
+    subclass_of: Vec<mime::Mime>,
+}
+
+impl ScoredGuess {
+    /// The candidate MIME type.
+    pub fn mime_type(&self) -> &mime::Mime {
+        &self.mime
+    }
+
+    /// The combined score of the candidate.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// The weight of the best glob matching the file name, if any.
+    pub fn glob_weight(&self) -> Option<GlobWeight> {
+        self.glob_weight
+    }
+
+    /// The priority of the best magic entry matching the data, if any.
+    pub fn magic_priority(&self) -> Option<MagicPriority> {
+        self.magic_priority
+    }
+
+    /// The other candidates of the same list that this candidate is a
+    /// subclass of, in the order of the list; for instance, `text/x-python`
+    /// for `text/x-python3`.
+    ///
+    /// A candidate with no parents in the list is the most generic
+    /// interpretation of its subclasses, and can be used to group them.
+    pub fn subclass_of(&self) -> &[mime::Mime] {
+        &self.subclass_of
+    }
+}
+
+impl<'a> GuessBuilder<'a> {
+    /// Sets the file name to be used to guess its MIME type.
+    ///
+    /// If you have a full path, you should extract the last component,
+    /// for instance using the [`Path::file_name()`][path_file_name]
+    /// method.
+    ///
+    /// [path_file_name]: https://doc.rust-lang.org/std/path/struct.Path.html#method.file_name
+    pub fn file_name(&mut self, name: &str) -> &mut Self {
+        self.file_name = Some(name.to_string());
+
+        self
+    }
+
+    /// Sets the data for which you want to guess the MIME type, replacing
+    /// any data set before.
+    ///
+    /// Only the beginning of the data is copied, up to the number of bytes
+    /// needed to evaluate every magic entry of the database, so the memory
+    /// used by the builder does not depend on the size of `data`.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mut guess_builder = mime_db.guess_mime_type();
+    /// guess_builder.data(b"GIF89a");
+    /// let guess = guess_builder.data(b"\x89PNG\r\n\x1a\n").guess();
+    /// assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
     /// ```
-    pub fn mime_type_equal(&self, mime_a: &Mime, mime_b: &Mime) -> bool {
-        let unaliased_a = self
-            .unalias_mime_type(mime_a)
-            .unwrap_or_else(|| mime_a.clone());
-        let unaliased_b = self
-            .unalias_mime_type(mime_b)
-            .unwrap_or_else(|| mime_b.clone());
+    pub fn data(&mut self, data: &[u8]) -> &mut Self {
+        self.data.clear();
 
-        unaliased_a == unaliased_b
+        self.extend_data(data)
     }
 
-    /// Checks whether a MIME type is a subclass of another MIME type.
+    /// Appends `data` to the data set before, for instance while receiving
+    /// the contents of a file in chunks.
+    ///
+    /// Like with [`data`], the accumulated data is limited to the number
+    /// of bytes needed to evaluate every magic entry of the database; once
+    /// that many bytes are accumulated, the following chunks are ignored.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mut guess_builder = mime_db.guess_mime_type();
+    /// for chunk in [&b"\x89PN"[..], &b"G\r\n\x1a\n"[..]] {
+    ///     guess_builder.extend_data(chunk);
+    /// }
+    /// assert_eq!(guess_builder.guess().mime_type(), &mime::IMAGE_PNG);
+    /// ```
+    ///
+    /// [`data`]: #method.data
+    pub fn extend_data(&mut self, data: &[u8]) -> &mut Self {
+        // Just copy the largest chunk necessary to match any rule in the
+        // magic entries
+        let max_data_size = self.db.magic.max_extents();
+        let available = max_data_size.saturating_sub(self.data.len());
+        self.data
+            .extend_from_slice(&data[..data.len().min(available)]);
+
+        self
+    }
+
+    /// Sets the metadata of the file for which you want to get the MIME type.
+    ///
+    /// The metadata can be used to match an existing file or path, for instance:
     ///
     /// ```rust
     /// # use std::error::Error;
-    /// # use std::str::FromStr;
-    /// # use mime::Mime;
+    /// use std::fs;
+    /// use std::str::FromStr;
+    /// use mime::Mime;
     /// #
     /// # fn main() -> Result<(), Box<dyn Error>> {
     /// # let mime_db = xdg_mime::SharedMimeInfo::new();
     /// // let mime_db = ...
-    /// let rust: Mime = "text/rust".parse()?;
-    /// let text: Mime = "text/plain".parse()?;
-    /// assert!(mime_db.mime_type_subclass(&rust, &text));
+    /// # let metadata = fs::metadata("src/lib.rs")?;
+    /// // let metadata = fs::metadata("/path/to/lib.rs")?;
+    /// let mut guess_builder = mime_db.guess_mime_type();
+    /// let guess = guess_builder
+    ///     .file_name("lib.rs")
+    ///     .metadata(metadata)
+    ///     .guess();
+    /// assert_eq!(guess.mime_type(), &Mime::from_str("text/rust")?);
     /// #
     /// # Ok(())
     /// # }
     /// ```
-    pub fn mime_type_subclass(&self, mime_type: &Mime, base: &Mime) -> bool {
-        let unaliased_mime = self
-            .unalias_mime_type(mime_type)
-            .unwrap_or_else(|| mime_type.clone());
-        let unaliased_base = self.unalias_mime_type(base).unwrap_or_else(|| base.clone());
-
-        if unaliased_mime == unaliased_base {
-            return true;
-        }
-
-        // Handle super-types
-        if unaliased_base.subtype() == mime::STAR {
-            let base_type = unaliased_base.type_();
-            let unaliased_type = unaliased_mime.type_();
-
-            if base_type == unaliased_type {
-                return true;
-            }
-        }
-
-        // The text/plain and application/octet-stream require some
-        // special handling:
-        //
-        //  - All text/* types are subclasses of text/plain.
-        //  - All streamable types (ie, everything except the
-        //    inode/* types) are subclasses of application/octet-stream
-        //
-        // https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html#subclassing
-        if unaliased_base == mime::TEXT_PLAIN && unaliased_mime.type_() == mime::TEXT {
-            return true;
-        }
-
-        if unaliased_base == mime::APPLICATION_OCTET_STREAM && unaliased_mime.type_() != "inode" {
-            return true;
-        }
-
-        if let Some(parents) = self.parents.lookup(&unaliased_mime) {
-            if parents
-                .iter()
-                .any(|p| self.mime_type_subclass(p, &unaliased_base))
-            {
-                return true;
-            }
-        }
+    ///
+    /// The metadata is also enough to guess the MIME type on its own,
+    /// without a file name or any data; depending on the type of the file,
+    /// the result is:
+    ///
+    /// | File type | MIME type |
+    /// |-----------|-----------|
+    /// | Directory | `inode/directory` |
+    /// | Symbolic link | `inode/symlink` |
+    /// | Character device | `inode/chardevice` |
+    /// | Block device | `inode/blockdevice` |
+    /// | Named pipe | `inode/fifo` |
+    /// | Socket | `inode/socket` |
+    /// | Empty file | `application/x-zerosize`, unless disabled using [`zero_size`] |
+    /// | Executable file | `application/x-executable` |
+    /// | Any other file | the [unknown type] |
+    ///
+    /// The types of directories, symbolic links, and devices take
+    /// precedence over the file name and the data even when those are
+    /// available, while the executable type is only used if they are not.
+    /// The `inode/*` types come from the type of the file, so they are
+    /// certain, while the types of empty and executable files are marked as
+    /// uncertain. Devices, named pipes and sockets are only recognized on
+    /// Unix.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// use std::fs;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db
+    ///     .guess_mime_type()
+    ///     .metadata(fs::metadata("src")?)
+    ///     .guess();
+    /// assert_eq!(guess.mime_type(), &*xdg_mime::INODE_DIRECTORY);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`zero_size`]: #method.zero_size
+    /// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+    pub fn metadata(&mut self, metadata: fs::Metadata) -> &mut Self {
+        self.metadata = Some(metadata);
 
-        false
+        self
     }
 
-    /// Creates a new [`GuessBuilder`] that can be used to guess the MIME type
-    /// of a file name, its contents, or a path.
+    /// Sets the path of the file for which you want to get the MIME type.
+    ///
+    /// The `path` will be used by the [`guess`] method to extract the
+    /// file name, metadata, and contents, unless you called the [`file_name`],
+    /// [`metadata`], and [`data`] methods, respectively.
     ///
     /// ```rust
     /// # use std::error::Error;
-    /// # use std::str::FromStr;
-    /// # use mime::Mime;
+    /// use std::fs;
+    /// use std::str::FromStr;
+    /// use mime::Mime;
     /// #
     /// # fn main() -> Result<(), Box<dyn Error>> {
     /// # let mime_db = xdg_mime::SharedMimeInfo::new();
     /// // let mime_db = ...
-    /// let mut gb = mime_db.guess_mime_type();
-    /// let guess = gb.file_name("foo.txt").guess();
-    /// assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
-    /// assert_eq!(guess.uncertain(), false);
+    /// let mut guess_builder = mime_db.guess_mime_type();
+    /// let guess = guess_builder
+    ///     .path("src")
+    ///     .guess();
+    /// assert_eq!(guess.mime_type(), &Mime::from_str("inode/directory")?);
     /// #
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// [`GuessBuilder`]: struct.GuessBuilder.html
-    pub fn guess_mime_type(&self) -> GuessBuilder {
-        GuessBuilder {
-            db: self,
-            file_name: None,
-            data: Vec::new(),
-            metadata: None,
-            path: None,
-            zero_size: true,
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
-    use std::str::FromStr;
+    /// [`guess`]: #method.guess
+    /// [`file_name`]: #method.file_name
+    /// [`metadata`]: #method.metadata
+    /// [`data`]: #method.data
+    pub fn path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let mut buf = PathBuf::new();
+        buf.push(path);
 
-    fn load_test_data() -> SharedMimeInfo {
-        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
-        let dir = PathBuf::from(&format!("{}/test_files", cwd));
-        SharedMimeInfo::new_for_directory(dir)
-    }
+        self.path = Some(buf);
 
-    #[test]
-    fn load_from_directory() {
-        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
-        let dir = PathBuf::from(&format!("{}/test_files", cwd));
-        SharedMimeInfo::new_for_directory(dir);
+        self
     }
 
-    #[test]
-    fn load_system() {
-        let _db = SharedMimeInfo::new();
+    /// Sets whether or not the [`guess`] method will return `application/x-zerosize` for empty files.
+    ///
+    /// If `allow` is set to `true`, using [`guess`] to guess the MIME types of empty files will result in `application/x-zerosize`.
+    /// Otherwise, the check for empty files will be disabled and other methods will be used to determine the MIME type.
+    /// Does not affect the results of non-empty files.
+    ///
+    /// Defaults to true.
+    ///
+    /// [`guess`]: #method.guess
+    pub fn zero_size(&mut self, allow: bool) -> &mut Self {
+        self.zero_size = allow;
+        self
     }
 
-    #[test]
-    fn load_default() {
+    /// Sets whether the [`guess`] method should prefer the MIME type matching
+    /// the file name over `application/x-zerosize` for empty files.
+    ///
+    /// This is useful for placeholder files, like the ones created by cloud
+    /// storage clients for files that have not been downloaded yet. The file
+    /// name is only used if it matches a single MIME type; otherwise, empty
+    /// files are still guessed as `application/x-zerosize`. The overridden
+    /// type is available through the [`conflict`] method of the result.
+    ///
+    /// Defaults to false.
+    ///
+    /// [`guess`]: #method.guess
+    /// [`conflict`]: struct.Guess.html#method.conflict
+    pub fn prefer_file_name(&mut self, prefer: bool) -> &mut Self {
+        self.prefer_file_name = prefer;
+        self
+    }
+
+    /// Sets how the [`guess`] method treats the names of backup files,
+    /// like `notes.txt~` or `notes.txt.~2~`.
+    ///
+    /// Only the matching of the file name is affected; the contents of the
+    /// file are sniffed in the same way.
+    ///
+    /// Defaults to [`BackupHandling::AsIs`].
+    ///
+    /// [`guess`]: #method.guess
+    /// [`BackupHandling::AsIs`]: enum.BackupHandling.html#variant.AsIs
+    pub fn backup_handling(&mut self, handling: BackupHandling) -> &mut Self {
+        self.backup_handling = handling;
+        self
+    }
+
+    /// Sets the maximum amount of time the [`guess`] method should wait
+    /// for the metadata and the contents of the file set using [`path`].
+    ///
+    /// If reading the file takes longer, for instance because it is on a
+    /// slow network mount, the guess is performed using only the file
+    /// name, and the result is marked as uncertain; you can check whether
+    /// this happened using the [`deadline_exceeded`] method of the result.
+    ///
+    /// When a deadline is set, the file is read in a separate thread, which
+    /// keeps running in the background after the deadline expires.
+    ///
+    /// [`guess`]: #method.guess
+    /// [`path`]: #method.path
+    /// [`deadline_exceeded`]: struct.Guess.html#method.deadline_exceeded
+    pub fn deadline(&mut self, deadline: Duration) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Records the time spent in each step of the guess, like reading the
+    /// file and matching the magic entries; you can retrieve it using the
+    /// [`timings`] method of the result.
+    ///
+    /// This is useful to know whether the cost of a guess comes from the
+    /// storage or from the matching.
+    ///
+    /// [`timings`]: struct.Guess.html#method.timings
+    pub fn timings(&mut self, enable: bool) -> &mut Self {
+        self.timings = enable.then(GuessTimings::default);
+        self
+    }
+
+    /// Checks how many sources of evidence, like the file name and the
+    /// contents of the file, agree with the result of the guess; you can
+    /// retrieve it using the [`agreement`] method of the result.
+    ///
+    /// Every source is consulted, even the ones that are not needed to
+    /// determine the MIME type, so this makes the guess slower.
+    ///
+    /// [`agreement`]: struct.Guess.html#method.agreement
+    pub fn check_agreement(&mut self, check: bool) -> &mut Self {
+        self.agreement = check;
+        self
+    }
+
+    // Fills out the metadata, file name and data from the path, unless
+    // they were set explicitly
+    fn load_path(&mut self) {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
+        // Set the file name
+        if self.file_name.is_none() {
+            if let Some(file_name) = path.file_name() {
+                self.file_name = file_name.to_os_string().into_string().ok();
+            }
+        }
+
+        let load_metadata = self.metadata.is_none();
+        let load_data =
+            self.data.is_empty() && self.metadata.as_ref().map_or(true, fs::Metadata::is_file);
+        if !load_metadata && !load_data {
+            return;
+        }
+
+        // Do not read the data needed only by the magic entries that are
+        // too long to match a file of the known size
+        let file_size = self.metadata.as_ref().map(fs::Metadata::len);
+        let max_data_size = match file_size {
+            Some(len) => self.db.magic.max_extents_for_len(len as usize),
+            None => self.db.magic.max_extents(),
+        };
+
+        let (metadata, data, error) = match self.deadline {
+            Some(deadline) => {
+                // Reading from a slow file system cannot be interrupted, so
+                // we do it in a separate thread, and stop waiting for it
+                // once the deadline expires
+                let (tx, rx) = mpsc::channel();
+                let mut timings = self.timings;
+                thread::spawn(move || {
+                    let res = read_path(
+                        &path,
+                        load_metadata,
+                        file_size,
+                        load_data,
+                        max_data_size,
+                        timings.as_mut(),
+                    );
+                    let _ = tx.send((res, timings));
+                });
+
+                match rx.recv_timeout(deadline) {
+                    Ok((res, timings)) => {
+                        self.timings = timings;
+                        res
+                    }
+                    Err(_) => {
+                        self.deadline_exceeded = true;
+                        return;
+                    }
+                }
+            }
+            None => read_path(
+                &path,
+                load_metadata,
+                file_size,
+                load_data,
+                max_data_size,
+                self.timings.as_mut(),
+            ),
+        };
+
+        if load_metadata {
+            self.metadata = metadata;
+        }
+
+        self.read_error = error;
+
+        if load_data {
+            match data {
+                Some(v) => {
+                    self.bytes_examined = v.len();
+                    self.data.extend(v);
+                }
+                None => self.data.clear(),
+            }
+        }
+    }
+
+    // Special types for directories, symbolic links, devices, empty files,
+    // and executables; the types of the file system objects are certain,
+    // since they come from the type of the file itself, while an empty or
+    // executable file can still be anything
+    fn guess_from_metadata(&self) -> Option<Guess> {
+        if let Some(metadata) = &self.metadata {
+            let file_type = metadata.file_type();
+
+            // Special type for directories
+            if file_type.is_dir() {
+                return Some(Guess::new_static(&INODE_DIRECTORY, false));
+            }
+
+            // Special type for symbolic links
+            if file_type.is_symlink() {
+                return Some(Guess::new_static(&INODE_SYMLINK, false));
+            }
+
+            // Special types for devices, pipes and sockets, whose contents
+            // should never be read
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::FileTypeExt;
+
+                if file_type.is_char_device() {
+                    return Some(Guess::new_static(&INODE_CHARDEVICE, false));
+                }
+                if file_type.is_block_device() {
+                    return Some(Guess::new_static(&INODE_BLOCKDEVICE, false));
+                }
+                if file_type.is_fifo() {
+                    return Some(Guess::new_static(&INODE_FIFO, false));
+                }
+                if file_type.is_socket() {
+                    return Some(Guess::new_static(&INODE_SOCKET, false));
+                }
+            }
+
+            // Special type for empty files; GIO does not sniff them, and
+            // treats them as new text files
+            if metadata.len() == 0
+                && file_type.is_file()
+                && self.db.conformance == Conformance::GioCompatible
+            {
+                return Some(Guess::new(mime::TEXT_PLAIN, true));
+            }
+            if self.zero_size && metadata.len() == 0 {
+                return Some(Guess::new_static(&APPLICATION_X_ZEROSIZE, true));
+            }
+
+            // Executables, if the metadata is all we have
+            #[cfg(unix)]
+            if self.db.conformance == Conformance::Lenient
+                && self.file_name.is_none()
+                && self.data.is_empty()
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                if file_type.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+                    return Some(Guess::new_static(&APPLICATION_X_EXECUTABLE, true));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Guesses the MIME type using the data set on the builder. The result is
+    /// a [`Guess`] instance that contains both the guessed MIME type, and whether
+    /// the result of the guess is certain.
+    ///
+    ///
+    /// If the file set using [`path`] cannot be read, the guess is performed
+    /// using only the information that is available, and the error is
+    /// available through the [`read_error`] method of the result; use
+    /// [`try_guess`] to get the error instead.
+    ///
+    /// [`Guess`]: struct.Guess.html
+    /// [`path`]: #method.path
+    /// [`read_error`]: struct.Guess.html#method.read_error
+    /// [`try_guess`]: #method.try_guess
+    pub fn guess(&mut self) -> Guess {
+        self.deadline_exceeded = false;
+        self.read_error = None;
+        self.bytes_examined = 0;
+        if let Some(timings) = &mut self.timings {
+            *timings = GuessTimings::default();
+        }
+
+        let mut guess = self.resolve();
+
+        guess.unknown = self.db.is_unknown_type(&guess.mime);
+        guess.deadline_exceeded = self.deadline_exceeded;
+        guess.read_error = self.read_error.take();
+        guess.timings = self.timings;
+        guess.bytes_examined = self.bytes_examined;
+        if let Some(name) = &self.file_name {
+            if self.backup_handling.flags(name) {
+                guess.backup_of = Some(guess.mime.clone().into_owned());
+            }
+        }
+        if self.agreement {
+            guess.agreement = Some(self.agreement_with(&guess.mime));
+        }
+
+        guess
+    }
+
+    // Consults every source of evidence, and checks whether they agree
+    // with the guessed MIME type
+    fn agreement_with(&self, mime_type: &mime::Mime) -> Agreement {
+        let agrees = |m: &mime::Mime| {
+            self.db.mime_type_subclass(mime_type, m) || self.db.mime_type_subclass(m, mime_type)
+        };
+
+        let file_name = match &self.file_name {
+            Some(name) if !self.deadline_exceeded => {
+                let name = self.backup_handling.file_name(name);
+                let name_mime_types = self.db.get_mime_types_from_file_name(name);
+                match name_mime_types.first() {
+                    Some(m) if !self.db.is_unknown_type(m) => {
+                        Some(name_mime_types.iter().any(agrees))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let data = if self.data.is_empty() {
+            None
+        } else {
+            self.db.sniff(&self.data).map(|(m, _)| agrees(m))
+        };
+
+        let metadata = self
+            .guess_from_metadata()
+            .map(|guess| self.db.mime_type_equal(&guess.mime, mime_type));
+
+        Agreement {
+            file_name,
+            data,
+            metadata,
+        }
+    }
+
+    /// Guesses the MIME type like [`guess`], but fails if the metadata or
+    /// the contents of the file set using [`path`] cannot be read.
+    ///
+    /// This allows telling apart files whose contents could not be
+    /// recognized from files that could not be read at all. Exceeding the
+    /// [`deadline`] is not considered an error.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let res = mime_db
+    ///     .guess_mime_type()
+    ///     .path("/does/not/exist.txt")
+    ///     .try_guess();
+    /// assert!(res.is_err());
+    /// ```
+    ///
+    /// [`guess`]: #method.guess
+    /// [`path`]: #method.path
+    /// [`deadline`]: #method.deadline
+    pub fn try_guess(&mut self) -> io::Result<Guess> {
+        let mut guess = self.guess();
+
+        match guess.read_error.take() {
+            Some(error) => Err(error),
+            None => Ok(guess),
+        }
+    }
+
+    /// Guesses the MIME type like [`guess`], and asks libmagic for the
+    /// MIME type of the same file, or of the same data, so that the two
+    /// answers can be compared.
+    ///
+    /// If a [`path`] is set, libmagic reads the file itself; otherwise,
+    /// it looks at the [`data`]. With only a file name, there is nothing
+    /// for libmagic to look at.
+    ///
+    /// ```rust
+    /// # fn main() -> std::io::Result<()> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let libmagic = xdg_mime::LibMagic::open()?;
+    /// let cross_check = mime_db
+    ///     .guess_mime_type()
+    ///     .data(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR")
+    ///     .cross_check(&libmagic);
+    /// assert_eq!(cross_check.guess().mime_type(), &mime::IMAGE_PNG);
+    /// assert_eq!(cross_check.parity(), Some(xdg_mime::Parity::Equal));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`guess`]: #method.guess
+    /// [`path`]: #method.path
+    /// [`data`]: #method.data
+    #[cfg(feature = "libmagic")]
+    pub fn cross_check(&mut self, libmagic: &LibMagic) -> CrossCheck {
+        let libmagic = match &self.path {
+            Some(path) => Some(libmagic.mime_type_for_path(path)),
+            None if !self.data.is_empty() => Some(libmagic.mime_type_for_data(&self.data)),
+            None => None,
+        };
+        let guess = self.guess();
+
+        let parity = match &libmagic {
+            Some(Ok(mime_type)) => Some(libmagic::parity(self.db, guess.mime_type(), mime_type)),
+            _ => None,
+        };
+
+        CrossCheck {
+            guess,
+            libmagic,
+            parity,
+        }
+    }
+
+    fn resolve(&mut self) -> Guess {
+        self.load_path();
+
+        // We could not read the file in time, so the name is all we have
+        if self.deadline_exceeded {
+            let mime_type = self
+                .file_name
+                .as_ref()
+                .and_then(|name| {
+                    let name = self.backup_handling.file_name(name);
+                    timed(self.timings.as_mut().map(|t| &mut t.glob), || {
+                        self.db.get_mime_types_from_file_name(name)
+                    })
+                    .into_iter()
+                    .next()
+                })
+                .unwrap_or_else(|| self.db.unknown_type.clone());
+
+            return Guess::new(mime_type, true);
+        }
+
+        let conformance = self.db.conformance;
+        let metadata_guess = self.guess_from_metadata();
+
+        // The specification does not special case empty files, so their
+        // name takes precedence
+        let prefer_file_name = self.prefer_file_name || conformance == Conformance::Spec;
+
+        let name_mime_types: Vec<mime::Mime> = match &self.file_name {
+            Some(file_name) if metadata_guess.is_none() || prefer_file_name => {
+                let file_name = self.backup_handling.file_name(file_name);
+                timed(self.timings.as_mut().map(|t| &mut t.glob), || {
+                    self.db.get_mime_types_from_file_name(file_name)
+                })
+            }
+            _ => Vec::new(),
+        };
+
+        if let Some(guess) = metadata_guess {
+            // Placeholder files are empty, but their name is still reliable
+            if prefer_file_name
+                && *guess.mime == *APPLICATION_X_ZEROSIZE
+                && name_mime_types.len() == 1
+                && !self.db.is_unknown_type(&name_mime_types[0])
+            {
+                let mut res = Guess::new(name_mime_types[0].clone(), false);
+                res.conflict = Some(guess.mime);
+                return res;
+            }
+
+            return guess;
+        }
+
+        // File name match, and no conflicts
+        if name_mime_types.len() == 1 && !self.db.is_unknown_type(&name_mime_types[0]) {
+            return Guess::new(name_mime_types[0].clone(), false);
+        }
+
+        self.bytes_examined = self.data.len();
+        let sniffed_mime = timed(self.timings.as_mut().map(|t| &mut t.magic), || {
+            self.db.sniff(&self.data)
+        })
+        .map(|(mime, priority)| {
+            let unaliased = self.db.unalias_mime_type(mime);
+            (unaliased.unwrap_or_else(|| mime.clone()), priority)
+        })
+        .unwrap_or((self.db.unknown_type.clone(), MagicPriority::FALLBACK));
+
+        if name_mime_types.is_empty() {
+            // No names and no data => unknown MIME type
+            if self.data.is_empty() {
+                return Guess::new(self.db.unknown_type.clone(), true);
+            }
+
+            let mut mime = sniffed_mime.0;
+            if conformance != Conformance::Lenient
+                && self.db.is_unknown_type(&mime)
+                && looks_like_text(&self.data)
+            {
+                mime = mime::TEXT_PLAIN;
+            }
+
+            let uncertain = self.db.is_unknown_type(&mime);
+            return Guess::new(mime, uncertain);
+        } else {
+            let (mut mime, priority) = sniffed_mime;
+
+            // "If no magic rule matches the data (or if the content is not
+            // available), use the default type of application/octet-stream
+            // for binary data, or text/plain for textual data."
+            // -- shared-mime-info, "Recommended checking order"
+            if self.db.is_unknown_type(&mime)
+                && !self.data.is_empty()
+                && looks_like_text(&self.data)
+            {
+                mime = mime::TEXT_PLAIN;
+            }
+
+            // From the content type guessing implementation in GIO:
+            //
+            // For security reasons we don't ever want to sniff desktop files
+            // where we know the filename and it doesn't have a .desktop extension.
+            // This is because desktop files allow executing any application and
+            // we don't want to make it possible to hide them looking like something
+            // else.
+            if self.file_name.is_some() && mime == *APPLICATION_X_DESKTOP {
+                mime = mime::TEXT_PLAIN;
+            }
+
+            if !self.db.is_unknown_type(&mime) {
+                // "If any of the mimetypes resulting from a glob match is
+                // equal to or a subclass of the result from magic sniffing,
+                // use this as the result."
+                // -- shared-mime-info, "Recommended checking order"
+                let name_subclass = name_mime_types
+                    .iter()
+                    .find(|m| self.db.mime_type_subclass(m, &mime));
+
+                match conformance {
+                    Conformance::Spec => {
+                        let mime = name_subclass.cloned().unwrap_or(mime);
+                        return Guess::new(mime, false);
+                    }
+                    Conformance::GioCompatible => {
+                        if priority.is_certain() {
+                            return Guess::new(mime, false);
+                        }
+
+                        if let Some(m) = name_subclass {
+                            return Guess::new(m.clone(), false);
+                        }
+                    }
+                    Conformance::Lenient => {
+                        // We found a match with a high confidence value
+                        if priority.is_certain() {
+                            return Guess::new(mime, false);
+                        }
+
+                        // We have possible conflicts, but the data matches the
+                        // file name, so let's see if the sniffed MIME type is
+                        // a subclass of the MIME type associated to the file name,
+                        // and use that as a tie breaker.
+                        if name_mime_types
+                            .iter()
+                            .any(|m| self.db.mime_type_subclass(&mime, m))
+                        {
+                            return Guess::new(mime, false);
+                        }
+                    }
+                }
+            }
+
+            // If there are conflicts, and the data does not help us,
+            // we just pick the first result
+            if let Some(mime_type) = name_mime_types.first() {
+                return Guess::new(mime_type.clone(), true);
+            }
+        }
+
+        // Okay, we give up
+        Guess::new(self.db.unknown_type.clone(), true)
+    }
+
+    /// Returns every candidate MIME type considered for the data set on
+    /// the builder, sorted in descending order by their [`score`].
+    ///
+    /// Unlike [`guess`], this method does not pick a single result, and
+    /// does not apply any conflict resolution policy; if no candidate can
+    /// be found, the result contains the [unknown type] with a score of 0.
+    ///
+    /// Aliases are resolved to their canonical MIME type, and each MIME
+    /// type appears only once in the list; the candidates that are
+    /// subclasses of other candidates, like `text/x-python3` and
+    /// `text/x-python`, list them in their [`subclass_of`].
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mut guess_builder = mime_db.guess_mime_type();
+    /// let candidates = guess_builder.file_name("foo.png").guess_all();
+    /// assert_eq!(candidates[0].mime_type(), &Mime::from_str("image/png")?);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`score`]: struct.ScoredGuess.html#method.score
+    /// [`subclass_of`]: struct.ScoredGuess.html#method.subclass_of
+    /// [`guess`]: #method.guess
+    /// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+    pub fn guess_all(&mut self) -> Vec<ScoredGuess> {
+        self.load_path();
+
+        if let Some(guess) = self.guess_from_metadata() {
+            return vec![ScoredGuess {
+                mime: guess.mime.into_owned(),
+                score: 100,
+                glob_weight: None,
+                magic_priority: None,
+                subclass_of: Vec::new(),
+            }];
+        }
+
+        let mut res: Vec<ScoredGuess> = Vec::new();
+
+        if let Some(file_name) = &self.file_name {
+            let file_name = self.backup_handling.file_name(file_name);
+            for (mime, weight) in self.db.globs.lookup_all_mime_types_for_file_name(file_name) {
+                // The globs are sorted by weight, so the first glob for each
+                // canonical MIME type has the highest weight
+                let mime = self.db.unalias_mime_type(&mime).unwrap_or(mime);
+                if res.iter().any(|c| c.mime == mime) {
+                    continue;
+                }
+
+                let weight = GlobWeight::saturating(weight);
+                res.push(ScoredGuess {
+                    mime,
+                    score: weight.get(),
+                    glob_weight: Some(weight),
+                    magic_priority: None,
+                    subclass_of: Vec::new(),
+                });
+            }
+        }
+
+        if !self.data.is_empty() {
+            for (mime, priority) in self.db.magic.lookup_data_all(&self.data) {
+                let mime = self
+                    .db
+                    .unalias_mime_type(mime)
+                    .unwrap_or_else(|| mime.clone());
+                let priority = MagicPriority::saturating(priority);
+                match res.iter_mut().find(|c| c.mime == mime) {
+                    Some(candidate) if candidate.magic_priority.is_some() => continue,
+                    Some(candidate) => {
+                        candidate.score += priority.get();
+                        candidate.magic_priority = Some(priority);
+                    }
+                    None => res.push(ScoredGuess {
+                        mime,
+                        score: priority.get(),
+                        glob_weight: None,
+                        magic_priority: Some(priority),
+                        subclass_of: Vec::new(),
+                    }),
+                }
+            }
+        }
+
+        if res.is_empty() {
+            res.push(ScoredGuess {
+                mime: self.db.unknown_type.clone(),
+                score: 0,
+                glob_weight: None,
+                magic_priority: None,
+                subclass_of: Vec::new(),
+            });
+        }
+
+        res.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.mime.cmp(&b.mime)));
+
+        for i in 0..res.len() {
+            let subclass_of = res
+                .iter()
+                .filter(|c| {
+                    c.mime != res[i].mime && self.db.mime_type_subclass(&res[i].mime, &c.mime)
+                })
+                .map(|c| c.mime.clone())
+                .collect();
+            res[i].subclass_of = subclass_of;
+        }
+
+        res
+    }
+}
+
+// Reads the metadata of the file at `path`, and the minimum amount of data
+// necessary for a match
+// Reads the metadata and the beginning of the file, returning the first
+// error encountered, if any
+fn read_path(
+    path: &Path,
+    load_metadata: bool,
+    file_size: Option<u64>,
+    load_data: bool,
+    max_data_size: usize,
+    mut timings: Option<&mut GuessTimings>,
+) -> (Option<fs::Metadata>, Option<Vec<u8>>, Option<io::Error>) {
+    let metadata = if load_metadata {
+        match timed(timings.as_deref_mut().map(|t| &mut t.metadata), || {
+            fs::metadata(path)
+        }) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => return (None, None, Some(e)),
+        }
+    } else {
+        None
+    };
+
+    if !load_data {
+        return (metadata, None, None);
+    }
+
+    let file_size = file_size.or_else(|| metadata.as_ref().map(fs::Metadata::len));
+    let chunk_size = match file_size {
+        Some(len) if (len as usize) < max_data_size => len as usize,
+        _ => max_data_size,
+    };
+
+    // Do not read the contents of directories and devices
+    if chunk_size == 0 || metadata.as_ref().is_some_and(|m| !m.is_file()) {
+        return (metadata, None, None);
+    }
+
+    // The file may have been truncated since its size was retrieved, so
+    // we keep whatever we could read
+    let mut buf = Vec::with_capacity(chunk_size);
+    let res = timed(timings.map(|t| &mut t.read), || {
+        File::open(path)?
+            .take(chunk_size as u64)
+            .read_to_end(&mut buf)
+    });
+    if let Err(e) = res {
+        return (metadata, None, Some(e));
+    }
+
+    if buf.is_empty() {
+        return (metadata, None, None);
+    }
+
+    (metadata, Some(buf), None)
+}
+
+impl Guess {
+    fn new(mime: mime::Mime, uncertain: bool) -> Guess {
+        Guess::from_cow(Cow::Owned(mime), uncertain)
+    }
+
+    fn new_static(mime: &'static mime::Mime, uncertain: bool) -> Guess {
+        Guess::from_cow(Cow::Borrowed(mime), uncertain)
+    }
+
+    fn from_cow(mime: Cow<'static, mime::Mime>, uncertain: bool) -> Guess {
+        Guess {
+            mime,
+            uncertain,
+            unknown: false,
+            conflict: None,
+            deadline_exceeded: false,
+            read_error: None,
+            timings: None,
+            agreement: None,
+            bytes_examined: 0,
+            backup_of: None,
+        }
+    }
+
+    /// The guessed MIME type.
+    pub fn mime_type(&self) -> &mime::Mime {
+        &self.mime
+    }
+
+    /// Whether the guessed MIME type is uncertain.
+    ///
+    /// If the MIME type was guessed only from its file name there can be
+    /// multiple matches, but the [`mime_type`] method will return just the
+    /// first match.
+    ///
+    /// If you only have a file name, and you want to gather all potential
+    /// matches, you should use the [`get_mime_types_from_file_name`] method
+    /// instead of performing a guess.
+    ///
+    /// [`mime_type`]: #method.mime_type
+    /// [`get_mime_types_from_file_name`]: struct.SharedMimeInfo.html#method.get_mime_types_from_file_name
+    pub fn uncertain(&self) -> bool {
+        self.uncertain
+    }
+
+    /// Whether the guessed MIME type is one of the special types that
+    /// describe a file system object rather than the contents of a file,
+    /// like [`INODE_DIRECTORY`], [`INODE_SYMLINK`], [`APPLICATION_X_ZEROSIZE`],
+    /// or any type in the [`INODE`] and [`X_CONTENT`] media types.
+    ///
+    /// [`INODE_DIRECTORY`]: static.INODE_DIRECTORY.html
+    /// [`INODE_SYMLINK`]: static.INODE_SYMLINK.html
+    /// [`APPLICATION_X_ZEROSIZE`]: static.APPLICATION_X_ZEROSIZE.html
+    /// [`INODE`]: constant.INODE.html
+    /// [`X_CONTENT`]: constant.X_CONTENT.html
+    pub fn is_special(&self) -> bool {
+        special::is_special(&self.mime)
+    }
+
+    /// Whether the MIME type could not be determined, and the guessed MIME
+    /// type is the [unknown type] of the database.
+    ///
+    /// You should use this method instead of comparing the guessed MIME
+    /// type with `application/octet-stream`.
+    ///
+    /// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+    pub fn is_unknown(&self) -> bool {
+        self.unknown
+    }
+
+    /// The MIME type that the guess overrode, if the file name and the
+    /// contents of the file disagreed.
+    ///
+    /// For instance, if [`prefer_file_name`] is set, and an empty file has
+    /// a name matching a MIME type, the conflicting type is
+    /// `application/x-zerosize`.
+    ///
+    /// [`prefer_file_name`]: struct.GuessBuilder.html#method.prefer_file_name
+    pub fn conflict(&self) -> Option<&mime::Mime> {
+        self.conflict.as_deref()
+    }
+
+    /// Whether the file could not be read before the [deadline] expired,
+    /// and the guess only used the file name.
+    ///
+    /// [deadline]: struct.GuessBuilder.html#method.deadline
+    pub fn deadline_exceeded(&self) -> bool {
+        self.deadline_exceeded
+    }
+
+    /// The error encountered while reading the metadata or the contents of
+    /// the file set using the [`path`] method of the builder, if any.
+    ///
+    /// If an error occurred, the guess only used the information that
+    /// was available, like the file name.
+    ///
+    /// [`path`]: struct.GuessBuilder.html#method.path
+    pub fn read_error(&self) -> Option<&io::Error> {
+        self.read_error.as_ref()
+    }
+
+    /// How many sources of evidence agree with the guessed MIME type, if
+    /// the [`check_agreement`] method of the builder was enabled.
+    ///
+    /// [`check_agreement`]: struct.GuessBuilder.html#method.check_agreement
+    pub fn agreement(&self) -> Option<&Agreement> {
+        self.agreement.as_ref()
+    }
+
+    /// The MIME type of the file a backup was made of, if the name of the
+    /// file is the one of a backup and the builder was set to flag them
+    /// using [`BackupHandling::StripAndFlag`].
+    ///
+    /// The MIME type is the guessed one, since the backup suffix was
+    /// removed before matching the file name.
+    ///
+    /// [`BackupHandling::StripAndFlag`]: enum.BackupHandling.html#variant.StripAndFlag
+    pub fn is_backup_of(&self) -> Option<&mime::Mime> {
+        self.backup_of.as_ref()
+    }
+
+    /// The chain of generic types of the guessed MIME type, from the type
+    /// itself to `application/octet-stream`, as returned by
+    /// [`SharedMimeInfo::generic_chain`].
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::testing::minimal_db();
+    /// // let mime_db = ...
+    /// let guess = mime_db.guess_mime_type().file_name("main.c").guess();
+    /// let handler = guess
+    ///     .generic_chain(&mime_db)
+    ///     .into_iter()
+    ///     .find(|mime_type| *mime_type == mime::TEXT_PLAIN);
+    /// assert_eq!(handler, Some(mime::TEXT_PLAIN));
+    /// ```
+    ///
+    /// [`SharedMimeInfo::generic_chain`]: struct.SharedMimeInfo.html#method.generic_chain
+    pub fn generic_chain(&self, db: &SharedMimeInfo) -> Vec<mime::Mime> {
+        db.generic_chain(&self.mime)
+    }
+
+    /// The number of bytes of the contents of the file that the guess
+    /// examined.
+    ///
+    /// This is the number of bytes read from the [`path`] set on the
+    /// builder, or, if the result depended on the contents, the number of
+    /// bytes of [`data`] inspected by the magic entries; it is 0 if the
+    /// result only depended on the file name or on the metadata, and no
+    /// data was read. The data read from a path is limited to the number of
+    /// bytes needed to evaluate every magic entry of the database, or to
+    /// the size of the file, if smaller.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db
+    ///     .guess_mime_type()
+    ///     .data(b"\x89PNG\r\n\x1a\n")
+    ///     .guess();
+    /// assert_eq!(guess.bytes_examined(), 8);
+    /// ```
+    ///
+    /// [`path`]: struct.GuessBuilder.html#method.path
+    /// [`data`]: struct.GuessBuilder.html#method.data
+    pub fn bytes_examined(&self) -> usize {
+        self.bytes_examined
+    }
+
+    /// The time spent in each step of the guess, if the [`timings`] method
+    /// of the builder was enabled.
+    ///
+    /// [`timings`]: struct.GuessBuilder.html#method.timings
+    pub fn timings(&self) -> Option<&GuessTimings> {
+        self.timings.as_ref()
+    }
+
+    /// Serializes the guess to JSON.
+    ///
+    /// The output is a single line containing an object with the fields
+    /// of the [`Serialize`] implementation of the guess, in the same
+    /// order.
+    ///
+    /// This method is only available if the `json` feature is enabled.
+    ///
+    /// [`Serialize`]: #impl-Serialize-for-Guess
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db.guess_mime_type().file_name("foo.txt").guess();
+    /// assert_eq!(
+    ///     guess.to_json(),
+    ///     r#"{"mime_type":"text/plain","uncertain":false,"unknown":false,"conflict":null,"deadline_exceeded":false}"#
+    /// );
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("the guess can be serialized")
+    }
+}
+
+/// Serializes the guess as a struct with the following fields, in this
+/// order:
+///
+///  - `mime_type`: the guessed MIME type, as a string
+///  - `uncertain`: whether the guess is uncertain, as a boolean
+///  - `unknown`: whether the MIME type could not be determined, as a
+///    boolean
+///  - `conflict`: the [conflicting] MIME type, as a string, or `None`
+///  - `deadline_exceeded`: whether the [deadline] expired, as a boolean
+///
+/// This schema is stable: new fields may be added at the end of the
+/// struct in future versions, but existing fields will not be removed or
+/// changed.
+///
+/// This implementation is only available if the `serde` feature is
+/// enabled.
+///
+/// [conflicting]: struct.Guess.html#method.conflict
+/// [deadline]: struct.Guess.html#method.deadline_exceeded
+#[cfg(feature = "serde")]
+impl serde::Serialize for Guess {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Guess", 5)?;
+        state.serialize_field("mime_type", self.mime_type().as_ref())?;
+        state.serialize_field("uncertain", &self.uncertain)?;
+        state.serialize_field("unknown", &self.unknown)?;
+        state.serialize_field("conflict", &self.conflict().map(|m| m.as_ref()))?;
+        state.serialize_field("deadline_exceeded", &self.deadline_exceeded)?;
+        state.end()
+    }
+}
+
+impl Default for SharedMimeInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Whether an unaliased MIME type is the same as `base`, or derives from it
+// without any explicit parent: every type is a subclass of its super-type,
+// like `image/*`; every `text/*` type is a subclass of `text/plain`; and
+// every streamable type, that is, everything except the `inode/*` types,
+// is a subclass of `application/octet-stream`
+//
+// https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html#subclassing
+fn is_derived_from(mime_type: &Mime, base: &Mime) -> bool {
+    if mime_type == base {
+        return true;
+    }
+
+    if base.subtype() == mime::STAR && base.type_() == mime_type.type_() {
+        return true;
+    }
+
+    if *base == mime::TEXT_PLAIN && mime_type.type_() == mime::TEXT {
+        return true;
+    }
+
+    *base == mime::APPLICATION_OCTET_STREAM && mime_type.type_() != "inode"
+}
+
+impl SharedMimeInfo {
+    fn create() -> SharedMimeInfo {
+        SharedMimeInfo {
+            aliases: Arc::new(alias::AliasesList::new()),
+            parents: Arc::new(parent::ParentsMap::new()),
+            icons: Arc::new(icon::IconMap::new()),
+            generic_icons: Arc::new(icon::IconMap::new()),
+            globs: Arc::new(glob::GlobMap::new()),
+            magic: Arc::new(magic::DeferredMagic::default()),
+            treemagic: Arc::new(Vec::new()),
+            namespaces: Arc::new(Vec::new()),
+            types: Arc::new(Vec::new()),
+            mime_dirs: Vec::new(),
+            mime_types_files: Vec::new(),
+            unknown_type: mime::APPLICATION_OCTET_STREAM,
+            lenient: false,
+            strict: false,
+            conformance: Conformance::default(),
+            generic_globs: GenericGlobs::default(),
+            #[cfg(feature = "special-names")]
+            special_names: false,
+            subclass_depth_limit: parent::DEFAULT_DEPTH_LIMIT,
+            ancestors: None,
+            load: Subsystems::default(),
+            diagnostics: Arc::new(Vec::new()),
+            all_diagnostics: Arc::new(OnceLock::new()),
+        }
+    }
+
+    fn load_directory<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        tier: Tier,
+        ctx: &mut diagnostics::ParseContext,
+    ) {
+        let mut mime_path = PathBuf::new();
+        mime_path.push(directory);
+        mime_path.push("mime");
+
+        let segment = self.read_directory(&mime_path);
+
+        let mut mime_dir = MimeDirectory::new(mime_path, tier);
+        mime_dir.version = MimeDirectory::read_version(&mime_dir.path);
+        mime_dir.comments = Arc::new(segment.data.comments.iter().cloned().collect());
+        mime_dir.segment = Arc::new(segment);
+
+        self.merge_directory(mime_dir, ctx);
+    }
+
+    fn parse_context(&self) -> diagnostics::ParseContext {
+        let mut ctx = diagnostics::ParseContext::new(self.lenient);
+        ctx.strict = self.strict;
+
+        ctx
+    }
+
+    // Reads the MIME data of a `mime` directory, without merging it with
+    // the one of the other directories
+    fn read_directory(&self, mime_path: &Path) -> Segment {
+        #[cfg(feature = "events")]
+        let start = std::time::Instant::now();
+        let ctx = &mut self.parse_context();
+
+        // The compiled cache takes precedence over the plain text files,
+        // like in xdgmime and GIO, except in strict mode, which checks the
+        // lines of the plain text files
+        let cache_path = mime_path.join("mime.cache");
+        // The magic entries are only parsed when they are needed
+        let load = Subsystems {
+            magic: false,
+            ..self.load
+        };
+        let cache = if self.strict {
+            None
+        } else {
+            cache::read_cache_from_file(&cache_path, load, ctx)
+        };
+        #[cfg(feature = "events")]
+        if cache.is_none() && cache_path.exists() {
+            events::file_skipped(
+                &cache_path,
+                if self.strict {
+                    "the plain text files are read in strict mode"
+                } else {
+                    "the compiled cache cannot be parsed"
+                },
+            );
+        }
+
+        // Without any compiled file, update-mime-database was never run,
+        // and the source XML files are the only MIME data available
+        let packages = match cache {
+            Some(_) => None,
+            None if ["globs2", "globs", "magic"]
+                .iter()
+                .any(|name| mime_path.join(name).exists()) =>
+            {
+                None
+            }
+            None => package::read_packages_from_dir(mime_path, ctx),
+        };
+
+        let (cache_file, cache) = cache.unzip();
+        let from_cache = cache.is_some();
+        let from_packages = packages.is_some();
+        let source_path = match (&cache, &packages) {
+            (Some(_), _) => Some(cache_path),
+            (None, Some(_)) => Some(mime_path.join("packages")),
+            (None, None) => None,
+        };
+
+        let mut data = match cache.or(packages) {
+            Some(data) => data,
+            None => MimeData {
+                aliases: alias::read_aliases_from_dir(mime_path, ctx),
+                subclasses: parent::read_subclasses_from_dir(mime_path, ctx),
+                icons: if load.icons {
+                    icon::read_icons_from_dir(mime_path, false, ctx)
+                } else {
+                    Vec::new()
+                },
+                generic_icons: if load.icons {
+                    icon::read_icons_from_dir(mime_path, true, ctx)
+                } else {
+                    Vec::new()
+                },
+                globs: if load.globs {
+                    glob::read_globs_from_dir(mime_path, ctx)
+                } else {
+                    Vec::new()
+                },
+                magic: Vec::new(),
+                treemagic: if load.treemagic {
+                    treemagic::read_treemagic_from_dir(mime_path, ctx)
+                } else {
+                    Vec::new()
+                },
+                namespaces: namespace::read_namespaces_from_dir(mime_path, ctx),
+                types: types::read_types_from_dir(mime_path, ctx),
+                comments: Vec::new(),
+            },
+        };
+
+        // The mime.cache file does not contain the tree magic entries, nor
+        // the list of MIME types
+        if from_cache {
+            if load.treemagic {
+                data.treemagic = treemagic::read_treemagic_from_dir(mime_path, ctx);
+            }
+            data.types = types::read_types_from_dir(mime_path, ctx);
+        }
+
+        // The source XML files are read as a whole
+        if !load.icons {
+            data.icons.clear();
+            data.generic_icons.clear();
+        }
+        if !load.globs {
+            data.globs.clear();
+        }
+        if !load.treemagic {
+            data.treemagic.clear();
+        }
+
+        let magic = match (self.load.magic, cache_file, from_packages) {
+            (false, _, _) => None,
+            (true, Some(file), _) => Some(magic::MagicSource::Cache(file)),
+            (true, None, true) => Some(magic::MagicSource::Entries(mem::take(&mut data.magic))),
+            (true, None, false) => Some(magic::MagicSource::Dir(mime_path.to_path_buf())),
+        };
+
+        let mut globs_path = match &source_path {
+            Some(path) => path.clone(),
+            None => mime_path.join("globs2"),
+        };
+        if source_path.is_none() && !globs_path.exists() {
+            globs_path.set_file_name("globs");
+        }
+
+        #[cfg(feature = "events")]
+        {
+            for diagnostic in &ctx.diagnostics {
+                events::parse_failure(diagnostic);
+            }
+            events::directory_loaded(mime_path, source_path.as_deref(), start.elapsed());
+        }
+
+        Segment {
+            data,
+            source_path,
+            globs_path,
+            magic: magic.map(|source| Arc::new(magic::MagicSegment::new(source))),
+            diagnostics: mem::take(&mut ctx.diagnostics),
+        }
+    }
+
+    // Merges the MIME data read from a directory after the one of the
+    // directories with a higher precedence
+    fn merge_directory(&mut self, mime_dir: MimeDirectory, ctx: &mut diagnostics::ParseContext) {
+        let tier = mime_dir.tier;
+        let segment = Arc::clone(&mime_dir.segment);
+        let data = &segment.data;
+        let source = |name: &str| match &segment.source_path {
+            Some(path) => path.clone(),
+            None => mime_dir.path.join(name),
+        };
+
+        ctx.diagnostics.extend(segment.diagnostics.iter().cloned());
+
+        let mut aliases = data.aliases.clone();
+        for a in &mut aliases {
+            a.tier = tier;
+        }
+        let aliases_path = source("aliases");
+        for a in &aliases {
+            let entry = format!("{} {}", a.alias, a.mime_type);
+            ctx.check_shadowed("aliases", a.alias.to_string(), &aliases_path, entry);
+        }
+        Arc::make_mut(&mut self.aliases).add_aliases(aliases);
+
+        let mut icons = data.icons.clone();
+        icons.iter_mut().for_each(|i| i.set_tier(tier));
+        let icons_path = source("icons");
+        for i in &icons {
+            let entry = format!("{}:{}", i.mime_type(), i.icon_name());
+            ctx.check_shadowed("icons", i.mime_type().to_string(), &icons_path, entry);
+        }
+        Arc::make_mut(&mut self.icons).add_icons(icons);
+
+        let mut generic_icons = data.generic_icons.clone();
+        generic_icons.iter_mut().for_each(|i| i.set_tier(tier));
+        let generic_icons_path = source("generic-icons");
+        for i in &generic_icons {
+            let entry = format!("{}:{}", i.mime_type(), i.icon_name());
+            let key = i.mime_type().to_string();
+            ctx.check_shadowed("generic-icons", key, &generic_icons_path, entry);
+        }
+        Arc::make_mut(&mut self.generic_icons).add_icons(generic_icons);
+
+        Arc::make_mut(&mut self.parents).add_subclasses(data.subclasses.clone());
+
+        let mut globs = data.globs.clone();
+        globs.iter_mut().for_each(|g| g.set_tier(tier));
+        self.sanitize_globs(&mut globs, &segment.globs_path, ctx);
+        for g in &globs {
+            let key = format!("{}:{}", g.mime_type(), g.pattern());
+            ctx.check_shadowed("globs", key, &segment.globs_path, g.to_v2_string());
+        }
+        Arc::make_mut(&mut self.globs).add_globs(&globs);
+
+        if let Some(magic) = &segment.magic {
+            Arc::make_mut(&mut self.magic).add_segment(Arc::clone(magic), tier);
+        }
+
+        Arc::make_mut(&mut self.treemagic).extend(data.treemagic.iter().cloned());
+
+        let namespaces_path = source("XMLnamespaces");
+        for ns in &data.namespaces {
+            let key = format!("{} {}", ns.namespace, ns.local_name);
+            let entry = format!("{} {}", key, ns.mime_type);
+            ctx.check_shadowed("XMLnamespaces", key, &namespaces_path, entry);
+        }
+        Arc::make_mut(&mut self.namespaces).extend(data.namespaces.iter().cloned());
+
+        let all_types = Arc::make_mut(&mut self.types);
+        all_types.extend(data.types.iter().cloned());
+        types::sort_types(all_types);
+
+        self.mime_dirs.push(mime_dir);
+    }
+
+    // Drops or down-weights the generic patterns, according to the policy
+    fn sanitize_globs(
+        &self,
+        globs: &mut Vec<glob::Glob>,
+        path: &Path,
+        ctx: &mut diagnostics::ParseContext,
+    ) {
+        if self.generic_globs == GenericGlobs::Keep {
+            return;
+        }
+
+        let dropped = self.generic_globs == GenericGlobs::Drop;
+        globs.retain_mut(|g| {
+            if !g.is_generic() {
+                return true;
+            }
+
+            ctx.diagnostics.push(Diagnostic::GenericGlob {
+                path: path.to_path_buf(),
+                entry: g.to_v2_string(),
+                dropped,
+            });
+            g.set_weight(GlobWeight::MIN);
+
+            !dropped
+        });
+    }
+
+    fn load_mime_types_file(
+        &mut self,
+        path: PathBuf,
+        tier: Tier,
+        ctx: &mut diagnostics::ParseContext,
+    ) {
+        let mut file_ctx = self.parse_context();
+        let globs = glob::read_globs_from_mime_types_file(&path, &mut file_ctx).unwrap_or_default();
+
+        let mut file = MimeDirectory::new(path, tier);
+        file.segment = Arc::new(Segment {
+            data: MimeData {
+                globs,
+                ..MimeData::default()
+            },
+            source_path: Some(file.path.clone()),
+            globs_path: file.path.clone(),
+            magic: None,
+            diagnostics: file_ctx.diagnostics,
+        });
+
+        self.merge_mime_types_file(file, ctx);
+    }
+
+    fn merge_mime_types_file(&mut self, file: MimeDirectory, ctx: &mut diagnostics::ParseContext) {
+        let segment = Arc::clone(&file.segment);
+        ctx.diagnostics.extend(segment.diagnostics.iter().cloned());
+
+        let mut globs = segment.data.globs.clone();
+        globs.iter_mut().for_each(|g| g.set_tier(file.tier));
+        self.sanitize_globs(&mut globs, &file.path, ctx);
+        for g in &globs {
+            let key = format!("{}:{}", g.mime_type(), g.pattern());
+            ctx.check_shadowed("globs", key, &file.path, g.to_v2_string());
+        }
+        Arc::make_mut(&mut self.globs).add_globs(&globs);
+
+        // The mime.types file has no other way to define a MIME type
+        let all_types = Arc::make_mut(&mut self.types);
+        all_types.extend(globs.iter().map(|g| g.mime_type().clone()));
+        types::sort_types(all_types);
+
+        self.mime_types_files.push(file);
+    }
+
+    // Adds the names of the curated table that no pattern matches yet
+    #[cfg(feature = "special-names")]
+    fn load_special_names(&mut self) {
+        self.special_names = true;
+
+        for (name, glob, subclass) in special_names::special_names() {
+            if self.globs.first_mime_type_for_file_name(name).is_some() {
+                continue;
+            }
+
+            let mime_type = glob.mime_type().clone();
+            Arc::make_mut(&mut self.globs).add_glob(glob);
+
+            if let Some(subclass) = subclass {
+                if self.parents.lookup(&mime_type).is_none() {
+                    Arc::make_mut(&mut self.parents).add_subclasses(vec![subclass]);
+                }
+            }
+
+            let all_types = Arc::make_mut(&mut self.types);
+            all_types.push(mime_type);
+            types::sort_types(all_types);
+        }
+    }
+
+    // Reports the MIME types whose parents reach the depth limit, and
+    // computes their ancestors, if requested
+    fn check_subclasses(&mut self, ctx: &mut diagnostics::ParseContext) {
+        let mut mime_types: Vec<Mime> = self
+            .parents
+            .iter()
+            .map(|(mime_type, _)| {
+                self.unalias_mime_type(mime_type)
+                    .unwrap_or_else(|| mime_type.clone())
+            })
+            .collect();
+        mime_types.sort();
+        mime_types.dedup();
+
+        let precompute = self.ancestors.is_some();
+        let mut ancestors = parent::Ancestors::default();
+        for mime_type in &mime_types {
+            let mut found = Vec::new();
+            let truncated = self
+                .walk_ancestors(mime_type, |ancestor| {
+                    found.push(ancestor.clone());
+                    false
+                })
+                .is_none();
+
+            if truncated {
+                ctx.diagnostics.push(Diagnostic::SubclassDepthExceeded {
+                    mime_type: mime_type.clone(),
+                    limit: self.subclass_depth_limit,
+                });
+            }
+            if precompute {
+                ancestors.insert(mime_type, &found, truncated);
+            }
+        }
+
+        if precompute {
+            self.ancestors = Some(Arc::new(ancestors));
+        }
+    }
+
+    // Reports databases that cannot recognize anything
+    fn check_empty(&self, ctx: &mut diagnostics::ParseContext) {
+        if self.is_empty() {
+            let searched = self
+                .mime_dirs
+                .iter()
+                .chain(&self.mime_types_files)
+                .map(|dir| dir.path.clone())
+                .collect();
+            ctx.diagnostics.push(Diagnostic::NoMimeData { searched });
+        }
+    }
+
+    /// Creates a new `SharedMimeInfo` instance containing all MIME information
+    /// under the [standard XDG base directories][xdg-basedir].
+    ///
+    /// The `$XDG_DATA_HOME` and `$XDG_DATA_DIRS` environment variables are
+    /// honored; if they are unset, or only contain relative paths, the
+    /// defaults mandated by the specification are used instead. If you need
+    /// to use different directories, see [`builder`].
+    ///
+    /// The `mime.cache` file generated by `update-mime-database` in each
+    /// directory is preferred over the plain text files, if it can be read.
+    /// If `update-mime-database` was never run on a directory, and it only
+    /// contains the source XML files in `mime/packages`, those are used
+    /// instead; the definitions in `Override.xml` take precedence over the
+    /// ones in the other packages.
+    ///
+    /// The magic entries are only parsed the first time the contents of a
+    /// file are checked, so that applications only looking at file names
+    /// do not pay for them.
+    ///
+    /// Loading the database is best-effort: the directories that cannot be
+    /// read, and the entries that cannot be parsed, are skipped, and
+    /// reported by the [`diagnostics`] method. Use [`try_new`] to fail
+    /// instead.
+    ///
+    /// [xdg-basedir]: http://standards.freedesktop.org/basedir-spec/basedir-spec-latest.html
+    /// [`builder`]: #method.builder
+    /// [`diagnostics`]: #method.diagnostics
+    /// [`try_new`]: #method.try_new
+    pub fn new() -> SharedMimeInfo {
+        SharedMimeInfo::builder().build()
+    }
+
+    /// Loads all the MIME information under the standard XDG base
+    /// directories, like [`new`], but fails if the home directory of the
+    /// user cannot be found, if one of the `mime` directories cannot be
+    /// read, or if an entry of the database cannot be parsed.
+    ///
+    /// See [`SharedMimeInfoBuilder::try_build`] for the details.
+    ///
+    /// ```rust
+    /// let mime_db = match xdg_mime::SharedMimeInfo::try_new() {
+    ///     Ok(mime_db) => mime_db,
+    ///     Err(e) => {
+    ///         eprintln!("Cannot load the MIME database: {}", e);
+    ///         return;
+    ///     }
+    /// };
+    /// let guess = mime_db.guess_mime_type().file_name("notes.txt").guess();
+    /// ```
+    ///
+    /// [`new`]: #method.new
+    /// [`SharedMimeInfoBuilder::try_build`]: struct.SharedMimeInfoBuilder.html#method.try_build
+    pub fn try_new() -> Result<SharedMimeInfo, Error> {
+        SharedMimeInfo::builder().try_build()
+    }
+
+    /// Loads all the MIME information under the standard XDG base
+    /// directories on a background thread, like [`new`], and returns a
+    /// handle to check whether the database is ready, or to wait for it.
+    ///
+    /// See [`Loading`] for the details.
+    ///
+    /// [`new`]: #method.new
+    /// [`Loading`]: struct.Loading.html
+    pub fn new_async() -> Loading {
+        SharedMimeInfo::builder().spawn_build()
+    }
+
+    /// Restores a database from a snapshot written by
+    /// [`serialize_snapshot`], for the standard XDG base directories used
+    /// by [`new`].
+    ///
+    /// The snapshot is rejected once one of the directories changed; see
+    /// [`SharedMimeInfoBuilder::build_from_snapshot`] for the details.
+    ///
+    /// ```rust
+    /// use std::fs;
+    /// use xdg_mime::SharedMimeInfo;
+    ///
+    /// let cache = std::env::temp_dir().join("xdg-mime-doctest.snapshot");
+    /// let snapshot = fs::read(&cache).unwrap_or_default();
+    /// let mime_db = match SharedMimeInfo::from_snapshot(&snapshot) {
+    ///     Ok(mime_db) => mime_db,
+    ///     Err(_) => {
+    ///         let mime_db = SharedMimeInfo::new();
+    ///         let _ = fs::write(&cache, mime_db.serialize_snapshot());
+    ///         mime_db
+    ///     }
+    /// };
+    /// # let _ = fs::remove_file(&cache);
+    /// ```
+    ///
+    /// This method requires the `snapshot` feature.
+    ///
+    /// [`serialize_snapshot`]: #method.serialize_snapshot
+    /// [`new`]: #method.new
+    /// [`SharedMimeInfoBuilder::build_from_snapshot`]: struct.SharedMimeInfoBuilder.html#method.build_from_snapshot
+    #[cfg(feature = "snapshot")]
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<SharedMimeInfo, Error> {
+        SharedMimeInfo::builder().build_from_snapshot(snapshot)
+    }
+
+    /// Creates a new [`SharedMimeInfoBuilder`], which can be used to override
+    /// the directories from which the MIME information is loaded.
+    ///
+    /// [`SharedMimeInfoBuilder`]: struct.SharedMimeInfoBuilder.html
+    pub fn builder() -> SharedMimeInfoBuilder {
+        SharedMimeInfoBuilder::default()
+    }
+
+    /// Loads all the MIME information under `directory`, and creates a new
+    /// [`SharedMimeInfo`] instance for it.
+    ///
+    /// This method is only really useful for testing purposes; you should
+    /// always use the [`new`] method, instead.
+    ///
+    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+    /// [`new`]: #method.new
+    pub fn new_for_directory<P: AsRef<Path>>(directory: P) -> SharedMimeInfo {
+        let mut db = SharedMimeInfo::create();
+        let mut ctx = diagnostics::ParseContext::new(false);
+
+        db.load_directory(directory, Tier::System, &mut ctx);
+        db.check_subclasses(&mut ctx);
+        db.check_empty(&mut ctx);
+        db.diagnostics = Arc::new(ctx.diagnostics);
+
+        db
+    }
+
+    /// Reloads the contents of the [`SharedMimeInfo`] type from the directories
+    /// used to populate it at construction time. You should use this method
+    /// if you're planning to keep the database around for long running operations
+    /// or applications.
+    ///
+    /// This method does not do anything if the directories haven't changed
+    /// since the time they were loaded last. Directories that did not exist
+    /// at the time they were loaded are also checked, so the MIME data will
+    /// be picked up once they are created. Only the directories that changed
+    /// are read again; the data of the other ones is kept from the last
+    /// time they were read, and merged again.
+    ///
+    /// This method will return `true` if the contents of the shared MIME
+    /// database were updated.
+    ///
+    /// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+    pub fn reload(&mut self) -> bool {
+        match self.reloaded() {
+            Some(db) => {
+                *self = db;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds the MIME information under `directory` to the database, for
+    /// instance the private MIME types that an application ships in its
+    /// installation prefix, in `share/mime`.
+    ///
+    /// The directory has a lower precedence than the ones already loaded,
+    /// like the [`extra_dirs`] of the builder, and it is loaded again by
+    /// the [`reload`] method. The problems found in the directory are added
+    /// to the [`diagnostics`].
+    ///
+    /// Returns `false` if the directory was already part of the database.
+    ///
+    /// ```rust
+    /// let mut mime_db = xdg_mime::SharedMimeInfo::new();
+    /// mime_db.add_directory("/opt/app/share");
+    /// ```
+    ///
+    /// [`extra_dirs`]: struct.SharedMimeInfoBuilder.html#method.extra_dirs
+    /// [`reload`]: #method.reload
+    /// [`diagnostics`]: #method.diagnostics
+    pub fn add_directory<P: AsRef<Path>>(&mut self, directory: P) -> bool {
+        let mime_path = directory.as_ref().join("mime");
+        if self.mime_dirs.iter().any(|dir| dir.path == mime_path) {
+            return false;
+        }
+
+        let mut ctx = self.parse_context();
+        self.load_directory(directory, Tier::System, &mut ctx);
+
+        // The checks of the whole database are done again
+        let mut diagnostics: Vec<Diagnostic> = self
+            .diagnostics
+            .iter()
+            .filter(|d| {
+                !matches!(
+                    d,
+                    Diagnostic::SubclassDepthExceeded { .. } | Diagnostic::NoMimeData { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        self.check_subclasses(&mut ctx);
+        self.check_empty(&mut ctx);
+        diagnostics.append(&mut ctx.diagnostics);
+        self.diagnostics = Arc::new(diagnostics);
+        self.all_diagnostics = Arc::new(OnceLock::new());
+
+        true
+    }
+
+    // The current modification times of the directories and files the
+    // database was loaded from, or None if none of them changed since
+    fn changed_mtimes(&self) -> Option<Vec<Option<SystemTime>>> {
+        let mut dirs = self.mime_dirs.iter().chain(&self.mime_types_files);
+        if !dirs.clone().any(MimeDirectory::is_changed) {
+            return None;
+        }
+
+        Some(
+            dirs.by_ref()
+                .map(|dir| MimeDirectory::current_mtime(&dir.path))
+                .collect(),
+        )
+    }
+
+    // Loads a new copy of the database from the same directories, only if
+    // they changed since the time they were loaded last
+    fn reloaded(&self) -> Option<SharedMimeInfo> {
+        // Do not reload the data if nothing has changed; directories that
+        // did not exist at construction time are still tracked, so that we
+        // can pick them up once they are created
+        if !self
+            .mime_dirs
+            .iter()
+            .chain(&self.mime_types_files)
+            .any(MimeDirectory::is_changed)
+        {
+            return None;
+        }
+
+        let mut db = SharedMimeInfo::create();
+        db.unknown_type = self.unknown_type.clone();
+        db.lenient = self.lenient;
+        db.strict = self.strict;
+        db.magic = Arc::new(magic::DeferredMagic::new(self.lenient, self.strict));
+        db.conformance = self.conformance;
+        db.generic_globs = self.generic_globs;
+        db.subclass_depth_limit = self.subclass_depth_limit;
+        if self.ancestors.is_some() {
+            db.ancestors = Some(Arc::default());
+        }
+        db.load = self.load;
+
+        // Only the directories that changed are read again; the data of
+        // the other ones is merged as it was read the last time
+        let mut ctx = self.parse_context();
+        for dir in &self.mime_dirs {
+            if !dir.is_changed() {
+                db.merge_directory(dir.clone(), &mut ctx);
+                continue;
+            }
+
+            // Pop the `mime` chunk, since load_directory() will
+            // automatically add it back
+            let mut base_dir = PathBuf::new();
+            base_dir.push(&dir.path);
+            base_dir.pop();
+
+            db.load_directory(base_dir, dir.tier, &mut ctx);
+        }
+
+        for file in &self.mime_types_files {
+            if file.is_changed() {
+                db.load_mime_types_file(file.path.clone(), file.tier, &mut ctx);
+            } else {
+                db.merge_mime_types_file(file.clone(), &mut ctx);
+            }
+        }
+
+        #[cfg(feature = "special-names")]
+        if self.special_names {
+            db.load_special_names();
+        }
+
+        db.check_subclasses(&mut ctx);
+        db.check_empty(&mut ctx);
+        db.diagnostics = Arc::new(ctx.diagnostics);
+
+        Some(db)
+    }
+
+    /// Retrieves the version of shared-mime-info that compiled the
+    /// database, read from the `version` file written by
+    /// `update-mime-database`, like `2.4`.
+    ///
+    /// The version comes from the directory with the highest precedence
+    /// that has a `version` file; use [`directory_versions`] to check each
+    /// directory. If no directory has one, the database was never compiled,
+    /// or it was compiled by an old version of shared-mime-info.
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::new();
+    ///
+    /// if mime_db.database_version().is_none() {
+    ///     eprintln!("The MIME database was never compiled");
+    /// }
+    /// ```
+    ///
+    /// [`directory_versions`]: #method.directory_versions
+    pub fn database_version(&self) -> Option<&str> {
+        self.mime_dirs.iter().find_map(|dir| dir.version.as_deref())
+    }
+
+    /// Iterates over the `mime` directories of the database, and the
+    /// version of shared-mime-info that compiled each of them, if any, in
+    /// order of precedence.
+    ///
+    /// Directories that did not exist when the database was loaded are
+    /// included, without a version.
+    pub fn directory_versions(&self) -> impl Iterator<Item = (&Path, Option<&str>)> {
+        self.mime_dirs
+            .iter()
+            .map(|dir| (dir.path.as_path(), dir.version.as_deref()))
+    }
+
+    /// Retrieves the problems found while loading the database.
+    ///
+    /// The magic entries are parsed, if they were not parsed yet, so that
+    /// their problems are included.
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::new();
+    ///
+    /// for diagnostic in mime_db.diagnostics() {
+    ///     eprintln!("{}", diagnostic);
+    /// }
+    /// ```
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.all_diagnostics.get_or_init(|| {
+            let mut res = self.diagnostics.to_vec();
+            res.extend_from_slice(self.magic.diagnostics());
+            res
+        })
+    }
+
+    /// Checks whether the database contains no glob patterns and no magic
+    /// entries, for instance because none of the MIME directories exist.
+    ///
+    /// An empty database returns the [unknown type] for every file, so
+    /// applications may want to warn the user about a broken installation;
+    /// in this case, the [diagnostics] contain a [`Diagnostic::NoMimeData`]
+    /// with the directories that were searched.
+    ///
+    /// ```rust
+    /// let mime_db = xdg_mime::SharedMimeInfo::new_for_directory("/does/not/exist");
+    /// assert!(mime_db.is_empty());
+    /// ```
+    ///
+    /// [unknown type]: #method.unknown_type
+    /// [diagnostics]: #method.diagnostics
+    /// [`Diagnostic::NoMimeData`]: enum.Diagnostic.html#variant.NoMimeData
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty() && self.magic.is_empty()
+    }
+
+    /// Retrieves the MIME type aliased by a MIME type, if any.
+    pub fn unalias_mime_type(&self, mime_type: &Mime) -> Option<Mime> {
+        self.aliases.unalias_mime_type(mime_type)
+    }
+
+    /// Retrieves where the definition of an alias comes from, or `None` if
+    /// `alias` is not an alias.
+    pub fn alias_source_tier(&self, alias: &Mime) -> Option<Tier> {
+        self.aliases.alias_tier(alias)
+    }
+
+    /// Retrieves where the icon of a MIME type comes from, or `None` if
+    /// the database does not contain an icon for it.
+    ///
+    /// When more than one directory defines an icon for the same MIME
+    /// type, the directory with the highest precedence wins, so the icons
+    /// of the user override the ones of the system; the icons that are
+    /// ignored are reported by the [`diagnostics`] method.
+    ///
+    /// [`diagnostics`]: #method.diagnostics
+    pub fn icon_source_tier(&self, mime_type: &Mime) -> Option<Tier> {
+        self.icons.find_icon(mime_type).map(icon::Icon::tier)
+    }
+
+    /// Retrieves where the generic icon of a MIME type comes from, like
+    /// [`icon_source_tier`], or `None` if the database does not contain a
+    /// generic icon for it.
+    ///
+    /// [`icon_source_tier`]: #method.icon_source_tier
+    pub fn generic_icon_source_tier(&self, mime_type: &Mime) -> Option<Tier> {
+        self.generic_icons
+            .find_icon(mime_type)
+            .map(icon::Icon::tier)
+    }
+
+    /// Looks up the icons associated to a MIME type.
+    ///
+    /// The icons can be looked up within the current [icon theme][xdg-icon-theme].
+    ///
+    /// [xdg-icon-theme]: https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
+    pub fn lookup_icon_names(&self, mime_type: &Mime) -> Vec<String> {
+        let mut res = Vec::new();
+
+        if let Some(v) = icon::find_icon(&self.icons, mime_type) {
+            res.push(v);
+        };
+
+        res.push(mime_type.essence_str().replace('/', "-"));
+
+        match icon::find_icon(&self.generic_icons, mime_type) {
+            Some(v) => res.push(v),
+            None => {
+                let generic = format!("{}-x-generic", mime_type.type_());
+                res.push(generic);
+            }
+        };
+
+        res
+    }
+
+    /// Looks up the generic icon associated to a MIME type.
+    ///
+    /// The icon can be looked up within the current [icon theme][xdg-icon-theme].
+    ///
+    /// [xdg-icon-theme]: https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
+    pub fn lookup_generic_icon_name(&self, mime_type: &Mime) -> Option<String> {
+        let res = match icon::find_icon(&self.generic_icons, mime_type) {
+            Some(v) => v,
+            None => format!("{}-x-generic", mime_type.type_()),
+        };
+
+        Some(res)
+    }
+
+    /// Retrieves the description of a MIME type, like "PNG image" for
+    /// `image/png`, suitable for showing to users.
+    ///
+    /// The description is the untranslated `<comment>` element of the XML
+    /// definition of the MIME type, like `image/png.xml`, in the directory
+    /// with the highest precedence that defines it; aliases are resolved
+    /// to their canonical MIME type. The definitions are read when needed,
+    /// and are not kept in memory.
+    ///
+    /// Use [`get_translated_description`] or [`get_description_for_locale`]
+    /// to get a translated description.
+    ///
+    /// [`get_translated_description`]: #method.get_translated_description
+    /// [`get_description_for_locale`]: #method.get_description_for_locale
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let png = Mime::from_str("image/png")?;
+    /// if let Some(description) = mime_db.get_description(&png) {
+    ///     println!("{}: {}", png, description);
+    /// }
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_description(&self, mime_type: &Mime) -> Option<String> {
+        self.get_description_for_locale(mime_type, &[])
+    }
+
+    /// Retrieves the description of a MIME type, translated in the first
+    /// of the `locales` for which a translation exists, like
+    /// [`get_description`].
+    ///
+    /// Each locale is a POSIX locale name, like `de_DE.UTF-8` or
+    /// `sr_RS@latin`, or a language tag, like `pt-BR`; more generic
+    /// variants are tried before moving on to the next locale, so `de_DE`
+    /// falls back to `de`. If no translation matches, or once the `C`
+    /// locale is reached, the untranslated description is returned.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let png = Mime::from_str("image/png")?;
+    /// let description = mime_db.get_description_for_locale(&png, &["de_DE", "fr"]);
+    /// # let _ = description;
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get_description`]: #method.get_description
+    pub fn get_description_for_locale(&self, mime_type: &Mime, locales: &[&str]) -> Option<String> {
+        let mime_type = self
+            .unalias_mime_type(mime_type)
+            .unwrap_or_else(|| mime_type.clone());
+
+        self.mime_dirs.iter().find_map(|dir| {
+            let comments = match dir.comments.get(&mime_type) {
+                Some(comments) => Cow::Borrowed(comments),
+                None => Cow::Owned(comment::read_comments_from_dir(&dir.path, &mime_type)),
+            };
+
+            comments.lookup(locales).map(str::to_string)
+        })
+    }
+
+    /// Retrieves the description of a MIME type, translated in the
+    /// language of the messages of the process.
+    ///
+    /// The locales are selected like gettext does: the first of the
+    /// `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables that is
+    /// set selects the locale, and unless it is the `C` locale, the
+    /// languages of the colon-separated `LANGUAGE` list are tried first;
+    /// they are then matched like [`get_description_for_locale`] does.
+    ///
+    /// To render the descriptions in a different locale, like the one of
+    /// each request of a server, use [`get_description_for_locale`].
+    ///
+    /// [`get_description_for_locale`]: #method.get_description_for_locale
+    pub fn get_translated_description(&self, mime_type: &Mime) -> Option<String> {
+        let locales = comment::process_locales();
+        let locales: Vec<&str> = locales.iter().map(String::as_str).collect();
+
+        self.get_description_for_locale(mime_type, &locales)
+    }
+
+    /// Retrieves the information stored in the database about `mime_type`.
+    ///
+    /// Aliases are resolved to their canonical MIME type; the fields of
+    /// the record are empty if the database contains no information
+    /// about the MIME type.
+    pub fn mime_type_record(&self, mime_type: &Mime) -> MimeTypeRecord {
+        let mime_type = self
+            .unalias_mime_type(mime_type)
+            .unwrap_or_else(|| mime_type.clone());
+
+        let mut aliases: Vec<Mime> = self
+            .aliases
+            .iter()
+            .filter(|a| a.mime_type == mime_type)
+            .map(|a| a.alias.clone())
+            .collect();
+        aliases.sort();
+        aliases.dedup();
+
+        let parents = self.parents.lookup(&mime_type).cloned().unwrap_or_default();
+
+        MimeTypeRecord {
+            icon: icon::find_icon(&self.icons, &mime_type),
+            generic_icon: self
+                .lookup_generic_icon_name(&mime_type)
+                .unwrap_or_default(),
+            globs: self.globs.patterns_for_mime_types(
+                &std::iter::once(&mime_type)
+                    .chain(&aliases)
+                    .collect::<Vec<_>>(),
+            ),
+            aliases,
+            parents,
+            mime_type,
+        }
+    }
+
+    /// Retrieves all the parent MIME types associated to `mime_type`.
+    pub fn get_parents(&self, mime_type: &Mime) -> Option<Vec<Mime>> {
+        let unaliased = self.aliases.unalias_mime_type(mime_type)?;
+
+        let parents = self
+            .parents
+            .lookup(&unaliased)
+            .map_or(&[][..], Vec::as_slice);
+
+        let mut res = Vec::with_capacity(1 + parents.len());
+        res.push(unaliased);
+        res.extend_from_slice(parents);
+
+        Some(res)
+    }
+
+    /// Retrieves the list of matching MIME types for the given file name,
+    /// without looking at the data inside the file.
+    ///
+    /// Aliases are resolved to their canonical MIME type, and each MIME
+    /// type appears only once in the list.
+    ///
+    /// Following the [recommended checking order], only the globs with
+    /// the highest weight are taken into account and, among those, only
+    /// the ones with the longest pattern. The resulting MIME types are
+    /// sorted alphabetically, so the list does not depend on the order in
+    /// which the globs were loaded.
+    ///
+    /// If no specific MIME-type can be determined, returns a single
+    /// element vector containing the [unknown type], which is
+    /// `application/octet-stream` by default.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mime_types: Vec<Mime> = mime_db.get_mime_types_from_file_name("file.txt");
+    /// assert_eq!(mime_types, vec![Mime::from_str("text/plain")?]);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The MIME types registered for the glob patterns are available,
+    /// without resolving aliases, through the [`glob_map`] method.
+    ///
+    /// [recommended checking order]: https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html
+    /// [unknown type]: #method.unknown_type
+    /// [`glob_map`]: #method.glob_map
+    pub fn get_mime_types_from_file_name(&self, file_name: &str) -> Vec<Mime> {
+        match self.globs.lookup_mime_type_for_file_name(file_name) {
+            Some(v) => self.dedup_aliases(v),
+            None => {
+                vec![self.unknown_type.clone()]
+            }
+        }
+    }
+
+    /// Retrieves the best MIME type matching the given file name.
+    ///
+    /// This is the first element of the list returned by
+    /// [`get_mime_types_from_file_name`], but it is computed without
+    /// building the whole list of candidates.
+    ///
+    /// Returns `None` if no glob pattern matches the file name.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mime_type = mime_db.first_mime_type_from_file_name("file.txt");
+    /// assert_eq!(mime_type, Some(mime::TEXT_PLAIN));
+    /// ```
+    ///
+    /// [`get_mime_types_from_file_name`]: #method.get_mime_types_from_file_name
+    pub fn first_mime_type_from_file_name(&self, file_name: &str) -> Option<Mime> {
+        let mime_type = self.globs.first_mime_type_for_file_name(file_name)?;
+
+        Some(
+            self.unalias_mime_type(mime_type)
+                .unwrap_or_else(|| mime_type.clone()),
+        )
+    }
+
+    /// Retrieves the best MIME type matching each of the given file names.
+    ///
+    /// The result is the same as calling [`first_mime_type_from_file_name`]
+    /// for every name, but the glob patterns are prepared only once for
+    /// the whole batch, which makes this faster for large numbers of names,
+    /// like the contents of a directory.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mime_types = mime_db.classify_names(["a.txt", "b.png", "c"]);
+    /// assert_eq!(
+    ///     mime_types,
+    ///     vec![Some(mime::TEXT_PLAIN), Some(mime::IMAGE_PNG), None]
+    /// );
+    /// ```
+    ///
+    /// [`first_mime_type_from_file_name`]: #method.first_mime_type_from_file_name
+    pub fn classify_names<'a, I>(&self, names: I) -> Vec<Option<Mime>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let batch = self.globs.batch_lookup();
+
+        // The unaliased MIME type of each glob, resolved on first use
+        let mut resolved: Vec<Option<Mime>> = vec![None; batch.len()];
+
+        names
+            .into_iter()
+            .map(|name| {
+                let idx = batch.lookup(name)?;
+                let mime_type = resolved[idx].get_or_insert_with(|| {
+                    let mime_type = batch.mime_type(idx);
+                    self.unalias_mime_type(mime_type)
+                        .unwrap_or_else(|| mime_type.clone())
+                });
+
+                Some(mime_type.clone())
+            })
+            .collect()
+    }
+
+    /// Iterates over every glob pattern matching the given file name, in
+    /// ranked order.
+    ///
+    /// The patterns are sorted like in the [recommended checking order]:
+    /// by weight, in descending order, then by length, from the longest
+    /// one, and then by MIME type; the MIME type of the first pattern is
+    /// the first one returned by [`get_mime_types_from_file_name`], once
+    /// aliases are resolved. This is mostly useful to show why a file is
+    /// classified a certain way.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// for m in mime_db.matching_globs("archive.tar.gz") {
+    ///     println!("{} {} ({})", m.weight().get(), m.pattern(), m.mime_type());
+    /// }
+    /// ```
+    ///
+    /// [recommended checking order]: https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html
+    /// [`get_mime_types_from_file_name`]: #method.get_mime_types_from_file_name
+    pub fn matching_globs(&self, file_name: &str) -> impl Iterator<Item = GlobMatch<'_>> {
+        self.globs.matching_globs(file_name).into_iter()
+    }
+
+    /// How the MIME types are guessed in the cases where the specification
+    /// and GIO disagree.
+    ///
+    /// See [`SharedMimeInfoBuilder::conformance`].
+    ///
+    /// [`SharedMimeInfoBuilder::conformance`]: struct.SharedMimeInfoBuilder.html#method.conformance
+    pub fn conformance(&self) -> Conformance {
+        self.conformance
+    }
+
+    /// The glob patterns loaded from the database.
+    ///
+    /// Unlike [`get_mime_types_from_file_name`], the lookups on the
+    /// [`GlobMap`] return the MIME types exactly as listed in the `globs2`
+    /// files, even if they are aliases.
+    ///
+    /// [`get_mime_types_from_file_name`]: #method.get_mime_types_from_file_name
+    /// [`GlobMap`]: struct.GlobMap.html
+    pub fn glob_map(&self) -> &GlobMap {
+        &self.globs
+    }
+
+    // Replaces aliases with their canonical MIME type, and removes the
+    // duplicates, preserving the order of the list
+    fn dedup_aliases(&self, mut mime_types: Vec<Mime>) -> Vec<Mime> {
+        // The list is deduplicated in place, keeping the first occurrence
+        // of each MIME type, as it rarely has more than a couple of entries
+        let mut len = 0;
+        for idx in 0..mime_types.len() {
+            if let Some(unaliased) = self.unalias_mime_type(&mime_types[idx]) {
+                mime_types[idx] = unaliased;
+            }
+
+            if !mime_types[..len].contains(&mime_types[idx]) {
+                mime_types.swap(len, idx);
+                len += 1;
+            }
+        }
+        mime_types.truncate(len);
+
+        mime_types
+    }
+
+    /// Iterates over the well-known file names in the database, like
+    /// `Makefile` or `COPYING`, and their associated MIME type.
+    ///
+    /// The file names are sorted alphabetically.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// for (name, mime_type) in mime_db.literal_names() {
+    ///     println!("{}: {}", name, mime_type);
+    /// }
+    /// ```
+    pub fn literal_names(&self) -> impl Iterator<Item = (&str, &Mime)> {
+        self.globs.literal_names().into_iter()
+    }
+
+    /// Checks whether `file_name` is one of the well-known file names
+    /// returned by [`literal_names`], ignoring case.
+    ///
+    /// [`literal_names`]: #method.literal_names
+    pub fn is_special_filename(&self, file_name: &str) -> bool {
+        self.globs.is_literal_name(file_name)
+    }
+
+    /// The MIME type used when the MIME type of a file name, or of some
+    /// data, cannot be determined.
+    ///
+    /// This is `application/octet-stream`, unless a different type was set
+    /// using [`SharedMimeInfoBuilder::unknown_type`].
+    ///
+    /// [`SharedMimeInfoBuilder::unknown_type`]: struct.SharedMimeInfoBuilder.html#method.unknown_type
+    pub fn unknown_type(&self) -> &Mime {
+        &self.unknown_type
+    }
+
+    /// Checks whether `mime_type` is the [unknown type].
+    ///
+    /// [unknown type]: #method.unknown_type
+    pub fn is_unknown_type(&self, mime_type: &Mime) -> bool {
+        *mime_type == self.unknown_type
+    }
+
+    /// Retrieves the MIME type for the given data, and the priority of the
+    /// match.
+    ///
+    /// Empty data is reported as `application/x-zerosize`, with the
+    /// [`ZERO_SIZE`] priority; a priority that [is certain] means that the
+    /// match should be trusted over the file name.
+    ///
+    /// [`ZERO_SIZE`]: struct.MagicPriority.html#associatedconstant.ZERO_SIZE
+    /// [is certain]: struct.MagicPriority.html#method.is_certain
+    pub fn get_mime_type_for_data(&self, data: &[u8]) -> Option<(Mime, MagicPriority)> {
+        self.sniff(data)
+            .map(|(mime_type, priority)| (mime_type.clone(), priority))
+    }
+
+    /// Retrieves the highest priority of the magic entries for a MIME type,
+    /// or `None` if the MIME type cannot be recognized from its contents.
+    ///
+    /// A [certain] priority means that a match should be trusted over
+    /// the file name, while some formats, like the ones based on XML, have
+    /// magic entries with a low priority that deliberately match more data
+    /// than they should.
+    ///
+    /// [certain]: struct.MagicPriority.html#method.is_certain
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let priority = mime_db.max_magic_priority(&mime::IMAGE_PNG);
+    /// assert!(priority.is_some());
+    /// ```
+    pub fn max_magic_priority(&self, mime_type: &Mime) -> Option<MagicPriority> {
+        let unaliased = self.unalias_mime_type(mime_type);
+        let mime_type = unaliased.as_ref().unwrap_or(mime_type);
+
+        self.magic
+            .entries()
+            .iter()
+            .filter(|entry| {
+                entry.mime_type() == mime_type
+                    || self.unalias_mime_type(entry.mime_type()).as_ref() == Some(mime_type)
+            })
+            .map(|entry| entry.priority())
+            .max()
+            .map(MagicPriority::saturating)
+    }
+
+    /// Checks whether the contents of a file could ever tell apart the
+    /// MIME types matching its name.
+    ///
+    /// The contents can only pick one of the candidates if a magic entry
+    /// exists for the MIME type of some of them, or for one of their
+    /// parents, but not for a parent of all of them; for instance, the
+    /// candidates `application/json` and `application/geo+json` cannot be
+    /// told apart, since only their common parent `application/javascript`
+    /// has magic entries. Applications can use this method to skip reading
+    /// the file, and apply their own conflict policy right away.
+    ///
+    /// Aliases are resolved, and a list with fewer than two distinct MIME
+    /// types has nothing to disambiguate.
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let candidates = mime_db.get_mime_types_from_file_name("notes.md");
+    /// if candidates.len() > 1 && !mime_db.can_disambiguate(&candidates) {
+    ///     println!("Reading the file would not help");
+    /// }
+    /// ```
+    pub fn can_disambiguate(&self, candidates: &[Mime]) -> bool {
+        let mut candidates: Vec<Mime> = candidates
+            .iter()
+            .map(|m| self.unalias_mime_type(m).unwrap_or_else(|| m.clone()))
+            .collect();
+        candidates.sort_by(|a, b| a.essence_str().cmp(b.essence_str()));
+        candidates.dedup();
+        if candidates.len() < 2 {
+            return false;
+        }
+
+        // The matches of a magic entry are used for the candidates that
+        // are equal to, or a subclass of, its MIME type
+        let mut seen = HashSet::new();
+        self.magic
+            .entries()
+            .iter()
+            .map(magic::MagicEntry::mime_type)
+            .filter(|mime_type| seen.insert(*mime_type))
+            .any(|mime_type| {
+                let matched = candidates
+                    .iter()
+                    .filter(|c| self.mime_type_subclass(c, mime_type))
+                    .count();
+                matched > 0 && matched < candidates.len()
+            })
+    }
+
+    /// Retrieves where the magic entries for a MIME type come from, or
+    /// `None` if the MIME type cannot be recognized from its contents.
+    ///
+    /// If the MIME type has entries from more than one source, the one
+    /// with the highest precedence is returned.
+    pub fn magic_source_tier(&self, mime_type: &Mime) -> Option<Tier> {
+        self.magic
+            .entries()
+            .iter()
+            .find(|entry| entry.mime_type() == mime_type)
+            .map(magic::MagicEntry::tier)
+    }
+
+    /// Retrieves the `x-content/*` MIME types of the contents of a mounted
+    /// volume, or of a directory, like `x-content/image-dcf` for the memory
+    /// card of a digital camera, using the tree magic entries of the
+    /// database.
+    ///
+    /// The MIME types are sorted by priority, and the list is empty if the
+    /// contents are not recognized. The `on-disc` flag of the entries is
+    /// not checked, since it depends on the device the volume is mounted
+    /// from.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mime_types = mime_db.get_mime_type_for_tree("/run/media/user/CAMERA");
+    /// # let _ = mime_types;
+    /// ```
+    pub fn get_mime_type_for_tree<P: AsRef<Path>>(&self, path: P) -> Vec<Mime> {
+        let is_a = |path: &Path, mime_type: &Mime| {
+            let guess = self.guess_mime_type().path(path).guess();
+            self.mime_type_subclass(guess.mime_type(), mime_type)
+        };
+
+        let mut entries: Vec<&treemagic::TreeMagicEntry> = self
+            .treemagic
+            .iter()
+            .filter(|entry| entry.matches(path.as_ref(), &is_a))
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.priority()));
+
+        let mut res: Vec<Mime> = Vec::new();
+        for entry in entries {
+            if !res.contains(entry.mime_type()) {
+                res.push(entry.mime_type().clone());
+            }
+        }
+
+        res
+    }
+
+    /// Iterates over every MIME type known to the database, sorted by
+    /// name, like the types listed in a file type picker.
+    ///
+    /// The MIME types are the ones listed in the `types` file of each
+    /// directory, or defined in its source XML files, plus the ones with
+    /// extensions in the `mime.types` files; aliases are not included.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// for mime_type in mime_db.list_mime_types() {
+    ///     println!("{}", mime_type);
+    /// }
+    /// ```
+    pub fn list_mime_types(&self) -> impl Iterator<Item = &Mime> {
+        self.types.iter()
+    }
+
+    /// Retrieves the MIME type of XML documents whose root element has the
+    /// given local name and namespace, like `image/svg+xml` for the `svg`
+    /// element in the `http://www.w3.org/2000/svg` namespace, using the
+    /// `XMLnamespaces` file of the database.
+    ///
+    /// This can resolve XML documents with a generic extension, like
+    /// `.xml`, to a more specific type; the root element has to be read
+    /// from the document by the caller.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mime_type = mime_db.get_mime_type_for_xml("svg", "http://www.w3.org/2000/svg");
+    /// # let _ = mime_type;
+    /// ```
+    pub fn get_mime_type_for_xml(&self, local_name: &str, namespace: &str) -> Option<Mime> {
+        namespace::lookup(&self.namespaces, namespace, local_name).cloned()
+    }
+
+    // Like get_mime_type_for_data(), but borrowing the MIME type from the
+    // database
+    fn sniff(&self, data: &[u8]) -> Option<(&Mime, MagicPriority)> {
+        if data.is_empty() {
+            return Some((&APPLICATION_X_ZEROSIZE, MagicPriority::ZERO_SIZE));
+        }
+
+        #[cfg(feature = "events")]
+        let start = std::time::Instant::now();
+        let res = self.magic.lookup_data(data);
+        #[cfg(feature = "events")]
+        events::check_magic_match(
+            data.len(),
+            res.map(|(mime_type, _)| mime_type),
+            start.elapsed(),
+        );
+
+        res.map(|(mime_type, priority)| (mime_type, MagicPriority::saturating(priority)))
+    }
+
+    /// Creates a [`Sniffer`], to determine the MIME type of data received
+    /// in multiple chunks, possibly out of order, without concatenating
+    /// them first.
+    ///
+    /// [`Sniffer`]: struct.Sniffer.html
+    pub fn sniffer(&self) -> Sniffer<'_> {
+        Sniffer::new(self.magic.entries())
+    }
+
+    /// Reads up to `limit` bytes from `reader`, and classifies them as
+    /// text or binary data.
+    ///
+    /// Data matching a magic rule is classified as text only if its
+    /// MIME type is a subclass of `text/plain`; otherwise, the data is
+    /// classified as binary if it contains ASCII control characters other
+    /// than whitespace, unless it starts with a UTF-16 byte order mark.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mut reader: &[u8] = b"Hello, world!\n";
+    /// let kind = mime_db.classify_stream(&mut reader, 4096).unwrap();
+    /// assert_eq!(kind, xdg_mime::StreamKind::Text("us-ascii"));
+    /// ```
+    pub fn classify_stream<R: Read + ?Sized>(
+        &self,
+        reader: &mut R,
+        limit: usize,
+    ) -> io::Result<StreamKind> {
+        let mut data = Vec::new();
+        reader.take(limit as u64).read_to_end(&mut data)?;
+
+        let kind = text::classify(&data);
+        if kind == StreamKind::Empty {
+            return Ok(kind);
+        }
+
+        if let Some((mime_type, _)) = self.magic.lookup_data(&data) {
+            if !self.mime_type_subclass(mime_type, &mime::TEXT_PLAIN) {
+                return Ok(StreamKind::Binary);
+            }
+        }
+
+        Ok(kind)
+    }
+
+    /// Retrieves the MIME types that could match data starting with
+    /// `prefix`, because their magic rules either already match it, or
+    /// look at data after the end of the prefix.
+    ///
+    /// This can be used to narrow down the type of a file while it is
+    /// being downloaded. The MIME types are listed in descending order by
+    /// priority of their magic entries, with the aliases resolved.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let possible = mime_db.possible_types_for_prefix(b"\x89PN");
+    /// assert!(possible.contains(&mime::IMAGE_PNG));
+    /// assert!(!possible.contains(&mime::IMAGE_GIF));
+    /// ```
+    pub fn possible_types_for_prefix(&self, prefix: &[u8]) -> Vec<Mime> {
+        let mut sniffer = self.sniffer();
+        sniffer.push_at(0, prefix);
+
+        self.dedup_aliases(sniffer.possible_mime_types())
+    }
+
+    /// Guesses the MIME type of an email attachment, using its declared
+    /// `Content-Type`, its file name, and its contents.
+    ///
+    /// The three sources are reconciled using the [scores] of the
+    /// candidates returned by [`guess_all`], in this order:
+    ///
+    ///  1. the contents: the best candidate matching a magic entry, unless
+    ///     a candidate matching the file name is a subclass of it, since it
+    ///     is more specific
+    ///  2. the file name: the best candidate matching a glob pattern
+    ///  3. the declared type
+    ///
+    /// Whenever candidates have the same score, the one related to the
+    /// declared type, because it is the same type, or one is a subclass of
+    /// the other, is preferred. A declared `application/octet-stream` is
+    /// ignored, and the declared type is returned as the [`conflict`] of
+    /// the result if it is not related to it.
+    ///
+    /// Desktop files are only recognized from their contents if their
+    /// file name or their declared type agree, for security reasons.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db.classify_attachment(
+    ///     Some(&mime::APPLICATION_OCTET_STREAM),
+    ///     Some("scan.pdf"),
+    ///     b"\x89PNG\r\n\x1a\n",
+    /// );
+    /// assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
+    /// ```
+    ///
+    /// [scores]: struct.ScoredGuess.html#method.score
+    /// [`guess_all`]: struct.GuessBuilder.html#method.guess_all
+    /// [`conflict`]: struct.Guess.html#method.conflict
+    pub fn classify_attachment(
+        &self,
+        declared: Option<&Mime>,
+        file_name: Option<&str>,
+        data: &[u8],
+    ) -> Guess {
+        let declared = declared
+            .map(|m| self.unalias_mime_type(m).unwrap_or_else(|| m.clone()))
+            .filter(|m| !self.is_unknown_type(m));
+        let related =
+            |a: &Mime, b: &Mime| self.mime_type_subclass(a, b) || self.mime_type_subclass(b, a);
+
+        let mut builder = self.guess_mime_type();
+        if let Some(file_name) = file_name {
+            builder.file_name(file_name);
+        }
+        let candidates = builder.data(data).guess_all();
+
+        // Picks the candidate with the highest score, using the declared
+        // type to break ties; the result is uncertain if the tie cannot
+        // be broken
+        let pick = |candidates: Vec<&ScoredGuess>| -> Option<Guess> {
+            let top = candidates.first()?.score;
+            let tied: Vec<&ScoredGuess> = candidates
+                .into_iter()
+                .take_while(|c| c.score == top)
+                .collect();
+
+            let preferred = declared
+                .as_ref()
+                .and_then(|d| tied.iter().find(|c| related(&c.mime, d)));
+
+            Some(match preferred {
+                Some(c) => Guess::new(c.mime.clone(), false),
+                None => Guess::new(tied[0].mime.clone(), tied.len() > 1),
+            })
+        };
+
+        let from_contents = candidates.iter().find(|c| {
+            c.magic_priority.is_some()
+                && (c.mime != *APPLICATION_X_DESKTOP
+                    || c.glob_weight.is_some()
+                    || declared.as_ref() == Some(&c.mime))
+        });
+        let from_name = candidates.iter().filter(|c| c.glob_weight.is_some());
+
+        let mut guess = match from_contents {
+            Some(content) => {
+                let more_specific = from_name
+                    .filter(|c| {
+                        c.mime != content.mime && self.mime_type_subclass(&c.mime, &content.mime)
+                    })
+                    .collect();
+
+                pick(more_specific).unwrap_or_else(|| Guess::new(content.mime.clone(), false))
+            }
+            None => match pick(from_name.collect()) {
+                Some(guess) => guess,
+                None => match &declared {
+                    Some(declared) => Guess::new(declared.clone(), false),
+                    None if !data.is_empty() && looks_like_text(data) => {
+                        Guess::new(mime::TEXT_PLAIN, true)
+                    }
+                    None => Guess::new(self.unknown_type.clone(), true),
+                },
+            },
+        };
+
+        if let Some(declared) = declared {
+            if !related(&guess.mime, &declared) {
+                guess.conflict = Some(Cow::Owned(declared));
+            }
+        }
+        guess.unknown = self.is_unknown_type(&guess.mime);
+
+        guess
+    }
+
+    /// Decides whether a file can be rendered inline, for instance in a
+    /// chat application, according to `policy`.
+    ///
+    /// The MIME type is guessed from the file name, if any, and the data;
+    /// the file cannot be previewed if the MIME type is not allowed by the
+    /// policy, or if the data matches a MIME type denied by it, even if
+    /// the file name takes precedence, or [conflicts] with it.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let policy = xdg_mime::PreviewPolicy::default();
+    ///
+    /// let decision = mime_db.is_safe_to_preview(Some("cat.png"), b"\x89PNG\r\n\x1a\n", &policy);
+    /// assert!(decision.is_safe());
+    /// assert_eq!(decision.mime_type(), &mime::IMAGE_PNG);
+    ///
+    /// let decision = mime_db.is_safe_to_preview(Some("cat.png"), b"#!/bin/sh\n", &policy);
+    /// assert!(!decision.is_safe());
+    /// ```
+    ///
+    /// [conflicts]: struct.Guess.html#method.conflict
+    pub fn is_safe_to_preview(
+        &self,
+        file_name: Option<&str>,
+        data: &[u8],
+        policy: &PreviewPolicy,
+    ) -> PreviewDecision {
+        let mut builder = self.guess_mime_type();
+        if let Some(file_name) = file_name {
+            builder.file_name(file_name);
+        }
+        let guess = builder.data(data).guess();
+
+        // A name match is used without looking at the data, so the data
+        // is checked separately
+        let sniffed = self.sniff(data).map(|(mime_type, _)| mime_type);
+
+        let safe = policy.allows(self, guess.mime_type())
+            && !guess.conflict().is_some_and(|m| policy.denies(self, m))
+            && !sniffed.is_some_and(|m| policy.denies(self, m));
+
+        PreviewDecision {
+            mime: guess.mime_type().clone(),
+            safe,
+        }
+    }
+
+    /// Guesses the MIME type of the top-level body parts of an email
+    /// message, or of a `multipart/*` entity, up to `limit` parts.
+    ///
+    /// The parts are decoded according to their `Content-Transfer-Encoding`
+    /// and classified using [`classify_attachment`], from their declared
+    /// MIME type, file name and contents; the parts of nested `multipart/*`
+    /// and `message/rfc822` parts are not examined. A message that is not
+    /// multipart has a single part, its body; data that does not look like
+    /// a message has no parts.
+    ///
+    /// This method is only available if the `message` feature is enabled.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let message = b"Content-Type: multipart/mixed; boundary=sep\r\n\
+    ///     \r\n\
+    ///     --sep\r\n\
+    ///     Content-Type: text/plain\r\n\
+    ///     \r\n\
+    ///     See the attachment.\r\n\
+    ///     --sep\r\n\
+    ///     Content-Type: application/octet-stream\r\n\
+    ///     Content-Disposition: attachment; filename=\"scan.pdf\"\r\n\
+    ///     \r\n\
+    ///     %PDF-1.7\r\n\
+    ///     --sep--\r\n";
+    ///
+    /// let parts = mime_db.classify_message_parts(message, 10);
+    /// assert_eq!(parts.len(), 2);
+    /// assert_eq!(parts[0].guess().mime_type(), &mime::TEXT_PLAIN);
+    /// assert_eq!(parts[1].file_name(), Some("scan.pdf"));
+    /// assert_eq!(parts[1].guess().mime_type(), &mime::APPLICATION_PDF);
+    /// ```
+    ///
+    /// [`classify_attachment`]: #method.classify_attachment
+    #[cfg(feature = "message")]
+    pub fn classify_message_parts(&self, data: &[u8], limit: usize) -> Vec<MessagePart> {
+        message::parts(data, limit)
+            .into_iter()
+            .map(|part| {
+                let guess = self.classify_attachment(
+                    part.declared.as_ref(),
+                    part.file_name.as_deref(),
+                    &part.data,
+                );
+
+                MessagePart {
+                    declared: part.declared,
+                    file_name: part.file_name,
+                    size: part.data.len(),
+                    guess,
+                }
+            })
+            .collect()
+    }
+
+    /// Guesses the MIME type of the contents of a `data:` URI.
+    ///
+    /// The media type declared in the URI is checked against the MIME type
+    /// of the decoded contents: if one of them is a subclass of the other,
+    /// the most specific one is returned; otherwise, the declared type is
+    /// returned, unless the contents match a magic entry with a priority of
+    /// 80 or above, and the other type is available through the
+    /// [`conflict`] method of the result.
+    ///
+    /// Returns `None` if `uri` is not a valid `data:` URI.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db
+    ///     .classify_data_uri("data:image/png;base64,iVBORw0KGgo=")
+    ///     .unwrap();
+    /// assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
+    /// assert!(guess.conflict().is_none());
+    /// ```
+    ///
+    /// [`conflict`]: struct.Guess.html#method.conflict
+    pub fn classify_data_uri(&self, uri: &str) -> Option<Guess> {
+        let uri = data_uri::DataUri::parse(uri)?;
+        let unaliased = |m: Mime| self.unalias_mime_type(&m).unwrap_or(m);
+
+        let declared = uri
+            .media_type
+            .and_then(special::parse_mime_type)
+            .map(unaliased);
+
+        let sniffed = if uri.payload.is_empty() {
+            None
+        } else {
+            self.sniff(&uri.payload)
+        };
+        let sniffed = sniffed.map(|(m, priority)| {
+            let mut m = unaliased(m.clone());
+
+            // Desktop files are only trusted if they are declared as such,
+            // like GIO does for their file name
+            if m == *APPLICATION_X_DESKTOP && declared.as_ref() != Some(&m) {
+                m = mime::TEXT_PLAIN;
+            }
+
+            (m, priority)
+        });
+
+        let mut guess = match (declared, sniffed) {
+            (None, None) => {
+                // The default media type of data URIs is text/plain
+                if looks_like_text(&uri.payload) {
+                    Guess::new(mime::TEXT_PLAIN, true)
+                } else {
+                    Guess::new(self.unknown_type.clone(), true)
+                }
+            }
+            (Some(declared), None) => Guess::new(declared, false),
+            (None, Some((sniffed, _))) => Guess::new(sniffed, false),
+            (Some(declared), Some((sniffed, priority))) => {
+                if self.mime_type_subclass(&sniffed, &declared) {
+                    Guess::new(sniffed, false)
+                } else if self.mime_type_subclass(&declared, &sniffed) {
+                    Guess::new(declared, false)
+                } else if priority.is_certain() {
+                    let mut guess = Guess::new(sniffed, false);
+                    guess.conflict = Some(Cow::Owned(declared));
+                    guess
+                } else {
+                    let mut guess = Guess::new(declared, true);
+                    guess.conflict = Some(Cow::Owned(sniffed));
+                    guess
+                }
+            }
+        };
+
+        guess.unknown = self.is_unknown_type(&guess.mime);
+
+        Some(guess)
+    }
+
+    /// Checks whether two MIME types are equal, taking into account
+    /// eventual aliases.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let x_markdown: Mime = "text/x-markdown".parse()?;
+    /// let markdown: Mime = "text/markdown".parse()?;
+    /// assert!(mime_db.mime_type_equal(&x_markdown, &markdown));
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mime_type_equal(&self, mime_a: &Mime, mime_b: &Mime) -> bool {
+        let unaliased_a = self
+            .unalias_mime_type(mime_a)
+            .unwrap_or_else(|| mime_a.clone());
+        let unaliased_b = self
+            .unalias_mime_type(mime_b)
+            .unwrap_or_else(|| mime_b.clone());
+
+        unaliased_a == unaliased_b
+    }
+
+    /// Checks whether a MIME type is a subclass of another MIME type.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let rust: Mime = "text/rust".parse()?;
+    /// let text: Mime = "text/plain".parse()?;
+    /// assert!(mime_db.mime_type_subclass(&rust, &text));
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mime_type_subclass(&self, mime_type: &Mime, base: &Mime) -> bool {
+        self.checked_mime_type_subclass(mime_type, base)
+            .unwrap_or(false)
+    }
+
+    /// Checks whether a MIME type is a subclass of another MIME type, like
+    /// [`mime_type_subclass`], following at most the number of levels of
+    /// parents set using [`SharedMimeInfoBuilder::subclass_depth_limit`].
+    ///
+    /// Returns `None` if the limit was reached before finding `base`.
+    ///
+    /// [`mime_type_subclass`]: #method.mime_type_subclass
+    /// [`SharedMimeInfoBuilder::subclass_depth_limit`]: struct.SharedMimeInfoBuilder.html#method.subclass_depth_limit
+    pub fn checked_mime_type_subclass(&self, mime_type: &Mime, base: &Mime) -> Option<bool> {
+        let unaliased_mime = self
+            .unalias_mime_type(mime_type)
+            .unwrap_or_else(|| mime_type.clone());
+        let unaliased_base = self.unalias_mime_type(base).unwrap_or_else(|| base.clone());
+
+        if let Some(ancestors) = &self.ancestors {
+            if ancestors.contains(&unaliased_mime, &unaliased_base) {
+                return Some(true);
+            }
+
+            if let Some((mut found, truncated)) = ancestors.get(&unaliased_mime) {
+                if found.any(|ancestor| is_derived_from(ancestor, &unaliased_base)) {
+                    return Some(true);
+                }
+
+                return if truncated { None } else { Some(false) };
+            }
+        }
+
+        self.walk_ancestors(&unaliased_mime, |ancestor| {
+            is_derived_from(ancestor, &unaliased_base)
+        })
+    }
+
+    /// Retrieves the chain of generic types of `mime_type`: the MIME type
+    /// itself, its ancestors, from the closest to the farthest, and then
+    /// the types every MIME type of its kind derives from, as defined by
+    /// the [specification]: `text/plain` for the `text/*` types, and
+    /// `application/octet-stream` for every type except the `inode/*` ones.
+    ///
+    /// This is the order in which the handlers of a file are usually
+    /// looked up: the handler of the type itself, and if there is none,
+    /// the handler of the closest generic type. Aliases are resolved, each
+    /// type appears only once, and the ancestors beyond the
+    /// [subclass depth limit] are left out.
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// let mime_db = xdg_mime::testing::minimal_db();
+    /// let svg = Mime::from_str("image/svg+xml").unwrap();
+    /// assert_eq!(
+    ///     mime_db.generic_chain(&svg),
+    ///     vec![
+    ///         svg,
+    ///         Mime::from_str("application/xml").unwrap(),
+    ///         mime::TEXT_PLAIN,
+    ///         mime::APPLICATION_OCTET_STREAM,
+    ///     ]
+    /// );
+    /// ```
+    ///
+    /// [specification]: https://specifications.freedesktop.org/shared-mime-info-spec/shared-mime-info-spec-latest.html#subclassing
+    /// [subclass depth limit]: struct.SharedMimeInfoBuilder.html#method.subclass_depth_limit
+    pub fn generic_chain(&self, mime_type: &Mime) -> Vec<Mime> {
+        let unaliased = self
+            .unalias_mime_type(mime_type)
+            .unwrap_or_else(|| mime_type.clone());
+
+        let mut res = Vec::new();
+        self.walk_ancestors(&unaliased, |ancestor| {
+            res.push(ancestor.clone());
+            false
+        });
+
+        if unaliased.type_() == mime::TEXT && !res.contains(&mime::TEXT_PLAIN) {
+            res.push(mime::TEXT_PLAIN);
+        }
+        if unaliased.type_() != "inode" && !res.contains(&mime::APPLICATION_OCTET_STREAM) {
+            res.push(mime::APPLICATION_OCTET_STREAM);
+        }
+
+        res
+    }
+
+    // Visits an unaliased MIME type and its ancestors, breadth first, until
+    // `visit` returns true; each ancestor is visited once, even if there is
+    // a cycle, and the parents beyond the depth limit are not visited, in
+    // which case None is returned
+    fn walk_ancestors<F>(&self, mime_type: &Mime, mut visit: F) -> Option<bool>
+    where
+        F: FnMut(&Mime) -> bool,
+    {
+        let mut seen: HashSet<Mime> = HashSet::from([mime_type.clone()]);
+        let mut level = vec![mime_type.clone()];
+        let mut depth = 0;
+
+        loop {
+            let mut next = Vec::new();
+            for m in &level {
+                if visit(m) {
+                    return Some(true);
+                }
+
+                for parent in self.parents.lookup(m).into_iter().flatten() {
+                    let parent = self
+                        .unalias_mime_type(parent)
+                        .unwrap_or_else(|| parent.clone());
+                    if seen.insert(parent.clone()) {
+                        next.push(parent);
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                return Some(false);
+            }
+            if depth == self.subclass_depth_limit {
+                return None;
+            }
+
+            depth += 1;
+            level = next;
+        }
+    }
+
+    /// Checks the database for inconsistencies, like MIME types without a
+    /// definition, subclasses of unknown types, or icons and aliases that
+    /// refer to unknown types.
+    ///
+    /// This method is mostly useful for distributors and for quality
+    /// assurance tools; the returned findings are sorted.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// for finding in mime_db.audit() {
+    ///     println!("{}", finding);
+    /// }
+    /// ```
+    pub fn audit(&self) -> Vec<Finding> {
+        audit::audit(self)
+    }
+
+    /// Lists the references to MIME types that are not defined in the
+    /// database: the subclasses of unknown types, as
+    /// [`Finding::UnknownParent`], and the aliases of unknown types, as
+    /// [`Finding::UnknownAliasTarget`].
+    ///
+    /// Dangling references are common when the MIME data of the user only
+    /// overrides part of the one of the system; they are a subset of the
+    /// findings of [`audit`], which is more expensive. The returned
+    /// findings are sorted.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// for reference in mime_db.dangling_references() {
+    ///     eprintln!("warning: {}", reference);
+    /// }
+    /// ```
+    ///
+    /// [`Finding::UnknownParent`]: enum.Finding.html#variant.UnknownParent
+    /// [`Finding::UnknownAliasTarget`]: enum.Finding.html#variant.UnknownAliasTarget
+    /// [`audit`]: #method.audit
+    pub fn dangling_references(&self) -> Vec<Finding> {
+        audit::dangling_references(self)
+    }
+
+    /// Writes the globs of the database using the format of the `globs2`
+    /// file, sorted by weight and then by pattern.
+    pub fn write_globs<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        glob::write_globs_v2(&self.globs, w)
+    }
+
+    /// Writes the aliases of the database using the format of the
+    /// `aliases` file, sorted by alias.
+    pub fn write_aliases<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        alias::write_aliases(&self.aliases, w)
+    }
+
+    /// Writes the subclasses of the database using the format of the
+    /// `subclasses` file, sorted by MIME type.
+    pub fn write_subclasses<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        parent::write_subclasses(&self.parents, w)
+    }
+
+    /// Writes the icons of the database using the format of the `icons`
+    /// file, sorted by MIME type.
+    pub fn write_icons<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        icon::write_icons(&self.icons, w)
+    }
+
+    /// Writes the generic icons of the database using the format of the
+    /// `generic-icons` file, sorted by MIME type.
+    pub fn write_generic_icons<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        icon::write_icons(&self.generic_icons, w)
+    }
+
+    /// Writes the magic entries of the database using the format of the
+    /// `magic` file, in the order in which they are matched.
+    pub fn write_magic<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        magic::write_magic(self.magic.entries(), w)
+    }
+
+    /// Writes the tree magic entries of the database using the format of
+    /// the `treemagic` file, in the order in which they were loaded.
+    pub fn write_treemagic<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        treemagic::write_treemagic(&self.treemagic, w)
+    }
+
+    /// Writes the root elements of XML documents of the database using the
+    /// format of the `XMLnamespaces` file, sorted by namespace and local
+    /// name.
+    pub fn write_namespaces<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        namespace::write_namespaces(&self.namespaces, w)
+    }
+
+    /// Writes the MIME types of the database using the format of the
+    /// `types` file, sorted by name.
+    pub fn write_types<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        types::write_types(&self.types, w)
+    }
+
+    /// Writes the `globs2`, `aliases`, `subclasses`, `icons`, and
+    /// `generic-icons` files for the database inside the `mime`
+    /// sub-directory of `directory`, creating it if needed.
+    ///
+    /// The saved files can be loaded back using [`new_for_directory`].
+    /// Magic entries are not saved.
+    ///
+    /// [`new_for_directory`]: #method.new_for_directory
+    pub fn save_to_directory<P: AsRef<Path>>(&self, directory: P) -> io::Result<()> {
+        let mime_path = directory.as_ref().join("mime");
+        fs::create_dir_all(&mime_path)?;
+
+        let mut f = io::BufWriter::new(File::create(mime_path.join("globs2"))?);
+        self.write_globs(&mut f)?;
+        f.flush()?;
+
+        let mut f = io::BufWriter::new(File::create(mime_path.join("aliases"))?);
+        self.write_aliases(&mut f)?;
+        f.flush()?;
+
+        let mut f = io::BufWriter::new(File::create(mime_path.join("subclasses"))?);
+        self.write_subclasses(&mut f)?;
+        f.flush()?;
+
+        let mut f = io::BufWriter::new(File::create(mime_path.join("icons"))?);
+        self.write_icons(&mut f)?;
+        f.flush()?;
+
+        let mut f = io::BufWriter::new(File::create(mime_path.join("generic-icons"))?);
+        self.write_generic_icons(&mut f)?;
+        f.flush()
+    }
+
+    /// Writes a self-contained copy of the database inside `directory`,
+    /// which can be loaded back using [`new_for_directory`], so that tests
+    /// can use the same MIME data on every system.
+    ///
+    /// The `mime` sub-directory contains the same files written by
+    /// [`save_to_directory`], plus the `magic`, `treemagic`, `types`, and
+    /// `XMLnamespaces` files; the entries of each file are sorted, except
+    /// for the magic and tree magic entries, which are kept in the order
+    /// in which they are matched, so exporting the same database always
+    /// writes the same files. Any `mime.cache` file is removed, since
+    /// it would take precedence over them.
+    ///
+    /// The SHA-256 digest of each file is written in the `SHA256SUMS` file,
+    /// which can be checked with `sha256sum -c SHA256SUMS`.
+    ///
+    /// This method requires the `hashes` feature.
+    ///
+    /// [`new_for_directory`]: #method.new_for_directory
+    /// [`save_to_directory`]: #method.save_to_directory
+    #[cfg(feature = "hashes")]
+    pub fn export_snapshot<P: AsRef<Path>>(&self, directory: P) -> io::Result<()> {
+        let mime_path = directory.as_ref().join("mime");
+        fs::create_dir_all(&mime_path)?;
+
+        let mut manifest = String::new();
+        for (name, write) in SNAPSHOT_FILES {
+            let mut contents = Vec::new();
+            write(self, &mut contents)?;
+            fs::write(mime_path.join(name), &contents)?;
+            manifest.push_str(&snapshot::manifest_line(
+                &format!("mime/{}", name),
+                &contents,
+            ));
+        }
+
+        match fs::remove_file(mime_path.join("mime.cache")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        fs::write(directory.as_ref().join("SHA256SUMS"), manifest)
+    }
+
+    /// Serializes the MIME data read from each directory and `mime.types`
+    /// file into a binary snapshot, which can be restored using
+    /// [`from_snapshot`] without reading and parsing the files again, for
+    /// instance by a command line tool that is run many times.
+    ///
+    /// The snapshot records the modification times of the directories,
+    /// so that it is rejected once they change. The magic entries are
+    /// parsed, if they were not yet. The problems found while parsing the
+    /// files are not kept, so the [`diagnostics`] of a restored database
+    /// only report the checks of the whole database, like the shadowed
+    /// entries. The directories added using [`add_directory`] are part of
+    /// the snapshot, but a snapshot that contains them does not match the
+    /// directories of a builder.
+    ///
+    /// The MIME data is encoded using [bincode], after a header with the
+    /// version of this crate. The encoding is not stable: a snapshot can
+    /// only be restored by the same version of this crate, and the other
+    /// versions reject it as invalid, so it should be treated as a cache,
+    /// and written again whenever it is rejected.
+    ///
+    /// This method requires the `snapshot` feature.
+    ///
+    /// [`from_snapshot`]: #method.from_snapshot
+    /// [`diagnostics`]: #method.diagnostics
+    /// [`add_directory`]: #method.add_directory
+    /// [bincode]: https://docs.rs/bincode
+    #[cfg(feature = "snapshot")]
+    pub fn serialize_snapshot(&self) -> Vec<u8> {
+        binary::serialize(self)
+    }
+
+    /// Computes a fingerprint of the MIME data, which changes whenever the
+    /// aliases, sub-classes, icons, glob patterns, magic entries, tree
+    /// magic entries, XML root elements or MIME types change.
+    ///
+    /// The fingerprint only depends on the merged data, and not on the
+    /// files it was loaded from, or on the order in which they were read,
+    /// so it can be stored alongside the results of a classification,
+    /// like a thumbnail database or a search index, to find out whether
+    /// they need to be computed again after the MIME database is updated.
+    ///
+    /// ```rust
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let fingerprint = mime_db.fingerprint();
+    /// assert_eq!(fingerprint, mime_db.clone().fingerprint());
+    /// ```
+    ///
+    /// This method requires the `hashes` feature.
+    #[cfg(feature = "hashes")]
+    pub fn fingerprint(&self) -> u64 {
+        // The fingerprint is derived from the manifest of a snapshot, so
+        // that it does not depend on the hasher of the standard library
+        let mut manifest = String::new();
+        for (name, write) in SNAPSHOT_FILES {
+            let mut contents = Vec::new();
+            // Writing to a vector cannot fail
+            let _ = write(self, &mut contents);
+            manifest.push_str(&snapshot::manifest_line(name, &contents));
+        }
+
+        let digest = snapshot::sha256(manifest.as_bytes());
+        let mut res = [0u8; 8];
+        res.copy_from_slice(&digest[..8]);
+
+        u64::from_be_bytes(res)
+    }
+
+    /// Performs queries against the database as if the changes added to
+    /// an [`Overlay`] by `overlay_fn` were part of it.
+    ///
+    /// The database is not modified: the queries of `query_fn` look up the
+    /// globs of the overlay first, and then the ones of the database, so
+    /// the changes can be used to preview the effect of a change before
+    /// committing it, for instance when associating a new extension to a
+    /// MIME type.
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let png = Mime::from_str("image/png").unwrap();
+    /// let preview = mime_db.with_overlay(
+    ///     |overlay| {
+    ///         overlay.add_glob(&png, "*.pic", xdg_mime::GlobWeight::DEFAULT);
+    ///     },
+    ///     |db| db.get_mime_types_from_file_name("image.pic"),
+    /// );
+    /// assert_eq!(preview, vec![png]);
+    /// ```
+    ///
+    /// [`Overlay`]: struct.Overlay.html
+    pub fn with_overlay<F, Q, R>(&self, overlay_fn: F, query_fn: Q) -> R
+    where
+        F: FnOnce(&mut Overlay),
+        Q: FnOnce(&SharedMimeInfo) -> R,
+    {
+        let mut overlay = Overlay::default();
+        overlay_fn(&mut overlay);
+
+        query_fn(&overlay.apply(self))
+    }
+
+    /// Creates a new [`GuessBuilder`] that can be used to guess the MIME type
+    /// of a file name, its contents, or a path.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let mut gb = mime_db.guess_mime_type();
+    /// let guess = gb.file_name("foo.txt").guess();
+    /// assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+    /// assert_eq!(guess.uncertain(), false);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`GuessBuilder`]: struct.GuessBuilder.html
+    pub fn guess_mime_type(&self) -> GuessBuilder<'_> {
+        GuessBuilder {
+            db: self,
+            file_name: None,
+            data: Vec::new(),
+            metadata: None,
+            path: None,
+            zero_size: true,
+            prefer_file_name: false,
+            backup_handling: BackupHandling::AsIs,
+            deadline: None,
+            deadline_exceeded: false,
+            read_error: None,
+            timings: None,
+            agreement: false,
+            bytes_examined: 0,
+        }
+    }
+
+    /// Reconciles the evidence about the MIME type of a file, possibly
+    /// coming from other detectors, using the aliases and the sub-classes
+    /// known to the database.
+    ///
+    /// Each MIME type collects the strength of the evidence for itself,
+    /// and for any of its parents, so evidence for `application/xml`
+    /// supports `image/svg+xml`; the MIME type with the strongest support
+    /// wins, and ties go to the most specific type. If some evidence is
+    /// neither a parent nor a sub-class of the result, the guess is
+    /// [uncertain], and the strongest such MIME type is the [conflict].
+    ///
+    /// Evidence for the [unknown type] is ignored; without any other
+    /// evidence, the result is the unknown type.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::str::FromStr;
+    /// # use mime::Mime;
+    /// use xdg_mime::{Evidence, GlobWeight};
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let mime_db = xdg_mime::SharedMimeInfo::new();
+    /// // let mime_db = ...
+    /// let guess = mime_db.resolve(vec![
+    ///     Evidence::FromName(Mime::from_str("image/svg+xml")?, GlobWeight::DEFAULT),
+    ///     Evidence::External(Mime::from_str("application/xml")?, 0.9),
+    /// ]);
+    /// assert_eq!(guess.mime_type(), &Mime::from_str("image/svg+xml")?);
+    /// assert_eq!(guess.uncertain(), false);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [uncertain]: struct.Guess.html#method.uncertain
+    /// [conflict]: struct.Guess.html#method.conflict
+    /// [unknown type]: #method.unknown_type
+    pub fn resolve(&self, evidence: Vec<Evidence>) -> Guess {
+        evidence::resolve(self, evidence)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::str::FromStr;
+
+    fn load_test_data() -> SharedMimeInfo {
+        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
+        let dir = PathBuf::from(&format!("{}/test_files", cwd));
+        SharedMimeInfo::new_for_directory(dir)
+    }
+
+    #[test]
+    fn load_from_directory() {
+        let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
+        let dir = PathBuf::from(&format!("{}/test_files", cwd));
+        SharedMimeInfo::new_for_directory(dir);
+    }
+
+    #[test]
+    fn load_system() {
+        let _db = SharedMimeInfo::new();
+    }
+
+    #[test]
+    fn load_with_builder() {
+        let db = SharedMimeInfo::builder()
+            .data_home("/nonexistent")
+            .data_dirs(["test_files"])
+            .build();
+
+        assert_eq!(
+            db.get_mime_types_from_file_name("foo.txt"),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(db.mime_dirs.len(), 2);
+    }
+
+    #[test]
+    fn load_with_injected_dirs() {
+        let db = SharedMimeInfo::builder()
+            .data_home("/nonexistent")
+            .data_dirs(["test_files/shadow"])
+            .override_dirs(["test_files/legacy"])
+            .extra_dirs(["test_files"])
+            .detect_sandbox(false)
+            .build();
+
+        let dirs: Vec<&Path> = db.mime_dirs.iter().map(|dir| dir.path.as_path()).collect();
+        assert_eq!(
+            dirs,
+            [
+                Path::new("test_files/legacy/mime"),
+                Path::new("/nonexistent/mime"),
+                Path::new("test_files/shadow/mime"),
+                Path::new("test_files/mime"),
+            ]
+        );
+
+        // Only the directory compiled by update-mime-database has a version
+        let versions: Vec<Option<&str>> = db.directory_versions().map(|(_, v)| v).collect();
+        assert_eq!(versions, [None, None, None, Some("2.4")]);
+        assert_eq!(db.database_version(), Some("2.4"));
+        assert_eq!(
+            db.get_mime_types_from_file_name("foo.mod"),
+            vec![Mime::from_str("application/x-modern").unwrap()]
+        );
+        assert_eq!(
+            db.get_mime_types_from_file_name("foo.txt"),
+            vec![mime::TEXT_PLAIN]
+        );
+    }
+
+    #[test]
+    fn cheap_clone() {
+        let mut mime_db = load_test_data();
+        let copy = mime_db.clone();
+        assert!(Arc::ptr_eq(&mime_db.globs, &copy.globs));
+        assert!(Arc::ptr_eq(&mime_db.magic, &copy.magic));
+
+        // Overlays share the data of the database
+        let png = Mime::from_str("image/png").unwrap();
+        let preview = mime_db.with_overlay(
+            |overlay| {
+                overlay.add_glob(&png, "*.pic", GlobWeight::DEFAULT);
+            },
+            |db| {
+                assert!(Arc::ptr_eq(&db.magic, &copy.magic));
+                db.get_mime_types_from_file_name("image.pic")
+            },
+        );
+        assert_eq!(preview, vec![png]);
+        assert!(Arc::ptr_eq(&mime_db.globs, &copy.globs));
+
+        // Runtime additions copy the data they modify
+        let mut app = testing::TempMimeDir::new().unwrap();
+        app.glob(50, "application/x-app-doc", "*.appdoc");
+        app.write().unwrap();
+        assert!(mime_db.add_directory(app.path()));
+        assert!(!Arc::ptr_eq(&mime_db.globs, &copy.globs));
+    }
+
+    #[cfg(feature = "hashes")]
+    #[test]
+    fn export_snapshot() {
+        let mime_db = load_test_data();
+        let tmp = testing::TempMimeDir::new().unwrap();
+        let dir = tmp.path();
+        mime_db.export_snapshot(dir).unwrap();
+
+        let manifest = fs::read_to_string(dir.join("SHA256SUMS")).unwrap();
+        assert_eq!(manifest.lines().count(), 9);
+        for line in manifest.lines() {
+            let (_, path) = line.split_once("  ").unwrap();
+            let contents = fs::read(dir.join(path)).unwrap();
+            assert_eq!(
+                snapshot::manifest_line(path, &contents),
+                format!("{}\n", line)
+            );
+        }
+
+        // The snapshot contains the same data, and exporting it again
+        // writes the same files
+        let copy = SharedMimeInfo::new_for_directory(dir);
+        let write_all = |db: &SharedMimeInfo| {
+            let mut out = Vec::new();
+            db.write_globs(&mut out).unwrap();
+            db.write_aliases(&mut out).unwrap();
+            db.write_subclasses(&mut out).unwrap();
+            db.write_magic(&mut out).unwrap();
+            out
+        };
+        assert_eq!(write_all(&copy), write_all(&mime_db));
+
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert_eq!(
+            copy.get_mime_type_for_data(png),
+            mime_db.get_mime_type_for_data(png)
+        );
+
+        copy.export_snapshot(dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("SHA256SUMS")).unwrap(),
+            manifest
+        );
+
+        // The compiled cache and the plain text files have the same data
+        assert_eq!(copy.fingerprint(), mime_db.fingerprint());
+    }
+
+    #[cfg(feature = "hashes")]
+    #[test]
+    fn fingerprint() {
+        let mime_db = load_test_data();
+        let fingerprint = mime_db.fingerprint();
+        assert_eq!(mime_db.clone().fingerprint(), fingerprint);
+        assert_ne!(
+            SharedMimeInfo::new_for_directory("test_files/legacy").fingerprint(),
+            fingerprint
+        );
+
+        let png = Mime::from_str("image/png").unwrap();
+        let changed = mime_db.with_overlay(
+            |overlay| {
+                overlay.add_glob(&png, "*.pic", GlobWeight::DEFAULT);
+            },
+            |db| db.fingerprint(),
+        );
+        assert_ne!(changed, fingerprint);
+        assert_eq!(mime_db.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn get_mime_type_for_tree() {
+        let mime_db = load_test_data();
+        let tree = testing::TempMimeDir::new().unwrap();
+        let root = tree.path();
+        let mime_types = |db: &SharedMimeInfo| -> Vec<String> {
+            db.get_mime_type_for_tree(root)
+                .iter()
+                .map(Mime::to_string)
+                .collect()
+        };
+
+        assert!(mime_types(&mime_db).is_empty());
+
+        // The paths are not case sensitive, unless required
+        fs::create_dir(root.join("DCIM")).unwrap();
+        assert!(mime_types(&mime_db).is_empty());
+        fs::write(root.join("DCIM/IMG_0001.JPG"), b"").unwrap();
+        assert_eq!(mime_types(&mime_db), vec!["x-content/image-dcf"]);
+
+        fs::write(root.join("AUTORUN"), b"").unwrap();
+        assert_eq!(mime_types(&mime_db), vec!["x-content/image-dcf"]);
+        fs::write(root.join("autorun"), b"").unwrap();
+        assert_eq!(
+            mime_types(&mime_db),
+            vec!["x-content/unix-software", "x-content/image-dcf"]
+        );
+
+        // Nested matches require the parent match, and one of the children
+        fs::create_dir(root.join("setup")).unwrap();
+        assert!(!mime_types(&mime_db).contains(&"x-content/software".to_string()));
+        fs::write(root.join("setup/start"), b"\x89PNG\r\n\x1a\n").unwrap();
+        assert!(!mime_types(&mime_db).contains(&"x-content/software".to_string()));
+        fs::write(root.join("setup/start"), b"Run the installer").unwrap();
+        assert!(mime_types(&mime_db).contains(&"x-content/software".to_string()));
+    }
+
+    #[cfg(feature = "libmagic")]
+    #[test]
+    fn cross_check_libmagic() {
+        let mime_db = load_test_data();
+        let libmagic = LibMagic::open().unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01\x08\x02\0\0\0";
+
+        let res = mime_db.guess_mime_type().data(png).cross_check(&libmagic);
+        assert_eq!(res.guess().mime_type(), &mime::IMAGE_PNG);
+        assert_eq!(
+            res.libmagic_type().unwrap().as_ref().unwrap(),
+            &mime::IMAGE_PNG
+        );
+        assert_eq!(res.parity(), Some(Parity::Equal));
+
+        // The file name wins over magic entries that are not certain
+        let res = mime_db
+            .guess_mime_type()
+            .file_name("picture.txt")
+            .data(png)
+            .cross_check(&libmagic);
+        assert_eq!(res.guess().mime_type(), &mime::TEXT_PLAIN);
+        assert_eq!(res.parity(), Some(Parity::Different));
+        assert!(!res.parity().unwrap().is_compatible());
+
+        // libmagic reads the file itself
+        let dir = testing::TempMimeDir::new().unwrap();
+        let path = dir.path().join("picture.png");
+        fs::write(&path, png).unwrap();
+        let res = mime_db.guess_mime_type().path(&path).cross_check(&libmagic);
+        assert_eq!(res.parity(), Some(Parity::Equal));
+
+        let res = mime_db
+            .guess_mime_type()
+            .path(dir.path().join("missing.png"))
+            .cross_check(&libmagic);
+        assert!(res.libmagic_type().unwrap().is_err());
+        assert_eq!(res.parity(), None);
+
+        let res = mime_db
+            .guess_mime_type()
+            .file_name("notes.txt")
+            .cross_check(&libmagic);
+        assert!(res.libmagic_type().is_none());
+    }
+
+    #[test]
+    fn resolve_evidence() {
+        let mime_db = load_test_data();
+        let svg = mime::IMAGE_SVG;
+        let xml = Mime::from_str("application/xml").unwrap();
+        let png = mime::IMAGE_PNG;
+
+        let guess = mime_db.resolve(Vec::new());
+        assert_eq!(guess.mime_type(), &mime::APPLICATION_OCTET_STREAM);
+        assert!(guess.is_unknown() && guess.uncertain());
+
+        // The specific type collects the evidence for its parent, and
+        // aliases are resolved
+        let guess = mime_db.resolve(vec![
+            Evidence::Declared(Mime::from_str("text/xml").unwrap()),
+            Evidence::FromName(svg.clone(), GlobWeight::new(30).unwrap()),
+            Evidence::FromMagic(png.clone(), MagicPriority::DEFAULT),
+        ]);
+        assert_eq!(guess.mime_type(), &svg);
+        assert_eq!(guess.conflict(), Some(&png));
+        assert!(guess.uncertain());
+
+        // Ties go to the most specific type
+        let guess = mime_db.resolve(vec![
+            Evidence::External(xml.clone(), 0.5),
+            Evidence::External(svg.clone(), 0.0),
+        ]);
+        assert_eq!(guess.mime_type(), &svg);
+        assert!(!guess.uncertain());
+
+        // A certain magic match beats a glob with the default weight, and
+        // the unknown type is ignored
+        let guess = mime_db.resolve(vec![
+            Evidence::FromName(xml.clone(), GlobWeight::DEFAULT),
+            Evidence::FromMagic(png.clone(), MagicPriority::CERTAIN),
+            Evidence::Declared(mime::APPLICATION_OCTET_STREAM),
+            Evidence::External(xml.clone(), f32::NAN),
+        ]);
+        assert_eq!(guess.mime_type(), &png);
+        assert_eq!(guess.conflict(), Some(&xml));
+    }
+
+    #[test]
+    fn list_mime_types() {
+        let mime_db = load_test_data();
+        let types: Vec<&Mime> = mime_db.list_mime_types().collect();
+        assert_eq!(types.len(), 790);
+        assert!(types.contains(&&mime::IMAGE_SVG));
+        assert!(types
+            .windows(2)
+            .all(|w| w[0].essence_str() < w[1].essence_str()));
+
+        // Aliases are not listed
+        let alias = Mime::from_str("text/xml").unwrap();
+        assert!(mime_db.unalias_mime_type(&alias).is_some());
+        assert!(!types.contains(&&alias));
+
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/packages");
+        assert!(mime_db
+            .list_mime_types()
+            .any(|m| m.essence_str() == "x-content/x-base-volume"));
+    }
+
+    #[test]
+    fn get_description() {
+        let mime_db = load_test_data();
+        assert_eq!(
+            mime_db.get_description(&mime::IMAGE_SVG),
+            Some("SVG image".to_string())
+        );
+        assert_eq!(mime_db.get_description(&mime::IMAGE_PNG), None);
+        assert_eq!(
+            mime_db.get_description_for_locale(&mime::IMAGE_SVG, &["de_DE.UTF-8"]),
+            Some("SVG-Bild".to_string())
+        );
+        assert_eq!(
+            mime_db.get_description_for_locale(&mime::IMAGE_SVG, &["it", "fr_FR"]),
+            Some("image SVG".to_string())
+        );
+        assert_eq!(
+            mime_db.get_description_for_locale(&mime::IMAGE_SVG, &["sv", "C", "fr"]),
+            Some("SVG image".to_string())
+        );
+
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/packages");
+        let base = Mime::from_str("application/x-base").unwrap();
+        assert_eq!(
+            mime_db.get_description(&base),
+            Some("Base document".to_string())
+        );
+    }
+
+    #[test]
+    fn get_mime_type_for_xml() {
+        let mime_db = load_test_data();
+        let svg = "http://www.w3.org/2000/svg";
+
+        assert_eq!(
+            mime_db.get_mime_type_for_xml("svg", svg),
+            Some(mime::IMAGE_SVG)
+        );
+        assert_eq!(
+            mime_db.get_mime_type_for_xml("html", "http://www.w3.org/1999/xhtml"),
+            Some(Mime::from_str("application/xhtml+xml").unwrap())
+        );
+        assert_eq!(mime_db.get_mime_type_for_xml("html", svg), None);
+        assert_eq!(mime_db.get_mime_type_for_xml("svg", ""), None);
+
+        // The plain text file is used without the mime.cache file
+        let dir = testing::TempMimeDir::new().unwrap();
+        fs::write(
+            dir.mime_path().join("XMLnamespaces"),
+            "http://example.com/ns doc application/x-doc\n",
+        )
+        .unwrap();
+        let mime_db = dir.load().unwrap();
+        assert_eq!(
+            mime_db.get_mime_type_for_xml("doc", "http://example.com/ns"),
+            Some(Mime::from_str("application/x-doc").unwrap())
+        );
+    }
+
+    #[test]
+    fn load_default() {
         let _db: SharedMimeInfo = Default::default();
     }
 
     #[test]
-    fn reload() {
-        // We don't load the system data in the, admittedly, remote case the system
-        // is getting updated *while* we run the test suite.
-        let mut _db = load_test_data();
+    fn reload() {
+        // We don't load the system data in the, admittedly, remote case the system
+        // is getting updated *while* we run the test suite.
+        let mut _db = load_test_data();
+
+        assert_eq!(_db.reload(), false);
+    }
+
+    #[test]
+    fn reload_new_directory() {
+        let dir = testing::TempMimeDir::new().unwrap();
+        fs::remove_dir(dir.mime_path()).unwrap();
+
+        let mut db = SharedMimeInfo::builder()
+            .data_home(dir.path())
+            .data_dirs(Vec::<PathBuf>::new())
+            .build();
+        assert_eq!(db.reload(), false);
+
+        // The directory is created after the database was loaded
+        fs::create_dir(dir.mime_path()).unwrap();
+        fs::write(dir.mime_path().join("globs2"), "50:text/x-foo:*.foo\n").unwrap();
+
+        assert_eq!(db.reload(), true);
+        assert_eq!(
+            db.get_mime_types_from_file_name("bar.foo"),
+            vec![Mime::from_str("text/x-foo").unwrap()]
+        );
+        assert_eq!(db.reload(), false);
+
+        // The directory is removed
+        fs::remove_dir_all(dir.mime_path()).unwrap();
+        assert_eq!(db.reload(), true);
+        assert_eq!(
+            db.get_mime_types_from_file_name("bar.foo"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+    }
+
+    #[test]
+    fn reload_changed_directory() {
+        let user = testing::TempMimeDir::new().unwrap();
+        fs::remove_dir(user.mime_path()).unwrap();
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .glob(50, "text/x-bar", "*.bar")
+            .magic(50, "text/x-bar", 0, b"BAR");
+        system.write().unwrap();
+
+        let mut mime_db = SharedMimeInfo::builder()
+            .override_dirs(Vec::<PathBuf>::new())
+            .data_dirs([system.path()])
+            .data_home(user.path())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+        assert_eq!(mime_db.sniff(b"BAR").map(|(m, _)| m), Some(&bar));
+        let segment = Arc::clone(&mime_db.mime_dirs[1].segment);
+
+        // Rewriting the files in place does not change the directory, so
+        // the system data is not read again
+        fs::write(system.mime_path().join("globs2"), "").unwrap();
+        fs::write(system.mime_path().join("magic"), "MIME-Magic\0\n").unwrap();
+        fs::create_dir(user.mime_path()).unwrap();
+        fs::write(user.mime_path().join("globs2"), "50:text/x-foo:*.foo\n").unwrap();
+
+        assert_eq!(mime_db.reload(), true);
+        assert!(Arc::ptr_eq(&segment, &mime_db.mime_dirs[1].segment));
+        assert_eq!(mime_db.get_mime_types_from_file_name("a.foo"), vec![foo]);
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.bar"),
+            vec![bar.clone()]
+        );
+        assert_eq!(mime_db.sniff(b"BAR").map(|(m, _)| m), Some(&bar));
+        assert!(mime_db.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn lookup_generic_icons() {
+        let mime_db = load_test_data();
+
+        assert_eq!(
+            mime_db.lookup_generic_icon_name(&mime::APPLICATION_JSON),
+            Some("text-x-script".to_string())
+        );
+        assert_eq!(
+            mime_db.lookup_generic_icon_name(&mime::TEXT_PLAIN),
+            Some("text-x-generic".to_string())
+        );
+    }
+
+    #[test]
+    fn unalias() {
+        let mime_db = load_test_data();
+
+        assert_eq!(
+            mime_db.unalias_mime_type(&Mime::from_str("application/ics").unwrap()),
+            Some(Mime::from_str("text/calendar").unwrap())
+        );
+        assert_eq!(
+            mime_db.unalias_mime_type(&Mime::from_str("text/plain").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn mime_type_equal() {
+        let mime_db = load_test_data();
+
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("application/wordperfect").unwrap(),
+                &Mime::from_str("application/vnd.wordperfect").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("application/x-gnome-app-info").unwrap(),
+                &Mime::from_str("application/x-desktop").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("application/x-wordperfect").unwrap(),
+                &Mime::from_str("application/vnd.wordperfect").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("application/x-wordperfect").unwrap(),
+                &Mime::from_str("audio/x-midi").unwrap(),
+            ),
+            false
+        );
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("application/octet-stream").unwrap(),
+                &Mime::from_str("text/plain").unwrap(),
+            ),
+            false
+        );
+        assert_eq!(
+            mime_db.mime_type_equal(
+                &Mime::from_str("text/plain").unwrap(),
+                &Mime::from_str("text/*").unwrap(),
+            ),
+            false
+        );
+    }
+
+    #[test]
+    fn mime_type_for_file_name() {
+        let mime_db = load_test_data();
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.txt"),
+            vec![Mime::from_str("text/plain").unwrap()]
+        );
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("bar.gif"),
+            vec![Mime::from_str("image/gif").unwrap()]
+        );
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("baz.mod"),
+            vec![Mime::from_str("audio/x-mod").unwrap()]
+        );
+    }
+
+    #[test]
+    fn mime_type_for_file_name_dedups_aliases() {
+        let mut mime_db = load_test_data();
+        let wordperfect = Mime::from_str("application/vnd.wordperfect").unwrap();
+
+        // A legacy override using an alias
+        Arc::make_mut(&mut mime_db.globs).add_glob(glob::Glob::new(
+            &Mime::from_str("application/x-wordperfect").unwrap(),
+            "*.wpd",
+            50,
+            false,
+        ));
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.wpd"),
+            vec![wordperfect]
+        );
+    }
+
+    #[test]
+    fn first_mime_type_for_file_name() {
+        let mime_db = load_test_data();
+
+        for name in [
+            "foo.txt",
+            "foo.c",
+            "FOO.C",
+            "foo.tar.gz",
+            "COPYING",
+            "foo.wpd",
+        ] {
+            assert_eq!(
+                mime_db.first_mime_type_from_file_name(name).as_ref(),
+                mime_db.get_mime_types_from_file_name(name).first()
+            );
+        }
+
+        assert_eq!(mime_db.first_mime_type_from_file_name("foo"), None);
+
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/legacy");
+        assert_eq!(
+            mime_db.first_mime_type_from_file_name("foo.legacy"),
+            Some(Mime::from_str("application/x-modern").unwrap())
+        );
+    }
+
+    #[test]
+    fn literal_names() {
+        let mime_db = load_test_data();
+
+        let copying = mime_db
+            .literal_names()
+            .find(|(name, _)| *name == "copying")
+            .map(|(_, mime_type)| mime_type.clone());
+        assert_eq!(copying, Some(Mime::from_str("text/x-copying").unwrap()));
+        assert!(mime_db.literal_names().all(|(name, _)| !name.contains('*')));
+
+        assert!(mime_db.is_special_filename("COPYING"));
+        assert!(mime_db.is_special_filename("meson.build"));
+        assert!(!mime_db.is_special_filename("foo.txt"));
+    }
+
+    #[test]
+    fn save_to_directory() {
+        let mime_db = load_test_data();
+
+        let dir = testing::TempMimeDir::new().unwrap();
+        mime_db.save_to_directory(dir.path()).unwrap();
+        let saved_db = SharedMimeInfo::new_for_directory(dir.path());
+
+        let mut globs = Vec::new();
+        mime_db.write_globs(&mut globs).unwrap();
+        assert_eq!(fs::read(dir.mime_path().join("globs2")).unwrap(), globs);
+        assert!(String::from_utf8(globs)
+            .unwrap()
+            .contains("50:text/x-copying:copying\n"));
+
+        for (original, saved) in [(&mime_db, &saved_db), (&saved_db, &mime_db)] {
+            let mut a = Vec::new();
+            let mut b = Vec::new();
+            original.write_aliases(&mut a).unwrap();
+            saved.write_aliases(&mut b).unwrap();
+            assert_eq!(a, b);
+
+            original.write_subclasses(&mut a).unwrap();
+            saved.write_subclasses(&mut b).unwrap();
+            original.write_icons(&mut a).unwrap();
+            saved.write_icons(&mut b).unwrap();
+            original.write_generic_icons(&mut a).unwrap();
+            saved.write_generic_icons(&mut b).unwrap();
+            assert_eq!(a, b);
+        }
+
+        assert_eq!(
+            saved_db.get_mime_types_from_file_name("file.txt"),
+            mime_db.get_mime_types_from_file_name("file.txt")
+        );
+        assert_eq!(
+            saved_db.get_parents(&Mime::from_str("application/rss+xml").unwrap()),
+            mime_db.get_parents(&Mime::from_str("application/rss+xml").unwrap())
+        );
+    }
+
+    #[test]
+    fn with_overlay() {
+        let mime_db = load_test_data();
+        let png = Mime::from_str("image/png").unwrap();
+
+        let (guess, all) = mime_db.with_overlay(
+            |overlay| {
+                overlay
+                    .add_glob(&png, "*.txt", GlobWeight::new(90).unwrap())
+                    .add_case_sensitive_glob(&png, "*.PIC", GlobWeight::DEFAULT)
+                    .add_glob(&png, "", GlobWeight::DEFAULT);
+            },
+            |db| {
+                (
+                    db.guess_mime_type().file_name("foo.txt").guess(),
+                    db.get_mime_types_from_file_name("foo.PIC"),
+                )
+            },
+        );
+        assert_eq!(guess.mime_type(), &png);
+        assert_eq!(all, vec![png.clone()]);
+
+        // The changes are not committed
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.txt"),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.PIC"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+
+        // Replacing the weight of an existing glob does not change it
+        // in the database either
+        let before = mime_db.guess_mime_type().file_name("foo.png").guess_all();
+        mime_db.with_overlay(
+            |overlay| {
+                overlay.add_glob(&png, "*.png", GlobWeight::new(10).unwrap());
+            },
+            |db| {
+                assert_eq!(
+                    db.guess_mime_type().file_name("foo.png").guess_all()[0].glob_weight(),
+                    GlobWeight::new(10)
+                );
+            },
+        );
+        assert_eq!(
+            mime_db.guess_mime_type().file_name("foo.png").guess_all(),
+            before
+        );
+    }
+
+    #[test]
+    fn add_directory() {
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .glob(50, "text/plain", "*.txt")
+            .glob(50, "image/png", "*.png");
+        let mut mime_db = system.load().unwrap();
+        assert!(mime_db.diagnostics().is_empty());
+
+        let mut app = testing::TempMimeDir::new().unwrap();
+        app.glob(50, "application/x-app-doc", "*.appdoc")
+            .subclass("application/x-app-doc", "text/plain")
+            .magic(50, "application/x-{broken}", 0, b"APP");
+        app.write().unwrap();
+
+        assert!(mime_db.add_directory(app.path()));
+        assert!(!mime_db.add_directory(app.path()));
+        let app_doc = Mime::from_str("application/x-app-doc").unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.appdoc"),
+            vec![app_doc.clone()]
+        );
+        assert!(mime_db.mime_type_subclass(&app_doc, &mime::TEXT_PLAIN));
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.txt"),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(mime_db.diagnostics().len(), 1);
+
+        // The new directory is reloaded with the others
+        assert!(!mime_db.reload());
+        fs::remove_dir_all(app.mime_path()).unwrap();
+        assert!(mime_db.reload());
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.appdoc"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert!(mime_db.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn can_disambiguate() {
+        let mime_db = load_test_data();
+        let mime = |s: &str| Mime::from_str(s).unwrap();
+
+        assert!(mime_db.can_disambiguate(&[mime::IMAGE_PNG, mime::IMAGE_JPEG]));
+        assert!(mime_db.can_disambiguate(&[mime("text/x-python"), mime("text/x-python3")]));
+        assert!(
+            mime_db.can_disambiguate(&[mime::APPLICATION_JSON, mime("application/x-ipynb+json")])
+        );
+
+        // Only the common parent has magic entries
+        assert!(!mime_db.can_disambiguate(&[mime::APPLICATION_JSON, mime("application/geo+json")]));
+
+        // Nothing to disambiguate
+        assert!(!mime_db.can_disambiguate(&[]));
+        assert!(!mime_db.can_disambiguate(&[mime::IMAGE_PNG]));
+        assert!(!mime_db.can_disambiguate(&[mime("text/calendar"), mime("application/ics")]));
+    }
+
+    #[test]
+    fn icon_precedence() {
+        let mut user = testing::TempMimeDir::new().unwrap();
+        user.glob(50, "image/png", "*.png")
+            .icon("image/png", "user-png")
+            .generic_icon("image/png", "user-x-generic");
+        user.write().unwrap();
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .glob(50, "image/png", "*.png")
+            .icon("image/png", "system-png")
+            .icon("image/svg+xml", "system-svg")
+            .generic_icon("image/png", "user-x-generic");
+        system.write().unwrap();
+
+        // The icons of the user take precedence, and the generic icon
+        // defined in both directories is not a conflict
+        let mime_db = SharedMimeInfo::builder()
+            .override_dirs(Vec::<PathBuf>::new())
+            .data_dirs([system.path()])
+            .data_home(user.path())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        let png = Mime::from_str("image/png").unwrap();
+        assert_eq!(
+            mime_db.lookup_icon_names(&png),
+            vec!["user-png", "image-png", "user-x-generic"]
+        );
+        assert_eq!(mime_db.icon_source_tier(&png), Some(Tier::User));
+        assert_eq!(mime_db.generic_icon_source_tier(&png), Some(Tier::User));
+        assert_eq!(
+            mime_db.icon_source_tier(&Mime::from_str("image/svg+xml").unwrap()),
+            Some(Tier::System)
+        );
+        assert_eq!(mime_db.icon_source_tier(&mime::TEXT_PLAIN), None);
+
+        // Only the conflicting icon is reported, and the snapshot keeps
+        // the icon that won
+        assert_eq!(
+            mime_db
+                .diagnostics()
+                .iter()
+                .map(Diagnostic::to_string)
+                .collect::<Vec<_>>(),
+            vec![format!(
+                "{}: 'image/png:system-png' is shadowed by 'image/png:user-png' in {}",
+                system.mime_path().join("icons").display(),
+                user.mime_path().join("icons").display()
+            )]
+        );
+        let mut out = Vec::new();
+        mime_db.write_icons(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "image/png:user-png\nimage/svg+xml:system-svg\n"
+        );
+    }
+
+    #[test]
+    fn try_build() {
+        let builder = |dir: &testing::TempMimeDir| {
+            let mut builder = SharedMimeInfo::builder();
+            builder
+                .override_dirs(Vec::<PathBuf>::new())
+                .data_dirs([dir.path()])
+                .data_home("/does/not/exist")
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false);
+            builder
+        };
+
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo");
+        dir.write().unwrap();
+        let mime_db = builder(&dir).try_build().unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![Mime::from_str("text/x-foo").unwrap()]
+        );
+
+        // An entry that cannot be parsed fails the whole database
+        dir.alias("application/x-foo", "not a MIME type");
+        dir.write().unwrap();
+        match builder(&dir).try_build() {
+            Err(Error::Parse(Diagnostic::InvalidMimeType { .. })) => {}
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+
+        // A missing mime directory is skipped, but not one that cannot be
+        // listed
+        let unreadable = testing::TempMimeDir::new().unwrap();
+        fs::remove_dir(unreadable.mime_path()).unwrap();
+        fs::write(unreadable.mime_path(), b"").unwrap();
+        match builder(&unreadable).try_build() {
+            Err(Error::UnreadableDirectory { path, .. }) => {
+                assert_eq!(path, unreadable.mime_path())
+            }
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn binary_snapshot() {
+        let builder = |dirs: &[PathBuf]| {
+            let mut builder = SharedMimeInfo::builder();
+            builder
+                .override_dirs(Vec::<PathBuf>::new())
+                .data_dirs(dirs)
+                .data_home("/does/not/exist")
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false);
+            builder
+        };
+
+        // The compiled test data, with every part of the MIME data
+        let test_files = [env::current_dir().unwrap().join("test_files")];
+        let mime_db = builder(&test_files).build();
+        let snapshot = mime_db.serialize_snapshot();
+        assert_eq!(snapshot, mime_db.serialize_snapshot());
+        let restored = builder(&test_files).build_from_snapshot(&snapshot).unwrap();
+        #[cfg(feature = "hashes")]
+        assert_eq!(restored.fingerprint(), mime_db.fingerprint());
+        assert_eq!(restored.database_version(), Some("2.4"));
+
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo")
+            .alias("application/x-foo", "text/x-foo")
+            .magic(50, "text/x-foo", 0, b"FOO");
+        dir.write().unwrap();
+        let dirs = [dir.path().to_path_buf()];
+        let foo = Mime::from_str("text/x-foo").unwrap();
+
+        let snapshot = builder(&dirs).build().serialize_snapshot();
+        let mut restored = builder(&dirs).build_from_snapshot(&snapshot).unwrap();
+        assert_eq!(
+            restored.get_mime_types_from_file_name("a.foo"),
+            vec![foo.clone()]
+        );
+        assert_eq!(
+            restored.unalias_mime_type(&Mime::from_str("application/x-foo").unwrap()),
+            Some(foo.clone())
+        );
+        assert_eq!(
+            restored.get_mime_type_for_data(b"FOO").map(|(m, _)| m),
+            Some(foo.clone())
+        );
+        assert!(!restored.reload());
+
+        // The snapshot only applies to the same directories and settings
+        let other = testing::TempMimeDir::new().unwrap();
+        let res = builder(&[dir.path().to_path_buf(), other.path().to_path_buf()])
+            .build_from_snapshot(&snapshot);
+        assert!(matches!(res, Err(Error::StaleSnapshot)));
+        let res = builder(&dirs)
+            .load_magic(false)
+            .build_from_snapshot(&snapshot);
+        assert!(matches!(res, Err(Error::StaleSnapshot)));
+
+        // Truncated, or not a snapshot at all
+        let res = builder(&dirs).build_from_snapshot(&snapshot[..snapshot.len() - 1]);
+        assert!(matches!(res, Err(Error::InvalidSnapshot)));
+        let res = builder(&dirs).build_from_snapshot(b"MIME-Magic\0\n");
+        assert!(matches!(res, Err(Error::InvalidSnapshot)));
+
+        // A change of the directory makes the snapshot stale, and the
+        // restored database picks it up when reloaded
+        fs::write(dir.mime_path().join("globs"), b"text/x-bar:*.bar\n").unwrap();
+        fs::remove_file(dir.mime_path().join("globs2")).unwrap();
+        let res = builder(&dirs).build_from_snapshot(&snapshot);
+        assert!(matches!(res, Err(Error::StaleSnapshot)));
+        assert!(restored.reload());
+        assert_eq!(
+            restored.get_mime_types_from_file_name("a.bar"),
+            vec![Mime::from_str("text/x-bar").unwrap()]
+        );
+    }
+
+    #[test]
+    fn directory_precedence() {
+        let mut user = testing::TempMimeDir::new().unwrap();
+        user.glob(50, "text/x-foo", "*.foo")
+            .alias("application/x-foo", "text/x-foo")
+            .magic(50, "text/x-foo", 0, b"FOO");
+        user.write().unwrap();
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .glob(60, "text/x-foo", "*.foo")
+            .glob(50, "text/x-bar", "*.bar")
+            .alias("application/x-foo", "text/x-bar")
+            .magic(50, "text/x-foo", 0, b"FOO")
+            .magic(40, "text/x-foo", 0, b"FOO2");
+        system.write().unwrap();
+
+        let mime_db = SharedMimeInfo::builder()
+            .override_dirs(Vec::<PathBuf>::new())
+            .data_dirs([system.path()])
+            .data_home(user.path())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+        let alias = Mime::from_str("application/x-foo").unwrap();
+
+        // The alias of the user wins, and is not listed for the MIME type
+        // of the system
+        assert_eq!(mime_db.unalias_mime_type(&alias), Some(foo.clone()));
+        assert_eq!(mime_db.mime_type_record(&foo).aliases(), &[alias]);
+        assert!(mime_db.mime_type_record(&bar).aliases().is_empty());
+
+        // The pattern of the user keeps its weight
+        assert_eq!(
+            mime_db.glob_map().lookup_all("a.foo"),
+            vec![(foo.clone(), GlobWeight::DEFAULT)]
+        );
+
+        // The magic entries of both directories are merged, but the one
+        // repeated by the system is only matched once
+        let entries: Vec<(&Mime, u32, Tier)> = mime_db
+            .magic
+            .entries()
+            .iter()
+            .map(|e| (e.mime_type(), e.priority(), e.tier()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![(&foo, 50, Tier::User), (&foo, 40, Tier::System)]
+        );
+    }
+
+    #[test]
+    fn source_tiers() {
+        let mime_db = SharedMimeInfo::builder()
+            .data_home("test_files/lenient")
+            .data_dirs(["test_files"])
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        let good = Mime::from_str("text/x-good").unwrap();
+        let png = Mime::from_str("image/png").unwrap();
+
+        let globs = mime_db.glob_map().globs_for_mime_type(&good);
+        assert_eq!(globs[0].pattern(), "*.good");
+        assert_eq!(globs[0].source_tier(), Tier::User);
+        let globs = mime_db.glob_map().globs_for_mime_type(&png);
+        assert_eq!(globs[0].pattern(), "*.png");
+        assert_eq!(globs[0].weight(), GlobWeight::DEFAULT);
+        assert_eq!(globs[0].source_tier(), Tier::System);
+
+        assert_eq!(
+            mime_db.alias_source_tier(&Mime::from_str("text/x-alias").unwrap()),
+            Some(Tier::User)
+        );
+        assert_eq!(
+            mime_db.alias_source_tier(&Mime::from_str("application/x-gnome-app-info").unwrap()),
+            Some(Tier::System)
+        );
+        assert_eq!(mime_db.alias_source_tier(&good), None);
+
+        assert_eq!(mime_db.magic_source_tier(&good), Some(Tier::User));
+        assert_eq!(mime_db.magic_source_tier(&png), Some(Tier::System));
+        assert_eq!(mime_db.magic_source_tier(&mime::TEXT_CSV), None);
+
+        mime_db.with_overlay(
+            |overlay| {
+                overlay.add_glob(&png, "*.pic", GlobWeight::DEFAULT);
+            },
+            |db| {
+                let tiers: Vec<(String, Tier)> = db
+                    .glob_map()
+                    .globs_for_mime_type(&png)
+                    .iter()
+                    .map(|g| (g.pattern(), g.source_tier()))
+                    .collect();
+                assert_eq!(
+                    tiers,
+                    vec![
+                        ("*.pic".to_string(), Tier::Runtime),
+                        ("*.png".to_string(), Tier::System)
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn mime_type_record() {
+        let mime_db = load_test_data();
+
+        let record =
+            mime_db.mime_type_record(&Mime::from_str("application/x-gnome-app-info").unwrap());
+        assert_eq!(
+            record.mime_type(),
+            &Mime::from_str("application/x-desktop").unwrap()
+        );
+        assert_eq!(
+            record.aliases(),
+            &[Mime::from_str("application/x-gnome-app-info").unwrap()]
+        );
+        assert_eq!(record.parents(), &[mime::TEXT_PLAIN]);
+        assert_eq!(
+            record.globs(),
+            &["*.desktop".to_string(), "*.kdelnk".to_string()]
+        );
+        assert_eq!(record.generic_icon(), "text-x-generic");
+
+        let unknown = mime_db.mime_type_record(&Mime::from_str("application/x-foo").unwrap());
+        assert!(unknown.aliases().is_empty());
+        assert!(unknown.globs().is_empty());
+        assert_eq!(unknown.icon(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn to_json() {
+        let mime_db = load_test_data();
+
+        let guess = mime_db.guess_mime_type().data(&[0, 1, 2, 3]).guess();
+        assert_eq!(
+            guess.to_json(),
+            r#"{"mime_type":"application/octet-stream","uncertain":true,"unknown":true,"conflict":null,"deadline_exceeded":false}"#
+        );
+
+        let record = mime_db.mime_type_record(&Mime::from_str("application/x-desktop").unwrap());
+        assert_eq!(
+            record.to_json(),
+            r#"{"mime_type":"application/x-desktop","aliases":["application/x-gnome-app-info"],"parents":["text/plain"],"icon":null,"generic_icon":"text-x-generic","globs":["*.desktop","*.kdelnk"]}"#
+        );
+    }
+
+    #[test]
+    fn load_source_packages() {
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/packages");
+        let base = Mime::from_str("application/x-base").unwrap();
+        let derived = Mime::from_str("application/x-derived").unwrap();
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.base"),
+            vec![base.clone()]
+        );
+        assert_eq!(mime_db.first_mime_type_from_file_name("foo.old"), None);
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"xxBASE\x01x\x01\x32"),
+            Some((base.clone(), MagicPriority::new(60).unwrap()))
+        );
+        assert!(mime_db.mime_type_subclass(&derived, &base));
+        assert_eq!(
+            mime_db.unalias_mime_type(&Mime::from_str("application/x-derived-old").unwrap()),
+            Some(derived)
+        );
+
+        match &mime_db.diagnostics()[0] {
+            Diagnostic::InvalidMimeType { path, .. } => {
+                assert!(path.starts_with("test_files/packages/mime/packages"))
+            }
+            d => panic!("unexpected diagnostic {:?}", d),
+        }
+    }
+
+    #[test]
+    fn generic_globs() {
+        let load = |policy| {
+            SharedMimeInfo::builder()
+                .data_home("test_files/generic")
+                .data_dirs(Vec::<PathBuf>::new())
+                .override_dirs(Vec::<PathBuf>::new())
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .generic_globs(policy)
+                .build()
+        };
+        let vendor = Mime::from_str("application/x-vendor").unwrap();
+        let low = Mime::from_str("text/x-low").unwrap();
+
+        let keep = load(GenericGlobs::Keep);
+        assert!(keep.diagnostics().is_empty());
+        assert_eq!(
+            keep.first_mime_type_from_file_name("foo.low"),
+            Some(vendor.clone())
+        );
+
+        let down_weight = load(GenericGlobs::DownWeight);
+        // Full patterns like `*.*` need the `glob` feature
+        let generic = if cfg!(feature = "glob") { 2 } else { 1 };
+        assert_eq!(down_weight.diagnostics().len(), generic);
+        assert_eq!(
+            down_weight.first_mime_type_from_file_name("foo.low"),
+            Some(low.clone())
+        );
+        assert_eq!(
+            down_weight.first_mime_type_from_file_name("foo.txt"),
+            Some(vendor)
+        );
+
+        let drop = load(GenericGlobs::Drop);
+        assert_eq!(drop.first_mime_type_from_file_name("foo.low"), Some(low));
+        assert_eq!(drop.first_mime_type_from_file_name("foo.txt"), None);
+        assert_eq!(
+            drop.diagnostics()[0].to_string(),
+            "test_files/generic/mime/globs2: '50:application/x-vendor:*' matches almost every \
+             file name, and was dropped"
+        );
+    }
+
+    #[test]
+    fn globs_for_aliases() {
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/legacy");
+        let legacy = Mime::from_str("application/x-legacy").unwrap();
+        let modern = Mime::from_str("application/x-modern").unwrap();
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.legacy"),
+            vec![modern.clone()]
+        );
+        assert_eq!(
+            mime_db.glob_map().lookup("foo.legacy"),
+            vec![legacy.clone()]
+        );
+
+        let candidates = mime_db
+            .guess_mime_type()
+            .file_name("foo.mod")
+            .data(b"LGCY")
+            .guess_all();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].mime_type(), &modern);
+        assert_eq!(candidates[0].glob_weight(), GlobWeight::new(60));
+        assert_eq!(candidates[0].magic_priority(), Some(MagicPriority::DEFAULT));
+
+        let candidates = mime_db.guess_mime_type().data(b"LGCY").guess_all();
+        assert_eq!(candidates[0].mime_type(), &modern);
+        let guess = mime_db.guess_mime_type().data(b"LGCY").guess();
+        assert_eq!(guess.mime_type(), &modern);
+
+        assert_eq!(
+            mime_db.mime_type_record(&legacy).globs(),
+            &["*.mod", "*.legacy", "*.modern"]
+        );
+    }
+
+    #[test]
+    fn shadowed_entries() {
+        let mime_db = SharedMimeInfo::builder()
+            .data_home("test_files/lenient")
+            .data_dirs(["test_files/shadow"])
+            .detect_sandbox(false)
+            .build();
+
+        let shadowed: Vec<String> = mime_db
+            .diagnostics()
+            .iter()
+            .filter(|d| matches!(d, Diagnostic::ShadowedEntry { .. }))
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            shadowed,
+            vec![
+                "test_files/shadow/mime/aliases: 'text/x-alias text/plain' is shadowed by \
+                 'text/x-alias text/x-good' in test_files/lenient/mime/aliases",
+                "test_files/shadow/mime/globs2: '10:text/x-good:*.good' is shadowed by \
+                 '50:text/x-good:*.good' in test_files/lenient/mime/globs2",
+            ]
+        );
+    }
+
+    #[test]
+    fn deferred_magic() {
+        let mime_db = load_test_data();
+        let copy = mime_db.clone();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.png"),
+            vec![mime::IMAGE_PNG]
+        );
+        assert!(!mime_db.magic.is_parsed());
+
+        // The entries of the mime.cache file are matched in place, and
+        // only copied to list them; the clones share the copies
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"\x89PNG\r\n\x1a\n"),
+            Some((mime::IMAGE_PNG, MagicPriority::DEFAULT))
+        );
+        assert!(!copy.magic.is_parsed());
+        assert!(mime_db.max_magic_priority(&mime::IMAGE_PNG).is_some());
+        assert!(copy.magic.is_parsed());
+
+        // The entries of the plain text files, and their diagnostics
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/plain", "*.txt")
+            .magic(50, "image/png", 0, b"\x89PNG")
+            .magic(50, "text/x-{broken}", 0, b"BROKEN");
+        let mime_db = dir.load().unwrap();
+        assert!(!mime_db.magic.is_parsed());
+        assert_eq!(mime_db.diagnostics().len(), 1);
+        assert!(mime_db.magic.is_parsed());
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"\x89PNG"),
+            Some((mime::IMAGE_PNG, MagicPriority::DEFAULT))
+        );
+    }
+
+    #[test]
+    fn selective_loading() {
+        let mime_db = SharedMimeInfo::builder()
+            .data_home("test_files")
+            .default_dirs(false)
+            .load_magic(false)
+            .load_icons(false)
+            .build();
+        assert_eq!(mime_db.mime_dirs.len(), 1);
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.png"),
+            vec![mime::IMAGE_PNG]
+        );
+        assert!(mime_db
+            .get_mime_type_for_data(b"\x89PNG\r\n\x1a\n")
+            .is_none());
+        assert_eq!(
+            mime_db.lookup_icon_names(&mime::IMAGE_SVG),
+            vec!["image-svg+xml", "image-x-generic"]
+        );
+        assert!(mime_db.mime_type_subclass(&mime::IMAGE_SVG, &mime::TEXT_XML));
+
+        // The plain text files are skipped as well
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/plain", "*.txt")
+            .magic(50, "image/png", 0, b"\x89PNG");
+        dir.write().unwrap();
+        let mime_db = SharedMimeInfo::builder()
+            .data_home(dir.path())
+            .default_dirs(false)
+            .load_globs(false)
+            .build();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.txt"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"\x89PNG"),
+            Some((mime::IMAGE_PNG, MagicPriority::new(50).unwrap()))
+        );
+    }
+
+    #[cfg(feature = "special-names")]
+    #[test]
+    fn special_names() {
+        let builder = || {
+            let mut builder = SharedMimeInfo::builder();
+            builder
+                .data_home("test_files")
+                .data_dirs(Vec::<PathBuf>::new())
+                .override_dirs(Vec::<PathBuf>::new())
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false);
+            builder
+        };
+
+        let mime_db = builder().build();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("Thumbs.db"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+
+        let mime_db = builder().special_names(true).build();
+        let thumbnails = Mime::from_str("application/x-windows-thumbnail-cache").unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("Thumbs.db"),
+            vec![thumbnails.clone()]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("ehthumbs.db"),
+            vec![thumbnails.clone()]
+        );
+        assert!(mime_db.mime_type_subclass(
+            &thumbnails,
+            &Mime::from_str("application/x-ole-storage").unwrap()
+        ));
+        assert!(mime_db.list_mime_types().any(|m| *m == thumbnails));
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("DESKTOP.INI"),
+            vec![Mime::from_str("application/x-windows-desktop-ini").unwrap()]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name(".directory"),
+            vec![Mime::from_str("application/x-desktop").unwrap()]
+        );
+
+        // The names known to the database keep their MIME type
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "application/octet-stream", ".DS_Store")
+            .glob(50, "text/plain", "*.txt");
+        dir.write().unwrap();
+        let mime_db = SharedMimeInfo::builder()
+            .data_home(dir.path())
+            .data_dirs(Vec::<PathBuf>::new())
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .special_names(true)
+            .build();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name(".DS_Store"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            mime_db
+                .glob_map()
+                .globs_for_mime_type(&mime::APPLICATION_OCTET_STREAM)
+                .len(),
+            1
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("Thumbs.db"),
+            vec![thumbnails]
+        );
+    }
+
+    #[test]
+    fn no_globs_marker() {
+        let mut user = testing::TempMimeDir::new().unwrap();
+        user.glob(50, "text/x-foo", "__NOGLOBS__")
+            .glob(50, "text/x-foo", "*.foo2");
+        user.write().unwrap();
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .glob(50, "text/x-foo", "*.foo")
+            .glob(50, "text/x-bar", "*.bar");
+        system.write().unwrap();
+
+        let mime_db = SharedMimeInfo::builder()
+            .data_home(user.path())
+            .data_dirs([system.path()])
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo2"),
+            vec![foo.clone()]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.bar"),
+            vec![Mime::from_str("text/x-bar").unwrap()]
+        );
+        assert_eq!(
+            mime_db.mime_type_record(&foo).globs(),
+            &["*.foo2".to_string()]
+        );
+        assert!(mime_db.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn no_magic_marker() {
+        let mut user = testing::TempMimeDir::new().unwrap();
+        user.magic(0, "text/x-foo", 0, b"__NOMAGIC__")
+            .magic(50, "text/x-foo", 0, b"FOO2");
+        user.write().unwrap();
+        let mut system = testing::TempMimeDir::new().unwrap();
+        system
+            .magic(50, "text/x-foo", 0, b"FOO")
+            .magic(50, "text/x-bar", 0, b"BAR");
+        system.write().unwrap();
+
+        let mime_db = SharedMimeInfo::builder()
+            .data_home(user.path())
+            .data_dirs([system.path()])
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"FOO2"),
+            Some((foo, MagicPriority::DEFAULT))
+        );
+        assert_eq!(mime_db.get_mime_type_for_data(b"FOO!"), None);
+        assert_eq!(mime_db.get_mime_type_for_data(b"__NOMAGIC__"), None);
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"BAR"),
+            Some((
+                Mime::from_str("text/x-bar").unwrap(),
+                MagicPriority::DEFAULT
+            ))
+        );
+    }
+
+    #[test]
+    fn mime_types_files() {
+        let mime_db = SharedMimeInfo::builder()
+            .data_home("test_files")
+            .data_dirs(Vec::<PathBuf>::new())
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .mime_types_files(["test_files/apache/mime.types"])
+            .build();
+
+        let apache_doc = Mime::from_str("application/x-apache-doc").unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.apdoc"),
+            vec![apache_doc.clone()]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("A.APX"),
+            vec![apache_doc.clone()]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.text"),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.txt"),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.broken"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            mime_db.mime_type_record(&apache_doc).globs(),
+            &["*.apdoc".to_string(), "*.apx".to_string()]
+        );
+
+        let invalid: Vec<String> = mime_db
+            .diagnostics()
+            .iter()
+            .filter(|d| matches!(d, Diagnostic::InvalidMimeType { .. }))
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            invalid,
+            vec!["test_files/apache/mime.types:5: invalid MIME type 'text/x-apache-{broken}'"]
+        );
+    }
+
+    #[test]
+    fn empty_database() {
+        let mime_db = SharedMimeInfo::builder()
+            .data_home("test_files/missing")
+            .data_dirs(["test_files/also-missing"])
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build();
+        assert!(mime_db.is_empty());
+        assert_eq!(
+            mime_db.diagnostics(),
+            &[Diagnostic::NoMimeData {
+                searched: vec![
+                    PathBuf::from("test_files/missing/mime"),
+                    PathBuf::from("test_files/also-missing/mime"),
+                ],
+            }]
+        );
+        assert_eq!(
+            mime_db.diagnostics()[0].to_string(),
+            "no MIME data found in test_files/missing/mime, test_files/also-missing/mime"
+        );
+
+        let mime_db = load_test_data();
+        assert!(!mime_db.is_empty());
+        assert!(!mime_db
+            .diagnostics()
+            .iter()
+            .any(|d| matches!(d, Diagnostic::NoMimeData { .. })));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let strict = SharedMimeInfo::new_for_directory("test_files/lenient");
+        let invalid: Vec<(String, Option<usize>)> = strict
+            .diagnostics()
+            .iter()
+            .filter_map(|d| match d {
+                Diagnostic::InvalidMimeType {
+                    mime_type, line, ..
+                } => Some((mime_type.clone(), *line)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            invalid,
+            vec![
+                ("\"text/x-quoted\"".to_string(), Some(2)),
+                ("text/x-{broken}".to_string(), Some(2)),
+                (" text/x-padded ".to_string(), Some(3)),
+                ("text/x-{broken}".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            strict.get_mime_types_from_file_name("foo.padded"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            strict.get_mime_type_for_data(b"GOOD"),
+            Some((
+                Mime::from_str("text/x-good").unwrap(),
+                MagicPriority::DEFAULT
+            ))
+        );
+
+        let lenient = SharedMimeInfo::builder()
+            .data_home("test_files/lenient")
+            .data_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .lenient(true)
+            .build();
+        // The entries with a MIME type that still cannot be parsed are
+        // skipped, and reported with the raw MIME type
+        let invalid: Vec<(String, String)> = lenient
+            .diagnostics()
+            .iter()
+            .filter_map(|d| match d {
+                Diagnostic::InvalidMimeType {
+                    mime_type, entry, ..
+                } => Some((mime_type.clone(), entry.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            invalid,
+            vec![
+                (
+                    "text/x-{broken}".to_string(),
+                    "50:text/x-{broken}:*.broken".to_string()
+                ),
+                (
+                    "text/x-{broken}".to_string(),
+                    "[50:text/x-{broken}]".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            lenient.get_mime_types_from_file_name("foo.broken"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+        assert_eq!(
+            lenient.get_mime_types_from_file_name("foo.padded"),
+            vec![Mime::from_str("text/x-padded").unwrap()]
+        );
+        assert_eq!(
+            lenient.unalias_mime_type(&Mime::from_str("text/x-quoted").unwrap()),
+            Some(Mime::from_str("text/x-good").unwrap())
+        );
+    }
+
+    #[test]
+    fn strict_parsing() {
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo")
+            .alias("text/x-bar", "text/x-foo")
+            .magic(50, "text/x-foo", 0, b"FOO");
+        dir.write().unwrap();
+
+        let append = |name: &str, line: &[u8]| {
+            let path = dir.mime_path().join(name);
+            let mut contents = fs::read(&path).unwrap();
+            contents.extend_from_slice(line);
+            fs::write(path, contents).unwrap();
+        };
+        append("globs2", b"heavy:text/x-foo:*.heavy\n");
+        append("aliases", b"text/x-baz\n");
+        append("subclasses", b"text/x-foo text/plain text/x-extra\n");
+        append("icons", b"text/x-foo\n");
+        append("magic", b"[50:text/x-broken\n");
+
+        let builder = |strict: bool| {
+            let mut builder = SharedMimeInfo::builder();
+            builder
+                .override_dirs(Vec::<PathBuf>::new())
+                .data_dirs([dir.path()])
+                .data_home("/does/not/exist")
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .strict(strict);
+            builder
+        };
 
-        assert_eq!(_db.reload(), false);
+        let mime_db = builder(false).build();
+        assert!(mime_db.diagnostics().is_empty());
+
+        let mime_db = builder(true).build();
+        let malformed: Vec<String> = mime_db
+            .diagnostics()
+            .iter()
+            .filter_map(|d| match d {
+                Diagnostic::MalformedEntry { path, line, entry } => Some(format!(
+                    "{}:{}: {}",
+                    path.file_name().unwrap().to_string_lossy(),
+                    line.unwrap_or(0),
+                    entry
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            malformed,
+            vec![
+                "aliases:2: text/x-baz",
+                "subclasses:1: text/x-foo text/plain text/x-extra",
+                "icons:1: text/x-foo",
+                "globs2:2: heavy:text/x-foo:*.heavy",
+                "magic:0: [50:text/x-broken",
+            ]
+        );
+
+        // The valid entries are still loaded
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![Mime::from_str("text/x-foo").unwrap()]
+        );
+        assert_eq!(
+            mime_db.get_mime_type_for_data(b"FOO").map(|(mime, _)| mime),
+            Some(Mime::from_str("text/x-foo").unwrap())
+        );
+        assert!(matches!(
+            builder(true).try_build(),
+            Err(Error::Parse(Diagnostic::MalformedEntry { .. }))
+        ));
     }
 
     #[test]
-    fn lookup_generic_icons() {
+    fn mime_type_for_file_data() {
         let mime_db = load_test_data();
 
+        let svg_data = include_bytes!("../test_files/files/rust-logo.svg");
         assert_eq!(
-            mime_db.lookup_generic_icon_name(&mime::APPLICATION_JSON),
-            Some("text-x-script".to_string())
+            mime_db.get_mime_type_for_data(svg_data),
+            Some((
+                Mime::from_str("image/svg+xml").unwrap(),
+                MagicPriority::new(80).unwrap()
+            ))
         );
+
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
         assert_eq!(
-            mime_db.lookup_generic_icon_name(&mime::TEXT_PLAIN),
-            Some("text-x-generic".to_string())
+            mime_db.get_mime_type_for_data(png_data),
+            Some((Mime::from_str("image/png").unwrap(), MagicPriority::DEFAULT))
         );
     }
 
     #[test]
-    fn unalias() {
-        let mime_db = load_test_data();
+    fn generic_chain() {
+        let mime_db = testing::minimal_db();
+        let chain = |mime_type: &str| {
+            let chain = mime_db.generic_chain(&Mime::from_str(mime_type).unwrap());
+            chain.iter().map(|m| m.to_string()).collect::<Vec<_>>()
+        };
 
         assert_eq!(
-            mime_db.unalias_mime_type(&Mime::from_str("application/ics").unwrap()),
-            Some(Mime::from_str("text/calendar").unwrap())
+            chain("text/x-c++src"),
+            vec![
+                "text/x-c++src",
+                "text/x-csrc",
+                "text/plain",
+                "application/octet-stream"
+            ]
         );
         assert_eq!(
-            mime_db.unalias_mime_type(&Mime::from_str("text/plain").unwrap()),
-            None
+            chain("text/xml"),
+            vec!["application/xml", "text/plain", "application/octet-stream"]
+        );
+        assert_eq!(
+            chain("text/x-foo"),
+            vec!["text/x-foo", "text/plain", "application/octet-stream"]
+        );
+        assert_eq!(
+            chain("image/png"),
+            vec!["image/png", "application/octet-stream"]
+        );
+        assert_eq!(
+            chain("application/octet-stream"),
+            vec!["application/octet-stream"]
+        );
+        assert_eq!(chain("inode/directory"), vec!["inode/directory"]);
+
+        let guess = mime_db.guess_mime_type().file_name("a.svg").guess();
+        assert_eq!(
+            guess.generic_chain(&mime_db),
+            mime_db.generic_chain(&Mime::from_str("image/svg+xml").unwrap())
         );
     }
 
     #[test]
-    fn mime_type_equal() {
+    fn mime_type_subclass() {
         let mime_db = load_test_data();
 
         assert_eq!(
-            mime_db.mime_type_equal(
-                &Mime::from_str("application/wordperfect").unwrap(),
-                &Mime::from_str("application/vnd.wordperfect").unwrap(),
+            mime_db.mime_type_subclass(
+                &Mime::from_str("application/rtf").unwrap(),
+                &Mime::from_str("text/plain").unwrap(),
             ),
             true
         );
         assert_eq!(
-            mime_db.mime_type_equal(
-                &Mime::from_str("application/x-gnome-app-info").unwrap(),
-                &Mime::from_str("application/x-desktop").unwrap(),
+            mime_db.mime_type_subclass(
+                &Mime::from_str("message/news").unwrap(),
+                &Mime::from_str("text/plain").unwrap(),
             ),
             true
         );
         assert_eq!(
-            mime_db.mime_type_equal(
-                &Mime::from_str("application/x-wordperfect").unwrap(),
-                &Mime::from_str("application/vnd.wordperfect").unwrap(),
+            mime_db.mime_type_subclass(
+                &Mime::from_str("message/news").unwrap(),
+                &Mime::from_str("message/*").unwrap(),
             ),
             true
         );
         assert_eq!(
-            mime_db.mime_type_equal(
-                &Mime::from_str("application/x-wordperfect").unwrap(),
-                &Mime::from_str("audio/x-midi").unwrap(),
+            mime_db.mime_type_subclass(
+                &Mime::from_str("message/news").unwrap(),
+                &Mime::from_str("text/*").unwrap(),
             ),
-            false
+            true
         );
         assert_eq!(
-            mime_db.mime_type_equal(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("message/news").unwrap(),
+                &Mime::from_str("application/octet-stream").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("application/rtf").unwrap(),
                 &Mime::from_str("application/octet-stream").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("application/x-gnome-app-info").unwrap(),
+                &Mime::from_str("text/plain").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("image/x-djvu").unwrap(),
+                &Mime::from_str("image/vnd.djvu").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("image/vnd.djvu").unwrap(),
+                &Mime::from_str("image/x-djvu").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("image/vnd.djvu").unwrap(),
                 &Mime::from_str("text/plain").unwrap(),
             ),
             false
         );
         assert_eq!(
-            mime_db.mime_type_equal(
-                &Mime::from_str("text/plain").unwrap(),
-                &Mime::from_str("text/*").unwrap(),
-            ),
-            false
+            mime_db.mime_type_subclass(
+                &Mime::from_str("image/vnd.djvu").unwrap(),
+                &Mime::from_str("text/*").unwrap(),
+            ),
+            false
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("text/*").unwrap(),
+                &Mime::from_str("text/plain").unwrap(),
+            ),
+            true
+        );
+        assert_eq!(
+            mime_db.mime_type_subclass(
+                &Mime::from_str("application/x-shellscript").unwrap(),
+                &mime::APPLICATION_OCTET_STREAM
+            ),
+            true
+        );
+    }
+
+    #[test]
+    fn subclass_depth_limit() {
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-a", "*.a")
+            .subclass("text/x-d", "text/x-c")
+            .subclass("text/x-c", "text/x-b")
+            .subclass("text/x-b", "text/x-a")
+            .subclass("application/x-ping", "application/x-pong")
+            .subclass("application/x-pong", "application/x-ping");
+        dir.write().unwrap();
+
+        let builder = |precompute: bool| {
+            let mut builder = SharedMimeInfo::builder();
+            builder
+                .data_home(dir.path())
+                .data_dirs(Vec::<PathBuf>::new())
+                .override_dirs(Vec::<PathBuf>::new())
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .precompute_ancestors(precompute);
+            builder
+        };
+
+        let a = Mime::from_str("text/x-a").unwrap();
+        let b = Mime::from_str("text/x-b").unwrap();
+        let d = Mime::from_str("text/x-d").unwrap();
+        let ping = Mime::from_str("application/x-ping").unwrap();
+        let pong = Mime::from_str("application/x-pong").unwrap();
+
+        for precompute in [false, true] {
+            // The cycle does not reach the default limit
+            let mime_db = builder(precompute).build();
+            assert_eq!(mime_db.checked_mime_type_subclass(&d, &a), Some(true));
+            assert_eq!(mime_db.checked_mime_type_subclass(&ping, &pong), Some(true));
+            assert_eq!(mime_db.checked_mime_type_subclass(&ping, &d), Some(false));
+            assert!(mime_db
+                .diagnostics()
+                .iter()
+                .all(|d| !matches!(d, Diagnostic::SubclassDepthExceeded { .. })));
+
+            let mime_db = builder(precompute).subclass_depth_limit(2).build();
+            assert_eq!(mime_db.checked_mime_type_subclass(&d, &b), Some(true));
+            assert_eq!(mime_db.checked_mime_type_subclass(&d, &a), None);
+            assert!(!mime_db.mime_type_subclass(&d, &a));
+            // The implicit parents are checked at every level
+            assert_eq!(
+                mime_db.checked_mime_type_subclass(&d, &mime::TEXT_PLAIN),
+                Some(true)
+            );
+            assert_eq!(
+                mime_db.diagnostics().to_vec(),
+                vec![Diagnostic::SubclassDepthExceeded {
+                    mime_type: d.clone(),
+                    limit: 2,
+                }]
+            );
+        }
+
+        // The precomputed ancestors give the same answers
+        let mime_db = load_test_data();
+        let mut precomputed = SharedMimeInfo::builder();
+        let precomputed = precomputed
+            .data_home("test_files")
+            .data_dirs(Vec::<PathBuf>::new())
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .precompute_ancestors(true)
+            .build();
+        let bases = [
+            mime::TEXT_PLAIN,
+            mime::APPLICATION_OCTET_STREAM,
+            Mime::from_str("application/xml").unwrap(),
+            Mime::from_str("image/*").unwrap(),
+            Mime::from_str("application/x-gnome-app-info").unwrap(),
+        ];
+        for mime_type in mime_db.list_mime_types() {
+            for base in &bases {
+                assert_eq!(
+                    precomputed.mime_type_subclass(mime_type, base),
+                    mime_db.mime_type_subclass(mime_type, base),
+                    "{} {}",
+                    mime_type,
+                    base
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn guess_none() {
+        let mime_db = load_test_data();
+
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.guess();
+        assert_eq!(guess.mime_type(), &mime::APPLICATION_OCTET_STREAM);
+        assert_eq!(guess.uncertain(), true);
+        assert!(guess.is_unknown());
+    }
+
+    #[test]
+    fn guess_custom_unknown_type() {
+        let unknown = Mime::from_str("application/x-unknown").unwrap();
+        let mime_db = SharedMimeInfo::builder()
+            .data_dirs(["test_files"])
+            .unknown_type(&unknown)
+            .build();
+
+        assert_eq!(mime_db.unknown_type(), &unknown);
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("foo.unknown-extension"),
+            vec![unknown.clone()]
+        );
+
+        let guess = mime_db.guess_mime_type().data(&[0, 1, 2, 3]).guess();
+        assert_eq!(guess.mime_type(), &unknown);
+        assert!(guess.is_unknown());
+
+        let guess = mime_db.guess_mime_type().file_name("foo.txt").guess();
+        assert!(!guess.is_unknown());
+    }
+
+    #[test]
+    fn guess_filename() {
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.file_name("foo.txt").guess();
+        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+        assert_eq!(guess.uncertain(), false);
+    }
+
+    #[test]
+    fn guess_backup_filename() {
+        let mime_db = load_test_data();
+        let trash = Mime::from_str("application/x-trash").unwrap();
+        let guess = |name: &str, handling| {
+            let guess = mime_db
+                .guess_mime_type()
+                .file_name(name)
+                .backup_handling(handling)
+                .guess();
+            (guess.mime_type().clone(), guess.is_backup_of().cloned())
+        };
+
+        assert_eq!(guess("foo.txt~", BackupHandling::AsIs), (trash, None));
+        assert_eq!(
+            guess("foo.txt~", BackupHandling::StripAndFlag),
+            (mime::TEXT_PLAIN, Some(mime::TEXT_PLAIN))
+        );
+        assert_eq!(
+            guess("foo.pdf.~3~", BackupHandling::StripAndFlag),
+            (mime::APPLICATION_PDF, Some(mime::APPLICATION_PDF))
+        );
+        assert_eq!(
+            guess("foo.txt~", BackupHandling::Ignore),
+            (mime::TEXT_PLAIN, None)
+        );
+        assert_eq!(
+            guess("foo.txt", BackupHandling::StripAndFlag),
+            (mime::TEXT_PLAIN, None)
+        );
+
+        // The contents are still sniffed like the ones of the original file
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let guess = mime_db
+            .guess_mime_type()
+            .file_name("logo~")
+            .data(png_data)
+            .backup_handling(BackupHandling::StripAndFlag)
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
+        assert_eq!(guess.is_backup_of(), Some(&mime::IMAGE_PNG));
+    }
+
+    #[test]
+    fn guess_data() {
+        let svg_data = include_bytes!("../test_files/files/rust-logo.svg");
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.data(svg_data).guess();
+        assert_eq!(guess.mime_type(), &Mime::from_str("image/svg+xml").unwrap());
+        assert_eq!(guess.uncertain(), false);
+    }
+
+    #[test]
+    fn guess_replaced_data() {
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let mime_db = load_test_data();
+        let max_data_size = magic::max_extents(mime_db.magic.entries());
+
+        let mut gb = mime_db.guess_mime_type();
+        gb.data(b"GIF89a");
+        assert_eq!(gb.data(png_data).guess().mime_type(), &mime::IMAGE_PNG);
+        assert_eq!(gb.data.len(), max_data_size.min(png_data.len()));
+
+        // Appended chunks are capped as a whole
+        let mut gb = mime_db.guess_mime_type();
+        for chunk in png_data.chunks(3) {
+            gb.extend_data(chunk);
+        }
+        assert_eq!(gb.data.len(), max_data_size.min(png_data.len()));
+        assert_eq!(gb.guess().mime_type(), &mime::IMAGE_PNG);
+
+        gb.extend_data(&vec![0; max_data_size + 1]);
+        assert_eq!(gb.data.len(), max_data_size);
+    }
+
+    #[test]
+    fn guess_bytes_examined() {
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let mime_db = load_test_data();
+        let max_data_size = magic::max_extents(mime_db.magic.entries());
+
+        // A single glob match does not need the contents
+        let mut gb = mime_db.guess_mime_type();
+        gb.file_name("rust-logo.png").data(png_data);
+        assert_eq!(gb.guess().bytes_examined(), 0);
+
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.data(png_data).guess();
+        assert_eq!(guess.bytes_examined(), max_data_size.min(png_data.len()));
+
+        // Data read from a path is counted, even if it was not needed
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files/files/rust-logo.png")
+            .guess();
+        assert!(guess.bytes_examined() > 0);
+        assert!(guess.bytes_examined() <= max_data_size.min(png_data.len()));
+
+        let guess = mime_db.guess_mime_type().path("test_files/files").guess();
+        assert_eq!(guess.bytes_examined(), 0);
+    }
+
+    #[test]
+    fn guess_both() {
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.file_name("rust-logo.png").data(png_data).guess();
+        assert_eq!(guess.mime_type(), &Mime::from_str("image/png").unwrap());
+        assert_eq!(guess.uncertain(), false);
+    }
+
+    #[test]
+    fn guess_all() {
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let candidates = gb.file_name("rust-logo.png").data(png_data).guess_all();
+        assert_eq!(
+            candidates[0].mime_type(),
+            &Mime::from_str("image/png").unwrap()
+        );
+        assert_eq!(candidates[0].glob_weight(), Some(GlobWeight::DEFAULT));
+        assert_eq!(candidates[0].magic_priority(), Some(MagicPriority::DEFAULT));
+        assert_eq!(candidates[0].score(), 100);
+        assert!(candidates
+            .windows(2)
+            .all(|pair| pair[0].score() >= pair[1].score()));
+
+        assert!(candidates.iter().all(|c| c.subclass_of().is_empty()));
+
+        // The candidates are related to their parents in the list
+        let python = Mime::from_str("text/x-python").unwrap();
+        let python3 = Mime::from_str("text/x-python3").unwrap();
+        let mut gb = mime_db.guess_mime_type();
+        let candidates = gb.file_name("foo.py").guess_all();
+        let mime_types: Vec<&Mime> = candidates.iter().map(|c| c.mime_type()).collect();
+        assert_eq!(mime_types, vec![&python, &python3]);
+        assert!(candidates[0].subclass_of().is_empty());
+        assert_eq!(candidates[1].subclass_of(), &[python]);
+
+        let mut gb = mime_db.guess_mime_type();
+        let candidates = gb.guess_all();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].mime_type(), &mime::APPLICATION_OCTET_STREAM);
+        assert_eq!(candidates[0].score(), 0);
+    }
+
+    #[test]
+    fn guess_script() {
+        let sh_data = include_bytes!("../test_files/files/script");
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.data(sh_data).guess();
+        assert_eq!(
+            guess.mime_type(),
+            &Mime::from_str("application/x-shellscript").unwrap()
+        );
+    }
+
+    #[test]
+    fn guess_script_with_name() {
+        let sh_data = include_bytes!("../test_files/files/gp");
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb.file_name("gp").data(sh_data).guess();
+        assert_eq!(
+            guess.mime_type(),
+            &Mime::from_str("application/x-shellscript").unwrap()
         );
     }
 
     #[test]
-    fn mime_type_for_file_name() {
+    fn guess_metadata_only() {
         let mime_db = load_test_data();
+        let guess_metadata = |metadata: fs::Metadata| {
+            let guess = mime_db.guess_mime_type().metadata(metadata).guess();
+            assert_eq!(guess.uncertain(), guess.mime_type().type_() != "inode");
+            guess.mime_type().clone()
+        };
 
-        assert_eq!(
-            mime_db.get_mime_types_from_file_name("foo.txt"),
-            vec![Mime::from_str("text/plain").unwrap()]
-        );
+        // The file system objects are certain
+        let metadata = fs::metadata("test_files").unwrap();
+        assert_eq!(guess_metadata(metadata), *INODE_DIRECTORY);
+
+        let metadata = fs::metadata("test_files/files/empty").unwrap();
+        assert_eq!(guess_metadata(metadata), *APPLICATION_X_ZEROSIZE);
+
+        let metadata = fs::metadata("test_files/files/text").unwrap();
+        assert_eq!(guess_metadata(metadata), mime::APPLICATION_OCTET_STREAM);
 
+        #[cfg(unix)]
+        {
+            let metadata = fs::metadata("test_files/files/script").unwrap();
+            assert_eq!(guess_metadata(metadata), *APPLICATION_X_EXECUTABLE);
+
+            let metadata = fs::metadata("/dev/null").unwrap();
+            assert_eq!(guess_metadata(metadata), *INODE_CHARDEVICE);
+
+            let dir = testing::TempMimeDir::new().unwrap();
+            let link = dir.path().join("link");
+            std::os::unix::fs::symlink("text", &link).unwrap();
+            let metadata = fs::symlink_metadata(&link).unwrap();
+            assert_eq!(guess_metadata(metadata), *INODE_SYMLINK);
+        }
+
+        // The executable type is not used if the file name is available
+        let metadata = fs::metadata("test_files/files/script").unwrap();
+        let guess = mime_db
+            .guess_mime_type()
+            .file_name("script.txt")
+            .metadata(metadata)
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+    }
+
+    #[test]
+    fn matching_globs() {
+        let mime_db = load_test_data();
+
+        let matches: Vec<(String, String, u32, bool)> = mime_db
+            .matching_globs("archive.tar.gz")
+            .map(|m| {
+                let mime_type = m.mime_type().to_string();
+                (m.pattern(), mime_type, m.weight().get(), m.case_sensitive())
+            })
+            .collect();
         assert_eq!(
-            mime_db.get_mime_types_from_file_name("bar.gif"),
-            vec![Mime::from_str("image/gif").unwrap()]
+            matches,
+            vec![
+                (
+                    "*.tar.gz".to_string(),
+                    "application/x-compressed-tar".to_string(),
+                    50,
+                    false
+                ),
+                (
+                    "*.gz".to_string(),
+                    "application/gzip".to_string(),
+                    50,
+                    false
+                ),
+            ]
         );
 
+        // The weight comes before the length of the pattern
+        let matches: Vec<String> = mime_db
+            .matching_globs("foo.py")
+            .map(|m| m.mime_type().to_string())
+            .collect();
+        assert_eq!(matches, vec!["text/x-python", "text/x-python3"]);
+
+        // Case sensitive patterns only match their own case
+        let matches: Vec<_> = mime_db.matching_globs("foo.C").collect();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].mime_type().essence_str(), "text/x-c++src");
+        assert_eq!(matches[0].pattern(), "*.C");
+        assert!(matches[0].case_sensitive());
+
+        assert_eq!(mime_db.matching_globs("foo").count(), 0);
+    }
+
+    #[test]
+    fn classify_names() {
+        let mime_db = load_test_data();
+        let names = [
+            "foo.txt",
+            "FOO.TXT",
+            "a.C",
+            "a.c",
+            "Makefile",
+            "bar.tar.gz",
+            "x",
+            "",
+            ".txt",
+            "a.svgz",
+            "a.txt~",
+        ];
+
+        let expected: Vec<Option<Mime>> = names
+            .iter()
+            .map(|name| mime_db.first_mime_type_from_file_name(name))
+            .collect();
+        assert_eq!(mime_db.classify_names(names), expected);
+        assert_eq!(expected[0], Some(mime::TEXT_PLAIN));
+        assert_eq!(expected[6], None);
+    }
+
+    #[test]
+    fn max_magic_priority() {
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.alias("application/x-old-doc", "application/x-doc")
+            .magic(40, "application/x-doc", 0, b"DOC1")
+            .magic(90, "application/x-old-doc", 0, b"DOC2")
+            .magic(60, "image/png", 0, b"\x89PNG");
+        let mime_db = dir.load().unwrap();
+
+        let doc = Mime::from_str("application/x-doc").unwrap();
+        let old_doc = Mime::from_str("application/x-old-doc").unwrap();
+        assert_eq!(mime_db.max_magic_priority(&doc), MagicPriority::new(90));
+        assert_eq!(mime_db.max_magic_priority(&old_doc), MagicPriority::new(90));
         assert_eq!(
-            mime_db.get_mime_types_from_file_name("baz.mod"),
-            vec![Mime::from_str("audio/x-mod").unwrap()]
+            mime_db.max_magic_priority(&mime::IMAGE_PNG),
+            MagicPriority::new(60)
         );
+        assert_eq!(mime_db.max_magic_priority(&mime::TEXT_PLAIN), None);
     }
 
     #[test]
-    fn mime_type_for_file_data() {
+    fn guess_agreement() {
         let mime_db = load_test_data();
 
-        let svg_data = include_bytes!("../test_files/files/rust-logo.svg");
+        let guess = mime_db.guess_mime_type().file_name("a.png").guess();
+        assert!(guess.agreement().is_none());
+
+        let agreement = |name: Option<&str>, data: &[u8]| {
+            let mut builder = mime_db.guess_mime_type();
+            if let Some(name) = name {
+                builder.file_name(name);
+            }
+            *builder
+                .data(data)
+                .check_agreement(true)
+                .guess()
+                .agreement()
+                .unwrap()
+        };
+
+        let png = b"\x89PNG\r\n\x1a\n";
+        let a = agreement(Some("a.png"), png);
         assert_eq!(
-            mime_db.get_mime_type_for_data(svg_data),
-            Some((Mime::from_str("image/svg+xml").unwrap(), 80))
+            (a.file_name(), a.data(), a.metadata()),
+            (Some(true), Some(true), None)
         );
+        assert_eq!((a.agreeing(), a.sources()), (2, 2));
 
-        let png_data = include_bytes!("../test_files/files/rust-logo.png");
-        assert_eq!(
-            mime_db.get_mime_type_for_data(png_data),
-            Some((Mime::from_str("image/png").unwrap(), 50))
+        // The name wins, but the contents disagree
+        let a = agreement(Some("a.txt"), png);
+        assert_eq!((a.file_name(), a.data()), (Some(true), Some(false)));
+        assert_eq!((a.agreeing(), a.sources()), (1, 2));
+
+        // The sniffed type is more specific than the one of the name
+        let a = agreement(
+            Some("a.xml"),
+            b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
         );
+        assert_eq!((a.agreeing(), a.sources()), (2, 2));
+
+        let a = agreement(Some("a.unknown"), b"\x00\x01");
+        assert_eq!((a.agreeing(), a.sources()), (0, 0));
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files")
+            .check_agreement(true)
+            .guess();
+        let a = guess.agreement().unwrap();
+        assert_eq!(a.metadata(), Some(true));
+        assert_eq!(a.file_name(), None);
     }
 
     #[test]
-    fn mime_type_subclass() {
+    fn classify_data_uri() {
         let mime_db = load_test_data();
+        let classify = |uri: &str| {
+            let guess = mime_db.classify_data_uri(uri).unwrap();
+            (
+                guess.mime_type().clone(),
+                guess.conflict().cloned(),
+                guess.uncertain(),
+            )
+        };
 
+        let png = "iVBORw0KGgo=";
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("application/rtf").unwrap(),
-                &Mime::from_str("text/plain").unwrap(),
-            ),
-            true
+            classify(&format!("data:image/png;base64,{}", png)),
+            (mime::IMAGE_PNG, None, false)
         );
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("message/news").unwrap(),
-                &Mime::from_str("text/plain").unwrap(),
-            ),
-            true
+            classify(&format!("data:;base64,{}", png)),
+            (mime::IMAGE_PNG, None, false)
         );
+
+        // The declared type wins over a weak magic match
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("message/news").unwrap(),
-                &Mime::from_str("message/*").unwrap(),
-            ),
-            true
+            classify(&format!("data:image/gif;base64,{}", png)),
+            (mime::IMAGE_GIF, Some(mime::IMAGE_PNG), true)
         );
+
+        // The contents are more specific than the declared type
+        let svg = "%3Csvg%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%2F%3E";
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("message/news").unwrap(),
-                &Mime::from_str("text/*").unwrap(),
-            ),
-            true
+            classify(&format!("data:application/xml,{}", svg)),
+            (mime::IMAGE_SVG, None, false)
         );
+
+        // Aliases are resolved
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("message/news").unwrap(),
-                &Mime::from_str("application/octet-stream").unwrap(),
-            ),
-            true
+            classify("data:text/xml,hello").0,
+            Mime::from_str("application/xml").unwrap()
         );
+
+        assert_eq!(classify("data:,hello"), (mime::TEXT_PLAIN, None, true));
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("application/rtf").unwrap(),
-                &Mime::from_str("application/octet-stream").unwrap(),
-            ),
-            true
+            classify("data:,%00%01"),
+            (mime::APPLICATION_OCTET_STREAM, None, true)
         );
+        assert!(mime_db
+            .classify_data_uri("data:,%00%01")
+            .unwrap()
+            .is_unknown());
+        assert!(mime_db.classify_data_uri("image/png,abc").is_none());
+
+        // Desktop files must be declared as such
+        let desktop = "data:,%5BDesktop%20Entry%5D%0AExec%3Dfoo%0A";
+        assert_eq!(classify(desktop).0, mime::TEXT_PLAIN);
+    }
+
+    #[test]
+    fn classify_attachment() {
+        let mime_db = load_test_data();
+        let classify = |declared: Option<&str>, name: Option<&str>, data: &[u8]| {
+            let declared = declared.map(|m| Mime::from_str(m).unwrap());
+            let guess = mime_db.classify_attachment(declared.as_ref(), name, data);
+            (
+                guess.mime_type().to_string(),
+                guess.conflict().map(|m| m.to_string()),
+                guess.uncertain(),
+            )
+        };
+        let png = b"\x89PNG\r\n\x1a\n";
+        let owned = |m: &str| m.to_string();
+
+        // The contents come first
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("application/x-gnome-app-info").unwrap(),
-                &Mime::from_str("text/plain").unwrap(),
-            ),
-            true
+            classify(Some("image/gif"), Some("a.txt"), png),
+            (owned("image/png"), Some(owned("image/gif")), false)
         );
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("image/x-djvu").unwrap(),
-                &Mime::from_str("image/vnd.djvu").unwrap(),
-            ),
-            true
+            classify(Some("application/octet-stream"), None, png),
+            (owned("image/png"), None, false)
         );
+
+        // The name is more specific than the contents
+        let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(classify(None, Some("a.svg"), svg).0, "image/svg+xml");
+
+        // Then the name, with the declared type breaking ties
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("image/vnd.djvu").unwrap(),
-                &Mime::from_str("image/x-djvu").unwrap(),
-            ),
-            true
+            classify(None, Some("a.txt"), b""),
+            (owned("text/plain"), None, false)
         );
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("image/vnd.djvu").unwrap(),
-                &Mime::from_str("text/plain").unwrap(),
-            ),
-            false
+            classify(Some("application/x-font-pcf"), Some("a.pcf"), b"\x00\x01"),
+            (owned("application/x-font-pcf"), None, false)
         );
+        assert!(classify(None, Some("a.pcf"), b"\x00\x01").2);
+
+        // And finally the declared type
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("image/vnd.djvu").unwrap(),
-                &Mime::from_str("text/*").unwrap(),
-            ),
-            false
+            classify(Some("application/x-custom"), Some("a.nothing"), b"\x00\x01"),
+            (owned("application/x-custom"), None, false)
         );
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("text/*").unwrap(),
-                &Mime::from_str("text/plain").unwrap(),
-            ),
-            true
+            classify(None, None, b"hello"),
+            (owned("text/plain"), None, true)
         );
         assert_eq!(
-            mime_db.mime_type_subclass(
-                &Mime::from_str("application/x-shellscript").unwrap(),
-                &mime::APPLICATION_OCTET_STREAM
-            ),
-            true
+            classify(Some("application/octet-stream"), None, b"\x00\x01"),
+            (owned("application/octet-stream"), None, true)
+        );
+
+        // Desktop files must be named or declared as such
+        let desktop = b"[Desktop Entry]\nExec=foo\n";
+        assert_eq!(classify(None, Some("a.txt"), desktop).0, "text/plain");
+        assert_eq!(
+            classify(Some("application/x-desktop"), None, desktop).0,
+            "application/x-desktop"
         );
     }
 
     #[test]
-    fn guess_none() {
+    fn is_safe_to_preview() {
         let mime_db = load_test_data();
+        let policy = PreviewPolicy::default();
+        let preview = |name: Option<&str>, data: &[u8]| {
+            let decision = mime_db.is_safe_to_preview(name, data, &policy);
+            (decision.mime_type().to_string(), decision.is_safe())
+        };
 
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.guess();
-        assert_eq!(guess.mime_type(), &mime::APPLICATION_OCTET_STREAM);
-        assert_eq!(guess.uncertain(), true);
-    }
+        let png = b"\x89PNG\r\n\x1a\n";
+        assert_eq!(preview(Some("a.png"), png), ("image/png".into(), true));
+        assert_eq!(
+            preview(Some("a.txt"), b"hello"),
+            ("text/plain".into(), true)
+        );
+        assert_eq!(
+            preview(Some("a.rs"), b"fn main"),
+            ("text/rust".into(), true)
+        );
 
-    #[test]
-    fn guess_filename() {
-        let mime_db = load_test_data();
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.file_name("foo.txt").guess();
-        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
-        assert_eq!(guess.uncertain(), false);
+        // Denied subclasses of the allowed types
+        assert!(!preview(Some("a.sh"), b"#!/bin/sh\n").1);
+        assert!(!preview(Some("a.html"), b"<html>").1);
+        assert!(!preview(Some("a.svg"), b"<svg/>").1);
+        assert!(!preview(Some("a.desktop"), b"[Desktop Entry]\n").1);
+
+        // Types that are not allowed
+        assert!(!preview(Some("a.pdf"), b"%PDF-").1);
+        assert!(!preview(None, b"\x00\x01\x02").1);
+
+        // The data matches a denied type
+        assert!(!preview(Some("a.txt"), b"<html>").1);
+        assert!(!preview(Some("a.png"), b"#!/bin/sh\n").1);
+
+        let mut policy = PreviewPolicy::new();
+        policy.allow(&mime::APPLICATION_PDF);
+        let decision = mime_db.is_safe_to_preview(Some("a.pdf"), b"%PDF-", &policy);
+        assert!(decision.is_safe());
+        assert!(!mime_db
+            .is_safe_to_preview(None, b"hello", &policy)
+            .is_safe());
     }
 
     #[test]
-    fn guess_data() {
-        let svg_data = include_bytes!("../test_files/files/rust-logo.svg");
+    fn guess_timings() {
         let mime_db = load_test_data();
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.data(svg_data).guess();
-        assert_eq!(guess.mime_type(), &Mime::from_str("image/svg+xml").unwrap());
-        assert_eq!(guess.uncertain(), false);
-    }
 
-    #[test]
-    fn guess_both() {
-        let png_data = include_bytes!("../test_files/files/rust-logo.png");
-        let mime_db = load_test_data();
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.file_name("rust-logo.png").data(png_data).guess();
-        assert_eq!(guess.mime_type(), &Mime::from_str("image/png").unwrap());
-        assert_eq!(guess.uncertain(), false);
+        let guess = mime_db.guess_mime_type().data(b"GIF89a").guess();
+        assert!(guess.timings().is_none());
+
+        let mut builder = mime_db.guess_mime_type();
+        builder.data(b"GIF89a").timings(true);
+        let timings = *builder.guess().timings().unwrap();
+        assert_eq!(timings.io(), Duration::ZERO);
+        assert_eq!(timings.glob(), Duration::ZERO);
+        assert_eq!(timings.total(), timings.magic());
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files/files/text")
+            .timings(true)
+            .guess();
+        let timings = guess.timings().unwrap();
+        assert!(timings.metadata() > Duration::ZERO);
+        assert!(timings.read() > Duration::ZERO);
+        assert!(timings.glob() > Duration::ZERO);
+        assert_eq!(timings.total(), timings.io() + timings.matching());
     }
 
     #[test]
-    fn guess_script() {
-        let sh_data = include_bytes!("../test_files/files/script");
+    fn guess_unreadable_file() {
         let mime_db = load_test_data();
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.data(sh_data).guess();
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files/files/missing.txt")
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
         assert_eq!(
-            guess.mime_type(),
-            &Mime::from_str("application/x-shellscript").unwrap()
+            guess.read_error().map(io::Error::kind),
+            Some(io::ErrorKind::NotFound)
         );
+
+        let res = mime_db
+            .guess_mime_type()
+            .path("test_files/files/missing.txt")
+            .try_guess();
+        assert_eq!(res.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+
+        // Directories are not read
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files")
+            .try_guess()
+            .unwrap();
+        assert_eq!(guess.mime_type(), &*INODE_DIRECTORY);
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files/files/text")
+            .try_guess()
+            .unwrap();
+        assert!(guess.read_error().is_none());
+        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
     }
 
     #[test]
-    fn guess_script_with_name() {
-        let sh_data = include_bytes!("../test_files/files/gp");
+    fn guess_truncated_file() {
         let mime_db = load_test_data();
-        let mut gb = mime_db.guess_mime_type();
-        let guess = gb.file_name("gp").data(sh_data).guess();
-        assert_eq!(
-            guess.mime_type(),
-            &Mime::from_str("application/x-shellscript").unwrap()
-        );
+        let dir = testing::TempMimeDir::new().unwrap();
+        let file = dir.path().join("logo");
+
+        // The metadata is retrieved before the file shrinks
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        fs::write(&file, png_data).unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        fs::write(&file, &png_data[..64]).unwrap();
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path(&file)
+            .metadata(metadata)
+            .try_guess()
+            .unwrap();
+        assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
+
+        let (_, data, error) = read_path(&file, false, Some(4096), true, 4096, None);
+        assert_eq!(data.as_deref(), Some(&png_data[..64]));
+        assert!(error.is_none());
+
+        // The file has been emptied
+        fs::write(&file, b"").unwrap();
+        let (_, data, error) = read_path(&file, false, Some(4096), true, 4096, None);
+        assert!(data.is_none());
+        assert!(error.is_none());
+    }
+
+    #[test]
+    fn guess_conformance() {
+        let mut dir = testing::TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo")
+            .glob(50, "application/x-foo", "*.foo")
+            .glob(50, "text/x-single", "*.single")
+            .magic(50, "application/x-bar", 0, b"BAR")
+            .magic(50, "text/x-baz", 0, b"BAZ")
+            .magic(90, "image/x-strong", 0, b"STRONG")
+            .subclass("application/x-foo", "application/x-bar")
+            .subclass("text/x-baz", "text/x-foo");
+        dir.write().unwrap();
+        let empty = dir.path().join("empty.single");
+        fs::write(&empty, b"").unwrap();
+
+        let guess = |conformance: Conformance, name: Option<&str>, data: &[u8]| {
+            let mime_db = SharedMimeInfo::builder()
+                .data_home(dir.path())
+                .data_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .conformance(conformance)
+                .build();
+            assert_eq!(mime_db.conformance(), conformance);
+
+            let mut gb = mime_db.guess_mime_type();
+            if let Some(name) = name {
+                gb.file_name(name);
+            }
+            let guess = gb.data(data).guess();
+            (guess.mime_type().to_string(), guess.uncertain())
+        };
+        let res = |mime_type: &str, uncertain: bool| (mime_type.to_string(), uncertain);
+
+        use Conformance::*;
+        for (name, data, expected) in [
+            // Text fallback without a file name
+            (
+                None,
+                &b"plain text"[..],
+                [
+                    res("text/plain", false),
+                    res("text/plain", false),
+                    res("application/octet-stream", true),
+                ],
+            ),
+            // A file name match is a subclass of the sniffed type
+            (
+                Some("a.foo"),
+                b"BAR",
+                [
+                    res("application/x-foo", false),
+                    res("application/x-foo", false),
+                    res("application/x-foo", true),
+                ],
+            ),
+            // The sniffed type is a subclass of a file name match
+            (
+                Some("a.foo"),
+                b"BAZ",
+                [
+                    res("text/x-baz", false),
+                    res("application/x-foo", true),
+                    res("text/x-baz", false),
+                ],
+            ),
+            // A certain magic match
+            (
+                Some("a.foo"),
+                b"STRONG",
+                [
+                    res("image/x-strong", false),
+                    res("image/x-strong", false),
+                    res("image/x-strong", false),
+                ],
+            ),
+        ] {
+            for (conformance, expected) in [Spec, GioCompatible, Lenient].into_iter().zip(expected)
+            {
+                assert_eq!(
+                    guess(conformance, name, data),
+                    expected,
+                    "{:?} {:?}",
+                    conformance,
+                    name
+                );
+            }
+        }
+
+        // Empty files, and executables
+        let guess_path = |conformance: Conformance, path: &Path, metadata_only: bool| {
+            let mime_db = SharedMimeInfo::builder()
+                .data_home(dir.path())
+                .data_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .conformance(conformance)
+                .build();
+
+            let mut gb = mime_db.guess_mime_type();
+            if metadata_only {
+                gb.metadata(fs::metadata(path).unwrap());
+            } else {
+                gb.path(path);
+            }
+            gb.guess().mime_type().to_string()
+        };
+
+        assert_eq!(guess_path(Spec, &empty, false), "text/x-single");
+        assert_eq!(guess_path(GioCompatible, &empty, false), "text/plain");
+        assert_eq!(guess_path(Lenient, &empty, false), "application/x-zerosize");
+
+        #[cfg(unix)]
+        {
+            let script = Path::new("test_files/files/script");
+            assert_eq!(guess_path(Spec, script, true), "application/octet-stream");
+            assert_eq!(
+                guess_path(GioCompatible, script, true),
+                "application/octet-stream"
+            );
+            assert_eq!(
+                guess_path(Lenient, script, true),
+                "application/x-executable"
+            );
+        }
     }
 
     #[test]
@@ -1204,6 +7426,7 @@ mod tests {
             guess.mime_type(),
             &Mime::from_str("application/x-zerosize").unwrap()
         );
+        assert!(guess.is_special());
     }
 
     #[test]
@@ -1226,6 +7449,81 @@ mod tests {
         assert_eq!(guess.mime_type(), &mime::APPLICATION_JSON);
     }
 
+    #[test]
+    fn cheap_clones() {
+        let mime_db = load_test_data();
+
+        // Guesses for special types borrow the statics
+        let guess = mime_db.guess_mime_type().path("test_files").guess();
+        assert_eq!(guess.mime_type(), &*INODE_DIRECTORY);
+        assert!(matches!(guess.mime, Cow::Borrowed(_)));
+
+        // Common types loaded from the database use the constants
+        let mime_types = mime_db.get_mime_types_from_file_name("rust-logo.png");
+        assert_eq!(mime_types, vec![mime::IMAGE_PNG]);
+        let png = mime_types[0].clone();
+        assert_eq!(png.as_ref().as_ptr(), mime_types[0].as_ref().as_ptr());
+    }
+
+    #[test]
+    fn guess_empty_prefer_file_name() {
+        let mime_db = load_test_data();
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb
+            .path("test_files/files/empty.json")
+            .prefer_file_name(true)
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::APPLICATION_JSON);
+        assert!(!guess.uncertain());
+        assert_eq!(guess.conflict(), Some(&*APPLICATION_X_ZEROSIZE));
+
+        // No file name match, so the file is still empty
+        let mut gb = mime_db.guess_mime_type();
+        let guess = gb
+            .path("test_files/files/empty")
+            .prefer_file_name(true)
+            .guess();
+        assert_eq!(guess.mime_type(), &*APPLICATION_X_ZEROSIZE);
+        assert_eq!(guess.conflict(), None);
+    }
+
+    #[test]
+    fn guess_deadline() {
+        let mime_db = load_test_data();
+
+        let guess = mime_db
+            .guess_mime_type()
+            .path("test_files/files/rust-logo.png")
+            .deadline(Duration::from_secs(60))
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::IMAGE_PNG);
+        assert!(!guess.deadline_exceeded());
+
+        // Opening a FIFO blocks until somebody opens it for writing, just
+        // like reading a file from an unresponsive network mount
+        let dir = testing::TempMimeDir::new().unwrap();
+        let fifo = dir.path().join("fifo.txt");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let metadata = fs::metadata("test_files/files/text").unwrap();
+        let guess = mime_db
+            .guess_mime_type()
+            .path(&fifo)
+            .metadata(metadata)
+            .deadline(Duration::from_millis(50))
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+        assert!(guess.uncertain());
+        assert!(guess.deadline_exceeded());
+
+        // Unblock the reading thread
+        drop(File::create(&fifo).unwrap());
+    }
+
     #[test]
     fn guess_empty_no_zero_size_no_extension() {
         let mime_db = load_test_data();
@@ -1263,6 +7561,61 @@ mod tests {
         assert!(!looks_like_text(&[0, 1, 2]));
     }
 
+    #[test]
+    fn possible_types_for_prefix() {
+        let mime_db = load_test_data();
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+
+        let all = mime_db.possible_types_for_prefix(b"");
+        assert!(all.contains(&mime::IMAGE_PNG));
+
+        let possible = mime_db.possible_types_for_prefix(&png_data[..3]);
+        assert!(possible.contains(&mime::IMAGE_PNG));
+        assert!(possible.len() < all.len());
+
+        // Every type matching the whole data is still possible with any
+        // of its prefixes
+        let (png, _) = mime_db.get_mime_type_for_data(png_data).unwrap();
+        for len in [1, 4, 16, 64] {
+            let possible = mime_db.possible_types_for_prefix(&png_data[..len]);
+            assert!(possible.contains(&png));
+        }
+
+        let possible = mime_db.possible_types_for_prefix(b"\x00\x00\x00\x00plainly not");
+        assert!(!possible.contains(&mime::IMAGE_PNG));
+    }
+
+    #[test]
+    fn classify_stream() {
+        let mime_db = load_test_data();
+
+        let mut png = File::open("test_files/files/rust-logo.png").unwrap();
+        let kind = mime_db.classify_stream(&mut png, 4096).unwrap();
+        assert_eq!(kind, StreamKind::Binary);
+
+        let mut svg = File::open("test_files/files/rust-logo.svg").unwrap();
+        let kind = mime_db.classify_stream(&mut svg, 4096).unwrap();
+        assert!(kind.is_text());
+
+        let mut text = File::open("test_files/files/text").unwrap();
+        let kind = mime_db.classify_stream(&mut text, 4096).unwrap();
+        assert!(kind.is_text());
+
+        let mut empty = File::open("test_files/files/empty").unwrap();
+        let kind = mime_db.classify_stream(&mut empty, 4096).unwrap();
+        assert_eq!(kind, StreamKind::Empty);
+
+        // A PDF starts with plain ASCII text
+        let mut reader: &[u8] = b"%PDF-1.7\n";
+        let kind = mime_db.classify_stream(&mut reader, 4096).unwrap();
+        assert_eq!(kind, StreamKind::Binary);
+
+        // Only the first bytes are considered
+        let mut reader: &[u8] = b"hello\x00";
+        let kind = mime_db.classify_stream(&mut reader, 5).unwrap();
+        assert_eq!(kind, StreamKind::Text("us-ascii"));
+    }
+
     #[test]
     fn guess_turtle() {
         let cwd = env::current_dir().unwrap().to_string_lossy().into_owned();
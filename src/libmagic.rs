@@ -0,0 +1,133 @@
+// Queries libmagic, the library used by file(1), through the magic crate,
+// to compare its results with the ones of the shared MIME database
+
+use std::io;
+use std::path::Path;
+
+use magic::cookie::{Cookie, DatabasePaths, Flags, Load};
+use mime::Mime;
+
+use crate::{Guess, SharedMimeInfo};
+
+/// A handle to libmagic, the library used by `file(1)`, loaded with its
+/// default database.
+///
+/// The handle is used by the [`cross_check`] method of [`GuessBuilder`]
+/// to compare the MIME type guessed using the shared MIME database with
+/// the one reported by libmagic. A handle can be moved to another thread,
+/// but cannot be shared between threads, since libmagic does not support
+/// concurrent queries.
+///
+/// [`cross_check`]: struct.GuessBuilder.html#method.cross_check
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+pub struct LibMagic {
+    cookie: Cookie<Load>,
+}
+
+// Each handle owns its cookie, whose state is only ever accessed through
+// a reference to the handle
+unsafe impl Send for LibMagic {}
+
+impl LibMagic {
+    /// Opens libmagic, and loads its default database.
+    pub fn open() -> io::Result<LibMagic> {
+        // Asks for the MIME type, without its parameters, and to follow
+        // the symbolic links, like the guesses do
+        let cookie = Cookie::open(Flags::MIME_TYPE | Flags::SYMLINK).map_err(io::Error::other)?;
+        // The error holds on to the cookie, so only its message is kept
+        let cookie = cookie
+            .load(&DatabasePaths::default())
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        Ok(LibMagic { cookie })
+    }
+
+    fn to_mime_type(res: Result<String, magic::cookie::Error>) -> io::Result<Mime> {
+        res.map_err(io::Error::other)?
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid MIME type"))
+    }
+
+    /// Retrieves the MIME type of `data`, according to libmagic.
+    pub fn mime_type_for_data(&self, data: &[u8]) -> io::Result<Mime> {
+        LibMagic::to_mime_type(self.cookie.buffer(data))
+    }
+
+    /// Retrieves the MIME type of the file at `path`, according to
+    /// libmagic.
+    pub fn mime_type_for_path<P: AsRef<Path>>(&self, path: P) -> io::Result<Mime> {
+        LibMagic::to_mime_type(self.cookie.file(path))
+    }
+}
+
+/// How the MIME type guessed using the shared MIME database relates to
+/// the one reported by libmagic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Parity {
+    /// The MIME types are the same, or aliases of each other.
+    Equal,
+    /// The guessed MIME type is a subclass of the one reported by
+    /// libmagic, like `image/svg+xml` and `text/xml`.
+    MoreSpecific,
+    /// The MIME type reported by libmagic is a subclass of the guessed
+    /// one.
+    LessSpecific,
+    /// The MIME types are unrelated.
+    Different,
+}
+
+impl Parity {
+    /// Whether the MIME types are compatible, that is, equal, or one a
+    /// subclass of the other.
+    pub fn is_compatible(self) -> bool {
+        self != Parity::Different
+    }
+}
+
+/// The result of the [`cross_check`] method of [`GuessBuilder`], with the
+/// guess of the shared MIME database, the answer of libmagic, and how
+/// they relate to each other.
+///
+/// libmagic uses its own names for some MIME types, like `text/x-c` for
+/// C sources, or `inode/x-empty` for empty files; unless the database
+/// knows them as aliases, they are reported as [different].
+///
+/// [`cross_check`]: struct.GuessBuilder.html#method.cross_check
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+/// [different]: enum.Parity.html#variant.Different
+pub struct CrossCheck {
+    pub(crate) guess: Guess,
+    pub(crate) libmagic: Option<io::Result<Mime>>,
+    pub(crate) parity: Option<Parity>,
+}
+
+impl CrossCheck {
+    /// The guess of the shared MIME database.
+    pub fn guess(&self) -> &Guess {
+        &self.guess
+    }
+
+    /// The MIME type reported by libmagic, or `None` if only a file name
+    /// was set on the builder, so libmagic had nothing to look at.
+    pub fn libmagic_type(&self) -> Option<&io::Result<Mime>> {
+        self.libmagic.as_ref()
+    }
+
+    /// How the guessed MIME type relates to the one reported by libmagic,
+    /// or `None` if libmagic did not report any.
+    pub fn parity(&self) -> Option<Parity> {
+        self.parity
+    }
+}
+
+pub fn parity(db: &SharedMimeInfo, guessed: &Mime, libmagic: &Mime) -> Parity {
+    if db.mime_type_equal(guessed, libmagic) {
+        Parity::Equal
+    } else if db.mime_type_subclass(guessed, libmagic) {
+        Parity::MoreSpecific
+    } else if db.mime_type_subclass(libmagic, guessed) {
+        Parity::LessSpecific
+    } else {
+        Parity::Different
+    }
+}
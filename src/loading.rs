@@ -0,0 +1,222 @@
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{SharedMimeInfo, SharedMimeInfoBuilder};
+
+/// A [`SharedMimeInfo`] database being loaded on a background thread,
+/// returned by [`SharedMimeInfo::new_async`] and
+/// [`SharedMimeInfoBuilder::spawn_build`].
+///
+/// Loading the database blocks for tens of milliseconds, and more when
+/// the files are not cached yet; an application can start while the
+/// database is loading, and check whether it is ready using
+/// [`poll_ready`], which returns `Poll::Pending` until then.
+///
+/// The handle is also a future resolving to the database, for the
+/// applications using an async runtime.
+///
+/// ```rust
+/// use std::task::Poll;
+///
+/// let loading = xdg_mime::SharedMimeInfo::new_async();
+/// match loading.poll_ready() {
+///     Poll::Ready(mime_db) => {
+///         let guess = mime_db.guess_mime_type().file_name("notes.txt").guess();
+///         assert_eq!(guess.mime_type(), &mime::TEXT_PLAIN);
+///     }
+///     Poll::Pending => println!("Still loading"),
+/// }
+///
+/// let mime_db = loading.wait();
+/// ```
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [`SharedMimeInfo::new_async`]: struct.SharedMimeInfo.html#method.new_async
+/// [`SharedMimeInfoBuilder::spawn_build`]: struct.SharedMimeInfoBuilder.html#method.spawn_build
+/// [`poll_ready`]: #method.poll_ready
+pub struct Loading {
+    shared: Arc<Shared>,
+    // The database, once it was moved out of the shared state, so that
+    // poll_ready() can borrow from it
+    db: OnceLock<SharedMimeInfo>,
+}
+
+// The state shared with the loading thread; a panic while loading is
+// kept, and resumed by the thread waiting for the database
+#[derive(Default)]
+struct Shared {
+    result: Mutex<Option<thread::Result<SharedMimeInfo>>>,
+    done: Condvar,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Loading {
+    pub(crate) fn spawn(builder: SharedMimeInfoBuilder) -> Loading {
+        let shared = Arc::new(Shared::default());
+
+        let state = Arc::clone(&shared);
+        thread::spawn(move || {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| builder.build()));
+            *lock(&state.result) = Some(res);
+            state.done.notify_all();
+
+            // The waker is stored before the result is checked, so a task
+            // polling concurrently either sees the result, or is woken
+            if let Some(waker) = lock(&state.waker).take() {
+                waker.wake();
+            }
+        });
+
+        Loading {
+            shared,
+            db: OnceLock::new(),
+        }
+    }
+
+    /// Checks whether the database finished loading, without blocking.
+    pub fn is_ready(&self) -> bool {
+        self.poll_ready().is_ready()
+    }
+
+    /// Returns the database if it finished loading, or `Poll::Pending`
+    /// while it is still loading, without blocking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if loading the database panicked.
+    pub fn poll_ready(&self) -> Poll<&SharedMimeInfo> {
+        if let Some(db) = self.db.get() {
+            return Poll::Ready(db);
+        }
+
+        match lock(&self.shared.result).take() {
+            Some(res) => Poll::Ready(self.db.get_or_init(|| unwrap(res))),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Blocks until the database finished loading, and returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if loading the database panicked.
+    pub fn wait(mut self) -> SharedMimeInfo {
+        if let Some(db) = self.db.take() {
+            return db;
+        }
+
+        let mut result = lock(&self.shared.result);
+        loop {
+            match result.take() {
+                Some(res) => return unwrap(res),
+                None => {
+                    result = self
+                        .shared
+                        .done
+                        .wait(result)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+    }
+}
+
+impl Future for Loading {
+    type Output = SharedMimeInfo;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<SharedMimeInfo> {
+        *lock(&self.shared.waker) = Some(cx.waker().clone());
+
+        match self.poll_ready() {
+            Poll::Ready(_) => Poll::Ready(self.db.take().expect("the database was already taken")),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// The loading thread never panics while holding the locks, so a poisoned
+// lock still holds a consistent state
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn unwrap(res: thread::Result<SharedMimeInfo>) -> SharedMimeInfo {
+    res.unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::task::Wake;
+
+    use mime::Mime;
+
+    use crate::testing::TempMimeDir;
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn spawn_build() {
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo");
+        dir.write().unwrap();
+        let spawn = || {
+            SharedMimeInfo::builder()
+                .override_dirs(Vec::<PathBuf>::new())
+                .data_dirs([dir.path()])
+                .data_home("/does/not/exist")
+                .extra_dirs(Vec::<PathBuf>::new())
+                .detect_sandbox(false)
+                .spawn_build()
+        };
+        let foo = Mime::from_str("text/x-foo").unwrap();
+
+        let mime_db = spawn().wait();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![foo.clone()]
+        );
+
+        // The database stays available once it is ready
+        let loading = spawn();
+        while !loading.is_ready() {
+            thread::yield_now();
+        }
+        match loading.poll_ready() {
+            Poll::Ready(mime_db) => {
+                assert_eq!(
+                    mime_db.get_mime_types_from_file_name("a.foo"),
+                    vec![foo.clone()]
+                )
+            }
+            Poll::Pending => panic!("the database is not ready"),
+        }
+        let mime_db = loading.wait();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![foo.clone()]
+        );
+
+        // Polled as a future, waking the task when it is ready
+        let mut loading = spawn();
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mime_db = loop {
+            match Pin::new(&mut loading).poll(&mut cx) {
+                Poll::Ready(mime_db) => break mime_db,
+                Poll::Pending => thread::yield_now(),
+            }
+        };
+        assert_eq!(mime_db.get_mime_types_from_file_name("a.foo"), vec![foo]);
+    }
+}
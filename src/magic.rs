@@ -7,15 +7,27 @@ use nom::multi::{many0, many1};
 use nom::number::complete::be_u16;
 use nom::sequence::tuple;
 use nom::IResult;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::str::{self, FromStr};
+use std::str;
+use std::sync::{Arc, OnceLock};
 use std::vec::Vec;
 
 use mime::Mime;
 
+use crate::cache;
+use crate::diagnostics::{Diagnostic, ParseContext};
+#[cfg(feature = "events")]
+use crate::events;
+use crate::priority::MagicPriority;
+use crate::sniffer::{Chunks, Sniffer};
+use crate::special::{self, APPLICATION_X_ZEROSIZE};
+use crate::tier::Tier;
+
 pub fn to_string(s: &[u8]) -> std::result::Result<&str, std::str::Utf8Error> {
     str::from_utf8(s)
 }
@@ -31,8 +43,14 @@ pub fn buf_to_u32(s: &[u8], or_default: u32) -> u32 {
     to_u32(to_string(s), or_default)
 }
 
+// The value of the rule used by a directory to remove all the magic
+// entries of a MIME type defined in the directories with a lower
+// precedence
+pub const NO_MAGIC: &[u8] = b"__NOMAGIC__";
+
 #[derive(Clone, Debug, PartialEq)]
-struct MagicRule {
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct MagicRule {
     indent: u32,
     start_offset: u32,
     value: Vec<u8>,
@@ -50,22 +68,141 @@ fn masked_slices_are_equal(a: &[u8], b: &[u8], mask: &[u8]) -> bool {
     masked_a.eq(masked_b)
 }
 
+// Whether the value appears in the data, at one of the `range_length`
+// offsets after `start_offset`; shared with the matchlets of a mime.cache
+// file, which are matched in place
+pub fn matches_range(
+    data: &[u8],
+    start_offset: u32,
+    range_length: u32,
+    value: &[u8],
+    mask: Option<&[u8]>,
+) -> bool {
+    let start = start_offset as usize;
+    let range_length = range_length as usize;
+
+    let mut data_windows = data.windows(value.len()).skip(start).take(range_length);
+
+    match mask {
+        Some(mask) => data_windows.any(|data_w| masked_slices_are_equal(data_w, value, mask)),
+        None => data_windows.any(|data_w| data_w == value),
+    }
+}
+
+// Whether a tree of rules matches, with the rules listed in depth first
+// order, each with its depth in the tree
+pub fn matches_tree<T>(
+    rules: impl Iterator<Item = (u32, T)>,
+    matches_data: impl Fn(&T) -> bool,
+) -> bool {
+    let mut current_level = 0;
+
+    let mut iter = rules.peekable();
+    while let Some((indent, rule)) = iter.next() {
+        // The rules are a flat list that represent a tree; the "indent"
+        // is the depth of the rule in the tree.
+        //
+        // Check the rule at the current level
+        if indent == current_level && matches_data(&rule) {
+            // If the next rule has a lower level, or it's the last
+            // rule, we found our match
+            match iter.peek() {
+                Some((next, _)) => {
+                    if *next <= current_level {
+                        return true;
+                    }
+
+                    // Otherwise, increase the level and check the
+                    // next rule
+                    current_level += 1;
+                }
+                None => {
+                    // last rule
+                    return true;
+                }
+            };
+        }
+    }
+
+    false
+}
+
 impl MagicRule {
+    // Rules with an empty value or range, or with a mask that does not
+    // match the length of the value, are rejected
+    pub fn new(
+        indent: u32,
+        offsets: Range<u32>,
+        word_size: u32,
+        value: &[u8],
+        mask: Option<&[u8]>,
+    ) -> Option<MagicRule> {
+        if value.is_empty() || offsets.is_empty() || mask.is_some_and(|m| m.len() != value.len()) {
+            return None;
+        }
+
+        Some(MagicRule {
+            indent,
+            start_offset: offsets.start,
+            value: value.to_vec(),
+            mask: mask.map(<[u8]>::to_vec),
+            word_size,
+            range_length: offsets.end - offsets.start,
+        })
+    }
+
     fn matches_data(&self, data: &[u8]) -> bool {
-        assert!(self.mask.is_none() || self.mask.as_ref().unwrap().len() == self.value.len());
+        matches_range(
+            data,
+            self.start_offset,
+            self.range_length,
+            &self.value,
+            self.mask.as_deref(),
+        )
+    }
 
+    // Like matches_data(), but for data that may not be available yet;
+    // returns None if the rule cannot be evaluated until more data arrives
+    fn matches_chunks(&self, chunks: &Chunks) -> Option<bool> {
         let start = self.start_offset as usize;
         let range_length = self.range_length as usize;
         let value_len = self.value.len();
+        let mut pending = false;
+
+        for offset in start..start + range_length {
+            let data_w = match chunks.window(offset, value_len) {
+                Some(v) => v,
+                None => {
+                    // The window is pending, unless the part that is
+                    // already available does not match
+                    if !chunks.is_absent(offset, value_len) {
+                        let avail = chunks.window_prefix(offset, value_len);
+                        let len = avail.len();
+                        pending |= match &self.mask {
+                            Some(mask) => {
+                                masked_slices_are_equal(&avail, &self.value[..len], &mask[..len])
+                            }
+                            None => *avail == self.value[..len],
+                        };
+                    }
+                    continue;
+                }
+            };
 
-        let mut data_windows = data.windows(value_len).skip(start).take(range_length);
+            let matched = match &self.mask {
+                Some(mask) => masked_slices_are_equal(&data_w, &self.value, mask),
+                None => *data_w == self.value[..],
+            };
 
-        match &self.mask {
-            Some(mask) => {
-                data_windows.any(|data_w| masked_slices_are_equal(data_w, &self.value, mask))
+            if matched {
+                return Some(true);
             }
+        }
 
-            None => data_windows.any(|data_w| data_w == &self.value[..]),
+        if pending {
+            None
+        } else {
+            Some(false)
         }
     }
 
@@ -76,6 +213,11 @@ impl MagicRule {
 
         value_len + offset + range_len
     }
+
+    // The length of the shortest data that can match the rule
+    fn min_extent(&self) -> usize {
+        self.start_offset as usize + self.value.len()
+    }
 }
 
 // Indentation level, can be 0
@@ -173,10 +315,13 @@ fn magic_rule(bytes: &[u8]) -> IResult<&[u8], MagicRule> {
 }
 
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct MagicEntry {
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     mime_type: Mime,
     priority: u32,
     rules: Vec<MagicRule>,
+    tier: Tier,
 }
 
 impl fmt::Debug for MagicEntry {
@@ -190,42 +335,90 @@ impl fmt::Debug for MagicEntry {
 }
 
 impl MagicEntry {
+    pub fn new(mime_type: &Mime, priority: u32, rules: Vec<MagicRule>) -> MagicEntry {
+        MagicEntry {
+            mime_type: special::intern(mime_type.clone()),
+            priority,
+            rules,
+            tier: Tier::System,
+        }
+    }
+
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime_type
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    pub fn rules(&self) -> &[MagicRule] {
+        &self.rules
+    }
+
+    pub fn tier(&self) -> Tier {
+        self.tier
+    }
+
+    pub fn set_tier(&mut self, tier: Tier) {
+        self.tier = tier;
+    }
+
+    // Creates the marker removing the entries of `mime_type` from the
+    // directories with a lower precedence
+    pub fn no_magic(mime_type: &Mime) -> MagicEntry {
+        let rule = MagicRule::new(0, 0..1, 1, NO_MAGIC, None).unwrap();
+
+        MagicEntry::new(mime_type, 0, vec![rule])
+    }
+
+    pub fn is_no_magic(&self) -> bool {
+        self.rules
+            .first()
+            .is_some_and(|rule| rule.value == NO_MAGIC)
+    }
+
     fn matches(&self, data: &[u8]) -> Option<(&Mime, u32)> {
+        let rules = self.rules.iter().map(|rule| (rule.indent, rule));
+
+        matches_tree(rules, |rule| rule.matches_data(data))
+            .then_some((&self.mime_type, self.priority))
+    }
+
+    // Like matches(), but for data that may not be available yet; returns
+    // None if the entry cannot be evaluated until more data arrives
+    pub fn matches_chunks(&self, chunks: &Chunks) -> Option<bool> {
         let mut current_level = 0;
 
         let mut iter = self.rules.iter().peekable();
         while let Some(rule) = iter.next() {
-            // The rules are a flat list that represent a tree; the "indent"
-            // is the depth of the rule in the tree.
-            //
-            // Check the rule at the current level
-            if rule.indent == current_level && rule.matches_data(data) {
-                // If the next rule has a lower level, or it's the last
-                // rule, we found our match
-                match iter.peek() {
-                    Some(next) => {
-                        if next.indent <= current_level {
-                            return Some((&self.mime_type, self.priority));
-                        }
-
-                        // Otherwise, increase the level and check the
-                        // next rule
-                        current_level += 1;
-                    }
-                    None => {
-                        // last rule
-                        return Some((&self.mime_type, self.priority));
-                    }
-                };
+            if rule.indent != current_level || !rule.matches_chunks(chunks)? {
+                continue;
+            }
+
+            match iter.peek() {
+                Some(next) if next.indent > current_level => current_level += 1,
+                _ => return Some(true),
             }
         }
 
-        None
+        Some(false)
     }
 
-    fn max_extents(&self) -> usize {
+    pub fn max_extents(&self) -> usize {
         self.rules.iter().map(MagicRule::extent).max().unwrap_or(0)
     }
+
+    // The length of the shortest data that can match the entry, since at
+    // least one of the top level rules must match
+    pub fn min_data_size(&self) -> usize {
+        self.rules
+            .iter()
+            .filter(|rule| rule.indent == 0)
+            .map(MagicRule::min_extent)
+            .min()
+            .unwrap_or(0)
+    }
 }
 
 fn priority(bytes: &[u8]) -> IResult<&[u8], u32> {
@@ -234,28 +427,36 @@ fn priority(bytes: &[u8]) -> IResult<&[u8], u32> {
     Ok((bytes, buf_to_u32(res, 0)))
 }
 
-fn mime_type(bytes: &[u8]) -> IResult<&[u8], Mime> {
-    map_res(map_res(take_until("]\n"), str::from_utf8), Mime::from_str)(bytes)
+fn mime_type(bytes: &[u8]) -> IResult<&[u8], &str> {
+    map_res(take_until("]\n"), str::from_utf8)(bytes)
 }
 
 // magic_header =
 // '[' <priority> ':' <mime_type> ']' '\n'
-fn magic_header(bytes: &[u8]) -> IResult<&[u8], (u32, Mime)> {
+fn magic_header(bytes: &[u8]) -> IResult<&[u8], (u32, &str)> {
     let (bytes, (_, _priority, _, _mime_type, _)) =
         tuple((tag("["), priority, tag(":"), mime_type, tag("]\n")))(bytes)?;
 
     Ok((bytes, (_priority, _mime_type)))
 }
 
+// An entry whose MIME type has not been validated yet, so that an entry
+// with an invalid type does not stop the parsing of the ones after it
+struct RawMagicEntry<'a> {
+    mime_type: &'a str,
+    priority: u32,
+    rules: Vec<MagicRule>,
+}
+
 // magic_entry =
 // <magic_header>
 // <magic_rule>+
-fn magic_entry(bytes: &[u8]) -> IResult<&[u8], MagicEntry> {
+fn raw_magic_entry(bytes: &[u8]) -> IResult<&[u8], RawMagicEntry<'_>> {
     let (bytes, (_header, _rules)) = tuple((magic_header, many1(magic_rule)))(bytes)?;
 
     Ok((
         bytes,
-        MagicEntry {
+        RawMagicEntry {
             priority: _header.0,
             mime_type: _header.1,
             rules: _rules,
@@ -263,17 +464,405 @@ fn magic_entry(bytes: &[u8]) -> IResult<&[u8], MagicEntry> {
     ))
 }
 
-fn from_u8_to_entries(bytes: &[u8]) -> IResult<&[u8], Vec<MagicEntry>> {
-    let (bytes, (_, entries)) = tuple((tag("MIME-Magic\0\n"), many0(magic_entry)))(bytes)?;
+#[cfg(test)]
+fn magic_entry(bytes: &[u8]) -> IResult<&[u8], MagicEntry> {
+    map_res(raw_magic_entry, |e| {
+        e.mime_type.parse::<Mime>().map(|mime_type| MagicEntry {
+            mime_type,
+            priority: e.priority,
+            rules: e.rules,
+            tier: Tier::System,
+        })
+    })(bytes)
+}
+
+fn from_u8_to_raw_entries(bytes: &[u8]) -> IResult<&[u8], Vec<RawMagicEntry<'_>>> {
+    let (bytes, (_, entries)) = tuple((tag("MIME-Magic\0\n"), many0(raw_magic_entry)))(bytes)?;
+
+    Ok((bytes, entries))
+}
+
+fn from_u8_to_entries_with_context<'a>(
+    bytes: &'a [u8],
+    path: &Path,
+    ctx: &mut ParseContext,
+) -> IResult<&'a [u8], Vec<MagicEntry>> {
+    let (bytes, raw_entries) = from_u8_to_raw_entries(bytes)?;
+
+    let mut entries = Vec::with_capacity(raw_entries.len());
+    for e in raw_entries {
+        let mime_type = if ctx.lenient {
+            e.mime_type.trim().trim_matches('"')
+        } else {
+            e.mime_type
+        };
+
+        match special::parse_mime_type(mime_type) {
+            Some(mime_type) => entries.push(MagicEntry {
+                mime_type,
+                priority: e.priority,
+                rules: e.rules,
+                tier: Tier::System,
+            }),
+            None => {
+                let header = format!("[{}:{}]", e.priority, e.mime_type);
+                ctx.check_mime_types(path, None, &header, [mime_type]);
+            }
+        }
+    }
+
+    // The parser stops at the first entry that cannot be parsed
+    if !bytes.is_empty() {
+        report_malformed(bytes, path, ctx);
+    }
 
     Ok((bytes, entries))
 }
 
-pub fn lookup_data(entries: &[MagicEntry], data: &[u8]) -> Option<(Mime, u32)> {
+// In strict mode, records the first line of the data that could not be
+// parsed
+fn report_malformed(bytes: &[u8], path: &Path, ctx: &mut ParseContext) {
+    if ctx.strict {
+        let line = bytes.split(|b| *b == b'\n').next().unwrap_or_default();
+        ctx.diagnostics.push(Diagnostic::MalformedEntry {
+            path: path.to_path_buf(),
+            line: None,
+            entry: String::from_utf8_lossy(line).into_owned(),
+        });
+    }
+}
+
+#[cfg(test)]
+fn from_u8_to_entries(bytes: &[u8]) -> IResult<&[u8], Vec<MagicEntry>> {
+    from_u8_to_entries_with_context(bytes, Path::new(""), &mut ParseContext::default())
+}
+
+// Adds the entries of a directory after the ones of the directories with a
+// higher precedence; the `__NOMAGIC__` markers remove the entries of their
+// MIME type from the directories added afterwards, but not the ones listed
+// alongside them, and are recorded in `deleted`; the entries that repeat
+// one of a directory with a higher precedence are dropped, so that stacked
+// copies of the same database are only matched once
+pub fn add_entries(
+    entries: &mut Vec<MagicEntry>,
+    deleted: &mut HashSet<Mime>,
+    new_entries: Vec<MagicEntry>,
+) {
+    let (markers, new_entries): (Vec<MagicEntry>, Vec<MagicEntry>) =
+        new_entries.into_iter().partition(MagicEntry::is_no_magic);
+
+    let mut loaded: HashMap<&Mime, Vec<&MagicEntry>> = HashMap::new();
+    for e in entries.iter() {
+        loaded.entry(&e.mime_type).or_default().push(e);
+    }
+    let is_duplicate = |e: &MagicEntry| {
+        loaded.get(&e.mime_type).is_some_and(|same_type| {
+            same_type
+                .iter()
+                .any(|old| old.priority == e.priority && old.rules == e.rules)
+        })
+    };
+
+    let new_entries: Vec<MagicEntry> = new_entries
+        .into_iter()
+        .filter(|e| !deleted.contains(&e.mime_type) && !is_duplicate(e))
+        .collect();
+    entries.extend(new_entries);
+    deleted.extend(markers.into_iter().map(|e| e.mime_type));
+}
+
+// Where the magic entries of a directory are read from
+#[derive(Clone, Debug)]
+pub enum MagicSource {
+    // The entries of the source XML files, which are parsed as a whole
+    Entries(Vec<MagicEntry>),
+    // The mime.cache file of a `mime` directory, whose entries are matched
+    // in place; if they cannot be read, the plain text file is used instead
+    Cache(Arc<cache::CacheFile>),
+    // The plain text file of a `mime` directory
+    Dir(PathBuf),
+}
+
+#[derive(Clone, Debug, Default)]
+struct ParsedMagic {
+    entries: Vec<MagicEntry>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// The entries of a directory, as used by the lookups
+enum Layer<'a> {
+    Parsed(&'a [MagicEntry]),
+    Mapped(&'a cache::MappedMagic),
+}
+
+impl<'a> Layer<'a> {
+    // The MIME types whose entries are removed from the directories with
+    // a lower precedence
+    fn markers(&self) -> Vec<&'a Mime> {
+        match self {
+            Layer::Parsed(entries) => entries
+                .iter()
+                .filter(|e| e.is_no_magic())
+                .map(MagicEntry::mime_type)
+                .collect(),
+            Layer::Mapped(magic) => magic.markers().iter().collect(),
+        }
+    }
+
+    fn lookup_data<'b>(&self, data: &'b [u8]) -> Box<dyn Iterator<Item = (&'a Mime, u32)> + 'b>
+    where
+        'a: 'b,
+    {
+        match *self {
+            Layer::Parsed(entries) => Box::new(
+                entries
+                    .iter()
+                    .filter(|e| !e.is_no_magic() && e.min_data_size() <= data.len())
+                    .filter_map(move |e| e.matches(data)),
+            ),
+            Layer::Mapped(magic) => Box::new(magic.lookup_data(data)),
+        }
+    }
+
+    fn extents(&self, deleted: &'a [&'a Mime]) -> Box<dyn Iterator<Item = (usize, usize)> + 'a> {
+        match *self {
+            Layer::Parsed(entries) => Box::new(
+                entries
+                    .iter()
+                    .filter(|e| !e.is_no_magic() && !deleted.contains(&e.mime_type()))
+                    .map(|e| (e.min_data_size(), e.max_extents())),
+            ),
+            Layer::Mapped(magic) => Box::new(magic.extents(deleted)),
+        }
+    }
+}
+
+// The magic entries of a directory, parsed the first time they are needed;
+// the reloaded copies of a database share the segments of the directories
+// that did not change, so their entries are only parsed once
+#[derive(Debug)]
+pub struct MagicSegment {
+    source: MagicSource,
+    parsed: OnceLock<ParsedMagic>,
+    // The entries of the mime.cache file, if they can be read
+    mapped: OnceLock<Option<cache::MappedMagic>>,
+}
+
+impl MagicSegment {
+    pub fn new(source: MagicSource) -> MagicSegment {
+        MagicSegment {
+            source,
+            parsed: OnceLock::new(),
+            mapped: OnceLock::new(),
+        }
+    }
+
+    // The entries of the directory, parsing them if needed
+    pub fn entries(&self, lenient: bool, strict: bool) -> &[MagicEntry] {
+        &self.parsed(lenient, strict).entries
+    }
+
+    fn parsed(&self, lenient: bool, strict: bool) -> &ParsedMagic {
+        self.parsed.get_or_init(|| {
+            let mut ctx = ParseContext::new(lenient);
+            ctx.strict = strict;
+            let entries = match &self.source {
+                MagicSource::Entries(entries) => entries.clone(),
+                MagicSource::Cache(file) => cache::read_magic_from_cache(file, &mut ctx)
+                    .unwrap_or_else(|| {
+                        let dir = file.path().parent().unwrap_or(Path::new(""));
+                        read_magic_from_dir(dir, &mut ctx)
+                    }),
+                MagicSource::Dir(dir) => read_magic_from_dir(dir, &mut ctx),
+            };
+
+            #[cfg(feature = "events")]
+            for diagnostic in &ctx.diagnostics {
+                events::parse_failure(diagnostic);
+            }
+
+            ParsedMagic {
+                entries,
+                diagnostics: ctx.diagnostics,
+            }
+        })
+    }
+
+    // The entries used by the lookups: the ones of the mime.cache file are
+    // matched in place, without parsing them
+    fn layer(&self, lenient: bool, strict: bool) -> Layer<'_> {
+        let mapped = self.mapped.get_or_init(|| match &self.source {
+            MagicSource::Cache(file) => cache::MappedMagic::new(Arc::clone(file)),
+            _ => None,
+        });
+
+        match mapped {
+            Some(magic) => Layer::Mapped(magic),
+            None => Layer::Parsed(self.entries(lenient, strict)),
+        }
+    }
+}
+
+// The magic entries of every directory, in order of precedence; they are
+// only parsed the first time they are needed, since most of the lookups
+// only use the file names, and the ones of the mime.cache files are not
+// parsed at all, unless they are listed
+#[derive(Clone, Debug, Default)]
+pub struct DeferredMagic {
+    sources: Vec<(Arc<MagicSegment>, Tier)>,
+    lenient: bool,
+    strict: bool,
+    parsed: OnceLock<ParsedMagic>,
+    max_extents: OnceLock<usize>,
+}
+
+impl DeferredMagic {
+    pub fn new(lenient: bool, strict: bool) -> DeferredMagic {
+        DeferredMagic {
+            lenient,
+            strict,
+            ..DeferredMagic::default()
+        }
+    }
+
+    pub fn add_segment(&mut self, segment: Arc<MagicSegment>, tier: Tier) {
+        self.sources.push((segment, tier));
+        self.parsed = OnceLock::new();
+        self.max_extents = OnceLock::new();
+    }
+
+    pub fn is_parsed(&self) -> bool {
+        self.parsed.get().is_some()
+    }
+
+    pub fn entries(&self) -> &[MagicEntry] {
+        &self.parsed().entries
+    }
+
+    // The problems found while parsing the entries
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.parsed().diagnostics
+    }
+
+    fn parsed(&self) -> &ParsedMagic {
+        self.parsed.get_or_init(|| {
+            let mut entries = Vec::new();
+            let mut deleted = HashSet::new();
+            let mut diagnostics = Vec::new();
+
+            for (segment, tier) in &self.sources {
+                let parsed = segment.parsed(self.lenient, self.strict);
+                let mut new_entries = parsed.entries.clone();
+                new_entries.iter_mut().for_each(|e| e.set_tier(*tier));
+                add_entries(&mut entries, &mut deleted, new_entries);
+                diagnostics.extend(parsed.diagnostics.iter().cloned());
+            }
+
+            ParsedMagic {
+                entries,
+                diagnostics,
+            }
+        })
+    }
+
+    // The entries of each directory, with the MIME types removed by the
+    // directories with a higher precedence; the lookups go through them
+    // instead of entries(), so that the mime.cache files are not parsed
+    fn layers(&self) -> Vec<(Layer<'_>, Vec<&Mime>)> {
+        let mut deleted = Vec::new();
+
+        self.sources
+            .iter()
+            .map(|(segment, _)| {
+                let layer = segment.layer(self.lenient, self.strict);
+                let markers = layer.markers();
+                let res = (layer, deleted.clone());
+                deleted.extend(markers);
+                res
+            })
+            .collect()
+    }
+
+    // Like lookup_data()
+    pub fn lookup_data(&self, data: &[u8]) -> Option<(&Mime, u32)> {
+        self.layers().into_iter().find_map(|(layer, deleted)| {
+            layer
+                .lookup_data(data)
+                .find(|(mime_type, _)| !deleted.contains(mime_type))
+        })
+    }
+
+    // Like lookup_data_all()
+    pub fn lookup_data_all(&self, data: &[u8]) -> Vec<(&Mime, u32)> {
+        let mut res: Vec<(&Mime, u32)> = Vec::new();
+
+        for (layer, deleted) in self.layers() {
+            let matches = layer
+                .lookup_data(data)
+                .filter(|(mime_type, _)| !deleted.contains(mime_type));
+            for (mime_type, priority) in matches {
+                match res.iter_mut().find(|(mime, _)| *mime == mime_type) {
+                    Some(entry) => entry.1 = entry.1.max(priority),
+                    None => res.push((mime_type, priority)),
+                }
+            }
+        }
+
+        res.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        res
+    }
+
+    // Like max_extents()
+    pub fn max_extents(&self) -> usize {
+        *self
+            .max_extents
+            .get_or_init(|| self.max_extents_for_len(usize::MAX))
+    }
+
+    // Like max_extents_for_len()
+    pub fn max_extents_for_len(&self, len: usize) -> usize {
+        self.layers()
+            .iter()
+            .flat_map(|(layer, deleted)| layer.extents(deleted))
+            .filter(|(min_data_size, _)| *min_data_size <= len)
+            .map(|(_, max_extents)| max_extents)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers()
+            .iter()
+            .all(|(layer, deleted)| layer.extents(deleted).next().is_none())
+    }
+}
+
+pub fn lookup_data<'a>(entries: &'a [MagicEntry], data: &[u8]) -> Option<(&'a Mime, u32)> {
     entries
         .iter()
+        .filter(|e| e.min_data_size() <= data.len())
         .find_map(|e| e.matches(data))
-        .map(|v| (v.0.clone(), v.1))
+}
+
+// Returns every MIME type with a magic entry matching the data, with the
+// highest priority among its matching entries, in descending order by
+// priority
+pub fn lookup_data_all<'a>(entries: &'a [MagicEntry], data: &[u8]) -> Vec<(&'a Mime, u32)> {
+    let mut res: Vec<(&Mime, u32)> = Vec::new();
+
+    let candidates = entries.iter().filter(|e| e.min_data_size() <= data.len());
+
+    for (mime_type, priority) in candidates.filter_map(|e| e.matches(data)) {
+        match res.iter_mut().find(|(mime, _)| *mime == mime_type) {
+            Some(entry) => entry.1 = entry.1.max(priority),
+            None => res.push((mime_type, priority)),
+        }
+    }
+
+    res.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    res
 }
 
 pub fn max_extents(entries: &[MagicEntry]) -> usize {
@@ -284,8 +873,22 @@ pub fn max_extents(entries: &[MagicEntry]) -> usize {
         .unwrap_or(0)
 }
 
-pub fn read_magic_from_file<P: AsRef<Path>>(file_name: P) -> Vec<MagicEntry> {
-    let mut f = match File::open(file_name) {
+// Like max_extents(), but ignoring the entries that cannot match data of
+// the given length
+pub fn max_extents_for_len(entries: &[MagicEntry], len: usize) -> usize {
+    entries
+        .iter()
+        .filter(|e| e.min_data_size() <= len)
+        .map(MagicEntry::max_extents)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn read_magic_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Vec<MagicEntry> {
+    let mut f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return Vec::new(),
     };
@@ -293,29 +896,234 @@ pub fn read_magic_from_file<P: AsRef<Path>>(file_name: P) -> Vec<MagicEntry> {
     let mut magic_buf = Vec::<u8>::new();
 
     f.read_to_end(&mut magic_buf).unwrap();
-    match from_u8_to_entries(magic_buf.as_slice()) {
+    match from_u8_to_entries_with_context(magic_buf.as_slice(), file_name.as_ref(), ctx) {
         Ok(v) => v.1,
-        Err(_) => Vec::new(),
+        Err(_) => {
+            report_malformed(&magic_buf, file_name.as_ref(), ctx);
+            Vec::new()
+        }
     }
 }
 
-pub fn read_magic_from_dir<P: AsRef<Path>>(dir: P) -> Vec<MagicEntry> {
+pub fn read_magic_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Vec<MagicEntry> {
     let mut magic_file = PathBuf::new();
     magic_file.push(dir);
     magic_file.push("magic");
 
-    read_magic_from_file(magic_file)
+    read_magic_from_file(magic_file, ctx)
+}
+
+pub fn write_magic<W: Write>(entries: &[MagicEntry], w: &mut W) -> io::Result<()> {
+    w.write_all(b"MIME-Magic\0\n")?;
+
+    for entry in entries {
+        writeln!(w, "[{}:{}]", entry.priority, entry.mime_type)?;
+
+        for rule in &entry.rules {
+            if rule.indent > 0 {
+                write!(w, "{}", rule.indent)?;
+            }
+            write!(w, ">{}=", rule.start_offset)?;
+            w.write_all(&(rule.value.len() as u16).to_be_bytes())?;
+            w.write_all(&rule.value)?;
+            if let Some(mask) = &rule.mask {
+                w.write_all(b"&")?;
+                w.write_all(mask)?;
+            }
+            if rule.word_size != 1 {
+                write!(w, "~{}", rule.word_size)?;
+            }
+            if rule.range_length != 1 {
+                write!(w, "+{}", rule.range_length)?;
+            }
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A set of magic rules, used to determine the MIME type of some data.
+///
+/// The [`SharedMimeInfo`] database uses the rules of the `magic` files in
+/// the XDG data directories; a `MagicDatabase` can also be loaded from a
+/// single file, or populated with custom entries, and used on its own,
+/// without loading the rest of the shared MIME database:
+///
+/// ```rust
+/// use xdg_mime::{MagicDatabase, MagicPriority};
+///
+/// let mut magic = MagicDatabase::new();
+/// magic.add_entry(&mime::APPLICATION_PDF, MagicPriority::DEFAULT, 0..1, b"%PDF-", None);
+/// magic.add_entry(&mime::TEXT_HTML, MagicPriority::DEFAULT, 0..64, b"<html", None);
+///
+/// assert_eq!(
+///     magic.sniff(b"%PDF-1.7"),
+///     Some((mime::APPLICATION_PDF, MagicPriority::DEFAULT))
+/// );
+/// assert_eq!(magic.sniff(b"\n\n<html>").map(|m| m.0), Some(mime::TEXT_HTML));
+/// assert_eq!(magic.sniff(b"GIF89a"), None);
+/// ```
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MagicDatabase {
+    entries: Vec<MagicEntry>,
+}
+
+impl MagicDatabase {
+    /// Creates an empty set of magic rules.
+    pub fn new() -> MagicDatabase {
+        MagicDatabase {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Loads the magic rules from a file in the `magic` format used by the
+    /// shared MIME database.
+    ///
+    /// Returns `None` if the file cannot be read, or if it is not a valid
+    /// magic file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Option<MagicDatabase> {
+        let bytes = std::fs::read(path).ok()?;
+
+        MagicDatabase::from_bytes(&bytes)
+    }
+
+    /// Loads the magic rules from the contents of a file in the `magic`
+    /// format used by the shared MIME database.
+    ///
+    /// Entries referring to invalid MIME types are skipped. Returns `None`
+    /// if the data does not start with the magic file header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<MagicDatabase> {
+        let mut ctx = ParseContext::default();
+        let (_, entries) = from_u8_to_entries_with_context(bytes, Path::new(""), &mut ctx).ok()?;
+
+        Some(MagicDatabase { entries })
+    }
+
+    /// Adds an entry for `mime_type`, matching if `value` is found at
+    /// any of the `offsets` of the data.
+    ///
+    /// If a `mask` is given, it must have the same length as `value`, and
+    /// it is applied to the data before comparing it with `value`; entries
+    /// with an invalid mask, an empty value, or an empty range of offsets
+    /// are ignored.
+    ///
+    /// Entries are evaluated in descending order of priority; entries with
+    /// the same priority are evaluated in the order they were added.
+    pub fn add_entry(
+        &mut self,
+        mime_type: &Mime,
+        priority: MagicPriority,
+        offsets: Range<u32>,
+        value: &[u8],
+        mask: Option<&[u8]>,
+    ) -> &mut Self {
+        let rule = match MagicRule::new(0, offsets, 1, value, mask) {
+            Some(rule) => rule,
+            None => return self,
+        };
+
+        let mut entry = MagicEntry::new(mime_type, priority.get(), vec![rule]);
+        entry.set_tier(Tier::Runtime);
+
+        let pos = self
+            .entries
+            .iter()
+            .position(|e| e.priority < entry.priority)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(pos, entry);
+
+        self
+    }
+
+    /// Removes the entries for `mime_type` added using [`add_entry`],
+    /// keeping the ones loaded from a magic file.
+    ///
+    /// [`add_entry`]: #method.add_entry
+    pub fn unregister_mime_type(&mut self, mime_type: &Mime) -> &mut Self {
+        self.entries
+            .retain(|e| e.tier != Tier::Runtime || e.mime_type != *mime_type);
+
+        self
+    }
+
+    /// Removes every entry added using [`add_entry`], keeping the ones
+    /// loaded from a magic file.
+    ///
+    /// [`add_entry`]: #method.add_entry
+    pub fn remove_runtime_entries(&mut self) -> &mut Self {
+        self.entries.retain(|e| e.tier != Tier::Runtime);
+
+        self
+    }
+
+    /// The number of entries in the database.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks whether the database contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of bytes at the start of the data needed to evaluate
+    /// every rule in the database.
+    pub fn max_data_size(&self) -> usize {
+        max_extents(&self.entries)
+    }
+
+    /// Retrieves the MIME type for the given data, and the priority of the
+    /// match, like [`SharedMimeInfo::get_mime_type_for_data`].
+    ///
+    /// [`SharedMimeInfo::get_mime_type_for_data`]: struct.SharedMimeInfo.html#method.get_mime_type_for_data
+    pub fn sniff(&self, data: &[u8]) -> Option<(Mime, MagicPriority)> {
+        if data.is_empty() {
+            return Some((APPLICATION_X_ZEROSIZE.clone(), MagicPriority::ZERO_SIZE));
+        }
+
+        lookup_data(&self.entries, data)
+            .map(|(mime_type, priority)| (mime_type.clone(), MagicPriority::saturating(priority)))
+    }
+
+    /// Reads the data needed to evaluate the rules from `reader`, and
+    /// retrieves its MIME type, like [`sniff`].
+    ///
+    /// At most [`max_data_size`] bytes are read.
+    ///
+    /// [`sniff`]: #method.sniff
+    /// [`max_data_size`]: #method.max_data_size
+    pub fn sniff_reader<R: Read>(&self, reader: R) -> io::Result<Option<(Mime, MagicPriority)>> {
+        let mut data = Vec::new();
+        reader
+            .take(self.max_data_size() as u64)
+            .read_to_end(&mut data)?;
+
+        Ok(self.sniff(&data))
+    }
+
+    /// Creates a [`Sniffer`], to determine the MIME type of data received
+    /// in multiple chunks.
+    ///
+    /// [`Sniffer`]: struct.Sniffer.html
+    pub fn sniffer(&self) -> Sniffer<'_> {
+        Sniffer::new(&self.entries)
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::char_lit_as_u8)]
 mod tests {
     use super::*;
     use nom::HexDisplay;
     use nom::Offset;
+    use std::str::FromStr;
 
     #[test]
     fn parse_magic_header() {
-        let res = magic_header(&"[50:application/x-yaml]\n".as_bytes());
+        let res = magic_header("[50:application/x-yaml]\n".as_bytes());
 
         match res {
             Ok((i, o)) => {
@@ -430,6 +1238,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn no_magic_marker() {
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+
+        let data = b"MIME-Magic\0\n[0:text/x-foo]\n>0=\x00\x0b__NOMAGIC__\n";
+        let (_, entries) = from_u8_to_entries(data).unwrap();
+        assert_eq!(entries, vec![MagicEntry::no_magic(&foo)]);
+        assert!(entries[0].is_no_magic());
+
+        let foo_rule = || MagicRule::new(0, 0..1, 1, b"FOO", None).unwrap();
+        let bar_rule = MagicRule::new(0, 0..1, 1, b"BAR", None).unwrap();
+        assert!(!MagicEntry::new(&foo, 50, vec![foo_rule()]).is_no_magic());
+
+        // The marker only applies to the entries added afterwards
+        let mut res = Vec::new();
+        let mut deleted = HashSet::new();
+        let user = vec![
+            MagicEntry::new(&foo, 60, vec![foo_rule()]),
+            MagicEntry::no_magic(&foo),
+        ];
+        add_entries(&mut res, &mut deleted, user);
+        let system = vec![
+            MagicEntry::new(&foo, 50, vec![foo_rule()]),
+            MagicEntry::new(&bar, 50, vec![bar_rule]),
+        ];
+        add_entries(&mut res, &mut deleted, system);
+
+        let found: Vec<(&Mime, u32)> = res.iter().map(|e| (e.mime_type(), e.priority())).collect();
+        assert_eq!(found, vec![(&foo, 60), (&bar, 50)]);
+        assert!(lookup_data(&res, b"__NOMAGIC__").is_none());
+    }
+
     #[test]
     fn magic_rule_matches_data() {
         let rule = MagicRule {
@@ -513,4 +1354,101 @@ mod tests {
         assert!(rule.matches_data(b"12345heLLO"));
         assert!(!rule.matches_data(b"HuLLO WORLD"));
     }
+
+    #[test]
+    fn magic_database() {
+        let db = MagicDatabase::from_file("test_files/mime/magic").unwrap();
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        assert_eq!(
+            db.sniff(png_data),
+            Some((mime::IMAGE_PNG, MagicPriority::DEFAULT))
+        );
+        assert_eq!(db.sniff_reader(&png_data[..]).unwrap(), db.sniff(png_data));
+        assert_eq!(
+            db.sniff(b""),
+            Some((APPLICATION_X_ZEROSIZE.clone(), MagicPriority::ZERO_SIZE))
+        );
+
+        assert_eq!(MagicDatabase::from_file("test_files/mime/globs2"), None);
+        assert_eq!(
+            MagicDatabase::from_bytes(b"MIME-Magic\0\n"),
+            Some(MagicDatabase::new())
+        );
+    }
+
+    #[test]
+    fn size_filter() {
+        let iso: Mime = "application/x-cd-image".parse().unwrap();
+        let mut db = MagicDatabase::new();
+        db.add_entry(
+            &mime::TEXT_PLAIN,
+            MagicPriority::DEFAULT,
+            0..1,
+            b"abc",
+            None,
+        )
+        .add_entry(&iso, MagicPriority::DEFAULT, 32769..32770, b"CD001", None);
+
+        let min_sizes: Vec<usize> = db.entries.iter().map(MagicEntry::min_data_size).collect();
+        assert_eq!(min_sizes, vec![3, 32774]);
+        assert_eq!(max_extents_for_len(&db.entries, 1024), 4);
+        assert_eq!(
+            max_extents_for_len(&db.entries, 1 << 20),
+            db.max_data_size()
+        );
+        assert_eq!(max_extents_for_len(&db.entries, 2), 0);
+
+        let mut data = vec![0u8; 32774];
+        data[32769..].copy_from_slice(b"CD001");
+        assert_eq!(db.sniff(&data), Some((iso, MagicPriority::DEFAULT)));
+        assert_eq!(db.sniff(&data[..32773]), None);
+    }
+
+    #[test]
+    fn magic_database_add_entry() {
+        let low = MagicPriority::new(20).unwrap();
+        let mut db = MagicDatabase::new();
+        db.add_entry(&mime::TEXT_PLAIN, low, 0..1, b"abc", None)
+            .add_entry(&mime::TEXT_CSV, MagicPriority::MAX, 2..4, b"x", None)
+            .add_entry(&mime::TEXT_CSS, low, 0..1, b"a", Some(&[0xdf]))
+            .add_entry(&mime::TEXT_HTML, low, 0..1, b"", None)
+            .add_entry(&mime::TEXT_HTML, low, 0..1, b"a", Some(b"ab"))
+            .add_entry(&mime::TEXT_HTML, low, 1..1, b"a", None);
+
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.max_data_size(), 5);
+        assert_eq!(db.sniff(b"abc"), Some((mime::TEXT_PLAIN, low)));
+        assert_eq!(
+            db.sniff(b"abcx"),
+            Some((mime::TEXT_CSV, MagicPriority::MAX))
+        );
+        assert_eq!(db.sniff(b"Abd"), Some((mime::TEXT_CSS, low)));
+        assert_eq!(db.sniff(b"bcd"), None);
+    }
+
+    #[test]
+    fn magic_database_remove_entries() {
+        let png_data = include_bytes!("../test_files/files/rust-logo.png");
+        let loaded = MagicDatabase::from_file("test_files/mime/magic").unwrap();
+        let mut db = loaded.clone();
+        db.add_entry(&mime::IMAGE_PNG, MagicPriority::MAX, 0..1, b"\x89", None)
+            .add_entry(&mime::TEXT_CSV, MagicPriority::MAX, 0..1, b"a,", None);
+        assert_eq!(db.len(), loaded.len() + 2);
+        assert_eq!(
+            db.sniff(png_data),
+            Some((mime::IMAGE_PNG, MagicPriority::MAX))
+        );
+
+        // The entries loaded from the file are kept
+        db.unregister_mime_type(&mime::IMAGE_PNG);
+        assert_eq!(db.len(), loaded.len() + 1);
+        assert_eq!(
+            db.sniff(png_data),
+            Some((mime::IMAGE_PNG, MagicPriority::DEFAULT))
+        );
+        assert_eq!(db.sniff(b"a,b"), Some((mime::TEXT_CSV, MagicPriority::MAX)));
+
+        db.remove_runtime_entries();
+        assert_eq!(db, loaded);
+    }
 }
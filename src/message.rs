@@ -0,0 +1,364 @@
+// A minimal parser for the top-level structure of Internet messages, as
+// defined by RFC 5322 and RFC 2046, enough to extract the body parts of a
+// `message/rfc822` or `multipart/*` document without depending on a full
+// MIME message parser
+
+use std::str;
+
+use mime::Mime;
+
+use crate::data_uri::decode_base64;
+use crate::Guess;
+
+/// A body part of a message, returned by the [`classify_message_parts`]
+/// method of [`SharedMimeInfo`].
+///
+/// [`classify_message_parts`]: struct.SharedMimeInfo.html#method.classify_message_parts
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+pub struct MessagePart {
+    pub(crate) declared: Option<Mime>,
+    pub(crate) file_name: Option<String>,
+    pub(crate) size: usize,
+    pub(crate) guess: Guess,
+}
+
+impl MessagePart {
+    /// The MIME type declared in the `Content-Type` header of the part.
+    pub fn declared_type(&self) -> Option<&Mime> {
+        self.declared.as_ref()
+    }
+
+    /// The file name declared in the `Content-Disposition` header of the
+    /// part, or in the `name` parameter of its `Content-Type` header.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// The size of the contents of the part, after decoding them.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The MIME type of the part.
+    pub fn guess(&self) -> &Guess {
+        &self.guess
+    }
+}
+
+// A body part, before its contents are classified
+pub struct RawPart {
+    pub declared: Option<Mime>,
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+// The fields of a header block, with their names in lowercase and their
+// folded lines joined
+struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Splits the data into lines, with their line terminator removed; each
+// line comes with its offset, and with the offset of the next line
+fn lines(data: &[u8]) -> impl Iterator<Item = (usize, &[u8], usize)> {
+    let mut start = 0;
+
+    std::iter::from_fn(move || {
+        if start >= data.len() {
+            return None;
+        }
+
+        let end = data[start..]
+            .iter()
+            .position(|ch| *ch == b'\n')
+            .map_or(data.len(), |pos| start + pos + 1);
+        let line = (start, trim_eol(&data[start..end]), end);
+        start = end;
+
+        Some(line)
+    })
+}
+
+fn trim_eol(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// Parses the header block at the start of the data, returning the headers
+// and the body after the empty line; data without headers is all body
+fn split_headers(data: &[u8]) -> (Headers, &[u8]) {
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for (_, line, next) in lines(data) {
+        if line.is_empty() {
+            return (Headers { fields }, &data[next..]);
+        }
+
+        let line = String::from_utf8_lossy(line);
+
+        // Folded lines continue the previous field
+        if line.starts_with([' ', '\t']) {
+            match fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    continue;
+                }
+                None => return (Headers { fields: Vec::new() }, data),
+            }
+        }
+
+        match line.split_once(':') {
+            Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                fields.push((name.to_ascii_lowercase(), value.trim().to_string()));
+            }
+            _ => return (Headers { fields: Vec::new() }, data),
+        }
+    }
+
+    // A header block without a body
+    (Headers { fields }, &[])
+}
+
+// Retrieves a parameter of a structured header value, like the boundary
+// of a `Content-Type`, removing the quotes around it
+fn parameter(value: &str, name: &str) -> Option<String> {
+    let mut params = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ';' if !quoted => params.push(std::mem::take(&mut current)),
+            ch => current.push(ch),
+        }
+    }
+    params.push(current);
+
+    params.iter().skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().to_string())
+    })
+}
+
+// The MIME type of a `Content-Type`, without its parameters
+fn media_type(value: &str) -> Option<Mime> {
+    let essence = value.split(';').next()?.trim();
+
+    essence.parse().ok()
+}
+
+// Invalid escape sequences are kept as they are
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+    let mut idx = 0;
+
+    while idx < data.len() {
+        if data[idx] != b'=' {
+            res.push(data[idx]);
+            idx += 1;
+            continue;
+        }
+
+        let rest = &data[idx + 1..];
+        if rest.starts_with(b"\r\n") {
+            idx += 3;
+        } else if rest.starts_with(b"\n") {
+            idx += 2;
+        } else {
+            let byte = rest
+                .get(..2)
+                .and_then(|hex| str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match byte {
+                Some(byte) => {
+                    res.push(byte);
+                    idx += 3;
+                }
+                None => {
+                    res.push(b'=');
+                    idx += 1;
+                }
+            }
+        }
+    }
+
+    res
+}
+
+fn raw_part(headers: &Headers, body: &[u8]) -> RawPart {
+    let content_type = headers.get("content-type");
+
+    let file_name = headers
+        .get("content-disposition")
+        .and_then(|value| parameter(value, "filename"))
+        .or_else(|| content_type.and_then(|value| parameter(value, "name")))
+        .filter(|name| !name.is_empty());
+
+    let encoding = headers.get("content-transfer-encoding").unwrap_or_default();
+    let data = if encoding.eq_ignore_ascii_case("base64") {
+        decode_base64(body).unwrap_or_else(|| body.to_vec())
+    } else if encoding.eq_ignore_ascii_case("quoted-printable") {
+        decode_quoted_printable(body)
+    } else {
+        body.to_vec()
+    };
+
+    RawPart {
+        declared: content_type.and_then(media_type),
+        file_name,
+        data,
+    }
+}
+
+// Splits the body of a multipart entity at each delimiter line, ignoring
+// the preamble and the epilogue; the line break before each delimiter is
+// part of the delimiter, and not of the body part
+fn split_multipart<'a>(body: &'a [u8], boundary: &str, limit: usize) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut res = Vec::new();
+    let mut part_start = None;
+
+    for (start, line, next) in lines(body) {
+        let line = match line.strip_prefix(delimiter.as_bytes()) {
+            Some(rest) => rest,
+            None => continue,
+        };
+
+        let is_close = line.starts_with(b"--");
+        let rest = if is_close { &line[2..] } else { line };
+        if !rest.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        if let Some(part_start) = part_start {
+            res.push(trim_eol(&body[part_start..start]));
+            if res.len() == limit {
+                return res;
+            }
+        }
+
+        if is_close {
+            return res;
+        }
+
+        part_start = Some(next);
+    }
+
+    // An unterminated multipart entity ends with the data
+    if let Some(part_start) = part_start.filter(|start| *start < body.len()) {
+        res.push(&body[part_start..]);
+    }
+
+    res
+}
+
+// Extracts up to `limit` top-level body parts from a message, or from a
+// multipart entity, whose boundary is taken from its first delimiter
+pub fn parts(data: &[u8], limit: usize) -> Vec<RawPart> {
+    if limit == 0 {
+        return Vec::new();
+    }
+
+    let (headers, body) = split_headers(data);
+    let content_type = headers.get("content-type");
+
+    let boundary = match content_type {
+        Some(value) if media_type(value).is_some_and(|m| m.type_() == mime::MULTIPART) => {
+            parameter(value, "boundary")
+        }
+        Some(_) => None,
+        None if headers.fields.is_empty() => lines(body)
+            .map(|(_, line, _)| line)
+            .find(|line| !line.is_empty())
+            .and_then(|line| line.strip_prefix(b"--"))
+            .and_then(|boundary| str::from_utf8(boundary).ok())
+            .map(|boundary| boundary.trim_end().to_string()),
+        None => None,
+    };
+
+    match boundary.filter(|boundary| !boundary.is_empty()) {
+        Some(boundary) => split_multipart(body, &boundary, limit)
+            .into_iter()
+            .map(|part| {
+                let (headers, body) = split_headers(part);
+                raw_part(&headers, body)
+            })
+            .collect(),
+        None if headers.fields.is_empty() => Vec::new(),
+        None => vec![raw_part(&headers, body)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_message() {
+        let message = b"From: a@example.com\r\n\
+            Content-Type: multipart/mixed;\r\n\
+            \tboundary=\"sep\"\r\n\
+            \r\n\
+            preamble\r\n\
+            --sep\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            \r\n\
+            Hello\r\n\
+            --sep\r\n\
+            Content-Type: image/png; name=\"a.png\"\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            iVBORw0KGgo=\r\n\
+            --sep--\r\n\
+            epilogue\r\n";
+
+        let parts = parts(message, 10);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].declared, Some(mime::TEXT_PLAIN));
+        assert_eq!(parts[0].data, b"Hello");
+        assert_eq!(parts[1].declared, Some(mime::IMAGE_PNG));
+        assert_eq!(parts[1].file_name.as_deref(), Some("a.png"));
+        assert_eq!(parts[1].data, b"\x89PNG\r\n\x1a\n");
+
+        assert_eq!(super::parts(message, 1).len(), 1);
+        assert!(super::parts(message, 0).is_empty());
+    }
+
+    #[test]
+    fn split_single_part() {
+        let message = b"Subject: hi\n\
+            Content-Type: text/plain\n\
+            Content-Transfer-Encoding: quoted-printable\n\
+            Content-Disposition: attachment; filename=notes.txt\n\
+            \n\
+            caf=C3=A9 =\n\
+            au lait=3\n";
+
+        let parts = parts(message, 10);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].file_name.as_deref(), Some("notes.txt"));
+        assert_eq!(parts[0].data, "café au lait=3\n".as_bytes());
+
+        // Without headers, the boundary comes from the first delimiter
+        let multipart = b"--xyz\n\nfirst\n--xyz\n\nsecond\n--xyz--\n";
+        let parts = super::parts(multipart, 10);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].data, b"second");
+
+        assert!(super::parts(b"just some text", 10).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+// The XMLnamespaces file, mapping the root element of XML documents to
+// their MIME type
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use mime::Mime;
+
+use crate::diagnostics::ParseContext;
+use crate::special;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct XmlNamespace {
+    pub namespace: String,
+    pub local_name: String,
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
+    pub mime_type: Mime,
+}
+
+impl XmlNamespace {
+    pub fn new(namespace: &str, local_name: &str, mime_type: &Mime) -> XmlNamespace {
+        XmlNamespace {
+            namespace: namespace.to_string(),
+            local_name: local_name.to_string(),
+            mime_type: special::intern(mime_type.clone()),
+        }
+    }
+
+    pub fn from_string(s: &str) -> Option<XmlNamespace> {
+        let mut chunks = s.split_whitespace().fuse();
+        let namespace = chunks.next()?;
+        let local_name = chunks.next()?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
+
+        // Consume the leftovers, if any
+        if chunks.next().is_some() {
+            return None;
+        }
+
+        Some(XmlNamespace::new(namespace, local_name, &mime_type))
+    }
+}
+
+pub fn read_namespaces_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Vec<XmlNamespace> {
+    let mut res = Vec::new();
+
+    let f = match File::open(&file_name) {
+        Ok(v) => v,
+        Err(_) => return res,
+    };
+
+    let file = BufReader::new(&f);
+    for (n, line) in file.lines().enumerate() {
+        let line = match line {
+            Ok(line) => ctx.normalize_line(&line, ' '),
+            Err(_) => return res,
+        };
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match XmlNamespace::from_string(&line) {
+            Some(v) => res.push(v),
+            None => ctx.check_mime_types(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split_whitespace().skip(2).take(1),
+            ),
+        }
+    }
+
+    res
+}
+
+pub fn read_namespaces_from_dir<P: AsRef<Path>>(
+    dir: P,
+    ctx: &mut ParseContext,
+) -> Vec<XmlNamespace> {
+    let mut namespaces_file = PathBuf::new();
+    namespaces_file.push(dir);
+    namespaces_file.push("XMLnamespaces");
+
+    read_namespaces_from_file(namespaces_file, ctx)
+}
+
+// The first definition of a root element takes precedence
+pub fn lookup<'a>(
+    namespaces: &'a [XmlNamespace],
+    namespace: &str,
+    local_name: &str,
+) -> Option<&'a Mime> {
+    namespaces
+        .iter()
+        .find(|ns| ns.namespace == namespace && ns.local_name == local_name)
+        .map(|ns| &ns.mime_type)
+}
+
+// Namespaces are sorted by namespace and local name; if a root element is
+// defined more than once, only the definition that takes precedence is
+// written
+pub fn write_namespaces<W: Write>(namespaces: &[XmlNamespace], w: &mut W) -> io::Result<()> {
+    let mut sorted: Vec<&XmlNamespace> = namespaces.iter().collect();
+    sorted.sort_by(|a, b| (&a.namespace, &a.local_name).cmp(&(&b.namespace, &b.local_name)));
+    sorted.dedup_by(|a, b| a.namespace == b.namespace && a.local_name == b.local_name);
+
+    for ns in sorted {
+        writeln!(w, "{} {} {}", ns.namespace, ns.local_name, ns.mime_type)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        let svg =
+            XmlNamespace::from_string("http://www.w3.org/2000/svg svg image/svg+xml").unwrap();
+        assert_eq!(svg.namespace, "http://www.w3.org/2000/svg");
+        assert_eq!(svg.local_name, "svg");
+        assert_eq!(svg.mime_type, mime::IMAGE_SVG);
+
+        assert!(XmlNamespace::from_string("http://www.w3.org/2000/svg svg").is_none());
+        assert!(
+            XmlNamespace::from_string("http://www.w3.org/2000/svg svg svg image/svg+xml").is_none()
+        );
+    }
+
+    #[test]
+    fn read_test_namespaces() {
+        let mut ctx = ParseContext::new(false);
+        let namespaces = read_namespaces_from_dir("test_files/mime", &mut ctx);
+        assert!(ctx.diagnostics.is_empty());
+        assert_eq!(namespaces.len(), 26);
+
+        let mut out = Vec::new();
+        write_namespaces(&namespaces, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            std::fs::read_to_string("test_files/mime/XMLnamespaces").unwrap()
+        );
+
+        let svg = "http://www.w3.org/2000/svg";
+        assert_eq!(lookup(&namespaces, svg, "svg"), Some(&mime::IMAGE_SVG));
+        assert_eq!(lookup(&namespaces, svg, "html"), None);
+    }
+}
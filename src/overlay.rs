@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use mime::Mime;
+
+use crate::glob::{Glob, GlobMap};
+use crate::priority::GlobWeight;
+use crate::tier::Tier;
+use crate::SharedMimeInfo;
+
+/// A set of pending changes to a [`SharedMimeInfo`] database.
+///
+/// An overlay is populated by the closure passed to
+/// [`SharedMimeInfo::with_overlay`], and it is only visible to the queries
+/// performed inside the second closure; the database is not modified.
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [`SharedMimeInfo::with_overlay`]: struct.SharedMimeInfo.html#method.with_overlay
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    globs: Vec<Glob>,
+}
+
+impl Overlay {
+    /// Adds a glob pattern for `mime_type`, with the given weight.
+    ///
+    /// If the database already contains the same pattern for `mime_type`,
+    /// the weight of the existing glob is replaced. Patterns that would be
+    /// rejected when loading the database, like empty or overly complex
+    /// ones, are ignored.
+    pub fn add_glob(&mut self, mime_type: &Mime, pattern: &str, weight: GlobWeight) -> &mut Self {
+        let weight = weight.get() as i32;
+
+        if let Some(mut glob) = Glob::checked(mime_type, pattern, weight, false) {
+            glob.set_tier(Tier::Runtime);
+            self.globs.push(glob);
+        }
+
+        self
+    }
+
+    /// Adds a case sensitive glob pattern for `mime_type`, with the given
+    /// weight.
+    ///
+    /// See [`add_glob`] for details.
+    ///
+    /// [`add_glob`]: #method.add_glob
+    pub fn add_case_sensitive_glob(
+        &mut self,
+        mime_type: &Mime,
+        pattern: &str,
+        weight: GlobWeight,
+    ) -> &mut Self {
+        let weight = weight.get() as i32;
+
+        if let Some(mut glob) = Glob::checked(mime_type, pattern, weight, true) {
+            glob.set_tier(Tier::Runtime);
+            self.globs.push(glob);
+        }
+
+        self
+    }
+
+    /// Checks whether the overlay contains any change.
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    // A clone of the database whose glob lookups check the globs of the
+    // overlay first, and then the ones of the database, which are shared
+    pub(crate) fn apply(self, db: &SharedMimeInfo) -> SharedMimeInfo {
+        let mut res = db.clone();
+        res.globs = Arc::new(GlobMap::layered(&db.globs, self.globs));
+
+        res
+    }
+}
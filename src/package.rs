@@ -0,0 +1,676 @@
+// Reads the shared-mime-info source files, in the `packages` directory,
+// for the systems where update-mime-database was never run; only the
+// elements that end up in the compiled files are read
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mime::Mime;
+
+use crate::alias::Alias;
+use crate::comment::Comments;
+use crate::diagnostics::ParseContext;
+use crate::glob::Glob;
+use crate::icon::Icon;
+use crate::magic::{MagicEntry, MagicRule};
+use crate::namespace::XmlNamespace;
+use crate::parent::Subclass;
+use crate::special;
+use crate::treemagic::{FileKind, TreeMagicEntry, TreeMatch};
+use crate::xml::{Event, Reader};
+use crate::MimeData;
+
+// The defaults used by update-mime-database for missing attributes
+const DEFAULT_WEIGHT: i32 = 50;
+const DEFAULT_PRIORITY: u32 = 50;
+
+// The package whose definitions take precedence over all the others
+const OVERRIDE_PACKAGE: &str = "Override.xml";
+
+// The data of each MIME type, accumulated across the source files, and
+// sorted by the names of the MIME types, like in the compiled files
+#[derive(Default)]
+struct TypeInfo {
+    globs: Vec<Glob>,
+    magic: Vec<MagicEntry>,
+    treemagic: Vec<TreeMagicEntry>,
+    namespaces: Vec<XmlNamespace>,
+    aliases: Vec<Alias>,
+    parents: Vec<Mime>,
+    icon: Option<String>,
+    generic_icon: Option<String>,
+    comments: Comments,
+    // Whether the globs or the magic entries read from the packages before
+    // were removed
+    globs_deleted: bool,
+    magic_deleted: bool,
+}
+
+// Parses a number like strtoul() with base 0: hexadecimal with a `0x`
+// prefix, octal with a leading zero, and decimal otherwise
+fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim();
+
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if s.len() > 1 && s.starts_with('0') {
+        u32::from_str_radix(&s[1..], 8).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+// Replaces the C escape sequences in the value of a string match
+fn parse_string(s: &str) -> Vec<u8> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes().peekable();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            res.push(byte);
+            continue;
+        }
+
+        let escaped = match bytes.next() {
+            Some(escaped) => escaped,
+            None => {
+                res.push(b'\\');
+                break;
+            }
+        };
+
+        let value = match escaped {
+            b'x' => {
+                let mut value = 0u8;
+                for _ in 0..2 {
+                    match bytes.peek().and_then(|ch| (*ch as char).to_digit(16)) {
+                        Some(digit) => {
+                            value = value.wrapping_mul(16).wrapping_add(digit as u8);
+                            bytes.next();
+                        }
+                        None => break,
+                    }
+                }
+                value
+            }
+            b'0'..=b'7' => {
+                let mut value = escaped - b'0';
+                for _ in 0..2 {
+                    match bytes.peek().filter(|ch| (b'0'..=b'7').contains(*ch)) {
+                        Some(digit) => {
+                            value = value.wrapping_mul(8).wrapping_add(digit - b'0');
+                            bytes.next();
+                        }
+                        None => break,
+                    }
+                }
+                value
+            }
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'v' => 0x0b,
+            other => other,
+        };
+        res.push(value);
+    }
+
+    res
+}
+
+// The mask of a string match is written in hexadecimal
+fn parse_string_mask(s: &str) -> Option<Vec<u8>> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+// Encodes a number for a match of the given type; host byte order values
+// are written in big endian, with the size of their words, and swapped
+// when matching
+fn parse_value(kind: &str, value: &str) -> Option<(Vec<u8>, u32)> {
+    if kind == "string" {
+        return Some((parse_string(value), 1));
+    }
+
+    let number = parse_number(value)?;
+    let res = match kind {
+        "byte" => (vec![u8::try_from(number).ok()?], 1),
+        "big16" => (u16::try_from(number).ok()?.to_be_bytes().to_vec(), 1),
+        "big32" => (number.to_be_bytes().to_vec(), 1),
+        "little16" => (u16::try_from(number).ok()?.to_le_bytes().to_vec(), 1),
+        "little32" => (number.to_le_bytes().to_vec(), 1),
+        "host16" => (u16::try_from(number).ok()?.to_be_bytes().to_vec(), 2),
+        "host32" => (number.to_be_bytes().to_vec(), 4),
+        _ => return None,
+    };
+
+    Some(res)
+}
+
+// Parses a `<match>` element; the offset is either a single offset, or an
+// inclusive range like `0:256`
+fn parse_match(event: &Event, indent: u32) -> Option<MagicRule> {
+    let kind = event.attribute("type")?;
+    let (value, word_size) = parse_value(kind, event.attribute("value")?)?;
+
+    let offset = event.attribute("offset")?;
+    let (start, end) = match offset.split_once(':') {
+        Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+        None => {
+            let start = parse_number(offset)?;
+            (start, start)
+        }
+    };
+    let offsets = start..end.checked_add(1)?;
+
+    let mask = match event.attribute("mask") {
+        Some(mask) if kind == "string" => Some(parse_string_mask(mask)?),
+        Some(mask) => Some(parse_value(kind, mask)?.0),
+        None => None,
+    };
+
+    MagicRule::new(indent, offsets, word_size, &value, mask.as_deref())
+}
+
+// Parses a `<treematch>` element
+fn parse_tree_match(event: &Event, indent: u32) -> Option<TreeMatch> {
+    let flag = |name| event.attribute(name) == Some("true");
+    let mime_type = match event.attribute("mimetype") {
+        Some(mime_type) => Some(special::parse_mime_type(mime_type)?),
+        None => None,
+    };
+
+    Some(TreeMatch {
+        indent,
+        path: event.attribute("path")?.to_string(),
+        kind: FileKind::from_name(event.attribute("type").unwrap_or("any"))?,
+        executable: flag("executable"),
+        match_case: flag("match-case"),
+        non_empty: flag("non-empty"),
+        on_disc: flag("on-disc"),
+        mime_type,
+    })
+}
+
+// The magic or tree magic entry being read, with the rules in the order
+// they appear; a rule that cannot be parsed invalidates the whole entry
+struct PendingEntry<T> {
+    priority: u32,
+    rules: Vec<Option<T>>,
+    depth: u32,
+}
+
+impl<T> PendingEntry<T> {
+    fn new(event: &Event) -> PendingEntry<T> {
+        let priority = event
+            .attribute("priority")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(DEFAULT_PRIORITY);
+
+        PendingEntry {
+            priority,
+            rules: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    // Rules that are not empty contain the rules at the next depth
+    fn push(&mut self, rule: Option<T>, empty: bool) {
+        self.rules.push(rule);
+        if !empty {
+            self.depth += 1;
+        }
+    }
+
+    fn finish(self) -> Option<(u32, Vec<T>)> {
+        let rules: Vec<T> = self.rules.into_iter().collect::<Option<_>>()?;
+
+        (!rules.is_empty()).then_some((self.priority, rules))
+    }
+}
+
+// Parses the `type` attribute of an element, reporting invalid ones
+fn parse_type(path: &Path, event: &Event, name: &str, ctx: &mut ParseContext) -> Option<Mime> {
+    let value = event.attribute("type")?;
+    let entry = format!("<{} type=\"{}\">", name, value);
+    ctx.check_mime_types(path, None, &entry, [value]);
+
+    special::parse_mime_type(value)
+}
+
+fn read_package(
+    path: &Path,
+    contents: &str,
+    types: &mut BTreeMap<String, TypeInfo>,
+    ctx: &mut ParseContext,
+) {
+    // The MIME type being read, with its name as written in the file
+    let mut current: Option<(String, Mime)> = None;
+    let mut magic: Option<PendingEntry<MagicRule>> = None;
+    let mut treemagic: Option<PendingEntry<TreeMatch>> = None;
+    // The language of the comment being read, if any
+    let mut in_comment: Option<Option<String>> = None;
+
+    for event in Reader::new(contents) {
+        let (name, empty) = match &event {
+            Event::Start { name, empty, .. } => (*name, *empty),
+            Event::Text(text) if in_comment.is_some() => {
+                let lang = in_comment.take().flatten();
+                if let Some((key, _)) = &current {
+                    let text = text.trim();
+                    if !text.is_empty() {
+                        let info = types.entry(key.clone()).or_default();
+                        info.comments.insert(lang, text.to_string());
+                    }
+                }
+                continue;
+            }
+            Event::End { name: "mime-type" } => {
+                current = None;
+                continue;
+            }
+            Event::End { name: "match" } => {
+                if let Some(pending) = magic.as_mut() {
+                    pending.depth = pending.depth.saturating_sub(1);
+                }
+                continue;
+            }
+            Event::End { name: "treematch" } => {
+                if let Some(pending) = treemagic.as_mut() {
+                    pending.depth = pending.depth.saturating_sub(1);
+                }
+                continue;
+            }
+            Event::End { name: "magic" } => {
+                let pending = magic.take().and_then(PendingEntry::finish);
+                if let (Some((key, mime_type)), Some((priority, rules))) = (&current, pending) {
+                    let entry = MagicEntry::new(mime_type, priority, rules);
+                    types.entry(key.clone()).or_default().magic.push(entry);
+                }
+                continue;
+            }
+            Event::End { name: "treemagic" } => {
+                let pending = treemagic.take().and_then(PendingEntry::finish);
+                if let (Some((key, mime_type)), Some((priority, matches))) = (&current, pending) {
+                    let entry = TreeMagicEntry::new(mime_type, priority, matches);
+                    types.entry(key.clone()).or_default().treemagic.push(entry);
+                }
+                continue;
+            }
+            _ => {
+                in_comment = None;
+                continue;
+            }
+        };
+        in_comment = None;
+
+        if name == "mime-type" {
+            let key = event.attribute("type").map(str::to_string);
+            current = key.zip(parse_type(path, &event, name, ctx));
+            // Every MIME type is listed, even without any other definition
+            if let Some((key, _)) = &current {
+                types.entry(key.clone()).or_default();
+            }
+            magic = None;
+            treemagic = None;
+            continue;
+        }
+
+        let (key, mime_type) = match &current {
+            Some(current) => current,
+            None => continue,
+        };
+        let info = types.entry(key.clone()).or_default();
+
+        match name {
+            "glob" => {
+                let pattern = match event.attribute("pattern") {
+                    Some(pattern) => pattern,
+                    None => continue,
+                };
+                let weight = event
+                    .attribute("weight")
+                    .and_then(|w| w.parse().ok())
+                    .unwrap_or(DEFAULT_WEIGHT);
+                // Case insensitive patterns are stored in lowercase
+                let cs = event.attribute("case-sensitive") == Some("true");
+                let pattern = if cs {
+                    pattern.to_string()
+                } else {
+                    pattern.to_lowercase()
+                };
+                info.globs
+                    .extend(Glob::checked(mime_type, &pattern, weight, cs));
+            }
+            "glob-deleteall" => {
+                info.globs.clear();
+                info.globs_deleted = true;
+            }
+            "magic-deleteall" => {
+                info.magic.clear();
+                info.magic_deleted = true;
+            }
+            "magic" if !empty => magic = Some(PendingEntry::new(&event)),
+            "match" => {
+                if let Some(pending) = magic.as_mut() {
+                    pending.push(parse_match(&event, pending.depth), empty);
+                }
+            }
+            "treemagic" if !empty => treemagic = Some(PendingEntry::new(&event)),
+            "treematch" => {
+                if let Some(pending) = treemagic.as_mut() {
+                    pending.push(parse_tree_match(&event, pending.depth), empty);
+                }
+            }
+            "root-XML" => {
+                let namespace = event.attribute("namespaceURI");
+                let local_name = event.attribute("localName");
+                if let (Some(namespace), Some(local_name)) = (namespace, local_name) {
+                    info.namespaces
+                        .push(XmlNamespace::new(namespace, local_name, mime_type));
+                }
+            }
+            "alias" => {
+                if let Some(alias) = parse_type(path, &event, name, ctx) {
+                    info.aliases.push(Alias::new(&alias, mime_type));
+                }
+            }
+            "sub-class-of" => {
+                if let Some(parent) = parse_type(path, &event, name, ctx) {
+                    info.parents.push(parent);
+                }
+            }
+            "icon" => info.icon = event.attribute("name").map(str::to_string),
+            "generic-icon" => info.generic_icon = event.attribute("name").map(str::to_string),
+            "comment" if !empty => {
+                in_comment = Some(event.attribute("xml:lang").map(str::to_string))
+            }
+            _ => {}
+        }
+    }
+}
+
+// Merges the definitions of Override.xml: the globs and the magic entries
+// of each MIME type it defines replace the ones from the other packages,
+// and its glob patterns, magic rules, root elements and aliases are
+// removed from the other MIME types, so that they cannot conflict with the overrides
+fn apply_overrides(types: &mut BTreeMap<String, TypeInfo>, overrides: BTreeMap<String, TypeInfo>) {
+    let patterns: HashSet<String> = overrides
+        .values()
+        .flat_map(|info| info.globs.iter().map(Glob::pattern))
+        .collect();
+    let rules: Vec<&[MagicRule]> = overrides
+        .values()
+        .flat_map(|info| info.magic.iter().map(MagicEntry::rules))
+        .collect();
+    let root_elements: HashSet<(&str, &str)> = overrides
+        .values()
+        .flat_map(|info| info.namespaces.iter())
+        .map(|ns| (ns.namespace.as_str(), ns.local_name.as_str()))
+        .collect();
+    let aliases: HashSet<&Mime> = overrides
+        .values()
+        .flat_map(|info| info.aliases.iter().map(|a| &a.alias))
+        .collect();
+
+    for info in types.values_mut() {
+        info.globs.retain(|g| !patterns.contains(&g.pattern()));
+        info.magic.retain(|e| !rules.contains(&e.rules()));
+        info.namespaces
+            .retain(|ns| !root_elements.contains(&(ns.namespace.as_str(), ns.local_name.as_str())));
+        info.aliases.retain(|a| !aliases.contains(&a.alias));
+    }
+
+    for (key, o) in overrides {
+        let info = types.entry(key).or_default();
+
+        if o.globs_deleted || !o.globs.is_empty() {
+            info.globs = o.globs;
+        }
+        info.globs_deleted |= o.globs_deleted;
+        if o.magic_deleted || !o.magic.is_empty() {
+            info.magic = o.magic;
+        }
+        info.magic_deleted |= o.magic_deleted;
+        if !o.treemagic.is_empty() {
+            info.treemagic = o.treemagic;
+        }
+        info.namespaces.extend(o.namespaces);
+        info.aliases.extend(o.aliases);
+        for parent in o.parents {
+            if !info.parents.contains(&parent) {
+                info.parents.push(parent);
+            }
+        }
+        info.icon = o.icon.or(info.icon.take());
+        info.generic_icon = o.generic_icon.or(info.generic_icon.take());
+        if !o.comments.is_empty() {
+            info.comments = o.comments;
+        }
+    }
+}
+
+// Reads the XML files in the `packages` directory, in alphabetical order,
+// like update-mime-database, and then Override.xml; returns None if there
+// is no such directory
+pub fn read_packages_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Option<MimeData> {
+    let packages_dir = dir.as_ref().join("packages");
+    let mut paths: Vec<_> = fs::read_dir(packages_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .collect();
+    paths.sort();
+
+    let is_override = |path: &PathBuf| {
+        path.file_name()
+            .is_some_and(|name| name == OVERRIDE_PACKAGE)
+    };
+    let (overrides, packages): (Vec<PathBuf>, Vec<PathBuf>) =
+        paths.into_iter().partition(is_override);
+
+    let mut types = BTreeMap::new();
+    for path in &packages {
+        if let Ok(contents) = fs::read_to_string(path) {
+            read_package(path, &contents, &mut types, ctx);
+        }
+    }
+
+    for path in &overrides {
+        if let Ok(contents) = fs::read_to_string(path) {
+            let mut override_types = BTreeMap::new();
+            read_package(path, &contents, &mut override_types, ctx);
+            apply_overrides(&mut types, override_types);
+        }
+    }
+
+    let mut res = MimeData::default();
+    for (mime_type, info) in types {
+        let mime_type = match special::parse_mime_type(&mime_type) {
+            Some(mime_type) => mime_type,
+            None => continue,
+        };
+        // Like update-mime-database, the deleted globs and magic entries
+        // are recorded, so that they are also removed from the other
+        // directories
+        if info.globs_deleted {
+            res.globs.push(Glob::no_globs(&mime_type));
+        }
+        res.globs.extend(info.globs);
+        if info.magic_deleted {
+            res.magic.push(MagicEntry::no_magic(&mime_type));
+        }
+        res.magic.extend(info.magic);
+        res.types.push(mime_type.clone());
+        res.treemagic.extend(info.treemagic);
+        res.namespaces.extend(info.namespaces);
+        res.aliases.extend(info.aliases);
+        res.subclasses.extend(
+            info.parents
+                .iter()
+                .map(|parent| Subclass::new(&mime_type, parent)),
+        );
+        res.icons
+            .extend(info.icon.map(|icon| Icon::new(&icon, &mime_type)));
+        res.generic_icons
+            .extend(info.generic_icon.map(|icon| Icon::new(&icon, &mime_type)));
+        if !info.comments.is_empty() {
+            res.comments.push((mime_type.clone(), info.comments));
+        }
+    }
+
+    // Like in the magic file, the entries with a higher priority come first
+    res.magic
+        .sort_by_key(|entry| std::cmp::Reverse(entry.priority()));
+    res.treemagic
+        .sort_by_key(|entry| std::cmp::Reverse(entry.priority()));
+    res.namespaces
+        .sort_by(|a, b| (&a.namespace, &a.local_name).cmp(&(&b.namespace, &b.local_name)));
+
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse_number("0x1F"), Some(31));
+        assert_eq!(parse_number("070707"), Some(0o70707));
+        assert_eq!(parse_number("0"), Some(0));
+        assert_eq!(parse_number("12a"), None);
+
+        assert_eq!(parse_string(r"\x89PNG\r\n\032\\"), b"\x89PNG\r\n\x1a\\");
+        assert_eq!(parse_string_mask("0xff00"), Some(vec![0xff, 0x00]));
+        assert_eq!(parse_string_mask("ff00"), None);
+
+        assert_eq!(parse_value("byte", "0x100"), None);
+        assert_eq!(parse_value("little16", "0x0102"), Some((vec![2, 1], 1)));
+        assert_eq!(
+            parse_value("host32", "0xa1b2c3d4"),
+            Some((vec![0xa1, 0xb2, 0xc3, 0xd4], 4))
+        );
+    }
+
+    #[test]
+    fn read_test_packages() {
+        let mut ctx = ParseContext::new(false);
+        let data = read_packages_from_dir("test_files/packages/mime", &mut ctx).unwrap();
+
+        let base: Mime = "application/x-base".parse().unwrap();
+        let derived: Mime = "application/x-derived".parse().unwrap();
+
+        // The second package removes the globs of the first one, and of
+        // the other directories
+        assert_eq!(
+            data.globs,
+            vec![
+                Glob::no_globs(&base),
+                Glob::new(&base, "*.base", 50, false),
+                Glob::new(&derived, "*.Derived", 80, true),
+            ]
+        );
+
+        // The magic of application/x-derived has an unknown match type
+        let rules = vec![
+            MagicRule::new(0, 0..5, 1, b"BASE\x01", None).unwrap(),
+            MagicRule::new(1, 8..9, 1, &[0x01, 0x02], Some(&[0xff, 0x0f])).unwrap(),
+            MagicRule::new(0, 0..1, 1, &[0xfe, 0xca, 0x00, 0x00], None).unwrap(),
+        ];
+        assert_eq!(data.magic, vec![MagicEntry::new(&base, 60, rules)]);
+
+        let mut treemagic = Vec::new();
+        crate::treemagic::write_treemagic(&data.treemagic, &mut treemagic).unwrap();
+        assert_eq!(
+            treemagic,
+            b"MIME-TreeMagic\0\n\
+              [60:x-content/x-base-volume]\n\
+              >\"BASE\"=directory,non-empty\n\
+              1>\"BASE/index\"=file,application/x-base\n"
+        );
+
+        let old: Mime = "application/x-derived-old".parse().unwrap();
+        assert_eq!(data.aliases, vec![Alias::new(&old, &derived)]);
+        assert_eq!(data.subclasses, vec![Subclass::new(&derived, &base)]);
+        let volume: Mime = "x-content/x-base-volume".parse().unwrap();
+        assert_eq!(data.types, vec![base.clone(), derived.clone(), volume]);
+        assert_eq!(data.comments.len(), 1);
+        assert_eq!(data.comments[0].0, base);
+        assert_eq!(
+            data.comments[0].1.lookup(&["fr_FR"]),
+            Some("Document de base")
+        );
+        assert_eq!(data.comments[0].1.lookup(&["de"]), Some("Base document"));
+        assert_eq!(
+            data.namespaces,
+            vec![XmlNamespace::new("http://example.com/base", "base", &base)]
+        );
+        assert_eq!(data.icons, vec![Icon::new("derived", &derived)]);
+        assert_eq!(
+            data.generic_icons,
+            vec![Icon::new("x-office-document", &base)]
+        );
+
+        assert_eq!(
+            ctx.diagnostics,
+            vec![Diagnostic::InvalidMimeType {
+                path: "test_files/packages/mime/packages/a-base.xml".into(),
+                line: None,
+                mime_type: "not a type".to_string(),
+                entry: "<mime-type type=\"not a type\">".to_string(),
+            }]
+        );
+
+        assert!(read_packages_from_dir("test_files/mime", &mut ctx).is_none());
+    }
+
+    #[test]
+    fn read_override_package() {
+        let mut ctx = ParseContext::new(false);
+        let data = read_packages_from_dir("test_files/override/mime", &mut ctx).unwrap();
+
+        let derived: Mime = "application/x-derived".parse().unwrap();
+        let other: Mime = "application/x-other".parse().unwrap();
+        let over: Mime = "application/x-override".parse().unwrap();
+
+        // Override.xml is read last, even if it sorts first, and replaces
+        // the globs of application/x-derived, but not its magic; the other
+        // MIME types lose the definitions that conflict with it
+        assert_eq!(
+            data.globs,
+            vec![
+                Glob::new(&derived, "*.drv", 50, false),
+                Glob::new(&other, "*.other", 50, false),
+                Glob::new(&over, "*.shared", 50, false),
+            ]
+        );
+        let mime_types: Vec<&Mime> = data.magic.iter().map(MagicEntry::mime_type).collect();
+        assert_eq!(mime_types, vec![&over, &derived]);
+
+        let alias: Mime = "application/x-shared-alias".parse().unwrap();
+        assert_eq!(data.aliases, vec![Alias::new(&alias, &over)]);
+        assert_eq!(
+            data.namespaces,
+            vec![XmlNamespace::new(
+                "http://example.com/shared",
+                "shared",
+                &over
+            )]
+        );
+        assert_eq!(data.icons, vec![Icon::new("derived-override", &derived)]);
+        assert_eq!(data.subclasses.len(), 1);
+    }
+}
@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 use mime::Mime;
 
+use crate::diagnostics::ParseContext;
+use crate::special;
+
+// The default number of levels of parents followed when checking whether
+// a MIME type is a subclass of another
+pub const DEFAULT_DEPTH_LIMIT: usize = 32;
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
 pub struct Subclass {
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     mime_type: Mime,
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
     parent_type: Mime,
 }
 
@@ -24,8 +32,8 @@ impl Subclass {
 
     fn from_string(s: &str) -> Option<Subclass> {
         let mut chunks = s.split_whitespace().fuse();
-        let mime_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
-        let parent_type = chunks.next().and_then(|s| Mime::from_str(s).ok())?;
+        let mime_type = chunks.next().and_then(special::parse_mime_type)?;
+        let parent_type = chunks.next().and_then(special::parse_mime_type)?;
 
         // Consume the leftovers, if any
         if chunks.next().is_some() {
@@ -45,6 +53,7 @@ impl fmt::Debug for Subclass {
     }
 }
 
+#[derive(Clone)]
 pub struct ParentsMap {
     parents: HashMap<Mime, Vec<Mime>>,
 }
@@ -57,10 +66,7 @@ impl ParentsMap {
     }
 
     fn add_subclass(&mut self, subclass: Subclass) {
-        let v = self
-            .parents
-            .entry(subclass.mime_type.clone())
-            .or_default();
+        let v = self.parents.entry(subclass.mime_type.clone()).or_default();
         if !v.contains(&subclass.parent_type) {
             v.push(subclass.parent_type);
         }
@@ -72,6 +78,10 @@ impl ParentsMap {
         }
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&Mime, &Vec<Mime>)> {
+        self.parents.iter()
+    }
+
     pub fn lookup(&self, mime_type: &Mime) -> Option<&Vec<Mime>> {
         self.parents.get(mime_type)
     }
@@ -81,20 +91,91 @@ impl ParentsMap {
     }
 }
 
-pub fn read_subclasses_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Subclass> {
-    let f = match File::open(file_name) {
+// The ancestors of the MIME types with parents, precomputed as one bitset
+// for each type, over all the MIME types of the graph
+#[derive(Clone, Debug, Default)]
+pub struct Ancestors {
+    index: HashMap<Mime, usize>,
+    mime_types: Vec<Mime>,
+    // The bitset of the ancestors of each MIME type, itself included, and
+    // whether the ancestors beyond the depth limit were left out
+    rows: HashMap<usize, (Vec<u64>, bool)>,
+}
+
+impl Ancestors {
+    fn index_of(&mut self, mime_type: &Mime) -> usize {
+        if let Some(idx) = self.index.get(mime_type) {
+            return *idx;
+        }
+
+        let idx = self.mime_types.len();
+        self.index.insert(mime_type.clone(), idx);
+        self.mime_types.push(mime_type.clone());
+
+        idx
+    }
+
+    pub fn insert(&mut self, mime_type: &Mime, ancestors: &[Mime], truncated: bool) {
+        let mut bits = Vec::new();
+        for ancestor in ancestors {
+            let idx = self.index_of(ancestor);
+            if bits.len() <= idx / 64 {
+                bits.resize(idx / 64 + 1, 0);
+            }
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
+
+        let idx = self.index_of(mime_type);
+        self.rows.insert(idx, (bits, truncated));
+    }
+
+    // Whether `ancestor` is one of the precomputed ancestors of `mime_type`
+    pub fn contains(&self, mime_type: &Mime, ancestor: &Mime) -> bool {
+        let row = self.index.get(mime_type).and_then(|idx| self.rows.get(idx));
+        match (row, self.index.get(ancestor)) {
+            (Some((bits, _)), Some(idx)) => bits
+                .get(idx / 64)
+                .is_some_and(|word| word & (1 << (idx % 64)) != 0),
+            _ => false,
+        }
+    }
+
+    // The precomputed ancestors of `mime_type`, and whether the ones
+    // beyond the depth limit were left out; returns None for the MIME
+    // types without parents
+    pub fn get(&self, mime_type: &Mime) -> Option<(impl Iterator<Item = &Mime>, bool)> {
+        let (bits, truncated) = self.rows.get(self.index.get(mime_type)?)?;
+        let ancestors = self
+            .mime_types
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| {
+                bits.get(idx / 64)
+                    .is_some_and(|word| word & (1 << (idx % 64)) != 0)
+            })
+            .map(|(_, mime_type)| mime_type);
+
+        Some((ancestors, *truncated))
+    }
+}
+
+pub fn read_subclasses_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Vec<Subclass> {
+    let f = match File::open(&file_name) {
         Ok(v) => v,
         Err(_) => return Vec::new(),
     };
 
     let mut res = Vec::new();
     let file = BufReader::new(&f);
-    for line in file.lines() {
+    for (n, line) in file.lines().enumerate() {
         if line.is_err() {
             return res; // FIXME: return error instead
         }
 
-        let line = line.unwrap();
+        let line = ctx.normalize_line(&line.unwrap(), ' ');
 
         if line.is_empty() || line.starts_with('#') {
             continue;
@@ -102,24 +183,45 @@ pub fn read_subclasses_from_file<P: AsRef<Path>>(file_name: P) -> Vec<Subclass>
 
         match Subclass::from_string(&line) {
             Some(v) => res.push(v),
-            None => continue,
+            None => ctx.skip_entry(
+                file_name.as_ref(),
+                Some(n + 1),
+                &line,
+                line.split_whitespace().take(2),
+            ),
         }
     }
 
     res
 }
 
-pub fn read_subclasses_from_dir<P: AsRef<Path>>(dir: P) -> Vec<Subclass> {
+pub fn read_subclasses_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Vec<Subclass> {
     let mut subclasses_file = PathBuf::new();
     subclasses_file.push(dir);
     subclasses_file.push("subclasses");
 
-    read_subclasses_from_file(subclasses_file)
+    read_subclasses_from_file(subclasses_file, ctx)
+}
+
+// Subclasses are sorted by MIME type; the parents of each type are
+// written in order of precedence
+pub fn write_subclasses<W: Write>(parents: &ParentsMap, w: &mut W) -> io::Result<()> {
+    let mut entries: Vec<(&Mime, &Vec<Mime>)> = parents.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (mime_type, parent_types) in entries {
+        for parent_type in parent_types {
+            writeln!(w, "{} {}", mime_type, parent_type)?;
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     #[test]
     fn from_str() {
@@ -151,6 +253,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ancestors() {
+        let rss = Mime::from_str("application/rss+xml").unwrap();
+        let xml = Mime::from_str("application/xml").unwrap();
+        let text = Mime::from_str("text/plain").unwrap();
+
+        let mut ancestors = Ancestors::default();
+        ancestors.insert(&rss, &[rss.clone(), xml.clone(), text.clone()], false);
+        ancestors.insert(&xml, std::slice::from_ref(&xml), true);
+
+        assert!(ancestors.contains(&rss, &text));
+        assert!(!ancestors.contains(&xml, &text));
+        assert!(!ancestors.contains(&text, &text));
+
+        let (found, truncated) = ancestors.get(&rss).unwrap();
+        assert_eq!(found.collect::<Vec<_>>(), vec![&rss, &xml, &text]);
+        assert!(!truncated);
+        assert!(ancestors.get(&xml).unwrap().1);
+        assert!(ancestors.get(&text).is_none());
+    }
+
     #[test]
     fn extra_tokens_yield_error() {
         assert!(Subclass::from_string("one/foo two/foo three/foo").is_none());
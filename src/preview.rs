@@ -0,0 +1,115 @@
+use mime::Mime;
+
+use crate::special::{APPLICATION_X_DESKTOP, APPLICATION_X_EXECUTABLE};
+use crate::SharedMimeInfo;
+
+/// The MIME types that can be rendered inline by an application, used by
+/// the [`is_safe_to_preview`] method of [`SharedMimeInfo`].
+///
+/// A MIME type can be previewed if it is a subclass of an allowed type,
+/// and it is not a subclass of a denied type; the types can use a `*`
+/// subtype, like `image/*`, to match every type with the same media type.
+///
+/// The default policy allows `image/*` and `text/*`, and denies some of
+/// their subclasses that can be used to run code, or that should not be
+/// rendered as plain text:
+///
+///  - `application/x-executable`, which includes shell scripts
+///  - `application/x-desktop`, for desktop launchers
+///  - `text/html` and `application/xhtml+xml`
+///  - `image/svg+xml`
+///
+/// ```rust
+/// # use mime::Mime;
+/// let mut policy = xdg_mime::PreviewPolicy::default();
+/// policy.allow(&mime::APPLICATION_PDF);
+/// policy.deny(&"image/x-xcf".parse::<Mime>().unwrap());
+/// ```
+///
+/// [`is_safe_to_preview`]: struct.SharedMimeInfo.html#method.is_safe_to_preview
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewPolicy {
+    allowed: Vec<Mime>,
+    denied: Vec<Mime>,
+}
+
+impl Default for PreviewPolicy {
+    fn default() -> Self {
+        let mut policy = PreviewPolicy::new();
+        policy
+            .allow(&mime::IMAGE_STAR)
+            .allow(&mime::TEXT_STAR)
+            .deny(&APPLICATION_X_EXECUTABLE)
+            .deny(&APPLICATION_X_DESKTOP)
+            .deny(&mime::TEXT_HTML)
+            .deny(&"application/xhtml+xml".parse().unwrap())
+            .deny(&mime::IMAGE_SVG);
+
+        policy
+    }
+}
+
+impl PreviewPolicy {
+    /// Creates a new policy, which does not allow any MIME type.
+    pub fn new() -> PreviewPolicy {
+        PreviewPolicy {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+        }
+    }
+
+    /// Allows the MIME type, and its subclasses.
+    pub fn allow(&mut self, mime_type: &Mime) -> &mut Self {
+        self.allowed.push(mime_type.clone());
+        self
+    }
+
+    /// Denies the MIME type, and its subclasses, even if they are allowed.
+    pub fn deny(&mut self, mime_type: &Mime) -> &mut Self {
+        self.denied.push(mime_type.clone());
+        self
+    }
+
+    // Whether the MIME type can be previewed; the unknown type cannot be,
+    // since every stream is a subclass of application/octet-stream
+    pub(crate) fn allows(&self, db: &SharedMimeInfo, mime_type: &Mime) -> bool {
+        if db.is_unknown_type(mime_type) {
+            return false;
+        }
+
+        let is_subclass = |base: &Mime| db.mime_type_subclass(mime_type, base);
+
+        self.allowed.iter().any(is_subclass) && !self.denied.iter().any(is_subclass)
+    }
+
+    pub(crate) fn denies(&self, db: &SharedMimeInfo, mime_type: &Mime) -> bool {
+        self.denied
+            .iter()
+            .any(|base| db.mime_type_subclass(mime_type, base))
+    }
+}
+
+/// Whether a file can be previewed, according to a [`PreviewPolicy`],
+/// returned by the [`is_safe_to_preview`] method of [`SharedMimeInfo`].
+///
+/// [`PreviewPolicy`]: struct.PreviewPolicy.html
+/// [`is_safe_to_preview`]: struct.SharedMimeInfo.html#method.is_safe_to_preview
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewDecision {
+    pub(crate) mime: Mime,
+    pub(crate) safe: bool,
+}
+
+impl PreviewDecision {
+    /// The MIME type of the file.
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime
+    }
+
+    /// Whether the file can be previewed.
+    pub fn is_safe(&self) -> bool {
+        self.safe
+    }
+}
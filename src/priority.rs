@@ -0,0 +1,165 @@
+use std::fmt;
+
+/// The priority of a magic entry, between 0 and 100.
+///
+/// When the contents of a file match more than one magic entry, the entry
+/// with the highest priority wins. Most entries use the [default] priority
+/// of 50; a priority of [80] or above is used for matches that are certain
+/// enough to override the MIME type derived from the file name.
+///
+/// Some results are not the match of a magic entry at all: empty data is
+/// reported as `application/x-zerosize` with the [`ZERO_SIZE`] priority,
+/// and data that does not match any entry is reported by the guessing
+/// pipeline as the unknown type with the [`FALLBACK`] priority.
+///
+/// [default]: #associatedconstant.DEFAULT
+/// [80]: #associatedconstant.CERTAIN
+/// [`ZERO_SIZE`]: #associatedconstant.ZERO_SIZE
+/// [`FALLBACK`]: #associatedconstant.FALLBACK
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MagicPriority(u8);
+
+impl MagicPriority {
+    /// The lowest priority.
+    pub const MIN: MagicPriority = MagicPriority(0);
+
+    /// The highest priority.
+    pub const MAX: MagicPriority = MagicPriority(100);
+
+    /// The priority used by most magic entries.
+    pub const DEFAULT: MagicPriority = MagicPriority(50);
+
+    /// The lowest priority of a match that is certain enough to override
+    /// the MIME type derived from the file name.
+    pub const CERTAIN: MagicPriority = MagicPriority(80);
+
+    /// The priority of `application/x-zerosize`, used for empty data.
+    pub const ZERO_SIZE: MagicPriority = MagicPriority::MAX;
+
+    /// The priority of the unknown type, used when the data does not match
+    /// any magic entry; like a certain match, it does not defer to the
+    /// file name.
+    pub const FALLBACK: MagicPriority = MagicPriority::CERTAIN;
+
+    /// Creates a new priority, if `priority` is between 0 and 100.
+    pub const fn new(priority: u32) -> Option<MagicPriority> {
+        if priority <= 100 {
+            Some(MagicPriority(priority as u8))
+        } else {
+            None
+        }
+    }
+
+    // Priorities read from the database are clamped to the valid range
+    pub(crate) fn saturating(priority: u32) -> MagicPriority {
+        MagicPriority(priority.min(100) as u8)
+    }
+
+    /// The priority, as an integer.
+    pub const fn get(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Whether the priority is at least [`CERTAIN`].
+    ///
+    /// [`CERTAIN`]: #associatedconstant.CERTAIN
+    pub const fn is_certain(self) -> bool {
+        self.0 >= MagicPriority::CERTAIN.0
+    }
+}
+
+impl Default for MagicPriority {
+    fn default() -> Self {
+        MagicPriority::DEFAULT
+    }
+}
+
+impl From<MagicPriority> for u32 {
+    fn from(priority: MagicPriority) -> u32 {
+        priority.get()
+    }
+}
+
+impl fmt::Display for MagicPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The weight of a glob pattern, between 0 and 100.
+///
+/// When a file name matches more than one glob, the glob with the highest
+/// weight wins. Most globs use the default weight of 50; a weight of 80 or
+/// above is used for patterns that are certain to identify a MIME type,
+/// like the name of a well-known file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlobWeight(u8);
+
+impl GlobWeight {
+    /// The lowest weight.
+    pub const MIN: GlobWeight = GlobWeight(0);
+
+    /// The highest weight.
+    pub const MAX: GlobWeight = GlobWeight(100);
+
+    /// The weight used by most globs.
+    pub const DEFAULT: GlobWeight = GlobWeight(50);
+
+    /// Creates a new weight, if `weight` is between 0 and 100.
+    pub const fn new(weight: u32) -> Option<GlobWeight> {
+        if weight <= 100 {
+            Some(GlobWeight(weight as u8))
+        } else {
+            None
+        }
+    }
+
+    // Weights read from the database are clamped to the valid range
+    pub(crate) fn saturating(weight: i32) -> GlobWeight {
+        GlobWeight(weight.clamp(0, 100) as u8)
+    }
+
+    /// The weight, as an integer.
+    pub const fn get(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+impl Default for GlobWeight {
+    fn default() -> Self {
+        GlobWeight::DEFAULT
+    }
+}
+
+impl From<GlobWeight> for u32 {
+    fn from(weight: GlobWeight) -> u32 {
+        weight.get()
+    }
+}
+
+impl fmt::Display for GlobWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_ranges() {
+        assert_eq!(MagicPriority::new(80).map(MagicPriority::get), Some(80));
+        assert_eq!(MagicPriority::new(101), None);
+        assert_eq!(MagicPriority::saturating(500), MagicPriority::MAX);
+        assert_eq!(MagicPriority::default().get(), 50);
+        assert!(MagicPriority::CERTAIN.is_certain());
+        assert!(MagicPriority::ZERO_SIZE.is_certain());
+        assert!(!MagicPriority::new(79).unwrap().is_certain());
+
+        assert_eq!(GlobWeight::new(100), Some(GlobWeight::MAX));
+        assert_eq!(GlobWeight::new(101), None);
+        assert_eq!(GlobWeight::saturating(-1), GlobWeight::MIN);
+        assert_eq!(GlobWeight::default().to_string(), "50");
+    }
+}
@@ -0,0 +1,100 @@
+use mime::Mime;
+
+/// The information stored in the database about a MIME type.
+///
+/// Records are returned by [`SharedMimeInfo::mime_type_record`].
+///
+/// [`SharedMimeInfo::mime_type_record`]: struct.SharedMimeInfo.html#method.mime_type_record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MimeTypeRecord {
+    pub(crate) mime_type: Mime,
+    pub(crate) aliases: Vec<Mime>,
+    pub(crate) parents: Vec<Mime>,
+    pub(crate) icon: Option<String>,
+    pub(crate) generic_icon: String,
+    pub(crate) globs: Vec<String>,
+}
+
+impl MimeTypeRecord {
+    /// The MIME type.
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime_type
+    }
+
+    /// The aliases of the MIME type, sorted.
+    pub fn aliases(&self) -> &[Mime] {
+        &self.aliases
+    }
+
+    /// The direct parents of the MIME type, in order of precedence.
+    pub fn parents(&self) -> &[Mime] {
+        &self.parents
+    }
+
+    /// The icon of the MIME type, if the database contains one.
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    /// The generic icon of the MIME type.
+    pub fn generic_icon(&self) -> &str {
+        &self.generic_icon
+    }
+
+    /// The glob patterns of the MIME type, sorted by weight.
+    pub fn globs(&self) -> &[String] {
+        &self.globs
+    }
+
+    /// Serializes the record to JSON.
+    ///
+    /// The output is a single line containing an object with the fields
+    /// of the [`Serialize`] implementation of the record, in the same
+    /// order.
+    ///
+    /// This method is only available if the `json` feature is enabled.
+    ///
+    /// [`Serialize`]: #impl-Serialize-for-MimeTypeRecord
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("the record can be serialized")
+    }
+}
+
+#[cfg(feature = "serde")]
+fn as_strings(mime_types: &[Mime]) -> Vec<&str> {
+    mime_types.iter().map(|m| m.as_ref()).collect()
+}
+
+/// Serializes the record as a struct with the following fields, in this
+/// order:
+///
+///  - `mime_type`: the MIME type, as a string
+///  - `aliases`: the aliases of the MIME type, as a sequence of strings
+///  - `parents`: the direct parents of the MIME type, as a sequence of
+///    strings
+///  - `icon`: the icon name, as a string, or `None`
+///  - `generic_icon`: the generic icon name, as a string
+///  - `globs`: the glob patterns, as a sequence of strings
+///
+/// This schema is stable: new fields may be added at the end of the
+/// struct in future versions, but existing fields will not be removed or
+/// changed.
+///
+/// This implementation is only available if the `serde` feature is
+/// enabled.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MimeTypeRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("MimeTypeRecord", 6)?;
+        state.serialize_field("mime_type", self.mime_type.as_ref())?;
+        state.serialize_field("aliases", &as_strings(&self.aliases))?;
+        state.serialize_field("parents", &as_strings(&self.parents))?;
+        state.serialize_field("icon", &self.icon)?;
+        state.serialize_field("generic_icon", &self.generic_icon)?;
+        state.serialize_field("globs", &self.globs)?;
+        state.end()
+    }
+}
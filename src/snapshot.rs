@@ -0,0 +1,47 @@
+// The manifest of the snapshots written by SharedMimeInfo::export_snapshot(),
+// using the format of sha256sum, so that the snapshots can be verified
+// with `sha256sum -c`
+
+use std::fmt::Write;
+
+use sha2::{Digest, Sha256};
+
+// The SHA-256 digest of the data
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+// A line of the manifest, for a file with the given path and contents
+pub fn manifest_line(path: &str, contents: &[u8]) -> String {
+    let mut line = String::with_capacity(path.len() + 67);
+    for byte in sha256(contents) {
+        let _ = write!(line, "{:02x}", byte);
+    }
+    let _ = writeln!(line, "  {}", path);
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_digests() {
+        assert_eq!(
+            manifest_line("empty", b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  empty\n"
+        );
+        assert_eq!(
+            manifest_line("abc", b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  abc\n"
+        );
+
+        // More than one block
+        let data = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            manifest_line("two", data),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1  two\n"
+        );
+    }
+}
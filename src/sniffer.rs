@@ -0,0 +1,362 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use mime::Mime;
+
+use crate::magic::MagicEntry;
+use crate::priority::MagicPriority;
+use crate::special::APPLICATION_X_ZEROSIZE;
+
+// Data received in chunks, possibly out of order and with gaps, indexed
+// by the offset of each chunk; the chunks never overlap, and are never
+// empty
+#[derive(Clone, Debug, Default)]
+pub struct Chunks {
+    chunks: BTreeMap<usize, Vec<u8>>,
+    complete: bool,
+    // The length of the whole data, if known
+    total_len: Option<usize>,
+}
+
+impl Chunks {
+    // Stores the parts of `bytes` that are not already available
+    fn insert(&mut self, offset: usize, bytes: &[u8]) {
+        let end = offset.saturating_add(bytes.len());
+        let mut pos = offset;
+
+        while pos < end {
+            if let Some((start, chunk)) = self.chunks.range(..=pos).next_back() {
+                if start + chunk.len() > pos {
+                    pos = start + chunk.len();
+                    continue;
+                }
+            }
+
+            let next = match self.chunks.range(pos..end).next() {
+                Some((start, _)) => *start,
+                None => end,
+            };
+            self.chunks
+                .insert(pos, bytes[pos - offset..next - offset].to_vec());
+            pos = next;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // Whether the missing data will never arrive
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    // Whether the bytes in `start..start + len` will never be available
+    pub fn is_absent(&self, start: usize, len: usize) -> bool {
+        self.complete
+            || self
+                .total_len
+                .is_some_and(|total_len| start.saturating_add(len) > total_len)
+    }
+
+    // Returns the bytes in `start..start + len`, if they are available;
+    // windows spanning multiple chunks are copied
+    pub fn window(&self, start: usize, len: usize) -> Option<Cow<'_, [u8]>> {
+        let res = self.window_prefix(start, len);
+
+        if res.len() == len {
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    // Returns the bytes available from `start`, up to `len` bytes
+    pub fn window_prefix(&self, start: usize, len: usize) -> Cow<'_, [u8]> {
+        let end = start.saturating_add(len);
+        let mut res = Vec::new();
+        let mut pos = start;
+
+        while pos < end {
+            let (offset, chunk) = match self.chunks.range(..=pos).next_back() {
+                Some((offset, chunk)) if offset + chunk.len() > pos => (offset, chunk),
+                _ => break,
+            };
+            let from = pos - offset;
+            let to = chunk.len().min(end - offset);
+
+            if pos == start && (to == end - offset || to == chunk.len()) {
+                // Check that the window does not continue in the next chunk
+                let next = offset + to;
+                if next == end || !self.chunks.contains_key(&next) {
+                    return Cow::Borrowed(&chunk[from..to]);
+                }
+            }
+
+            res.extend_from_slice(&chunk[from..to]);
+            pos = offset + to;
+        }
+
+        Cow::Owned(res)
+    }
+}
+
+/// Content sniffing for data received in multiple chunks.
+///
+/// A sniffer is created using [`SharedMimeInfo::sniffer`]; the chunks can
+/// be added in any order, and each magic rule is evaluated as soon as the
+/// data it looks at is available, so the MIME type can often be determined
+/// before all the data has arrived.
+///
+/// ```rust
+/// # let mime_db = xdg_mime::SharedMimeInfo::new();
+/// // let mime_db = ...
+/// let mut sniffer = mime_db.sniffer();
+/// sniffer.push_at(4, b"\x0d\x0a\x1a\x0a");
+/// sniffer.push_at(0, b"\x89PNG");
+/// let (mime_type, _) = sniffer.finish().unwrap();
+/// assert_eq!(mime_type, mime::IMAGE_PNG);
+/// ```
+///
+/// [`SharedMimeInfo::sniffer`]: struct.SharedMimeInfo.html#method.sniffer
+#[derive(Clone, Debug)]
+pub struct Sniffer<'a> {
+    entries: &'a [MagicEntry],
+    chunks: Chunks,
+    // The result of each magic entry, if it is already known
+    results: Vec<Option<bool>>,
+}
+
+impl<'a> Sniffer<'a> {
+    pub(crate) fn new(entries: &'a [MagicEntry]) -> Sniffer<'a> {
+        Sniffer {
+            entries,
+            chunks: Chunks::default(),
+            results: vec![None; entries.len()],
+        }
+    }
+
+    // Evaluates the pending entries that look at data after `offset`
+    fn update(&mut self, offset: usize) {
+        for (entry, result) in self.entries.iter().zip(self.results.iter_mut()) {
+            if result.is_some() {
+                continue;
+            }
+
+            // Skip the entries that need more data than there is
+            if self.chunks.is_absent(0, entry.min_data_size()) {
+                *result = Some(false);
+            } else if offset < entry.max_extents() || self.chunks.is_complete() {
+                *result = entry.matches_chunks(&self.chunks);
+            }
+        }
+    }
+
+    /// Adds a chunk of data, starting at `offset`.
+    ///
+    /// Chunks can overlap, as long as their contents agree. If the length
+    /// of the data was set using [`set_total_len`], the bytes after the
+    /// end of the data are ignored.
+    ///
+    /// [`set_total_len`]: #method.set_total_len
+    pub fn push_at(&mut self, offset: usize, bytes: &[u8]) {
+        if self.chunks.is_complete() {
+            return;
+        }
+
+        let bytes = match self.chunks.total_len {
+            Some(total_len) => &bytes[..bytes.len().min(total_len.saturating_sub(offset))],
+            None => bytes,
+        };
+
+        self.chunks.insert(offset, bytes);
+        self.update(offset);
+    }
+
+    /// Sets the length of the whole data, if it is known in advance, for
+    /// instance from the size of a file.
+    ///
+    /// The magic rules that look past the end of the data are known not
+    /// to match without waiting for [`finish`], so the result can be
+    /// decided sooner.
+    ///
+    /// [`finish`]: #method.finish
+    pub fn set_total_len(&mut self, total_len: usize) {
+        if self.chunks.is_complete() {
+            return;
+        }
+
+        self.chunks.total_len = Some(total_len);
+        self.update(0);
+    }
+
+    /// Checks whether the result of the sniffing is known, and cannot be
+    /// changed by adding more data.
+    pub fn is_decided(&self) -> bool {
+        for result in &self.results {
+            match result {
+                Some(true) => return true,
+                Some(false) => continue,
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Retrieves the MIME type of the data, and the priority of the match,
+    /// as soon as it is known.
+    ///
+    /// Returns `None` if no magic entry matches the data, or if adding
+    /// more data could still change the result; use [`is_decided`] to
+    /// tell the two cases apart.
+    ///
+    /// [`is_decided`]: #method.is_decided
+    pub fn mime_type(&self) -> Option<(Mime, MagicPriority)> {
+        for (entry, result) in self.entries.iter().zip(&self.results) {
+            match result {
+                Some(true) => {
+                    let priority = MagicPriority::saturating(entry.priority());
+                    return Some((entry.mime_type().clone(), priority));
+                }
+                Some(false) => continue,
+                None => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Retrieves the MIME types whose magic entries already match the
+    /// data, or could still match it once more data is added, in the order
+    /// in which the entries are checked.
+    ///
+    /// Each MIME type is listed only once.
+    pub fn possible_mime_types(&self) -> Vec<Mime> {
+        let mut res: Vec<Mime> = Vec::new();
+
+        for (entry, result) in self.entries.iter().zip(&self.results) {
+            if *result != Some(false) && !res.contains(entry.mime_type()) {
+                res.push(entry.mime_type().clone());
+            }
+        }
+
+        res
+    }
+
+    /// Marks the end of the data, and retrieves its MIME type, like
+    /// [`SharedMimeInfo::get_mime_type_for_data`].
+    ///
+    /// The data that is still missing is treated as absent, so the rules
+    /// looking at it do not match.
+    ///
+    /// [`SharedMimeInfo::get_mime_type_for_data`]: struct.SharedMimeInfo.html#method.get_mime_type_for_data
+    pub fn finish(mut self) -> Option<(Mime, MagicPriority)> {
+        if self.chunks.is_empty() {
+            return Some((APPLICATION_X_ZEROSIZE.clone(), MagicPriority::ZERO_SIZE));
+        }
+
+        self.chunks.complete = true;
+        self.update(0);
+
+        self.mime_type()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SharedMimeInfo;
+    use std::str::FromStr;
+
+    #[test]
+    fn window() {
+        let mut chunks = Chunks::default();
+        chunks.insert(4, b"4567");
+        chunks.insert(0, b"01");
+        assert_eq!(chunks.window(4, 2).as_deref(), Some(&b"45"[..]));
+        assert!(matches!(chunks.window(5, 3), Some(Cow::Borrowed(_))));
+        assert_eq!(chunks.window(1, 2), None);
+
+        chunks.insert(1, b"123");
+        assert_eq!(chunks.window(0, 8).as_deref(), Some(&b"01234567"[..]));
+        assert!(matches!(chunks.window(1, 4), Some(Cow::Owned(_))));
+        assert_eq!(chunks.window(6, 4), None);
+        assert_eq!(&*chunks.window_prefix(6, 4), b"67");
+        assert_eq!(&*chunks.window_prefix(2, 4), b"2345");
+        assert_eq!(&*chunks.window_prefix(9, 4), b"");
+    }
+
+    #[test]
+    fn sniff_out_of_order() {
+        let mime_db = SharedMimeInfo::new_for_directory("test_files/lenient");
+        let good = Mime::from_str("text/x-good").unwrap();
+
+        let mut sniffer = mime_db.sniffer();
+        sniffer.push_at(2, b"OD");
+        assert!(!sniffer.is_decided());
+        assert_eq!(sniffer.mime_type(), None);
+        assert_eq!(sniffer.possible_mime_types(), vec![good.clone()]);
+
+        sniffer.push_at(0, b"GO");
+        assert!(sniffer.is_decided());
+        assert_eq!(
+            sniffer.mime_type(),
+            Some((good.clone(), MagicPriority::DEFAULT))
+        );
+
+        let mut sniffer = mime_db.sniffer();
+        sniffer.push_at(0, b"BAD!");
+        assert!(sniffer.is_decided());
+        assert_eq!(sniffer.finish(), None);
+
+        let mut sniffer = mime_db.sniffer();
+        sniffer.push_at(1, b"OOD");
+        assert_eq!(sniffer.finish(), None);
+
+        // Data that is too short for the entry to match
+        let mut sniffer = mime_db.sniffer();
+        sniffer.push_at(2, b"OD");
+        sniffer.set_total_len(3);
+        assert!(sniffer.is_decided());
+        assert_eq!(sniffer.mime_type(), None);
+
+        let mut sniffer = mime_db.sniffer();
+        sniffer.set_total_len(4);
+        sniffer.push_at(2, b"ODIES");
+        assert!(!sniffer.is_decided());
+        sniffer.push_at(0, b"GO");
+        assert_eq!(
+            sniffer.mime_type(),
+            Some((good.clone(), MagicPriority::DEFAULT))
+        );
+
+        assert_eq!(
+            mime_db.sniffer().finish(),
+            Some((APPLICATION_X_ZEROSIZE.clone(), MagicPriority::ZERO_SIZE))
+        );
+    }
+
+    #[test]
+    fn sniff_like_contiguous_data() {
+        let mime_db = SharedMimeInfo::new_for_directory("test_files");
+
+        for data in [
+            &include_bytes!("../test_files/files/rust-logo.png")[..],
+            &include_bytes!("../test_files/files/rust-logo.svg")[..],
+            &include_bytes!("../test_files/files/gp")[..],
+        ] {
+            let mut sniffer = mime_db.sniffer();
+            let chunks: Vec<(usize, &[u8])> = data
+                .chunks(509)
+                .enumerate()
+                .map(|(i, chunk)| (i * 509, chunk))
+                .collect();
+            for (offset, chunk) in chunks.into_iter().rev() {
+                sniffer.push_at(offset, chunk);
+            }
+
+            assert_eq!(sniffer.finish(), mime_db.get_mime_type_for_data(data));
+        }
+    }
+}
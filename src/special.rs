@@ -0,0 +1,137 @@
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use mime::Mime;
+
+/// The media type of the special types describing file system objects,
+/// like directories and symbolic links.
+pub const INODE: &str = "inode";
+
+/// The media type of the special types describing the contents of a
+/// volume, like `x-content/image-dcf` for digital camera memory cards.
+pub const X_CONTENT: &str = "x-content";
+
+/// The MIME type of directories.
+pub static INODE_DIRECTORY: LazyLock<Mime> = LazyLock::new(|| "inode/directory".parse().unwrap());
+
+/// The MIME type of symbolic links.
+pub static INODE_SYMLINK: LazyLock<Mime> = LazyLock::new(|| "inode/symlink".parse().unwrap());
+
+/// The MIME type of character devices.
+pub static INODE_CHARDEVICE: LazyLock<Mime> = LazyLock::new(|| "inode/chardevice".parse().unwrap());
+
+/// The MIME type of block devices.
+pub static INODE_BLOCKDEVICE: LazyLock<Mime> =
+    LazyLock::new(|| "inode/blockdevice".parse().unwrap());
+
+/// The MIME type of named pipes.
+pub static INODE_FIFO: LazyLock<Mime> = LazyLock::new(|| "inode/fifo".parse().unwrap());
+
+/// The MIME type of sockets.
+pub static INODE_SOCKET: LazyLock<Mime> = LazyLock::new(|| "inode/socket".parse().unwrap());
+
+/// The MIME type of empty files.
+pub static APPLICATION_X_ZEROSIZE: LazyLock<Mime> =
+    LazyLock::new(|| "application/x-zerosize".parse().unwrap());
+
+/// The MIME type of executable files.
+pub static APPLICATION_X_EXECUTABLE: LazyLock<Mime> =
+    LazyLock::new(|| "application/x-executable".parse().unwrap());
+
+/// The MIME type of desktop launchers.
+pub static APPLICATION_X_DESKTOP: LazyLock<Mime> =
+    LazyLock::new(|| "application/x-desktop".parse().unwrap());
+
+// The MIME types defined by the mime crate, which can be cloned without
+// allocating, unlike the parsed ones
+static COMMON: &[Mime] = &[
+    mime::TEXT_PLAIN,
+    mime::TEXT_HTML,
+    mime::TEXT_CSS,
+    mime::TEXT_JAVASCRIPT,
+    mime::TEXT_XML,
+    mime::TEXT_EVENT_STREAM,
+    mime::TEXT_CSV,
+    mime::TEXT_TAB_SEPARATED_VALUES,
+    mime::TEXT_VCARD,
+    mime::IMAGE_JPEG,
+    mime::IMAGE_GIF,
+    mime::IMAGE_PNG,
+    mime::IMAGE_BMP,
+    mime::IMAGE_SVG,
+    mime::FONT_WOFF,
+    mime::FONT_WOFF2,
+    mime::APPLICATION_JSON,
+    mime::APPLICATION_JAVASCRIPT,
+    mime::APPLICATION_WWW_FORM_URLENCODED,
+    mime::APPLICATION_MSGPACK,
+    mime::APPLICATION_PDF,
+    mime::APPLICATION_OCTET_STREAM,
+    mime::MULTIPART_FORM_DATA,
+];
+
+// Parses a MIME type loaded from the database; the common types are
+// replaced with their constant, so that the results of the lookups are
+// cheap to clone
+pub fn parse_mime_type(s: &str) -> Option<Mime> {
+    let mime_type = Mime::from_str(s).ok()?;
+
+    Some(intern(mime_type))
+}
+
+pub fn intern(mime_type: Mime) -> Mime {
+    COMMON
+        .iter()
+        .find(|common| **common == mime_type)
+        .cloned()
+        .unwrap_or(mime_type)
+}
+
+// Whether the MIME type does not describe the contents of a file, but
+// rather a property of the file system object or of a volume
+pub fn is_special(mime_type: &Mime) -> bool {
+    mime_type.type_() == INODE
+        || mime_type.type_() == X_CONTENT
+        || *mime_type == *APPLICATION_X_ZEROSIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_types() {
+        assert_eq!(INODE_DIRECTORY.type_(), INODE);
+        assert_eq!(INODE_SYMLINK.essence_str(), "inode/symlink");
+        assert!(is_special(&INODE_DIRECTORY));
+        assert!(is_special(&INODE_CHARDEVICE));
+        assert_eq!(INODE_FIFO.essence_str(), "inode/fifo");
+        assert!(is_special(&APPLICATION_X_ZEROSIZE));
+        assert!(is_special(&"x-content/image-dcf".parse().unwrap()));
+        assert!(!is_special(&APPLICATION_X_EXECUTABLE));
+        assert!(!is_special(&mime::TEXT_PLAIN));
+    }
+
+    #[test]
+    fn parse_common_types() {
+        // Cloning the constants copies a pointer to a static string,
+        // while cloning parsed types copies the string
+        let is_cheap = |m: &Mime| m.clone().as_ref().as_ptr() == m.as_ref().as_ptr();
+
+        let png = parse_mime_type("image/png").unwrap();
+        assert_eq!(png, mime::IMAGE_PNG);
+        assert!(is_cheap(&png));
+        assert!(!is_cheap(&Mime::from_str("image/png").unwrap()));
+
+        assert_eq!(parse_mime_type("IMAGE/PNG"), Some(mime::IMAGE_PNG));
+        assert_eq!(
+            parse_mime_type("text/plain; charset=utf-8"),
+            Some(mime::TEXT_PLAIN_UTF_8)
+        );
+        assert_eq!(
+            parse_mime_type("inode/directory").as_ref(),
+            Some(&*INODE_DIRECTORY)
+        );
+        assert_eq!(parse_mime_type("text/x-{}"), None);
+    }
+}
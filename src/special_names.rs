@@ -0,0 +1,64 @@
+// A curated table of the files that file systems, file managers and sync
+// clients leave behind in shared directories, like `Thumbs.db` or
+// `.DS_Store`; older databases only know some of them, so the table is
+// only used for the names that the database does not match
+
+use std::str::FromStr;
+
+use mime::Mime;
+
+use crate::glob::Glob;
+use crate::parent::Subclass;
+
+// Below the default weight, so that the patterns added at runtime for the
+// same names take precedence
+pub const WEIGHT: i32 = 10;
+
+// The name, its MIME type, and the parent of the MIME types that the
+// database may not define
+const SPECIAL_NAMES: &[(&str, &str, Option<&str>)] = &[
+    (".directory", "application/x-desktop", None),
+    (".DS_Store", "application/x-apple-ds-store", None),
+    (".hidden", "text/plain", None),
+    (
+        "desktop.ini",
+        "application/x-windows-desktop-ini",
+        Some("text/plain"),
+    ),
+    (
+        "ehthumbs.db",
+        "application/x-windows-thumbnail-cache",
+        Some("application/x-ole-storage"),
+    ),
+    (
+        "Thumbs.db",
+        "application/x-windows-thumbnail-cache",
+        Some("application/x-ole-storage"),
+    ),
+];
+
+pub fn special_names() -> impl Iterator<Item = (&'static str, Glob, Option<Subclass>)> {
+    SPECIAL_NAMES.iter().map(|(name, mime_type, parent)| {
+        let mime_type = Mime::from_str(mime_type).unwrap();
+        let glob = Glob::new(&mime_type, name, WEIGHT, false);
+        let subclass =
+            parent.map(|parent| Subclass::new(&mime_type, &Mime::from_str(parent).unwrap()));
+
+        (*name, glob, subclass)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_special_names() {
+        let mut globs = crate::GlobMap::new();
+        for (name, glob, _) in special_names() {
+            assert!(Glob::checked(glob.mime_type(), name, WEIGHT, false).is_some());
+            globs.add_glob(glob);
+            assert!(globs.is_literal_name(&name.to_uppercase()));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use mime::Mime;
+
+use crate::priority::MagicPriority;
+use crate::SharedMimeInfo;
+
+/// A stack of [`SharedMimeInfo`] databases, queried as a single database.
+///
+/// Applications can keep their own MIME types in a separate database, and
+/// put it on top of the system database, without merging the two; the
+/// databases earlier in the stack take precedence:
+///
+///  - the MIME types for a file name come from the first database with a
+///    glob pattern matching it
+///  - the MIME type of some data comes from the magic entry with the
+///    highest priority, in any of the databases; if the priority is the
+///    same, the earlier database wins
+///  - aliases and icons come from the first database defining them
+///  - the parents of a MIME type are collected from all the databases
+///
+/// The [unknown type] is the one of the first database.
+///
+/// ```rust
+/// # let system_db = xdg_mime::SharedMimeInfo::new();
+/// # let app_db = xdg_mime::SharedMimeInfo::new_for_directory("test_files");
+/// // let system_db = xdg_mime::SharedMimeInfo::new();
+/// // let app_db = xdg_mime::SharedMimeInfo::new_for_directory("/usr/share/app");
+/// let stack = xdg_mime::DatabaseStack::new([&app_db, &system_db]);
+/// let mime_types = stack.get_mime_types_from_file_name("file.txt");
+/// assert_eq!(mime_types, vec![mime::TEXT_PLAIN]);
+/// ```
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+/// [unknown type]: struct.SharedMimeInfo.html#method.unknown_type
+#[derive(Clone)]
+pub struct DatabaseStack<'a> {
+    dbs: Vec<&'a SharedMimeInfo>,
+}
+
+impl<'a> DatabaseStack<'a> {
+    /// Creates a new stack from a list of databases, in order of
+    /// precedence.
+    pub fn new<I>(dbs: I) -> DatabaseStack<'a>
+    where
+        I: IntoIterator<Item = &'a SharedMimeInfo>,
+    {
+        DatabaseStack {
+            dbs: dbs.into_iter().collect(),
+        }
+    }
+
+    /// The databases in the stack, in order of precedence.
+    pub fn databases(&self) -> &[&'a SharedMimeInfo] {
+        &self.dbs
+    }
+
+    /// The MIME type returned when the MIME type of a file name, or of
+    /// some data, cannot be determined.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stack is empty.
+    pub fn unknown_type(&self) -> &Mime {
+        self.dbs[0].unknown_type()
+    }
+
+    fn is_unknown_type(&self, mime_type: &Mime) -> bool {
+        self.dbs.iter().any(|db| db.is_unknown_type(mime_type))
+    }
+
+    /// Retrieves the canonical MIME type for an alias, like
+    /// [`SharedMimeInfo::unalias_mime_type`].
+    ///
+    /// [`SharedMimeInfo::unalias_mime_type`]: struct.SharedMimeInfo.html#method.unalias_mime_type
+    pub fn unalias_mime_type(&self, mime_type: &Mime) -> Option<Mime> {
+        self.dbs
+            .iter()
+            .find_map(|db| db.unalias_mime_type(mime_type))
+    }
+
+    fn unaliased(&self, mime_type: &Mime) -> Mime {
+        self.unalias_mime_type(mime_type)
+            .unwrap_or_else(|| mime_type.clone())
+    }
+
+    /// Retrieves the matching MIME types for a file name, like
+    /// [`SharedMimeInfo::get_mime_types_from_file_name`], from the first
+    /// database with a matching glob pattern.
+    ///
+    /// [`SharedMimeInfo::get_mime_types_from_file_name`]: struct.SharedMimeInfo.html#method.get_mime_types_from_file_name
+    pub fn get_mime_types_from_file_name(&self, file_name: &str) -> Vec<Mime> {
+        for db in &self.dbs {
+            if let Some(mime_types) = db.globs.lookup_mime_type_for_file_name(file_name) {
+                let mut res: Vec<Mime> = Vec::with_capacity(mime_types.len());
+                for mime_type in mime_types {
+                    let mime_type = self.unaliased(&mime_type);
+                    if !res.contains(&mime_type) {
+                        res.push(mime_type);
+                    }
+                }
+
+                return res;
+            }
+        }
+
+        self.dbs
+            .first()
+            .map(|db| db.unknown_type().clone())
+            .into_iter()
+            .collect()
+    }
+
+    /// Retrieves the MIME type for some data, and the priority of the
+    /// match, like [`SharedMimeInfo::get_mime_type_for_data`].
+    ///
+    /// [`SharedMimeInfo::get_mime_type_for_data`]: struct.SharedMimeInfo.html#method.get_mime_type_for_data
+    pub fn get_mime_type_for_data(&self, data: &[u8]) -> Option<(Mime, MagicPriority)> {
+        let mut res: Option<(&Mime, MagicPriority)> = None;
+
+        for db in &self.dbs {
+            if let Some((mime_type, priority)) = db.sniff(data) {
+                if res.map_or(true, |(_, best)| priority > best) {
+                    res = Some((mime_type, priority));
+                }
+            }
+        }
+
+        res.map(|(mime_type, priority)| (self.unaliased(mime_type), priority))
+    }
+
+    /// Retrieves the MIME type of a file, using its name and its contents,
+    /// like the [`guess`] method of [`GuessBuilder`] does with a single
+    /// database.
+    ///
+    /// The name is used on its own if it matches a single MIME type;
+    /// otherwise, the data is used to pick one of the matches, or, if no
+    /// file name is given, on its own.
+    ///
+    /// [`guess`]: struct.GuessBuilder.html#method.guess
+    /// [`GuessBuilder`]: struct.GuessBuilder.html
+    pub fn guess_mime_type(&self, file_name: Option<&str>, data: &[u8]) -> Mime {
+        let name_mime_types = match file_name {
+            Some(file_name) => self.get_mime_types_from_file_name(file_name),
+            None => Vec::new(),
+        };
+
+        if name_mime_types.len() == 1 && !self.is_unknown_type(&name_mime_types[0]) {
+            return name_mime_types[0].clone();
+        }
+
+        let sniffed = if data.is_empty() {
+            None
+        } else {
+            self.get_mime_type_for_data(data)
+        };
+
+        if let Some((mime_type, priority)) = sniffed {
+            let certain = priority.is_certain()
+                || name_mime_types.is_empty()
+                || name_mime_types
+                    .iter()
+                    .any(|m| self.mime_type_subclass(&mime_type, m));
+
+            if certain {
+                return mime_type;
+            }
+        }
+
+        match name_mime_types.into_iter().next() {
+            Some(mime_type) => mime_type,
+            None => self.unknown_type().clone(),
+        }
+    }
+
+    /// Retrieves the MIME type and its parents, like
+    /// [`SharedMimeInfo::get_parents`], collecting the parents from all
+    /// the databases.
+    ///
+    /// [`SharedMimeInfo::get_parents`]: struct.SharedMimeInfo.html#method.get_parents
+    pub fn get_parents(&self, mime_type: &Mime) -> Option<Vec<Mime>> {
+        let unaliased = self.unalias_mime_type(mime_type)?;
+
+        let mut res = vec![unaliased.clone()];
+        for db in &self.dbs {
+            for parent in db.parents.lookup(&unaliased).into_iter().flatten() {
+                if !res.contains(parent) {
+                    res.push(parent.clone());
+                }
+            }
+        }
+
+        Some(res)
+    }
+
+    /// Checks whether two MIME types are equal, taking into account the
+    /// aliases in all the databases.
+    pub fn mime_type_equal(&self, mime_a: &Mime, mime_b: &Mime) -> bool {
+        self.unaliased(mime_a) == self.unaliased(mime_b)
+    }
+
+    /// Checks whether a MIME type is a subclass of another MIME type,
+    /// taking into account the subclasses defined in all the databases.
+    pub fn mime_type_subclass(&self, mime_type: &Mime, base: &Mime) -> bool {
+        let unaliased_mime = self.unaliased(mime_type);
+        let unaliased_base = self.unaliased(base);
+
+        if self
+            .dbs
+            .iter()
+            .any(|db| db.mime_type_subclass(&unaliased_mime, &unaliased_base))
+        {
+            return true;
+        }
+
+        // A parent defined in one database may have its own parents in
+        // another one
+        self.dbs.iter().any(|db| {
+            db.parents
+                .lookup(&unaliased_mime)
+                .into_iter()
+                .flatten()
+                .any(|p| self.mime_type_subclass(p, &unaliased_base))
+        })
+    }
+
+    /// Looks up the icons associated to a MIME type, like
+    /// [`SharedMimeInfo::lookup_icon_names`], using the first database
+    /// defining an icon for it.
+    ///
+    /// [`SharedMimeInfo::lookup_icon_names`]: struct.SharedMimeInfo.html#method.lookup_icon_names
+    pub fn lookup_icon_names(&self, mime_type: &Mime) -> Vec<String> {
+        let mut res = Vec::new();
+
+        if let Some(icon) = self
+            .dbs
+            .iter()
+            .find_map(|db| crate::icon::find_icon(&db.icons, mime_type))
+        {
+            res.push(icon);
+        }
+
+        res.push(mime_type.essence_str().replace('/', "-"));
+
+        if let Some(icon) = self.lookup_generic_icon_name(mime_type) {
+            res.push(icon);
+        }
+
+        res
+    }
+
+    /// Looks up the generic icon associated to a MIME type, like
+    /// [`SharedMimeInfo::lookup_generic_icon_name`], using the first
+    /// database defining a generic icon for it.
+    ///
+    /// [`SharedMimeInfo::lookup_generic_icon_name`]: struct.SharedMimeInfo.html#method.lookup_generic_icon_name
+    pub fn lookup_generic_icon_name(&self, mime_type: &Mime) -> Option<String> {
+        let res = self
+            .dbs
+            .iter()
+            .find_map(|db| crate::icon::find_icon(&db.generic_icons, mime_type))
+            .unwrap_or_else(|| format!("{}-x-generic", mime_type.type_()));
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{minimal_db, TempMimeDir};
+
+    #[test]
+    fn stacked_lookups() {
+        let system_db = minimal_db();
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "application/x-app-doc", "*.txt")
+            .glob(50, "application/x-app-data", "*.appdata")
+            .alias("application/x-old-app-doc", "application/x-app-doc")
+            .subclass("application/x-app-doc", "application/xml")
+            .magic(40, "application/x-app-data", 0, b"APPDATA")
+            .magic(40, "application/x-app-png", 0, b"\x89PNG")
+            .generic_icon("application/x-app-doc", "x-office-document");
+        let app_db = dir.load().unwrap();
+        let stack = DatabaseStack::new([&app_db, &system_db]);
+
+        let app_doc: Mime = "application/x-app-doc".parse().unwrap();
+        let app_data: Mime = "application/x-app-data".parse().unwrap();
+        let xml: Mime = "application/xml".parse().unwrap();
+
+        // The application database shadows the system globs
+        assert_eq!(
+            stack.get_mime_types_from_file_name("a.txt"),
+            vec![app_doc.clone()]
+        );
+        assert_eq!(
+            stack.get_mime_types_from_file_name("a.png"),
+            vec![mime::IMAGE_PNG]
+        );
+        assert_eq!(
+            stack.get_mime_types_from_file_name("a.unknown"),
+            vec![mime::APPLICATION_OCTET_STREAM]
+        );
+
+        // The system entry has a higher priority
+        assert_eq!(
+            stack.get_mime_type_for_data(b"\x89PNG\r\n").map(|(m, _)| m),
+            Some(mime::IMAGE_PNG)
+        );
+        assert_eq!(
+            stack.get_mime_type_for_data(b"APPDATA").map(|(m, _)| m),
+            Some(app_data.clone())
+        );
+
+        assert_eq!(
+            stack.unalias_mime_type(&"application/x-old-app-doc".parse().unwrap()),
+            Some(app_doc.clone())
+        );
+        assert_eq!(
+            stack.unalias_mime_type(&"text/xml".parse().unwrap()),
+            Some(xml.clone())
+        );
+
+        // The parents of the application type are in the system database
+        assert!(stack.mime_type_subclass(&app_doc, &xml));
+        assert!(stack.mime_type_subclass(&app_doc, &mime::TEXT_PLAIN));
+        assert!(!system_db.mime_type_subclass(&app_doc, &mime::TEXT_PLAIN));
+
+        assert_eq!(
+            stack.lookup_generic_icon_name(&app_doc),
+            Some("x-office-document".to_string())
+        );
+        assert_eq!(
+            stack.lookup_generic_icon_name(&mime::IMAGE_PNG),
+            Some("image-x-generic".to_string())
+        );
+
+        assert_eq!(stack.guess_mime_type(Some("a.appdata"), b""), app_data);
+        assert_eq!(stack.guess_mime_type(None, b"\x89PNG\r\n"), mime::IMAGE_PNG);
+        assert_eq!(
+            stack.guess_mime_type(None, b"\x00\x01"),
+            mime::APPLICATION_OCTET_STREAM
+        );
+    }
+}
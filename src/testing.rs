@@ -0,0 +1,363 @@
+//! Utilities to test code depending on the MIME database, without relying
+//! on the MIME data installed on the host system.
+//!
+//! The [`minimal_db`] function returns a small, fixed database, which is
+//! enough to exercise the usual lookups; for anything more specific, a
+//! [`TempMimeDir`] can be used to write a custom layout in a temporary
+//! directory, and load it.
+//!
+//! ```rust
+//! use xdg_mime::testing::TempMimeDir;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut dir = TempMimeDir::new()?;
+//! dir.glob(50, "text/x-foo", "*.foo")
+//!     .magic(50, "text/x-foo", 0, b"FOO!")
+//!     .subclass("text/x-foo", "text/plain");
+//! let mime_db = dir.load()?;
+//!
+//! let foo: mime::Mime = "text/x-foo".parse().unwrap();
+//! assert_eq!(mime_db.get_mime_types_from_file_name("a.foo"), vec![foo.clone()]);
+//! assert!(mime_db.mime_type_subclass(&foo, &mime::TEXT_PLAIN));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`minimal_db`]: fn.minimal_db.html
+//! [`TempMimeDir`]: struct.TempMimeDir.html
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::SharedMimeInfo;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A MIME data directory, written in a temporary location.
+///
+/// The contents of the directory are accumulated using the methods of
+/// this type, and written when calling [`write`] or [`load`]; the
+/// directory is removed when the value is dropped.
+///
+/// [`write`]: #method.write
+/// [`load`]: #method.load
+#[derive(Debug)]
+pub struct TempMimeDir {
+    path: PathBuf,
+    globs: Vec<String>,
+    aliases: Vec<String>,
+    subclasses: Vec<String>,
+    icons: Vec<String>,
+    generic_icons: Vec<String>,
+    magic: Vec<(u32, String, u32, Vec<u8>)>,
+}
+
+impl TempMimeDir {
+    /// Creates a new, empty directory under the temporary directory of
+    /// the system.
+    pub fn new() -> io::Result<TempMimeDir> {
+        let name = format!(
+            "xdg-mime-testing-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = env::temp_dir().join(name);
+
+        fs::create_dir_all(path.join("mime"))?;
+
+        Ok(TempMimeDir {
+            path,
+            globs: Vec::new(),
+            aliases: Vec::new(),
+            subclasses: Vec::new(),
+            icons: Vec::new(),
+            generic_icons: Vec::new(),
+            magic: Vec::new(),
+        })
+    }
+
+    /// The base directory, containing the `mime` directory.
+    ///
+    /// This is the directory to pass to [`SharedMimeInfo::new_for_directory`].
+    ///
+    /// [`SharedMimeInfo::new_for_directory`]: ../struct.SharedMimeInfo.html#method.new_for_directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The `mime` directory, containing the MIME data files.
+    pub fn mime_path(&self) -> PathBuf {
+        self.path.join("mime")
+    }
+
+    /// Adds a glob pattern for `mime_type` to the `globs2` file.
+    pub fn glob(&mut self, weight: i32, mime_type: &str, pattern: &str) -> &mut Self {
+        self.globs
+            .push(format!("{}:{}:{}", weight, mime_type, pattern));
+        self
+    }
+
+    /// Adds a case sensitive glob pattern for `mime_type` to the `globs2`
+    /// file.
+    pub fn case_sensitive_glob(
+        &mut self,
+        weight: i32,
+        mime_type: &str,
+        pattern: &str,
+    ) -> &mut Self {
+        self.globs
+            .push(format!("{}:{}:{}:cs", weight, mime_type, pattern));
+        self
+    }
+
+    /// Adds an alias to the `aliases` file.
+    pub fn alias(&mut self, alias: &str, mime_type: &str) -> &mut Self {
+        self.aliases.push(format!("{} {}", alias, mime_type));
+        self
+    }
+
+    /// Adds a parent type for `mime_type` to the `subclasses` file.
+    pub fn subclass(&mut self, mime_type: &str, parent: &str) -> &mut Self {
+        self.subclasses.push(format!("{} {}", mime_type, parent));
+        self
+    }
+
+    /// Adds an icon name for `mime_type` to the `icons` file.
+    pub fn icon(&mut self, mime_type: &str, icon_name: &str) -> &mut Self {
+        self.icons.push(format!("{}:{}", mime_type, icon_name));
+        self
+    }
+
+    /// Adds a generic icon name for `mime_type` to the `generic-icons`
+    /// file.
+    pub fn generic_icon(&mut self, mime_type: &str, icon_name: &str) -> &mut Self {
+        self.generic_icons
+            .push(format!("{}:{}", mime_type, icon_name));
+        self
+    }
+
+    /// Adds a magic entry for `mime_type` to the `magic` file, with a
+    /// single rule matching `value` at `offset`.
+    pub fn magic(
+        &mut self,
+        priority: u32,
+        mime_type: &str,
+        offset: u32,
+        value: &[u8],
+    ) -> &mut Self {
+        self.magic
+            .push((priority, mime_type.to_string(), offset, value.to_vec()));
+        self
+    }
+
+    /// Writes the MIME data files.
+    pub fn write(&self) -> io::Result<()> {
+        let mime_path = self.mime_path();
+
+        let lines = |lines: &[String]| -> String {
+            lines.iter().map(|line| format!("{}\n", line)).collect()
+        };
+
+        fs::write(mime_path.join("globs2"), lines(&self.globs))?;
+        fs::write(mime_path.join("aliases"), lines(&self.aliases))?;
+        fs::write(mime_path.join("subclasses"), lines(&self.subclasses))?;
+        fs::write(mime_path.join("icons"), lines(&self.icons))?;
+        fs::write(mime_path.join("generic-icons"), lines(&self.generic_icons))?;
+
+        // The entries are sorted by priority in the magic file
+        let mut entries: Vec<&(u32, String, u32, Vec<u8>)> = self.magic.iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+
+        let mut magic = b"MIME-Magic\0\n".to_vec();
+        for (priority, mime_type, offset, value) in entries {
+            let len = u16::try_from(value.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "magic value too long"))?;
+
+            magic.extend(format!("[{}:{}]\n>{}=", priority, mime_type, offset).bytes());
+            magic.extend(len.to_be_bytes());
+            magic.extend(value);
+            magic.push(b'\n');
+        }
+        fs::write(mime_path.join("magic"), magic)
+    }
+
+    /// Writes the MIME data files, and loads a database using only this
+    /// directory.
+    ///
+    /// Unlike [`SharedMimeInfo::new_for_directory`], the database does not
+    /// use the directories listed in the `XDG_MIME_OVERRIDE_DIRS` and
+    /// `XDG_MIME_EXTRA_DIRS` environment variables.
+    ///
+    /// [`SharedMimeInfo::new_for_directory`]: ../struct.SharedMimeInfo.html#method.new_for_directory
+    pub fn load(&self) -> io::Result<SharedMimeInfo> {
+        self.write()?;
+
+        Ok(SharedMimeInfo::builder()
+            .data_home(&self.path)
+            .data_dirs(Vec::<PathBuf>::new())
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false)
+            .build())
+    }
+}
+
+impl Drop for TempMimeDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Creates a small database, independent from the MIME data installed on
+/// the system.
+///
+/// The database contains:
+///
+///  - glob patterns for `text/plain` (`*.txt`), `text/x-csrc` (`*.c`),
+///    `text/x-c++src` (`*.C`, case sensitive), `text/x-makefile`
+///    (`makefile`), `image/png` (`*.png`), `application/xml` (`*.xml`),
+///    `image/svg+xml` (`*.svg`), and `application/x-shellscript` (`*.sh`)
+///  - magic rules for `image/png`, `image/svg+xml`, `application/xml` and
+///    `application/x-shellscript`
+///  - the `text/xml` alias of `application/xml`, and the `application/x-sh`
+///    alias of `application/x-shellscript`
+///  - `application/xml`, `application/x-shellscript`, `text/x-csrc` and
+///    `text/x-makefile` as subclasses of `text/plain`, `image/svg+xml` as
+///    a subclass of `application/xml`, and `text/x-c++src` as a subclass
+///    of `text/x-csrc`
+///  - the `text-x-generic` and `image-x-generic` generic icons
+///
+/// The database does not refer to any directory, so reloading it never
+/// changes it.
+///
+/// ```rust
+/// let mime_db = xdg_mime::testing::minimal_db();
+/// assert_eq!(
+///     mime_db.get_mime_types_from_file_name("notes.txt"),
+///     vec![mime::TEXT_PLAIN]
+/// );
+/// ```
+///
+/// # Panics
+///
+/// Panics if the data cannot be written to the temporary directory.
+pub fn minimal_db() -> SharedMimeInfo {
+    let mut dir = TempMimeDir::new().expect("cannot create the MIME directory");
+
+    dir.glob(50, "text/plain", "*.txt")
+        .glob(50, "text/x-csrc", "*.c")
+        .case_sensitive_glob(50, "text/x-c++src", "*.C")
+        .glob(50, "text/x-makefile", "makefile")
+        .glob(50, "image/png", "*.png")
+        .glob(50, "application/xml", "*.xml")
+        .glob(50, "image/svg+xml", "*.svg")
+        .glob(50, "application/x-shellscript", "*.sh")
+        .magic(50, "image/png", 0, b"\x89PNG")
+        .magic(80, "image/svg+xml", 0, b"<svg")
+        .magic(40, "application/xml", 0, b"<?xml")
+        .magic(50, "application/x-shellscript", 0, b"#!/bin/sh")
+        .alias("text/xml", "application/xml")
+        .alias("application/x-sh", "application/x-shellscript")
+        .subclass("application/xml", "text/plain")
+        .subclass("application/x-shellscript", "text/plain")
+        .subclass("text/x-csrc", "text/plain")
+        .subclass("text/x-makefile", "text/plain")
+        .subclass("image/svg+xml", "application/xml")
+        .subclass("text/x-c++src", "text/x-csrc")
+        .generic_icon("text/plain", "text-x-generic")
+        .generic_icon("image/png", "image-x-generic");
+
+    let mut db = dir.load().expect("cannot write the MIME directory");
+    db.mime_dirs.clear();
+
+    // Parse the magic entries before the directory is removed
+    db.magic.entries();
+
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mime::Mime;
+
+    #[test]
+    fn minimal_db_lookups() {
+        let mime_db = minimal_db();
+        let xml: Mime = "application/xml".parse().unwrap();
+        let c: Mime = "text/x-csrc".parse().unwrap();
+        let cpp: Mime = "text/x-c++src".parse().unwrap();
+
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.c"),
+            vec![c.clone()]
+        );
+        assert_eq!(
+            mime_db.first_mime_type_from_file_name("a.C"),
+            Some(cpp.clone())
+        );
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("Makefile"),
+            vec!["text/x-makefile".parse::<Mime>().unwrap()]
+        );
+        assert_eq!(
+            mime_db
+                .get_mime_type_for_data(b"\x89PNG\r\n\x1a\n")
+                .map(|(m, _)| m),
+            Some(mime::IMAGE_PNG)
+        );
+        assert_eq!(
+            mime_db.unalias_mime_type(&"text/xml".parse().unwrap()),
+            Some(xml.clone())
+        );
+        assert!(mime_db.mime_type_subclass(&mime::IMAGE_SVG, &mime::TEXT_PLAIN));
+        assert!(mime_db.mime_type_subclass(&cpp, &c));
+        assert_eq!(
+            mime_db.lookup_generic_icon_name(&mime::IMAGE_PNG),
+            Some("image-x-generic".to_string())
+        );
+
+        let guess = mime_db
+            .guess_mime_type()
+            .file_name("image.svg")
+            .data(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>")
+            .guess();
+        assert_eq!(guess.mime_type(), &mime::IMAGE_SVG);
+        assert!(mime_db.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn temp_mime_dir() {
+        let mut dir = TempMimeDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+        assert!(path.join("mime").is_dir());
+
+        dir.glob(50, "text/x-foo", "*.foo")
+            .alias("text/x-old-foo", "text/x-foo")
+            .icon("text/x-foo", "foo-icon");
+        let mime_db = SharedMimeInfo::new_for_directory(dir.path());
+        assert!(mime_db.glob_map().is_empty());
+
+        let mime_db = dir.load().unwrap();
+        let foo: Mime = "text/x-foo".parse().unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.FOO"),
+            vec![foo.clone()]
+        );
+        assert_eq!(
+            mime_db.unalias_mime_type(&"text/x-old-foo".parse().unwrap()),
+            Some(foo.clone())
+        );
+        assert_eq!(
+            mime_db.lookup_icon_names(&foo).first(),
+            Some(&"foo-icon".to_string())
+        );
+
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,99 @@
+/// The kind of contents of a stream, as determined by
+/// [`SharedMimeInfo::classify_stream`].
+///
+/// [`SharedMimeInfo::classify_stream`]: struct.SharedMimeInfo.html#method.classify_stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    /// Textual data, with the name of its character set, as used by the
+    /// `charset` parameter of `text/*` MIME types: `us-ascii`, `utf-8`,
+    /// `utf-16le`, `utf-16be`, or `unknown-8bit` for text using any other
+    /// 8 bit encoding.
+    Text(&'static str),
+    /// Binary data.
+    Binary,
+    /// No data at all.
+    Empty,
+}
+
+impl StreamKind {
+    /// Checks whether the stream contains text.
+    pub fn is_text(&self) -> bool {
+        matches!(self, StreamKind::Text(_))
+    }
+}
+
+// Byte order marks, and the character set they identify
+const BOMS: &[(&[u8], &str)] = &[
+    (b"\xef\xbb\xbf", "utf-8"),
+    (b"\xff\xfe", "utf-16le"),
+    (b"\xfe\xff", "utf-16be"),
+];
+
+// Classifies the data using only its contents; the data may be the
+// beginning of a longer stream, so an incomplete UTF-8 sequence at the
+// end is not taken into account
+pub fn classify(data: &[u8]) -> StreamKind {
+    if data.is_empty() {
+        return StreamKind::Empty;
+    }
+
+    for (bom, charset) in BOMS {
+        if data.starts_with(bom) {
+            return StreamKind::Text(charset);
+        }
+    }
+
+    // "Checking the first 128 bytes of the file for ASCII
+    // control characters is a good way to guess whether a
+    // file is binary or text."
+    // -- shared-mime-info, "Recommended checking order"
+    if data
+        .iter()
+        .any(|ch| ch.is_ascii_control() && !ch.is_ascii_whitespace())
+    {
+        return StreamKind::Binary;
+    }
+
+    if data.is_ascii() {
+        return StreamKind::Text("us-ascii");
+    }
+
+    match std::str::from_utf8(data) {
+        Ok(_) => StreamKind::Text("utf-8"),
+        Err(e) if e.error_len().is_none() => StreamKind::Text("utf-8"),
+        Err(_) => StreamKind::Text("unknown-8bit"),
+    }
+}
+
+// The check used when guessing the type of data that does not match any
+// magic rule
+pub fn looks_like_text(data: &[u8]) -> bool {
+    classify(&data[..data.len().min(128)]) != StreamKind::Binary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_data() {
+        assert_eq!(classify(b""), StreamKind::Empty);
+        assert_eq!(classify(b"hello\n"), StreamKind::Text("us-ascii"));
+        assert_eq!(classify("h\u{e9}llo".as_bytes()), StreamKind::Text("utf-8"));
+        assert_eq!(classify(b"h\xe9llo"), StreamKind::Text("unknown-8bit"));
+        assert_eq!(classify(b"hello\x00"), StreamKind::Binary);
+        assert_eq!(
+            classify(b"\xff\xfeh\x00i\x00"),
+            StreamKind::Text("utf-16le")
+        );
+        assert_eq!(
+            classify(b"\xfe\xff\x00h\x00i"),
+            StreamKind::Text("utf-16be")
+        );
+        assert_eq!(classify(b"\xef\xbb\xbfhi"), StreamKind::Text("utf-8"));
+
+        // A multibyte sequence cut at the end of the data
+        let data = "h\u{e9}".as_bytes();
+        assert_eq!(classify(&data[..2]), StreamKind::Text("utf-8"));
+    }
+}
@@ -0,0 +1,266 @@
+//! Access to the thumbnailers installed on the system.
+//!
+//! Thumbnailers are described by `.thumbnailer` files inside the
+//! `thumbnailers` sub-directory of the XDG data directories; each file
+//! lists the MIME types the thumbnailer can handle, and the command line
+//! used to run it.
+//!
+//! ```rust
+//! # use std::error::Error;
+//! # use std::str::FromStr;
+//! # use mime::Mime;
+//! use xdg_mime::thumbnailer::Thumbnailers;
+//! #
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! let thumbnailers = Thumbnailers::new();
+//! if let Some(thumbnailer) = thumbnailers.thumbnailer_for(&Mime::from_str("image/png")?) {
+//!     println!("{}", thumbnailer.exec());
+//! }
+//! #
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available if the `thumbnailer` feature is enabled,
+//! which it is by default.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use mime::Mime;
+
+use crate::{basedir, SharedMimeInfo};
+
+const GROUP: &str = "[Thumbnailer Entry]";
+
+/// A thumbnailer, as described by a `.thumbnailer` file.
+#[derive(Clone, PartialEq)]
+pub struct Thumbnailer {
+    path: PathBuf,
+    exec: String,
+    try_exec: Option<String>,
+    mime_types: Vec<Mime>,
+}
+
+impl fmt::Debug for Thumbnailer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Thumbnailer {}: {} {:?}",
+            self.path.display(),
+            self.exec,
+            self.mime_types
+        )
+    }
+}
+
+impl Thumbnailer {
+    fn from_string(path: &Path, s: &str) -> Option<Thumbnailer> {
+        let mut in_group = false;
+        let mut exec = None;
+        let mut try_exec = None;
+        let mut mime_types = Vec::new();
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_group = line == GROUP;
+                continue;
+            }
+
+            if !in_group {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => continue,
+            };
+
+            match key {
+                "Exec" => exec = Some(value.to_string()),
+                "TryExec" => try_exec = Some(value.to_string()),
+                "MimeType" => {
+                    mime_types = value
+                        .split(';')
+                        .filter_map(|s| Mime::from_str(s).ok())
+                        .collect();
+                }
+                _ => continue,
+            }
+        }
+
+        let exec = exec.filter(|s| !s.is_empty())?;
+        if mime_types.is_empty() {
+            return None;
+        }
+
+        Some(Thumbnailer {
+            path: path.to_path_buf(),
+            exec,
+            try_exec,
+            mime_types,
+        })
+    }
+
+    /// Loads a thumbnailer from a `.thumbnailer` file.
+    ///
+    /// Returns `None` if the file cannot be read, or if it does not
+    /// contain a valid thumbnailer entry.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Option<Thumbnailer> {
+        let contents = fs::read_to_string(&path).ok()?;
+
+        Thumbnailer::from_string(path.as_ref(), &contents)
+    }
+
+    /// The path of the `.thumbnailer` file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The command line used to run the thumbnailer.
+    pub fn exec(&self) -> &str {
+        &self.exec
+    }
+
+    /// The program that must be available for the thumbnailer to work,
+    /// if any.
+    pub fn try_exec(&self) -> Option<&str> {
+        self.try_exec.as_deref()
+    }
+
+    /// The MIME types handled by the thumbnailer.
+    pub fn mime_types(&self) -> &[Mime] {
+        &self.mime_types
+    }
+}
+
+/// The list of available thumbnailers.
+#[derive(Clone, Debug, Default)]
+pub struct Thumbnailers {
+    thumbnailers: Vec<Thumbnailer>,
+}
+
+impl Thumbnailers {
+    /// Loads all the thumbnailers under the [standard XDG base directories][xdg-basedir].
+    ///
+    /// [xdg-basedir]: http://standards.freedesktop.org/basedir-spec/basedir-spec-latest.html
+    pub fn new() -> Thumbnailers {
+        let dirs = basedir::data_home().into_iter().chain(basedir::data_dirs());
+
+        Thumbnailers::new_for_directories(dirs)
+    }
+
+    /// Loads all the thumbnailers under the data directories used to
+    /// populate the `db` database.
+    pub fn for_database(db: &SharedMimeInfo) -> Thumbnailers {
+        let dirs = db
+            .mime_dirs
+            .iter()
+            .filter_map(|dir| dir.path.parent().map(Path::to_path_buf));
+
+        Thumbnailers::new_for_directories(dirs)
+    }
+
+    /// Loads all the thumbnailers under the `thumbnailers` sub-directory
+    /// of each directory in `dirs`, in order of precedence.
+    ///
+    /// If two directories contain a file with the same name, only the one
+    /// in the directory that comes first is used.
+    pub fn new_for_directories<I, P>(dirs: I) -> Thumbnailers
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut seen = HashSet::new();
+        let mut thumbnailers = Vec::new();
+
+        for dir in dirs {
+            let entries = match fs::read_dir(dir.as_ref().join("thumbnailers")) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "thumbnailer"))
+                .collect();
+            paths.sort();
+
+            for path in paths {
+                let file_name = path.file_name().map(|s| s.to_os_string());
+                if !seen.insert(file_name) {
+                    continue;
+                }
+
+                if let Some(thumbnailer) = Thumbnailer::from_file(&path) {
+                    thumbnailers.push(thumbnailer);
+                }
+            }
+        }
+
+        Thumbnailers { thumbnailers }
+    }
+
+    /// Checks whether any thumbnailer can handle `mime_type`.
+    pub fn has_thumbnailer(&self, mime_type: &Mime) -> bool {
+        self.thumbnailer_for(mime_type).is_some()
+    }
+
+    /// Retrieves the first thumbnailer that can handle `mime_type`.
+    pub fn thumbnailer_for(&self, mime_type: &Mime) -> Option<&Thumbnailer> {
+        self.thumbnailers
+            .iter()
+            .find(|t| t.mime_types.iter().any(|m| m == mime_type))
+    }
+
+    /// Iterates over all the available thumbnailers.
+    pub fn iter(&self) -> impl Iterator<Item = &Thumbnailer> {
+        self.thumbnailers.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_thumbnailer() {
+        let t =
+            Thumbnailer::from_file("test_files/thumbnailers/gdk-pixbuf-thumbnailer.thumbnailer")
+                .unwrap();
+        assert_eq!(t.exec(), "gdk-pixbuf-thumbnailer -s %s %u %o");
+        assert_eq!(t.try_exec(), Some("gdk-pixbuf-thumbnailer"));
+        assert_eq!(t.mime_types().len(), 5);
+
+        assert!(Thumbnailer::from_file("test_files/thumbnailers/broken.thumbnailer").is_none());
+        assert!(Thumbnailer::from_string(
+            Path::new("foo"),
+            "[Desktop Entry]\nExec=foo\nMimeType=image/png;"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn thumbnailers_for_database() {
+        let db = SharedMimeInfo::new_for_directory("test_files");
+        let thumbnailers = Thumbnailers::for_database(&db);
+
+        assert_eq!(thumbnailers.iter().count(), 1);
+        assert!(thumbnailers.has_thumbnailer(&mime::IMAGE_PNG));
+        assert!(!thumbnailers.has_thumbnailer(&mime::TEXT_PLAIN));
+        assert_eq!(
+            thumbnailers
+                .thumbnailer_for(&mime::IMAGE_GIF)
+                .unwrap()
+                .path(),
+            Path::new("test_files/thumbnailers/gdk-pixbuf-thumbnailer.thumbnailer")
+        );
+    }
+}
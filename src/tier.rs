@@ -0,0 +1,20 @@
+/// Where an entry of the [`SharedMimeInfo`] database comes from.
+///
+/// Settings applications can use the tier of the entries for a MIME type
+/// to tell the associations customized by the user from the ones provided
+/// by the system, for instance to offer resetting them.
+///
+/// [`SharedMimeInfo`]: struct.SharedMimeInfo.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tier {
+    /// The entry was loaded from the `$XDG_DATA_HOME` directory, or from
+    /// the `$HOME/.mime.types` file, of the user.
+    User,
+    /// The entry was loaded from any other directory or file.
+    System,
+    /// The entry was added at runtime, for instance by an [`Overlay`].
+    ///
+    /// [`Overlay`]: struct.Overlay.html
+    Runtime,
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/// The time spent in each step of a guess, recorded when the [`timings`]
+/// method of [`GuessBuilder`] is enabled.
+///
+/// The time spent on the metadata and on the contents of the file is the
+/// I/O cost of the guess, while the time spent on the glob patterns and
+/// on the magic entries is its matching cost. Steps that were not needed
+/// for the guess have a zero duration.
+///
+/// ```rust
+/// # let mime_db = xdg_mime::SharedMimeInfo::new();
+/// // let mime_db = ...
+/// let guess = mime_db
+///     .guess_mime_type()
+///     .path("src/lib.rs")
+///     .timings(true)
+///     .guess();
+/// let timings = guess.timings().unwrap();
+/// assert_eq!(timings.total(), timings.io() + timings.matching());
+/// ```
+///
+/// [`timings`]: struct.GuessBuilder.html#method.timings
+/// [`GuessBuilder`]: struct.GuessBuilder.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GuessTimings {
+    pub(crate) metadata: Duration,
+    pub(crate) read: Duration,
+    pub(crate) glob: Duration,
+    pub(crate) magic: Duration,
+}
+
+impl GuessTimings {
+    /// The time spent retrieving the metadata of the file.
+    pub fn metadata(&self) -> Duration {
+        self.metadata
+    }
+
+    /// The time spent opening and reading the contents of the file.
+    ///
+    /// If the file was read with a [deadline] that expired, this is zero.
+    ///
+    /// [deadline]: struct.GuessBuilder.html#method.deadline
+    pub fn read(&self) -> Duration {
+        self.read
+    }
+
+    /// The time spent matching the file name against the glob patterns.
+    pub fn glob(&self) -> Duration {
+        self.glob
+    }
+
+    /// The time spent matching the data against the magic entries.
+    pub fn magic(&self) -> Duration {
+        self.magic
+    }
+
+    /// The time spent on the metadata and the contents of the file.
+    pub fn io(&self) -> Duration {
+        self.metadata + self.read
+    }
+
+    /// The time spent on the glob patterns and the magic entries.
+    pub fn matching(&self) -> Duration {
+        self.glob + self.magic
+    }
+
+    /// The time spent in all the steps.
+    pub fn total(&self) -> Duration {
+        self.io() + self.matching()
+    }
+}
+
+// Runs f, adding the time it took to slot, if there is one
+pub fn timed<T>(slot: Option<&mut Duration>, f: impl FnOnce() -> T) -> T {
+    match slot {
+        Some(slot) => {
+            let start = Instant::now();
+            let res = f();
+            *slot += start.elapsed();
+            res
+        }
+        None => f(),
+    }
+}
@@ -0,0 +1,427 @@
+// The treemagic file, used to recognize the contents of mounted volumes
+// and directories, like the `DCIM` directory of digital cameras
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use mime::Mime;
+
+use crate::diagnostics::ParseContext;
+use crate::special;
+
+// The type of file a tree match looks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileKind {
+    File,
+    Directory,
+    Link,
+    Any,
+}
+
+impl FileKind {
+    pub fn from_name(name: &str) -> Option<FileKind> {
+        match name {
+            "file" => Some(FileKind::File),
+            "directory" => Some(FileKind::Directory),
+            "link" => Some(FileKind::Link),
+            "any" => Some(FileKind::Any),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Link => "link",
+            FileKind::Any => "any",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct TreeMatch {
+    pub indent: u32,
+    // Relative to the root of the tree, with `/` as the separator
+    pub path: String,
+    pub kind: FileKind,
+    pub executable: bool,
+    pub match_case: bool,
+    pub non_empty: bool,
+    pub on_disc: bool,
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::option_mime"))]
+    pub mime_type: Option<Mime>,
+}
+
+// Resolves each component of the path, ignoring the case unless
+// `match_case` is set
+fn resolve(root: &Path, path: &str, match_case: bool) -> Option<PathBuf> {
+    let mut res = root.to_path_buf();
+
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        let exact = res.join(component);
+        if match_case || fs::symlink_metadata(&exact).is_ok() {
+            res = exact;
+            continue;
+        }
+
+        let component = component.to_lowercase();
+        let entry = fs::read_dir(&res)
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == component)?;
+        res = entry.path();
+    }
+
+    Some(res)
+}
+
+impl TreeMatch {
+    // The `on-disc` flag cannot be checked without knowing the volume the
+    // tree is mounted from, so it is ignored
+    fn matches<F>(&self, root: &Path, is_a: &F) -> bool
+    where
+        F: Fn(&Path, &Mime) -> bool,
+    {
+        let path = match resolve(root, &self.path, self.match_case) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        let link_metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        if self.kind == FileKind::Link && !link_metadata.file_type().is_symlink() {
+            return false;
+        }
+
+        // Other than links, the targets of symbolic links are matched
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => return self.kind == FileKind::Link && !self.non_empty,
+        };
+        let kind_matches = match self.kind {
+            FileKind::File => metadata.is_file(),
+            FileKind::Directory => metadata.is_dir(),
+            FileKind::Link | FileKind::Any => true,
+        };
+        if !kind_matches {
+            return false;
+        }
+
+        #[cfg(unix)]
+        if self.executable {
+            use std::os::unix::fs::PermissionsExt;
+
+            if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+                return false;
+            }
+        }
+
+        if self.non_empty {
+            let is_empty = if metadata.is_dir() {
+                fs::read_dir(&path).map_or(true, |mut entries| entries.next().is_none())
+            } else {
+                metadata.len() == 0
+            };
+            if is_empty {
+                return false;
+            }
+        }
+
+        match &self.mime_type {
+            Some(mime_type) => is_a(&path, mime_type),
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "snapshot", derive(serde::Serialize, serde::Deserialize))]
+pub struct TreeMagicEntry {
+    #[cfg_attr(feature = "snapshot", serde(with = "crate::binary::mime_type"))]
+    mime_type: Mime,
+    priority: u32,
+    matches: Vec<TreeMatch>,
+}
+
+// Each match in the slice is followed by its children; a match with
+// children only matches if one of its children matches as well. The
+// paths of the children are relative to the root of the tree too
+fn matches_siblings<F>(matches: &[TreeMatch], root: &Path, is_a: &F) -> bool
+where
+    F: Fn(&Path, &Mime) -> bool,
+{
+    let mut idx = 0;
+
+    while idx < matches.len() {
+        let current = &matches[idx];
+        let children = matches[idx + 1..]
+            .iter()
+            .take_while(|m| m.indent > current.indent)
+            .count();
+        let end = idx + 1 + children;
+
+        if current.matches(root, is_a)
+            && (children == 0 || matches_siblings(&matches[idx + 1..end], root, is_a))
+        {
+            return true;
+        }
+
+        idx = end;
+    }
+
+    false
+}
+
+impl TreeMagicEntry {
+    pub fn new(mime_type: &Mime, priority: u32, matches: Vec<TreeMatch>) -> TreeMagicEntry {
+        TreeMagicEntry {
+            mime_type: special::intern(mime_type.clone()),
+            priority,
+            matches,
+        }
+    }
+
+    pub fn mime_type(&self) -> &Mime {
+        &self.mime_type
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    pub fn matches<F>(&self, root: &Path, is_a: &F) -> bool
+    where
+        F: Fn(&Path, &Mime) -> bool,
+    {
+        matches_siblings(&self.matches, root, is_a)
+    }
+}
+
+// Parses a match like `1>"DCIM/100"=directory,non-empty`; the flags that
+// are not recognized are ignored, for extensibility
+fn parse_match(line: &str) -> Option<TreeMatch> {
+    let (indent, rest) = line.split_once('>')?;
+    let indent = match indent {
+        "" => 0,
+        indent => indent.parse().ok()?,
+    };
+
+    let (path, rest) = rest.strip_prefix('"')?.split_once("\"=")?;
+    let mut fields = rest.split(',');
+    let kind = FileKind::from_name(fields.next()?)?;
+
+    let mut res = TreeMatch {
+        indent,
+        path: path.to_string(),
+        kind,
+        executable: false,
+        match_case: false,
+        non_empty: false,
+        on_disc: false,
+        mime_type: None,
+    };
+
+    for field in fields {
+        match field {
+            "executable" => res.executable = true,
+            "match-case" => res.match_case = true,
+            "non-empty" => res.non_empty = true,
+            "on-disc" => res.on_disc = true,
+            field if field.contains('/') => res.mime_type = Some(special::parse_mime_type(field)?),
+            _ => {}
+        }
+    }
+
+    Some(res)
+}
+
+// An entry being read; entries with an invalid MIME type, or with an
+// invalid match, are skipped as a whole
+struct PendingEntry {
+    mime_type: Option<Mime>,
+    priority: u32,
+    matches: Option<Vec<TreeMatch>>,
+}
+
+impl PendingEntry {
+    fn finish(self) -> Option<TreeMagicEntry> {
+        let matches = self.matches.filter(|matches| !matches.is_empty())?;
+
+        Some(TreeMagicEntry::new(
+            &self.mime_type?,
+            self.priority,
+            matches,
+        ))
+    }
+}
+
+fn parse_header(line: &str) -> Option<(u32, &str)> {
+    let (priority, mime_type) = line.strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+
+    Some((priority.parse().ok()?, mime_type))
+}
+
+pub fn read_treemagic_from_file<P: AsRef<Path>>(
+    file_name: P,
+    ctx: &mut ParseContext,
+) -> Vec<TreeMagicEntry> {
+    let buf = match fs::read(&file_name) {
+        Ok(buf) => buf,
+        Err(_) => return Vec::new(),
+    };
+    let contents = match buf.strip_prefix(b"MIME-TreeMagic\0\n") {
+        Some(contents) => String::from_utf8_lossy(contents),
+        None => return Vec::new(),
+    };
+
+    let mut res = Vec::new();
+    let mut current: Option<PendingEntry> = None;
+
+    for (idx, line) in contents.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some((priority, mime_type)) = parse_header(line) {
+            res.extend(current.take().and_then(PendingEntry::finish));
+
+            // The first line is the signature of the file
+            ctx.check_mime_types(file_name.as_ref(), Some(idx + 2), line, [mime_type]);
+            current = Some(PendingEntry {
+                mime_type: special::parse_mime_type(mime_type),
+                priority,
+                matches: Some(Vec::new()),
+            });
+            continue;
+        }
+
+        if let Some(entry) = current.as_mut() {
+            match (entry.matches.as_mut(), parse_match(line)) {
+                (Some(matches), Some(m)) => matches.push(m),
+                _ => entry.matches = None,
+            }
+        }
+    }
+    res.extend(current.and_then(PendingEntry::finish));
+
+    // Entries with a higher priority come first
+    res.sort_by_key(|e| std::cmp::Reverse(e.priority));
+
+    res
+}
+
+pub fn read_treemagic_from_dir<P: AsRef<Path>>(
+    dir: P,
+    ctx: &mut ParseContext,
+) -> Vec<TreeMagicEntry> {
+    read_treemagic_from_file(dir.as_ref().join("treemagic"), ctx)
+}
+
+pub fn write_treemagic<W: Write>(entries: &[TreeMagicEntry], w: &mut W) -> io::Result<()> {
+    w.write_all(b"MIME-TreeMagic\0\n")?;
+
+    for entry in entries {
+        writeln!(w, "[{}:{}]", entry.priority, entry.mime_type)?;
+
+        for m in &entry.matches {
+            if m.indent > 0 {
+                write!(w, "{}", m.indent)?;
+            }
+            write!(w, ">\"{}\"={}", m.path, m.kind.name())?;
+            for (flag, set) in [
+                ("executable", m.executable),
+                ("match-case", m.match_case),
+                ("non-empty", m.non_empty),
+                ("on-disc", m.on_disc),
+            ] {
+                if set {
+                    write!(w, ",{}", flag)?;
+                }
+            }
+            if let Some(mime_type) = &m.mime_type {
+                write!(w, ",{}", mime_type)?;
+            }
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::testing::TempMimeDir;
+
+    #[test]
+    fn read_test_treemagic() {
+        let mut ctx = ParseContext::new(false);
+        let entries = read_treemagic_from_dir("test_files/mime", &mut ctx);
+        assert!(ctx.diagnostics.is_empty());
+
+        let mime_types: Vec<String> = entries.iter().map(|e| e.mime_type().to_string()).collect();
+        assert_eq!(
+            mime_types,
+            vec![
+                "x-content/unix-software",
+                "x-content/image-dcf",
+                "x-content/software"
+            ]
+        );
+        assert_eq!(
+            entries[2].matches[1],
+            TreeMatch {
+                indent: 1,
+                path: "setup/start".to_string(),
+                kind: FileKind::File,
+                executable: false,
+                match_case: false,
+                non_empty: false,
+                on_disc: false,
+                mime_type: Some(mime::TEXT_PLAIN),
+            }
+        );
+
+        let mut out = Vec::new();
+        write_treemagic(&entries, &mut out).unwrap();
+        assert_eq!(out, fs::read("test_files/mime/treemagic").unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_entries() {
+        let dir = TempMimeDir::new().unwrap();
+        let path = dir.mime_path().join("treemagic");
+        fs::write(
+            &path,
+            "MIME-TreeMagic\0\n\
+             [50:not a type]\n\
+             >\"a\"=file\n\
+             [50:x-content/bad-match]\n\
+             >\"a\"=socket\n\
+             [50:x-content/good]\n\
+             >\"a\"=any,on-disc,unknown-flag\n",
+        )
+        .unwrap();
+
+        let mut ctx = ParseContext::new(false);
+        let entries = read_treemagic_from_file(&path, &mut ctx);
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].matches[0].on_disc);
+        assert_eq!(
+            ctx.diagnostics,
+            vec![Diagnostic::InvalidMimeType {
+                path,
+                line: Some(2),
+                mime_type: "not a type".to_string(),
+                entry: "[50:not a type]".to_string(),
+            }]
+        );
+    }
+}
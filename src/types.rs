@@ -0,0 +1,108 @@
+// The types file, listing every MIME type defined in a directory
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use mime::Mime;
+
+use crate::diagnostics::ParseContext;
+use crate::special;
+
+pub fn read_types_from_file<P: AsRef<Path>>(file_name: P, ctx: &mut ParseContext) -> Vec<Mime> {
+    let mut res = Vec::new();
+
+    let f = match File::open(&file_name) {
+        Ok(v) => v,
+        Err(_) => return res,
+    };
+
+    let file = BufReader::new(&f);
+    for (n, line) in file.lines().enumerate() {
+        let line = match line {
+            Ok(line) => ctx.normalize_line(&line, ' '),
+            Err(_) => return res,
+        };
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match special::parse_mime_type(&line) {
+            Some(mime_type) => res.push(mime_type),
+            None => ctx.check_mime_types(file_name.as_ref(), Some(n + 1), &line, [line.as_str()]),
+        }
+    }
+
+    res
+}
+
+pub fn read_types_from_dir<P: AsRef<Path>>(dir: P, ctx: &mut ParseContext) -> Vec<Mime> {
+    let mut types_file = PathBuf::new();
+    types_file.push(dir);
+    types_file.push("types");
+
+    read_types_from_file(types_file, ctx)
+}
+
+// Sorts the MIME types by name, and removes the duplicates
+pub fn sort_types(types: &mut Vec<Mime>) {
+    types.sort_by(|a, b| a.essence_str().cmp(b.essence_str()));
+    types.dedup();
+}
+
+pub fn write_types<W: Write>(types: &[Mime], w: &mut W) -> io::Result<()> {
+    for mime_type in types {
+        writeln!(w, "{}", mime_type)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::testing::TempMimeDir;
+
+    #[test]
+    fn read_test_types() {
+        let mut ctx = ParseContext::new(false);
+        let mut types = read_types_from_dir("test_files/mime", &mut ctx);
+        assert!(ctx.diagnostics.is_empty());
+        assert_eq!(types.len(), 790);
+        assert!(types.contains(&mime::IMAGE_SVG));
+
+        // MIME types are compared in lowercase
+        let contents = std::fs::read_to_string("test_files/mime/types").unwrap();
+        let mut expected: Vec<String> = contents.lines().map(str::to_lowercase).collect();
+        expected.sort();
+
+        sort_types(&mut types);
+        let mut out = Vec::new();
+        write_types(&types, &mut out).unwrap();
+        let written: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn reject_invalid_types() {
+        let dir = TempMimeDir::new().unwrap();
+        let path = dir.mime_path().join("types");
+        std::fs::write(&path, "text/plain\nnot a type\n\ntext/plain\n").unwrap();
+
+        let mut ctx = ParseContext::new(false);
+        let mut types = read_types_from_file(&path, &mut ctx);
+        sort_types(&mut types);
+        assert_eq!(types, vec![mime::TEXT_PLAIN]);
+        assert_eq!(
+            ctx.diagnostics,
+            vec![Diagnostic::InvalidMimeType {
+                path,
+                line: Some(2),
+                mime_type: "not a type".to_string(),
+                entry: "not a type".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,442 @@
+// Watches the directories of a SyncSharedMimeInfo using the notify crate,
+// so that the database is reloaded, or flagged as stale, as soon as they
+// change
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::SyncSharedMimeInfo;
+
+// How often the thread checks whether it should stop, and whether the
+// pending changes are due
+const IDLE_TIMEOUT: Duration = Duration::from_millis(250);
+const PENDING_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// What a [`Watcher`] does when the directories of the database change.
+///
+/// [`Watcher`]: struct.Watcher.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WatchMode {
+    /// The database is reloaded once the changes are due, according to
+    /// its [debounce controls].
+    ///
+    /// [debounce controls]: struct.SyncSharedMimeInfo.html#method.set_debounce
+    #[default]
+    Reload,
+    /// The database is only flagged as [stale], and the application
+    /// decides when to reload it.
+    ///
+    /// [stale]: struct.Watcher.html#method.is_stale
+    Flag,
+}
+
+/// Watches the directories used to populate a [`SyncSharedMimeInfo`]
+/// database, using the file system notifications of the platform, like
+/// inotify on Linux, so that daemons do not have to poll them.
+///
+/// The directories are watched from a separate thread, which stops when
+/// the watcher is dropped. Directories that did not exist when the
+/// database was loaded are watched as well, and picked up once they are
+/// created.
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use xdg_mime::{SyncSharedMimeInfo, WatchMode, Watcher};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mime_db = Arc::new(SyncSharedMimeInfo::new());
+/// let watcher = Watcher::new(Arc::clone(&mime_db), WatchMode::Reload)?;
+///
+/// // The database is now reloaded when the directories change
+/// # drop(watcher);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SyncSharedMimeInfo`]: struct.SyncSharedMimeInfo.html
+pub struct Watcher {
+    mime_db: Arc<SyncSharedMimeInfo>,
+    stale: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Starts watching the directories of `mime_db`.
+    ///
+    /// Fails if the file system notifications are not available, or if
+    /// none of the directories, nor their parents, can be watched.
+    pub fn new(mime_db: Arc<SyncSharedMimeInfo>, mode: WatchMode) -> io::Result<Watcher> {
+        let snapshot = mime_db.snapshot();
+        let dirs = snapshot
+            .mime_dirs
+            .iter()
+            .map(|dir| dir.path.clone())
+            .collect();
+        let files = snapshot
+            .mime_types_files
+            .iter()
+            .map(|file| file.path.clone())
+            .collect();
+
+        let mut watches = Watches::new(dirs, files)?;
+        if !watches.watch() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no directory of the database can be watched",
+            ));
+        }
+
+        let stale = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let mime_db = Arc::clone(&mime_db);
+            let stale = Arc::clone(&stale);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("xdg-mime-watcher".to_string())
+                .spawn(move || watches.run(&mime_db, mode, &stale, &stop))?
+        };
+
+        Ok(Watcher {
+            mime_db,
+            stale,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Whether the directories changed since the database was last
+    /// reloaded by the watcher.
+    ///
+    /// With [`WatchMode::Reload`], the flag is cleared once the changes
+    /// are due, and the database was reloaded.
+    ///
+    /// [`WatchMode::Reload`]: enum.WatchMode.html#variant.Reload
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Acquire)
+    }
+
+    /// Reloads the database, like [`SyncSharedMimeInfo::reload`], and
+    /// clears the [stale] flag.
+    ///
+    /// [`SyncSharedMimeInfo::reload`]: struct.SyncSharedMimeInfo.html#method.reload
+    /// [stale]: #method.is_stale
+    pub fn reload(&self) -> bool {
+        self.stale.store(false, Ordering::Release);
+        self.mime_db.reload()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn to_io_error(e: notify::Error) -> io::Error {
+    match e.kind {
+        notify::ErrorKind::Io(e) => e,
+        _ => io::Error::other(e),
+    }
+}
+
+// The directories are watched for the changes of their contents, and the
+// parent of each directory or file for it being created or removed; the
+// watches of the parents also report the changes of the files themselves
+struct Watches {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Watches {
+    fn new(dirs: Vec<PathBuf>, files: Vec<PathBuf>) -> io::Result<Watches> {
+        let (tx, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(tx).map_err(to_io_error)?;
+
+        Ok(Watches {
+            watcher,
+            events,
+            dirs,
+            files,
+        })
+    }
+
+    // Watches the directories that exist, and the parents of every
+    // directory and file; returns whether anything is watched. This is
+    // done again after each change, since a directory that is created,
+    // or removed and created again, needs a new watch
+    fn watch(&mut self) -> bool {
+        let mut watched = false;
+
+        for path in self.dirs.iter().chain(&self.files) {
+            if let Some(parent) = path.parent() {
+                watched |= self
+                    .watcher
+                    .watch(parent, RecursiveMode::NonRecursive)
+                    .is_ok();
+            }
+        }
+
+        for dir in &self.dirs {
+            if dir.is_dir() {
+                watched |= self.watcher.watch(dir, RecursiveMode::NonRecursive).is_ok();
+            }
+        }
+
+        watched
+    }
+
+    // Whether the event is about the watched directories and files, or
+    // the entries of the directories; opening or reading them is not a
+    // change
+    fn is_relevant(&self, event: &Event) -> bool {
+        match event.kind {
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => {}
+            EventKind::Access(_) => return false,
+            _ => {}
+        }
+
+        event.paths.iter().any(|path| {
+            self.files.iter().any(|file| path == file)
+                || self
+                    .dirs
+                    .iter()
+                    .any(|dir| path == dir || path.parent() == Some(dir.as_path()))
+        })
+    }
+
+    // Waits for the first event, and takes the pending ones; returns
+    // whether any of them is relevant
+    fn read_events(&mut self, timeout: Duration) -> bool {
+        let first = match self.events.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return false,
+            // The watcher is gone; keep checking whether to stop
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(timeout);
+                return false;
+            }
+        };
+
+        let mut changed = false;
+        for event in std::iter::once(first).chain(self.events.try_iter()) {
+            changed |= match event {
+                Ok(event) => self.is_relevant(&event),
+                // Some events may have been lost, like when the queue of
+                // the kernel overflows
+                Err(_) => true,
+            };
+        }
+
+        if changed {
+            self.watch();
+        }
+
+        changed
+    }
+
+    // Waits for events, until `stop` is set
+    fn run(
+        &mut self,
+        mime_db: &SyncSharedMimeInfo,
+        mode: WatchMode,
+        stale: &AtomicBool,
+        stop: &AtomicBool,
+    ) {
+        while !stop.load(Ordering::Acquire) {
+            let timeout = if mime_db.has_pending_changes() {
+                PENDING_TIMEOUT
+            } else {
+                IDLE_TIMEOUT
+            };
+
+            let changed = self.read_events(timeout);
+            if changed {
+                stale.store(true, Ordering::Release);
+            }
+
+            // The debounce controls of the database decide when the
+            // changes are due
+            if mode == WatchMode::Reload
+                && (changed || mime_db.has_pending_changes())
+                && mime_db.poll()
+            {
+                stale.store(false, Ordering::Release);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::Instant;
+
+    use mime::Mime;
+
+    use crate::testing::TempMimeDir;
+    use crate::{Coalescing, Debounce, SharedMimeInfo, SharedMimeInfoBuilder};
+
+    fn wait_for<F: Fn() -> bool>(check: F) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if check() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        false
+    }
+
+    // Moves the modification time forward, so that it differs from the
+    // one set by the next change, even within the resolution of the clock
+    // of the file system
+    fn touch(path: &Path) {
+        let mtime = fs::metadata(path).unwrap().modified().unwrap();
+        fs::File::open(path)
+            .unwrap()
+            .set_modified(mtime + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    // Creates the mime directory of `dir` at once, with a globs2 file
+    fn create_mime_dir(dir: &TempMimeDir, globs: &str) {
+        let new_dir = dir.path().join("mime.new");
+        fs::create_dir(&new_dir).unwrap();
+        fs::write(new_dir.join("globs2"), globs).unwrap();
+        touch(&new_dir);
+        fs::rename(&new_dir, dir.mime_path()).unwrap();
+    }
+
+    // Replaces the globs2 file of `dir`, like update-mime-database does
+    fn replace_globs(dir: &TempMimeDir, globs: &str) {
+        fs::write(dir.mime_path().join("globs2.new"), globs).unwrap();
+        fs::rename(
+            dir.mime_path().join("globs2.new"),
+            dir.mime_path().join("globs2"),
+        )
+        .unwrap();
+    }
+
+    fn builder(dir: &TempMimeDir) -> SharedMimeInfoBuilder {
+        let mut builder = SharedMimeInfo::builder();
+        builder
+            .data_home(dir.path())
+            .data_dirs(Vec::<PathBuf>::new())
+            .override_dirs(Vec::<PathBuf>::new())
+            .extra_dirs(Vec::<PathBuf>::new())
+            .detect_sandbox(false);
+        builder
+    }
+
+    #[test]
+    fn watch_directories() {
+        let dir = TempMimeDir::new().unwrap();
+        fs::remove_dir(dir.mime_path()).unwrap();
+        let db = builder(&dir).build();
+        let mime_db = Arc::new(SyncSharedMimeInfo::from(db));
+        mime_db.set_debounce(Debounce::new(Duration::ZERO, Coalescing::Quiet));
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+
+        // The directory is created after the database was loaded
+        let watcher = Watcher::new(Arc::clone(&mime_db), WatchMode::Reload).unwrap();
+        create_mime_dir(&dir, "50:text/x-foo:*.foo\n");
+        assert!(wait_for(
+            || mime_db.get_mime_types_from_file_name("a.foo") == vec![foo.clone()]
+        ));
+        assert!(wait_for(|| !watcher.is_stale()));
+        drop(watcher);
+
+        // Only the flag is set
+        let watcher = Watcher::new(Arc::clone(&mime_db), WatchMode::Flag).unwrap();
+        replace_globs(&dir, "50:text/x-bar:*.foo\n");
+        assert!(wait_for(|| watcher.is_stale()));
+        assert_eq!(mime_db.get_mime_types_from_file_name("a.foo"), vec![foo]);
+
+        watcher.reload();
+        assert!(!watcher.is_stale());
+        assert_eq!(mime_db.get_mime_types_from_file_name("a.foo"), vec![bar]);
+    }
+
+    #[test]
+    fn watch_recreated_directory() {
+        let mut dir = TempMimeDir::new().unwrap();
+        dir.glob(50, "text/x-foo", "*.foo");
+        dir.write().unwrap();
+        let mime_db = Arc::new(SyncSharedMimeInfo::from(builder(&dir).build()));
+        mime_db.set_debounce(Debounce::new(Duration::ZERO, Coalescing::Quiet));
+        let watcher = Watcher::new(Arc::clone(&mime_db), WatchMode::Reload).unwrap();
+        let foo = Mime::from_str("text/x-foo").unwrap();
+        let bar = Mime::from_str("text/x-bar").unwrap();
+
+        // The directory is removed, and its watch with it
+        fs::remove_dir_all(dir.mime_path()).unwrap();
+        assert!(wait_for(|| mime_db.get_mime_types_from_file_name("a.foo")
+            == vec![mime::APPLICATION_OCTET_STREAM]));
+
+        // The directory created again is watched again, so that the
+        // changes of its contents are still seen
+        create_mime_dir(&dir, "50:text/x-foo:*.foo\n");
+        assert!(wait_for(
+            || mime_db.get_mime_types_from_file_name("a.foo") == vec![foo.clone()]
+        ));
+        replace_globs(&dir, "50:text/x-bar:*.foo\n");
+        assert!(wait_for(
+            || mime_db.get_mime_types_from_file_name("a.foo") == vec![bar.clone()]
+        ));
+        drop(watcher);
+    }
+
+    #[test]
+    fn watch_mime_types_file() {
+        let dir = TempMimeDir::new().unwrap();
+        let mime_types = dir.path().join("mime.types");
+        fs::write(&mime_types, "text/x-foo foo\n").unwrap();
+        touch(&mime_types);
+        let db = builder(&dir).mime_types_files([&mime_types]).build();
+        let mime_db = Arc::new(SyncSharedMimeInfo::from(db));
+        mime_db.set_debounce(Debounce::new(Duration::ZERO, Coalescing::Quiet));
+        let watcher = Watcher::new(Arc::clone(&mime_db), WatchMode::Flag).unwrap();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![Mime::from_str("text/x-foo").unwrap()]
+        );
+
+        // The file is rewritten in place, without creating a new entry in
+        // its directory
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&mime_types)
+            .unwrap();
+        file.write_all(b"text/x-bar foo\n").unwrap();
+        drop(file);
+        assert!(wait_for(|| watcher.is_stale()));
+
+        watcher.reload();
+        assert_eq!(
+            mime_db.get_mime_types_from_file_name("a.foo"),
+            vec![Mime::from_str("text/x-bar").unwrap()]
+        );
+    }
+}
@@ -0,0 +1,309 @@
+// Reads the shared-mime-info source files using quick-xml; the comments
+// and processing instructions are skipped, the general entities declared
+// in the internal subset of the document type declaration are expanded,
+// and the namespace prefixes are removed from the names of the elements,
+// but not from the ones of the attributes, like `xml:lang`
+
+use std::collections::HashMap;
+
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesRef, BytesStart, Event as XmlEvent};
+use quick_xml::XmlVersion;
+
+#[derive(Debug, PartialEq)]
+pub enum Event<'a> {
+    Start {
+        name: &'a str,
+        attributes: Vec<(String, String)>,
+        // Whether the element has no content, like `<glob/>`
+        empty: bool,
+    },
+    End {
+        name: &'a str,
+    },
+    Text(String),
+}
+
+impl Event<'_> {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        match self {
+            Event::Start { attributes, .. } => attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+}
+
+pub struct Reader<'a> {
+    input: &'a str,
+    reader: quick_xml::Reader<&'a [u8]>,
+    // The general entities declared by the document
+    entities: HashMap<String, String>,
+    // The event read after a run of text
+    pending: Option<Event<'a>>,
+    done: bool,
+}
+
+// The declarations of general entities, like `<!ENTITY foo "bar">`, in the
+// document type declaration; the parameter and external entities are not
+// supported
+fn entity_declarations(doctype: &str) -> HashMap<String, String> {
+    let mut res = HashMap::new();
+    let mut rest = doctype;
+
+    while let Some(idx) = rest.find("<!ENTITY") {
+        rest = &rest[idx + "<!ENTITY".len()..];
+
+        let decl = rest.trim_start();
+        let name_end = decl.find(char::is_whitespace).unwrap_or(decl.len());
+        let (name, value) = decl.split_at(name_end);
+        let value = value.trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) if name != "%" => quote,
+            _ => continue,
+        };
+        if let Some(end) = value[1..].find(quote) {
+            res.entry(name.to_string())
+                .or_insert_with(|| value[1..end + 1].to_string());
+        }
+    }
+
+    res
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a str) -> Reader<'a> {
+        let mut reader = quick_xml::Reader::from_str(input);
+        reader.config_mut().check_comments = true;
+
+        Reader {
+            input,
+            reader,
+            entities: HashMap::new(),
+            pending: None,
+            done: false,
+        }
+    }
+
+    // The local name of the element whose start or end tag is at `pos`
+    fn element_name(&self, pos: usize) -> Option<&'a str> {
+        let tag = self.input.get(pos..)?.trim_start_matches(['<', '/']);
+        let end = tag.find(|c: char| c.is_whitespace() || c == '/' || c == '>')?;
+
+        tag[..end].rsplit(':').next()
+    }
+
+    fn resolve_entity(&self, name: &str) -> Option<&str> {
+        resolve_predefined_entity(name).or_else(|| self.entities.get(name).map(String::as_str))
+    }
+
+    // The replacement text of a reference; the values of the declared
+    // entities can only contain character references and predefined
+    // entities, so they are not expanded recursively
+    fn resolve_ref(&self, r: &BytesRef) -> Option<String> {
+        if let Some(ch) = r.resolve_char_ref().ok()? {
+            return Some(ch.to_string());
+        }
+
+        let name = r.decode().ok()?;
+        if let Some(value) = resolve_predefined_entity(&name) {
+            return Some(value.to_string());
+        }
+
+        let value = self.entities.get(name.as_ref())?;
+        let value = quick_xml::escape::unescape_with(value, resolve_predefined_entity).ok()?;
+
+        Some(value.into_owned())
+    }
+
+    fn start(&self, pos: usize, start: BytesStart, empty: bool) -> Option<Event<'a>> {
+        let mut attributes = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr.ok()?;
+            let key = std::str::from_utf8(attr.key.as_ref()).ok()?.to_string();
+            // The values of the entities are expanded once more, for the
+            // references they contain
+            let value = attr
+                .normalized_value_with(XmlVersion::Implicit1_0, 2, |name| self.resolve_entity(name))
+                .ok()?;
+            attributes.push((key, value.into_owned()));
+        }
+
+        Some(Event::Start {
+            name: self.element_name(pos)?,
+            attributes,
+            empty,
+        })
+    }
+
+    // Reads the next event; the runs of text, split by the references and
+    // the comments, are returned as a single event
+    fn read(&mut self) -> Option<Event<'a>> {
+        let mut text: Option<String> = None;
+
+        loop {
+            let pos = usize::try_from(self.reader.buffer_position()).ok()?;
+            let event = match self.reader.read_event().ok()? {
+                XmlEvent::Start(start) => self.start(pos, start, false)?,
+                XmlEvent::Empty(start) => self.start(pos, start, true)?,
+                XmlEvent::End(_) => Event::End {
+                    name: self.element_name(pos)?,
+                },
+                XmlEvent::Text(t) => {
+                    let t = t.xml10_content().ok()?;
+                    text.get_or_insert_with(String::new).push_str(&t);
+                    continue;
+                }
+                XmlEvent::CData(t) => {
+                    let t = t.decode().ok()?;
+                    text.get_or_insert_with(String::new).push_str(&t);
+                    continue;
+                }
+                XmlEvent::GeneralRef(r) => {
+                    let t = self.resolve_ref(&r)?;
+                    text.get_or_insert_with(String::new).push_str(&t);
+                    continue;
+                }
+                XmlEvent::DocType(doctype) => {
+                    self.entities = entity_declarations(&doctype.decode().ok()?);
+                    continue;
+                }
+                XmlEvent::Comment(_) | XmlEvent::Decl(_) | XmlEvent::PI(_) => continue,
+                XmlEvent::Eof => {
+                    self.done = true;
+                    return text.map(Event::Text);
+                }
+            };
+
+            return match text {
+                Some(text) => {
+                    self.pending = Some(event);
+                    Some(Event::Text(text))
+                }
+                None => Some(event),
+            };
+        }
+    }
+}
+
+// Returns None once the end of the input is reached, or at the first
+// malformed construct
+impl<'a> Iterator for Reader<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+        if self.done {
+            return None;
+        }
+
+        let res = self.read();
+        if res.is_none() {
+            self.done = true;
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(doc: &str) -> Vec<Event<'_>> {
+        Reader::new(doc)
+            .filter(|e| !matches!(e, Event::Text(t) if t.trim().is_empty()))
+            .collect()
+    }
+
+    #[test]
+    fn read_events() {
+        let doc = r#"<?xml version="1.0"?>
+<!DOCTYPE mime-info [ <!ELEMENT glob EMPTY> ]>
+<!-- a <comment> -->
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type='text/x-foo'>
+    <comment xml:lang="fr">Fichier &lt;foo&gt; &#233;</comment>
+    <glob pattern="*.foo" weight="60"/>
+    <match value=">"/>
+  </mime-type>
+</mime-info>"#;
+
+        let events = events(doc);
+        assert_eq!(events.len(), 9);
+        assert_eq!(events[1].attribute("type"), Some("text/x-foo"));
+        assert_eq!(events[2].attribute("xml:lang"), Some("fr"));
+        assert_eq!(events[3], Event::Text("Fichier <foo> é".to_string()));
+        assert_eq!(
+            events[5],
+            Event::Start {
+                name: "glob",
+                attributes: vec![
+                    ("pattern".into(), "*.foo".into()),
+                    ("weight".into(), "60".into())
+                ],
+                empty: true,
+            }
+        );
+        assert_eq!(events[6].attribute("value"), Some(">"));
+        assert_eq!(events[8], Event::End { name: "mime-info" });
+    }
+
+    #[test]
+    fn qualified_attributes() {
+        let doc = r#"<comment xmlns:foo="urn:foo" foo:lang="de" xml:lang="fr">x</comment>"#;
+
+        let events = events(doc);
+        assert_eq!(events[0].attribute("xml:lang"), Some("fr"));
+        assert_eq!(events[0].attribute("foo:lang"), Some("de"));
+        assert_eq!(events[0].attribute("lang"), None);
+    }
+
+    #[test]
+    fn declared_entities() {
+        let doc = r#"<!DOCTYPE mime-info [
+  <!ENTITY vendor "ACME &amp; Co">
+  <!ENTITY % param "ignored">
+]>
+<comment>&vendor; &amp; &apos;file&apos;</comment>
+<glob pattern="*.&vendor;"/>"#;
+
+        let res = events(doc);
+        assert_eq!(res[1], Event::Text("ACME & Co & 'file'".to_string()));
+        assert_eq!(res[3].attribute("pattern"), Some("*.ACME & Co"));
+
+        // The references to unknown entities are malformed
+        assert_eq!(events("<comment>&unknown;</comment>").len(), 1);
+        assert_eq!(events(r#"<glob pattern="&unknown;"/>"#).len(), 0);
+    }
+
+    #[test]
+    fn text_runs() {
+        // The text split by comments and CDATA sections is joined
+        let doc = "<comment>a<!-- <b> --> c<![CDATA[ <d> ]]>e</comment>";
+        assert_eq!(events(doc)[1], Event::Text("a c <d> e".to_string()));
+
+        // A CDATA section is not special inside an attribute
+        let doc = r#"<glob pattern="<![CDATA[*.foo]]>"/>"#;
+        assert_eq!(
+            events(doc)[0].attribute("pattern"),
+            Some("<![CDATA[*.foo]]>")
+        );
+    }
+
+    #[test]
+    fn malformed_documents() {
+        // Malformed documents stop at the first error
+        assert_eq!(events("<glob pattern=*.foo/>").len(), 0);
+        assert_eq!(events("<mime-info><glob></mime-info>").len(), 2);
+        assert_eq!(events("<mime-info><!-- a -- b --></mime-info>").len(), 1);
+        assert_eq!(events("<mime-info><glob pattern='*.foo").len(), 1);
+        assert_eq!(events("<mime-info>&#xffffffff;</mime-info>").len(), 1);
+        assert_eq!(events("<mime-info a='1' a='2'/>").len(), 0);
+        assert_eq!(events("").len(), 0);
+    }
+}